@@ -0,0 +1,22 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use silent_crdt::auth::{JwtManager, Role};
+use std::hint::black_box;
+
+/// 对比启用与禁用验证缓存时的 token 验证吞吐
+fn bench_verify_token(c: &mut Criterion) {
+    let cached = JwtManager::new("bench-secret");
+    let uncached = JwtManager::new("bench-secret").with_cache_capacity(0);
+    let token = cached
+        .generate_token("node1".to_string(), Role::Writer, 3600)
+        .unwrap();
+
+    c.bench_function("verify_token_uncached", |b| {
+        b.iter(|| uncached.verify_token(black_box(&token)).unwrap())
+    });
+    c.bench_function("verify_token_cached", |b| {
+        b.iter(|| cached.verify_token(black_box(&token)).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_verify_token);
+criterion_main!(benches);
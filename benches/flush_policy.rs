@@ -0,0 +1,51 @@
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use silent_crdt::storage::{FlushPolicy, SledStorage, StorageBackend, StorageConfig};
+use silent_crdt::sync::{Change, ChangeRequest, SyncState};
+use std::time::Duration;
+
+/// 对比立即落盘与按间隔合并落盘时每秒可完成的保存次数（与 /sync 每次请求的写入路径一致）
+fn bench_save_state(c: &mut Criterion) {
+    let node_id = "bench-node";
+    let mut state = SyncState::new(node_id.to_string());
+    state
+        .apply_changes(ChangeRequest {
+            changes: (0..100)
+                .map(|i| Change {
+                    op: "set".to_string(),
+                    key: format!("key{}", i),
+                    value: Some(i.to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+        })
+        .unwrap();
+
+    let policies = [
+        ("save_state_immediate", FlushPolicy::Immediate),
+        (
+            "save_state_periodic",
+            FlushPolicy::Periodic(Duration::from_millis(100)),
+        ),
+    ];
+    let mut group = c.benchmark_group("flush_policy");
+    group.throughput(Throughput::Elements(1));
+    for (name, flush_policy) in policies {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let storage = SledStorage::with_config(
+            temp_dir.path().to_str().unwrap(),
+            &StorageConfig {
+                flush_policy,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        group.bench_function(name, |b| {
+            b.iter(|| storage.save_state(node_id, &state).unwrap())
+        });
+        storage.flush_pending().unwrap();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_save_state);
+criterion_main!(benches);
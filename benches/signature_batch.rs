@@ -0,0 +1,42 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use silent_crdt::signature::{SignatureManager, SignedOperation, verify_batch};
+use std::hint::black_box;
+
+fn signed_ops(n: usize) -> Vec<SignedOperation> {
+    let manager = SignatureManager::new("node1".to_string());
+    (0..n)
+        .map(|i| {
+            manager
+                .sign_operation(
+                    format!("op{}", i),
+                    i as i64,
+                    "GCounter.Increment".to_string(),
+                    format!("counter+{}", i),
+                    "{}".to_string(),
+                )
+                .unwrap()
+        })
+        .collect()
+}
+
+/// 对比逐个验证与批量验证的吞吐
+fn bench_signature_verification(c: &mut Criterion) {
+    let mut group = c.benchmark_group("signature_verification");
+    for n in [16, 128, 1024] {
+        let ops = signed_ops(n);
+        group.bench_with_input(BenchmarkId::new("individual", n), &ops, |b, ops| {
+            b.iter(|| {
+                for op in ops {
+                    black_box(op.verify()).unwrap();
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("batch", n), &ops, |b, ops| {
+            b.iter(|| assert!(verify_batch(black_box(ops)).is_empty()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_signature_verification);
+criterion_main!(benches);
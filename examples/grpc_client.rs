@@ -36,18 +36,24 @@ async fn main() -> Result<()> {
                     key: "counter1".to_string(),
                     value: None,
                     delta: Some(5),
+                    field: None,
+                    position: None,
                 },
                 Change {
                     op: "set".to_string(),
                     key: "name".to_string(),
                     value: Some("Alice".to_string()),
                     delta: None,
+                    field: None,
+                    position: None,
                 },
                 Change {
                     op: "add".to_string(),
                     key: "tags".to_string(),
                     value: Some("rust".to_string()),
                     delta: None,
+                    field: None,
+                    position: None,
                 },
             ],
         })
@@ -87,12 +93,18 @@ async fn main() -> Result<()> {
 
     // 6. 获取操作历史
     println!("\n📖 获取操作历史...");
-    let history_response = client.get_history(GetHistoryRequest {}).await?.into_inner();
+    let history_response = client
+        .get_history(GetHistoryRequest {
+            ts_format: "iso".to_string(),
+        })
+        .await?
+        .into_inner();
     println!("   历史条目数: {}", history_response.entries.len());
     for (i, entry) in history_response.entries.iter().take(5).enumerate() {
         println!(
-            "   [{}] {}: {} - {}",
+            "   [{}] {} {}: {} - {}",
             i + 1,
+            entry.timestamp_iso.as_deref().unwrap_or("-"),
             entry.operation_type,
             entry.key,
             entry.details
@@ -120,6 +132,26 @@ async fn main() -> Result<()> {
         }
     }
 
+    // 8. 跟踪操作日志：重放最早一条之后的操作，再持续接收新操作
+    println!("\n📡 跟踪操作日志（Ctrl+C 退出）...");
+    let mut stream = client
+        .stream_op_log(StreamOpLogRequest {
+            after_id: oplog_response.entries.first().map(|entry| entry.id.clone()),
+        })
+        .await?
+        .into_inner();
+    while let Some(event) = stream.message().await? {
+        if event.dropped > 0 {
+            println!("   ⚠️  消费过慢，丢弃了 {} 个事件", event.dropped);
+            continue;
+        }
+        if let Some(entry) = event.entry {
+            // operation 形如 "PNCounter.Increment(key, node, +1)"，括号前即操作类型
+            let op_type = entry.operation.split('(').next().unwrap_or_default();
+            println!("   {} {}: {}", &entry.id[..12], op_type, entry.key);
+        }
+    }
+
     println!("\n✅ gRPC 客户端测试完成！");
 
     Ok(())
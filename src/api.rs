@@ -1,605 +1,5281 @@
-use crate::auth::{JwtManager, Role};
-use crate::signature::SignatureManager;
-use crate::storage::Storage;
-use crate::sync::{ChangeRequest, SyncRequest, SyncResponse, SyncState};
+use crate::auth::{DEFAULT_REVOCATION_RETENTION_SECS, JwtManager, KeyScope, RevocationStore, Role};
+use crate::batch::{
+    AcceptedResponse, ApplyBatcher, DurabilityResponse, TicketOutcome, TicketStatus,
+};
+use crate::compression::{self, SYNC_CONTENT_ENCODING};
+use crate::crdt::{
+    CRDT_TYPES, DEFAULT_MAX_CRDT_DEPTH, KeyCollation, KeyFilter, MERKLE_DEPTH, MapDiff,
+    VectorClock, check_crdt_depth,
+};
+use crate::encryption::FieldEncryptor;
+use crate::metrics::{DocumentSize, Metrics};
+use crate::policy::ConflictPolicy;
+use crate::schema::KeySchema;
+use crate::selftest::{DEFAULT_SELF_TEST_ROUNDS, run_self_test};
+use crate::signature::{KeyPair, PublicKeys, SignatureManager, SignedOperation};
+use crate::snapshot::SnapshotPolicy;
+use crate::storage::{
+    DEFAULT_DOCUMENT, Durability, ImportProgress, SnapshotMeta, StorageBackend, read_state_file,
+};
+use crate::sync::{
+    BundleFormat, CHANGE_OPS, ChangeError, ChangeRequest, ChangeValidation, DEFAULT_STATS_TOP_KEYS,
+    FieldError, OpLog, OpLogEntry, OpLogFilter, OpLogPage, ReproBundle, RestoreReport, SetRace,
+    StateBundle, SyncRequest, SyncResponse, SyncState, format_ts_iso,
+};
+use crate::telemetry::{RequestTelemetry, SlowOpLogger};
+use bytes::Bytes;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use silent::prelude::*;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::{RwLock, broadcast};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::{Stream, StreamExt};
+
+/// 默认限流突发容量（令牌桶最多积累的请求数）
+pub const DEFAULT_RATE_LIMIT_BURST: u32 = 20;
+
+/// 令牌桶数量超过该值时清理已补满的桶
+const RATE_LIMIT_PRUNE_THRESHOLD: usize = 10_000;
+
+/// 默认幂等键保留时间（秒）
+pub const DEFAULT_IDEMPOTENCY_TTL_SECS: u64 = 600;
+
+/// 默认最多保留的幂等键数量
+pub const DEFAULT_IDEMPOTENCY_CAPACITY: usize = 10_000;
+
+/// 默认请求体大小上限（10 MiB）
+pub const DEFAULT_MAX_BODY_BYTES: usize = 10 * 1024 * 1024;
+
+/// 文档 ID 的最大长度（字节）
+pub const MAX_DOCUMENT_ID_LEN: usize = 128;
+
+/// 操作广播的缓冲容量，订阅方落后超过该数量时丢弃事件并收到重新同步提示
+pub const OP_EVENT_CAPACITY: usize = 1024;
+
+/// 状态变更广播的缓冲容量，WebSocket 订阅方落后超过该数量时收到重新同步提示
+pub const CHANGE_EVENT_CAPACITY: usize = 256;
+
+/// 状态变更事件（由 `/sync`、`/merge` 与 `/admin/force-pull` 发布，推送给 `/subscribe` 的订阅方）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChangeEvent {
+    /// 发生变更的文档
+    pub doc_id: String,
+    /// 变更来源（`sync` / `merge` / `force-pull`）
+    pub source: &'static str,
+    /// 值发生变化的键（按键名排序）
+    pub keys: Vec<String>,
+    /// 变更后的状态哈希
+    pub state_hash: String,
+    /// 变更后文档操作日志中最后一条操作的 ID（用作 SSE 事件 ID）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_op_id: Option<String>,
+}
+
+/// 推送给 SSE 订阅方的状态变更
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StateChange {
+    /// 值发生变化的键（按键名排序）
+    pub changed_keys: Vec<String>,
+    /// 变更后的状态哈希
+    pub state_hash: String,
+    /// 事件生成时间（毫秒时间戳）
+    pub ts: i64,
+    /// 事件 ID，客户端重连时通过 `Last-Event-ID` 发回以补发之后的变更
+    #[serde(skip)]
+    pub id: Option<String>,
+}
+
+/// SSE 流中的消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SseMessage {
+    Change(StateChange),
+    /// 订阅方消费过慢，`skipped` 个事件已被丢弃，应重新拉取状态
+    Resync {
+        skipped: u64,
+    },
+}
+
+/// SSE 保活注释的发送间隔（秒），避免代理断开空闲连接
+const SSE_KEEP_ALIVE_SECS: u64 = 15;
+
+/// 推送给 WebSocket 订阅方的消息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum SubscribeMessage {
+    /// 连接建立时发送，携带默认文档当前的状态哈希
+    Hello {
+        state_hash: String,
+    },
+    Change(ChangeEvent),
+    /// 订阅方消费过慢，`skipped` 个事件已被丢弃，应重新拉取状态
+    ResyncRecommended {
+        skipped: u64,
+        message: String,
+    },
+}
+
+/// 对等节点 HTTP 客户端的连接配置
+#[derive(Debug, Clone)]
+pub struct PeerClientConfig {
+    /// 以 HTTP/2 直连对等节点（要求对端支持明文 HTTP/2）
+    pub http2_prior_knowledge: bool,
+    /// 空闲连接保留时长
+    pub pool_idle_timeout: Duration,
+    /// 每个对等节点保留的最大空闲连接数
+    pub pool_max_idle_per_host: usize,
+    /// TCP keep-alive 探测间隔
+    pub tcp_keepalive: Duration,
+    /// 单次请求超时
+    pub timeout: Duration,
+    /// 以 HTTPS 访问对等节点（本节点启用 TLS 时集群内所有节点都应启用）
+    pub https: bool,
+    /// 额外信任的对等节点证书 CA（PEM），用于自签名证书
+    pub ca_cert_pem: Option<Vec<u8>>,
+    /// 以 zstd 压缩推送给对等节点的状态（要求对端支持 `Content-Encoding: zstd`）
+    pub compress: bool,
+}
+
+impl Default for PeerClientConfig {
+    fn default() -> Self {
+        Self {
+            http2_prior_knowledge: false,
+            pool_idle_timeout: Duration::from_secs(90),
+            pool_max_idle_per_host: 8,
+            tcp_keepalive: Duration::from_secs(60),
+            timeout: Duration::from_secs(30),
+            https: false,
+            ca_cert_pem: None,
+            compress: false,
+        }
+    }
+}
+
+impl PeerClientConfig {
+    /// 构建共享的对等节点客户端，连接池在各次同步之间复用
+    pub fn build(&self) -> anyhow::Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .tcp_keepalive(self.tcp_keepalive)
+            .timeout(self.timeout)
+            .http2_adaptive_window(true)
+            .http2_keep_alive_interval(self.tcp_keepalive)
+            .http2_keep_alive_while_idle(true);
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(ca_cert_pem) = &self.ca_cert_pem {
+            builder = builder.add_root_certificate(reqwest::Certificate::from_pem(ca_cert_pem)?);
+        }
+        Ok(builder.build()?)
+    }
+}
 
 /// 应用状态
 #[derive(Clone)]
 pub struct AppState {
     pub node_id: String,
-    pub sync_state: Arc<RwLock<SyncState>>,
-    pub storage: Arc<Storage>,
+    pub sync_state: Arc<RwLock<SyncState>>, // 默认文档
+    pub documents: Arc<RwLock<HashMap<String, Arc<RwLock<SyncState>>>>>, // 已加载的所有文档（含默认文档）
+    pub storage: Arc<dyn StorageBackend>,
     pub jwt_manager: Arc<JwtManager>,
+    pub revocations: Arc<RevocationStore>, // 已吊销 token 的 jti
     pub signature_manager: Arc<SignatureManager>,
-    pub auth_enabled: bool, // 是否启用权限控制
+    pub peer_client: reqwest::Client, // 对等节点共享客户端（克隆开销很小）
+    pub peer_scheme: &'static str,    // 访问对等节点的协议（http / https）
+    pub compress_peer_sync: bool,     // 推送给对等节点的状态是否压缩
+    pub auth_enabled: bool,           // 是否启用权限控制
+    pub apply_batcher: Option<Arc<ApplyBatcher>>, // 写入合并缓冲区（启用时）
+    pub max_body_bytes: usize,        // 请求体大小上限
+    pub default_role: Option<Role>,   // 未携带 token 时使用的角色
+    pub max_crdt_depth: usize,        // 传入数据允许的 CRDT 嵌套深度
+    pub needs_recovery: Arc<AtomicBool>, // 写入临界区发生 panic 且尚未恢复
+    pub op_events: broadcast::Sender<OpLogEntry>, // 新应用操作的广播（供订阅接口使用）
+    pub change_events: broadcast::Sender<ChangeEvent>, // 状态变更的广播（供 WebSocket 订阅使用）
+    pub slow_ops: SlowOpLogger,       // 慢操作日志（未配置阈值时不计时）
+    pub metrics: Arc<Metrics>,        // Prometheus 指标
+    pub require_signatures: bool,     // 合并时是否拒绝未签名的操作
+    pub rate_limiter: Option<Arc<RateLimiter>>, // 按调用方限流（启用时）
+    pub snapshot_policy: Option<Arc<SnapshotPolicy>>, // 自动快照策略（启用时）
+    pub cors: Option<Arc<CorsConfig>>, // 允许跨域访问的来源（启用时）
+    pub idempotency: Arc<IdempotencyCache>, // 最近处理过的 Idempotency-Key 及其响应
 }
 
 impl AppState {
     pub fn new(
         node_id: String,
-        storage: Storage,
+        storage: Arc<dyn StorageBackend>,
         jwt_secret: String,
         auth_enabled: bool,
     ) -> anyhow::Result<Self> {
-        let sync_state = if let Some(state) = storage.load_state(&node_id)? {
-            Arc::new(RwLock::new(state))
-        } else {
-            Arc::new(RwLock::new(SyncState::new(node_id.clone())))
+        let mut sync_state = storage
+            .load_state(&node_id)?
+            .unwrap_or_else(|| SyncState::new(node_id.clone()));
+        sync_state.schemas = storage.load_schemas(&node_id)?;
+
+        // 复用已持久化的签名密钥，保证重启后公钥不变
+        let keypair = match storage.load_keypair(&node_id)? {
+            Some(keypair) => keypair,
+            None => {
+                let keypair = KeyPair::generate();
+                storage.save_keypair(&node_id, &keypair)?;
+                keypair
+            }
         };
+        // 同时恢复轮换下线、可能仍在宽限期内的旧公钥
+        let signature_manager = Arc::new(
+            SignatureManager::from_keypair(node_id.clone(), keypair)
+                .with_retiring_keys(storage.load_retiring_keys(&node_id)?),
+        );
+        // 本节点记录的操作均以该密钥签名
+        sync_state.op_log.signer = Some(signature_manager.clone());
+
+        let (op_events, _) = broadcast::channel(OP_EVENT_CAPACITY);
+        let sender = op_events.clone();
+        sync_state.hooks.on_operation(move |entry| {
+            if sender.receiver_count() > 0 {
+                let _ = sender.send(entry.clone());
+            }
+        });
+        let sync_state = Arc::new(RwLock::new(sync_state));
+        let documents = HashMap::from([(DEFAULT_DOCUMENT.to_string(), sync_state.clone())]);
 
         let jwt_manager = Arc::new(JwtManager::new(&jwt_secret));
-        let signature_manager = Arc::new(SignatureManager::new(node_id.clone()));
 
         Ok(Self {
             node_id,
             sync_state,
-            storage: Arc::new(storage),
+            documents: Arc::new(RwLock::new(documents)),
+            storage,
             jwt_manager,
+            revocations: Arc::new(RevocationStore::new()),
             signature_manager,
+            peer_client: PeerClientConfig::default().build()?,
+            peer_scheme: "http",
+            compress_peer_sync: false,
             auth_enabled,
+            apply_batcher: None,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            default_role: None,
+            max_crdt_depth: DEFAULT_MAX_CRDT_DEPTH,
+            needs_recovery: Arc::new(AtomicBool::new(false)),
+            op_events,
+            change_events: broadcast::channel(CHANGE_EVENT_CAPACITY).0,
+            slow_ops: SlowOpLogger::default(),
+            metrics: Arc::new(Metrics::default()),
+            require_signatures: false,
+            rate_limiter: None,
+            snapshot_policy: None,
+            cors: None,
+            idempotency: Arc::new(IdempotencyCache::new(
+                Duration::from_secs(DEFAULT_IDEMPOTENCY_TTL_SECS),
+                DEFAULT_IDEMPOTENCY_CAPACITY,
+            )),
         })
     }
-}
 
-// 实现中间件处理器，用于在所有请求中注入 AppState
-#[async_trait::async_trait]
-impl MiddleWareHandler for AppState {
-    async fn handle(&self, mut req: Request, next: &Next) -> Result<Response> {
-        req.extensions_mut().insert(self.clone());
-        next.call(req).await
+    /// 按配置重建对等节点共享客户端
+    pub fn with_peer_client_config(mut self, config: &PeerClientConfig) -> anyhow::Result<Self> {
+        self.peer_client = config.build()?;
+        self.peer_scheme = if config.https { "https" } else { "http" };
+        self.compress_peer_sync = config.compress;
+        Ok(self)
     }
-}
-
-/// POST /sync - 接收变更请求
-async fn sync_handler(mut req: Request) -> Result<Response> {
-    let state = req.extensions().get::<AppState>().unwrap().clone();
 
-    // 解析请求体
-    let change_request: ChangeRequest = req.json_parse().await?;
+    /// 对等节点（host:port）上某个路径的 URL
+    pub fn peer_url(&self, peer: &str, path: &str) -> String {
+        format!("{}://{}{}", self.peer_scheme, peer, path)
+    }
 
-    // 应用变更
-    let mut sync_state = state.sync_state.write().await;
-    sync_state
-        .apply_changes(change_request)
-        .map_err(|e| SilentError::business_error(StatusCode::BAD_REQUEST, e))?;
+    /// 从对等节点的 `/auth/public-key` 获取其公钥并登记为受信任，用于验证该节点签发的操作
+    pub async fn refresh_peer_keys(&self, peer: &str) -> anyhow::Result<PublicKeys> {
+        use anyhow::Context;
 
-    // 保存状态
-    state
-        .storage
-        .save_state(&state.node_id, &sync_state)
-        .map_err(|e| {
-            SilentError::business_error(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to save state: {}", e),
-            )
-        })?;
+        let keys: PublicKeys = self
+            .peer_client
+            .get(self.peer_url(peer, "/auth/public-key"))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Failed to fetch public key from {}", peer))?
+            .json()
+            .await
+            .with_context(|| format!("Invalid public key response from {}", peer))?;
+        self.signature_manager.trust_peer_keys(keys.clone())?;
+        tracing::debug!("Trusted public key of node {} from {}", keys.node_id, peer);
+        Ok(keys)
+    }
 
-    let state_hash = sync_state.state_hash();
-    drop(sync_state);
+    /// 轮换签名密钥：新密钥与 retiring 集合持久化成功后才在内存中生效
+    pub fn rotate_signing_key(&self) -> anyhow::Result<KeyPair> {
+        self.signature_manager.rotate_with(|keypair, retiring| {
+            self.storage
+                .save_rotated_keypair(&self.node_id, keypair, retiring)
+        })
+    }
 
-    let response = SyncResponse {
-        success: true,
-        state_hash,
-        message: "Changes applied successfully".to_string(),
-    };
+    /// 设置慢操作日志阈值（毫秒），`None` 时不记录
+    pub fn with_slow_op_threshold(mut self, threshold_ms: Option<u64>) -> Self {
+        self.slow_ops = SlowOpLogger::new(threshold_ms);
+        self
+    }
 
-    Ok(Response::json(&response))
-}
+    /// 计算状态哈希，耗时超过慢操作阈值时记录日志
+    pub fn timed_state_hash(&self, sync_state: &SyncState) -> String {
+        self.slow_ops
+            .time("state_hash", sync_state.op_log.ops.len(), Vec::new, || {
+                sync_state.state_hash()
+            })
+    }
 
-/// POST /sync-peer - 触发与其他节点的同步
-#[derive(Debug, Deserialize)]
-struct SyncPeerRequest {
-    peer: String,
-}
+    /// 设置传入数据允许的 CRDT 最大嵌套深度
+    pub fn with_max_crdt_depth(mut self, max_crdt_depth: usize) -> Self {
+        self.max_crdt_depth = max_crdt_depth;
+        self
+    }
 
-async fn sync_peer_handler(mut req: Request) -> Result<Response> {
-    let state = req.extensions().get::<AppState>().unwrap().clone();
+    /// 设置计数器单节点贡献上限（需在启动时、状态被共享前调用）
+    pub fn with_counter_cap(self, counter_cap: Option<u64>) -> Self {
+        if let Ok(mut sync_state) = self.sync_state.try_write() {
+            sync_state.counter_cap = counter_cap;
+        }
+        self
+    }
 
-    // 解析请求体
-    let peer_req: SyncPeerRequest = req.json_parse().await?;
+    /// 设置不同键的数量上限（需在启动时、状态被共享前调用）
+    pub fn with_max_keys(self, max_keys: Option<usize>) -> Self {
+        if let Ok(mut sync_state) = self.sync_state.try_write() {
+            sync_state.max_keys = max_keys;
+        }
+        self
+    }
 
-    // 获取当前状态
-    let current_state = {
-        let sync_state = state.sync_state.read().await;
-        sync_state.clone()
-    };
+    /// 设置单个集合的元素数量上限（需在启动时、状态被共享前调用）
+    pub fn with_max_set_elements(self, max_set_elements: Option<usize>) -> Self {
+        if let Ok(mut sync_state) = self.sync_state.try_write() {
+            sync_state.max_set_elements = max_set_elements;
+        }
+        self
+    }
 
-    // 构建同步请求
-    let sync_request = SyncRequest {
-        from_node: state.node_id.clone(),
-        state: current_state,
-    };
+    /// 设置是否在 decrement 时自动将 GCounter 转换为 PNCounter（需在启动时、状态被共享前调用）
+    pub fn with_auto_promote_counters(self, enabled: bool) -> Self {
+        if let Ok(mut sync_state) = self.sync_state.try_write() {
+            sync_state.auto_promote_counters = enabled;
+        }
+        self
+    }
 
-    // 发送同步请求到对等节点
-    let client = reqwest::Client::new();
-    let peer_url = format!("http://{}/merge", peer_req.peer);
+    /// 启用敏感字段加密（需在启动时、状态被共享前调用）
+    pub fn with_field_encryption(self, encryptor: Option<FieldEncryptor>) -> Self {
+        if let Ok(mut sync_state) = self.sync_state.try_write() {
+            sync_state.encryptor = encryptor.map(Arc::new);
+        }
+        self
+    }
 
-    let response = client
-        .post(&peer_url)
-        .json(&sync_request)
-        .send()
-        .await
-        .map_err(|e| {
-            SilentError::business_error(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to sync with peer: {}", e),
-            )
-        })?;
+    /// 使用密钥文件中的签名密钥（base64 编码的 32 字节私钥）代替存储中的密钥
+    ///
+    /// 文件不存在时生成新密钥并写入该文件（Unix 下权限为 0600）。
+    pub fn with_key_file(mut self, path: &std::path::Path) -> anyhow::Result<Self> {
+        let keypair = if path.exists() {
+            KeyPair::from_base64(&std::fs::read_to_string(path)?)?
+        } else {
+            let keypair = KeyPair::generate();
+            write_key_file(path, &keypair)?;
+            tracing::info!("Generated signing key file: {}", path.display());
+            keypair
+        };
+        self.signature_manager = Arc::new(SignatureManager::from_keypair(
+            self.node_id.clone(),
+            keypair,
+        ));
+        if let Ok(mut sync_state) = self.sync_state.try_write() {
+            sync_state.op_log.signer = Some(self.signature_manager.clone());
+        }
+        Ok(self)
+    }
 
-    if response.status().is_success() {
-        let sync_response: SyncResponse = response.json().await.map_err(|e| {
-            SilentError::business_error(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to parse peer response: {}", e),
-            )
-        })?;
+    /// 设置合并时是否要求所有传入操作都带有有效签名（关闭时接受未签名的旧操作）
+    pub fn with_require_signatures(mut self, require_signatures: bool) -> Self {
+        self.require_signatures = require_signatures;
+        self
+    }
 
-        Ok(Response::json(&sync_response))
-    } else {
-        Err(SilentError::business_error(
-            StatusCode::BAD_GATEWAY,
-            format!("Peer returned error: {}", response.status()),
-        ))
+    /// 替换 JWT 管理器（如使用 RS256 密钥对签发与验证 token）
+    pub fn with_jwt_manager(mut self, jwt_manager: JwtManager) -> Self {
+        self.jwt_manager = Arc::new(jwt_manager);
+        self
     }
-}
 
-/// POST /merge - 接收来自其他节点的同步请求
-async fn merge_handler(mut req: Request) -> Result<Response> {
-    let state = req.extensions().get::<AppState>().unwrap().clone();
+    /// 添加仅用于验证的 JWT 备用密钥（需在启动时、状态被共享前调用）
+    pub fn with_jwt_fallback_secrets(mut self, secrets: &[String]) -> Self {
+        if let Some(jwt_manager) = Arc::get_mut(&mut self.jwt_manager) {
+            jwt_manager.add_fallback_secrets(secrets);
+        }
+        self
+    }
 
-    // 解析请求体
-    let sync_request: SyncRequest = req.json_parse().await?;
+    /// 设置未携带 token 的请求所使用的默认角色（仅在启用权限控制时生效）
+    pub fn with_default_role(mut self, default_role: Option<Role>) -> Self {
+        self.default_role = default_role;
+        self
+    }
 
-    // 合并状态
-    let mut sync_state = state.sync_state.write().await;
-    sync_state.merge(&sync_request.state);
+    /// 设置请求体大小上限
+    pub fn with_max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.max_body_bytes = max_body_bytes;
+        self
+    }
 
-    // 保存状态
-    state
-        .storage
-        .save_state(&state.node_id, &sync_state)
-        .map_err(|e| {
-            SilentError::business_error(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to save state: {}", e),
-            )
-        })?;
+    /// 启用按调用方的令牌桶限流：每秒补充 `rps` 个令牌，最多积累 `burst` 个，`None` 或非正数时不限流
+    pub fn with_rate_limit(mut self, rps: Option<f64>, burst: u32) -> Self {
+        self.rate_limiter = rps
+            .filter(|rps| *rps > 0.0)
+            .map(|rps| Arc::new(RateLimiter::new(rps, burst)));
+        self
+    }
 
-    let state_hash = sync_state.state_hash();
-    drop(sync_state);
+    /// 设置允许跨域访问的来源（`*` 表示任意来源），为空时不添加跨域响应头
+    pub fn with_cors_origins(mut self, origins: Vec<String>) -> Self {
+        self.cors = (!origins.is_empty()).then(|| Arc::new(CorsConfig::new(origins)));
+        self
+    }
 
-    tracing::info!("Merged state from node: {}", sync_request.from_node);
+    /// 设置幂等键的保留时间（秒）与最多保留的数量
+    pub fn with_idempotency(mut self, ttl_secs: u64, capacity: usize) -> Self {
+        self.idempotency = Arc::new(IdempotencyCache::new(
+            Duration::from_secs(ttl_secs),
+            capacity,
+        ));
+        self
+    }
 
-    let response = SyncResponse {
-        success: true,
-        state_hash,
-        message: format!("Merged state from {}", sync_request.from_node),
-    };
+    /// 设置默认文档的自动快照策略
+    pub fn with_snapshot_policy(mut self, policy: Option<SnapshotPolicy>) -> Self {
+        self.snapshot_policy = policy.map(Arc::new);
+        self
+    }
 
-    Ok(Response::json(&response))
-}
+    /// 记录文档新应用的操作数，默认文档累计达到自动快照阈值时保存快照（失败只记录日志）
+    pub fn record_applied_ops(&self, doc_id: &str, sync_state: &SyncState, count: usize) {
+        let Some(policy) = &self.snapshot_policy else {
+            return;
+        };
+        if doc_id != DEFAULT_DOCUMENT || !policy.record_ops(count as u64) {
+            return;
+        }
+        match policy.snapshot(self.storage.as_ref(), &self.node_id, sync_state) {
+            Ok(meta) => tracing::info!("Saved automatic snapshot version {}", meta.version),
+            Err(e) => tracing::warn!("Failed to save automatic snapshot: {}", e),
+        }
+    }
 
-/// GET /state - 获取当前状态
-async fn get_state_handler(req: Request) -> Result<Response> {
-    let state = req.extensions().get::<AppState>().unwrap().clone();
+    /// 启用写入合并窗口：/sync 仅受理变更，由后台任务批量应用
+    pub fn with_apply_batcher(mut self) -> Self {
+        self.apply_batcher = Some(Arc::new(ApplyBatcher::new()));
+        self
+    }
 
-    let sync_state = state.sync_state.read().await;
-    let state_json = serde_json::to_string_pretty(&*sync_state).map_err(|e| {
-        SilentError::business_error(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to serialize state: {}", e),
-        )
-    })?;
+    /// 立即应用并持久化所有排队的批量变更
+    pub async fn flush_apply_batch(&self) -> anyhow::Result<usize> {
+        let Some(batcher) = &self.apply_batcher else {
+            return Ok(0);
+        };
+        batcher.flush(self).await
+    }
 
-    Ok(Response::text(&state_json))
-}
+    /// 在写锁内执行临界区操作，隔离其中的 panic
+    ///
+    /// 发生 panic 时内存状态可能只修改了一半：标记节点待恢复并立即尝试恢复，
+    /// 返回 503 提示调用方重试；恢复失败时节点保持待恢复状态，需通过 `/admin/recover` 处理。
+    pub fn guarded<T>(
+        &self,
+        sync_state: &mut SyncState,
+        f: impl FnOnce(&mut SyncState) -> T,
+    ) -> Result<T> {
+        self.guarded_document(DEFAULT_DOCUMENT, sync_state, f)
+    }
 
-/// GET /state-hash - 获取状态哈希
-async fn get_state_hash_handler(req: Request) -> Result<Response> {
-    let state = req.extensions().get::<AppState>().unwrap().clone();
+    /// 与 `guarded` 相同，发生 panic 时从 `doc_id` 文档的持久化状态恢复
+    pub fn guarded_document<T>(
+        &self,
+        doc_id: &str,
+        sync_state: &mut SyncState,
+        f: impl FnOnce(&mut SyncState) -> T,
+    ) -> Result<T> {
+        match catch_unwind(AssertUnwindSafe(|| f(&mut *sync_state))) {
+            Ok(result) => Ok(result),
+            Err(_) => {
+                tracing::error!(
+                    "Panic while holding the write lock of document {}, recovering state",
+                    doc_id
+                );
+                self.needs_recovery.store(true, Ordering::SeqCst);
+                let message = match self.recover_document(doc_id, sync_state) {
+                    Ok(report) => format!(
+                        "Request aborted by an internal panic; state recovered from {}, retry the request",
+                        report.source
+                    ),
+                    Err(e) => format!("Node state needs recovery: {}", e),
+                };
+                Err(SilentError::business_error(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    message,
+                ))
+            }
+        }
+    }
 
-    let sync_state = state.sync_state.read().await;
-    let state_hash = sync_state.state_hash();
+    /// 恢复可疑的内存状态：优先重新加载最近持久化的状态，没有时由操作日志重建
+    pub fn recover(&self, sync_state: &mut SyncState) -> anyhow::Result<RecoveryReport> {
+        self.recover_document(DEFAULT_DOCUMENT, sync_state)
+    }
 
-    #[derive(Serialize)]
-    struct StateHashResponse {
-        hash: String,
+    /// 恢复 `doc_id` 文档的内存状态
+    pub fn recover_document(
+        &self,
+        doc_id: &str,
+        sync_state: &mut SyncState,
+    ) -> anyhow::Result<RecoveryReport> {
+        let report = match self.storage.load_document_state(&self.node_id, doc_id)? {
+            Some(persisted) => {
+                sync_state.replace_with(persisted);
+                // 已应用但尚未保存的批量变更随内存状态一起丢失
+                if doc_id == DEFAULT_DOCUMENT
+                    && let Some(batcher) = &self.apply_batcher
+                {
+                    let lost = batcher.discard_unsaved();
+                    if lost > 0 {
+                        tracing::warn!("Discarded {} unsaved batched change request(s)", lost);
+                    }
+                }
+                RecoveryReport {
+                    source: "persisted state",
+                    ops_replayed: 0,
+                    state_hash: sync_state.state_hash(),
+                }
+            }
+            None => {
+                let ops_replayed = sync_state.rebuild_from_oplog();
+                RecoveryReport {
+                    source: "oplog rebuild",
+                    ops_replayed,
+                    state_hash: sync_state.state_hash(),
+                }
+            }
+        };
+        self.needs_recovery.store(false, Ordering::SeqCst);
+        tracing::warn!(
+            "Recovered document {} from {} (state hash {})",
+            doc_id,
+            report.source,
+            report.state_hash
+        );
+        Ok(report)
     }
 
-    Ok(Response::json(&StateHashResponse { hash: state_hash }))
-}
+    /// 向订阅方发布状态变更（没有订阅方时不做任何事）
+    pub fn publish_change(
+        &self,
+        doc_id: &str,
+        source: &'static str,
+        keys: Vec<String>,
+        state_hash: &str,
+        last_op_id: Option<String>,
+    ) {
+        if self.change_events.receiver_count() > 0 {
+            let _ = self.change_events.send(ChangeEvent {
+                doc_id: doc_id.to_string(),
+                source,
+                keys,
+                state_hash: state_hash.to_string(),
+                last_op_id,
+            });
+        }
+    }
 
-/// GET /oplog - 导出操作日志
-async fn get_oplog_handler(req: Request) -> Result<Response> {
-    let state = req.extensions().get::<AppState>().unwrap().clone();
+    /// 获取已存在的文档：优先使用已加载的文档，其次从存储加载，未知文档返回 `None`
+    pub async fn document(&self, doc_id: &str) -> anyhow::Result<Option<Arc<RwLock<SyncState>>>> {
+        if let Some(document) = self.documents.read().await.get(doc_id) {
+            return Ok(Some(document.clone()));
+        }
+        match self.storage.load_document_state(&self.node_id, doc_id)? {
+            Some(loaded) => Ok(Some(self.insert_document(doc_id, loaded).await)),
+            None => Ok(None),
+        }
+    }
 
-    let sync_state = state.sync_state.read().await;
-    let oplog_json = sync_state.export_oplog().map_err(|e| {
-        SilentError::business_error(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to export oplog: {}", e),
-        )
-    })?;
+    /// 获取文档，不存在时创建空文档（用于写入，创建后的持久化由写入方完成）
+    pub async fn document_or_create(&self, doc_id: &str) -> anyhow::Result<Arc<RwLock<SyncState>>> {
+        if let Some(document) = self.document(doc_id).await? {
+            return Ok(document);
+        }
+        tracing::info!("Created document: {}", doc_id);
+        Ok(self
+            .insert_document(doc_id, SyncState::new(self.node_id.clone()))
+            .await)
+    }
 
-    Ok(Response::text(&oplog_json))
-}
+    /// 以默认文档的本地配置登记文档；并发登记同一文档时保留先登记的实例
+    async fn insert_document(
+        &self,
+        doc_id: &str,
+        mut document: SyncState,
+    ) -> Arc<RwLock<SyncState>> {
+        document.inherit_local_config(&*self.sync_state.read().await);
+        self.documents
+            .write()
+            .await
+            .entry(doc_id.to_string())
+            .or_insert_with(|| Arc::new(RwLock::new(document)))
+            .clone()
+    }
 
-/// GET /history - 获取操作历史（带详细信息）
-async fn get_history_handler(req: Request) -> Result<Response> {
-    let state = req.extensions().get::<AppState>().unwrap().clone();
-    let sync_state = state.sync_state.read().await;
+    /// 以 Prometheus 文本格式输出指标（含所有文档的当前规模）
+    pub async fn render_metrics(&self) -> anyhow::Result<String> {
+        let mut sizes = Vec::new();
+        for (doc_id, document) in self.documents().await? {
+            sizes.push(DocumentSize::of(&doc_id, &*document.read().await));
+        }
+        Ok(self.metrics.render(&sizes))
+    }
 
-    #[derive(Serialize)]
-    struct HistoryEntry {
-        id: String,
-        timestamp: i64,
-        operation_type: String,
-        key: String,
-        details: String,
-        node_id: String,
-        causal_context: std::collections::HashMap<String, i64>,
+    /// 节点上的所有文档（含已持久化但尚未加载的命名文档），按文档 ID 排序
+    pub async fn documents(&self) -> anyhow::Result<Vec<(String, Arc<RwLock<SyncState>>)>> {
+        for doc_id in self.storage.list_documents(&self.node_id)? {
+            self.document(&doc_id).await?;
+        }
+        let mut documents: Vec<_> = self
+            .documents
+            .read()
+            .await
+            .iter()
+            .map(|(doc_id, document)| (doc_id.clone(), document.clone()))
+            .collect();
+        documents.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(documents)
     }
+}
 
-    let oplog = &sync_state.op_log;
-    let mut history: Vec<HistoryEntry> = Vec::new();
+/// 将签名私钥以 base64 写入文件（Unix 下仅所有者可读写）
+fn write_key_file(path: &std::path::Path, keypair: &KeyPair) -> anyhow::Result<()> {
+    use base64::Engine;
+    let encoded = base64::engine::general_purpose::STANDARD.encode(keypair.secret_key_bytes());
 
-    for entry in &oplog.ops {
-        let (op_type, key, details) = match &entry.op {
-            crate::sync::Operation::GCounterIncrement {
-                key,
-                node_id,
-                delta,
-            } => (
-                "GCounter.Increment",
-                key.clone(),
-                format!("节点 {} 增加 {}", node_id, delta),
-            ),
-            crate::sync::Operation::PNCounterIncrement {
-                key,
-                node_id,
-                delta,
-            } => (
-                "PNCounter.Increment",
-                key.clone(),
-                format!("节点 {} 增加 {}", node_id, delta),
-            ),
-            crate::sync::Operation::PNCounterDecrement {
-                key,
-                node_id,
-                delta,
-            } => (
-                "PNCounter.Decrement",
-                key.clone(),
-                format!("节点 {} 减少 {}", node_id, delta),
-            ),
-            crate::sync::Operation::LwwRegisterSet {
-                key,
-                value,
-                timestamp,
-                node_id,
-            } => (
-                "LWWRegister.Set",
-                key.clone(),
-                format!("节点 {} 设置为 '{}' (ts: {})", node_id, value, timestamp),
-            ),
-            crate::sync::Operation::OrSetAdd {
-                key,
-                value,
-                unique_id,
-            } => (
-                "ORSet.Add",
-                key.clone(),
-                format!("添加元素 '{}' (id: {})", value, &unique_id[..8]),
-            ),
-            crate::sync::Operation::OrSetRemove { key, value } => {
-                ("ORSet.Remove", key.clone(), format!("移除元素 '{}'", value))
-            }
-        };
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(path)?, encoded.as_bytes())?;
+    Ok(())
+}
 
-        history.push(HistoryEntry {
-            id: entry.id.clone(),
-            timestamp: entry.ts,
-            operation_type: op_type.to_string(),
-            key,
-            details,
-            node_id: oplog.node_id.clone(),
-            causal_context: entry
-                .causal
-                .clocks
-                .iter()
-                .map(|(k, v)| (k.clone(), *v as i64))
-                .collect(),
-        });
+/// 校验文档 ID：非空、不超过长度上限，且仅包含字母、数字、`-`、`_` 与 `.`
+pub fn validate_document_id(doc_id: &str) -> std::result::Result<(), String> {
+    if doc_id.is_empty() {
+        return Err("Document ID must not be empty".to_string());
+    }
+    if doc_id.len() > MAX_DOCUMENT_ID_LEN {
+        return Err(format!(
+            "Document ID must be at most {} bytes",
+            MAX_DOCUMENT_ID_LEN
+        ));
     }
+    if !doc_id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(format!(
+            "Invalid document ID '{}': only letters, digits, '-', '_' and '.' are allowed",
+            doc_id
+        ));
+    }
+    Ok(())
+}
 
-    Ok(Response::json(&history))
+/// 请求作用的文档 ID：`/doc/<doc_id>/...` 路由取路径参数，其余路由为默认文档
+fn request_document_id(req: &Request) -> Result<String> {
+    let doc_id: String = match req.get_path_params("doc_id") {
+        Ok(doc_id) => doc_id,
+        Err(_) => return Ok(DEFAULT_DOCUMENT.to_string()),
+    };
+    validate_document_id(&doc_id)
+        .map_err(|e| SilentError::business_error(StatusCode::BAD_REQUEST, e))?;
+    Ok(doc_id)
 }
 
-/// GET /conflicts - 检测并返回可能的冲突
-async fn get_conflicts_handler(req: Request) -> Result<Response> {
-    let state = req.extensions().get::<AppState>().unwrap().clone();
-    let sync_state = state.sync_state.read().await;
+fn document_load_error(e: anyhow::Error) -> SilentError {
+    SilentError::business_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to load document: {}", e),
+    )
+}
 
-    #[derive(Serialize)]
-    struct Conflict {
-        key: String,
-        conflict_type: String,
-        operations: Vec<ConflictOperation>,
-        resolution: String,
-    }
+/// 获取读请求作用的文档，未知文档返回 404
+async fn read_document(req: &Request, state: &AppState) -> Result<Arc<RwLock<SyncState>>> {
+    let doc_id = request_document_id(req)?;
+    state
+        .document(&doc_id)
+        .await
+        .map_err(document_load_error)?
+        .ok_or_else(|| {
+            SilentError::business_error(
+                StatusCode::NOT_FOUND,
+                format!("Document not found: {}", doc_id),
+            )
+        })
+}
 
-    #[derive(Serialize)]
-    struct ConflictOperation {
-        id: String,
-        timestamp: i64,
-        node_id: String,
-        details: String,
+/// 获取写请求作用的文档 ID 与文档，不存在时创建
+async fn write_document(
+    req: &Request,
+    state: &AppState,
+) -> Result<(String, Arc<RwLock<SyncState>>)> {
+    let doc_id = request_document_id(req)?;
+    let document = state
+        .document_or_create(&doc_id)
+        .await
+        .map_err(document_load_error)?;
+    Ok((doc_id, document))
+}
+
+/// 对等节点上同一文档的接口路径（默认文档不带 `/doc/<doc_id>` 前缀）
+fn document_path(doc_id: &str, path: &str) -> String {
+    if doc_id == DEFAULT_DOCUMENT {
+        format!("/{}", path)
+    } else {
+        format!("/doc/{}/{}", doc_id, path)
     }
+}
 
-    let mut conflicts: Vec<Conflict> = Vec::new();
-    let oplog = &sync_state.op_log;
+/// 状态恢复结果
+#[derive(Debug, Clone, Serialize)]
+pub struct RecoveryReport {
+    pub source: &'static str,
+    pub ops_replayed: usize,
+    pub state_hash: String,
+}
 
-    // 检测 LWWRegister 的并发写入
-    let mut lww_writes: std::collections::HashMap<String, Vec<&crate::sync::OpLogEntry>> =
-        std::collections::HashMap::new();
+/// 节点待恢复时仍可访问的路径
+const RECOVERY_EXEMPT_PATHS: &[&str] = &["/health", "/admin/recover"];
 
-    for entry in &oplog.ops {
-        if let crate::sync::Operation::LwwRegisterSet { key, .. } = &entry.op {
-            lww_writes.entry(key.clone()).or_default().push(entry);
+// 实现中间件处理器，用于在所有请求中注入 AppState
+#[async_trait::async_trait]
+impl MiddleWareHandler for AppState {
+    async fn handle(&self, mut req: Request, next: &Next) -> Result<Response> {
+        if self.needs_recovery.load(Ordering::SeqCst)
+            && !RECOVERY_EXEMPT_PATHS.contains(&req.uri().path())
+        {
+            return Err(SilentError::business_error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Node state needs recovery after an internal panic; POST /admin/recover",
+            ));
         }
+        req.extensions_mut().insert(self.clone());
+        next.call(req).await
     }
+}
 
-    for (key, entries) in lww_writes {
-        if entries.len() > 1 {
-            // 检查是否有并发写入（向量时钟无法比较）
-            let mut concurrent_writes = Vec::new();
-            for i in 0..entries.len() {
-                for j in (i + 1)..entries.len() {
-                    let clock1 = &entries[i].causal;
-                    let clock2 = &entries[j].causal;
-
-                    if !clock1.happens_before(clock2) && !clock2.happens_before(clock1) {
-                        if concurrent_writes.is_empty()
-                            && let crate::sync::Operation::LwwRegisterSet {
-                                value,
-                                timestamp,
-                                node_id,
-                                ..
-                            } = &entries[i].op
-                        {
-                            concurrent_writes.push(ConflictOperation {
-                                id: entries[i].id.clone(),
-                                timestamp: *timestamp,
-                                node_id: node_id.clone(),
-                                details: format!("设置为 '{}'", value),
-                            });
-                        }
-
-                        if let crate::sync::Operation::LwwRegisterSet {
-                            value,
-                            timestamp,
-                            node_id,
-                            ..
-                        } = &entries[j].op
-                        {
-                            concurrent_writes.push(ConflictOperation {
-                                id: entries[j].id.clone(),
-                                timestamp: *timestamp,
-                                node_id: node_id.clone(),
-                                details: format!("设置为 '{}'", value),
-                            });
-                        }
-                    }
-                }
-            }
-
-            if !concurrent_writes.is_empty() {
-                // 找出最终胜出的值
-                let winner_node = concurrent_writes
-                    .iter()
-                    .max_by(|a, b| {
-                        a.timestamp
-                            .cmp(&b.timestamp)
-                            .then_with(|| a.node_id.cmp(&b.node_id))
-                    })
-                    .map(|w| w.node_id.clone())
-                    .unwrap();
+/// 请求体读取错误
+#[derive(Debug)]
+pub enum BodyError {
+    /// 超过大小上限
+    TooLarge { limit: usize },
+    /// 读取失败
+    Read(String),
+}
 
-                conflicts.push(Conflict {
-                    key: key.clone(),
-                    conflict_type: "LWWRegister 并发写入".to_string(),
-                    operations: concurrent_writes,
-                    resolution: format!(
-                        "根据 LWW 规则，时间戳较大的操作胜出 (节点: {})",
-                        winner_node
-                    ),
-                });
-            }
-        }
+/// 最多读取 `limit` 字节的请求体，超限时立即停止读取
+pub async fn read_body_limited<B>(body: B, limit: usize) -> std::result::Result<Bytes, BodyError>
+where
+    B: http_body::Body<Data = Bytes>,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    match Limited::new(body, limit).collect().await {
+        Ok(collected) => Ok(collected.to_bytes()),
+        Err(e) if e.is::<LengthLimitError>() => Err(BodyError::TooLarge { limit }),
+        Err(e) => Err(BodyError::Read(e.to_string())),
     }
+}
 
-    Ok(Response::json(&conflicts))
+/// 构造 413 错误（结构化 JSON 消息）
+fn payload_too_large(limit: usize) -> SilentError {
+    let body = serde_json::json!({
+        "error_code": "payload_too_large",
+        "message": format!("Request body exceeds limit of {} bytes", limit),
+        "limit": limit,
+    });
+    SilentError::business_error(StatusCode::PAYLOAD_TOO_LARGE, body.to_string())
 }
 
-/// GET /health - 健康检查
-async fn health_handler(_req: Request) -> Result<Response> {
-    #[derive(Serialize)]
-    struct HealthResponse {
-        status: String,
-        timestamp: i64,
-    }
+/// 构造 400 校验错误（结构化 JSON 消息，列出全部字段错误）
+fn validation_failed(errors: Vec<FieldError>) -> SilentError {
+    let message = errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("; ");
+    let body = serde_json::json!({
+        "error_code": "validation_failed",
+        "message": message,
+        "errors": errors,
+    });
+    SilentError::business_error(StatusCode::BAD_REQUEST, body.to_string())
+}
 
-    let response = HealthResponse {
-        status: "ok".to_string(),
-        timestamp: chrono::Local::now()
-            .naive_local()
-            .and_utc()
-            .timestamp_millis(),
+/// 构造应用变更失败的错误（结构化 JSON 消息）：引用的键不存在返回 404，其余返回 400
+fn change_failed(error: ChangeError) -> SilentError {
+    let status = match error {
+        ChangeError::InvalidKey(_) => StatusCode::NOT_FOUND,
+        _ => StatusCode::BAD_REQUEST,
     };
-
-    Ok(Response::json(&response))
+    let body = serde_json::json!({
+        "error_code": error.code(),
+        "message": error.to_string(),
+    });
+    SilentError::business_error(status, body.to_string())
 }
 
-/// POST /auth/token - 生成 JWT token
-async fn generate_token_handler(mut req: Request) -> Result<Response> {
-    let state = req.extensions().get::<AppState>().unwrap().clone();
+/// 是否请求了格式化输出（`?pretty=true` 或 `Accept: application/json; pretty=true`）
+fn pretty_requested(query: Option<&str>, accept: Option<&str>) -> bool {
+    let flag = |param: &str| matches!(param.trim(), "pretty" | "pretty=true" | "pretty=1");
+    query.is_some_and(|q| q.split('&').any(flag)) || accept.is_some_and(|a| a.split(';').any(flag))
+}
 
-    #[derive(Deserialize)]
-    struct TokenRequest {
-        node_id: String,
-        role: Role,
-        expires_in_secs: Option<u64>,
+/// 将值序列化为 JSON，默认紧凑输出
+fn render_json<T: Serialize + ?Sized>(value: &T, pretty: bool) -> serde_json::Result<String> {
+    if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
     }
+}
 
-    #[derive(Serialize)]
-    struct TokenResponse {
-        token: String,
-        expires_in: u64,
+/// 构造 JSON 响应，默认紧凑输出，按请求参数切换为格式化输出
+fn json_response<T: Serialize + ?Sized>(req: &Request, value: &T) -> Result<Response> {
+    let accept = req.headers().get("Accept").and_then(|v| v.to_str().ok());
+    if !pretty_requested(req.uri().query(), accept) {
+        return Ok(Response::json(value));
     }
 
-    let token_req: TokenRequest = req.json_parse().await?;
-    let expires_in = token_req.expires_in_secs.unwrap_or(3600); // 默认 1 小时
-
-    let token = state
-        .jwt_manager
-        .generate_token(token_req.node_id, token_req.role, expires_in)
+    let body = render_json(value, true)
+        .and_then(serde_json::value::RawValue::from_string)
         .map_err(|e| {
             SilentError::business_error(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to generate token: {}", e),
+                format!("Failed to serialize response: {}", e),
             )
         })?;
-
-    Ok(Response::json(&TokenResponse { token, expires_in }))
+    Ok(Response::json(&body))
 }
 
-/// GET /auth/public-key - 获取节点的公钥
-async fn get_public_key_handler(req: Request) -> Result<Response> {
-    let state = req.extensions().get::<AppState>().unwrap().clone();
+/// 在请求体大小上限内读取请求体，`Content-Encoding: zstd` 时解压（解压后同样受上限约束）
+async fn read_body_bytes(req: &mut Request, state: &AppState) -> Result<Bytes> {
+    let limit = state.max_body_bytes;
+    let compressed = match req
+        .headers()
+        .get("Content-Encoding")
+        .and_then(|v| v.to_str().ok())
+    {
+        None | Some("identity") => false,
+        Some(SYNC_CONTENT_ENCODING) => true,
+        Some(other) => {
+            return Err(SilentError::business_error(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!(
+                    "Unsupported Content-Encoding: {} (expected {})",
+                    other, SYNC_CONTENT_ENCODING
+                ),
+            ));
+        }
+    };
 
-    #[derive(Serialize)]
-    struct PublicKeyResponse {
-        node_id: String,
-        public_key: String,
+    // Content-Length 已超限时无需读取请求体
+    let content_length = req
+        .headers()
+        .get("Content-Length")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if content_length.is_some_and(|len| len > limit) {
+        return Err(payload_too_large(limit));
+    }
+
+    let bytes = read_body_limited(req.take_body(), limit)
+        .await
+        .map_err(|e| match e {
+            BodyError::TooLarge { limit } => payload_too_large(limit),
+            BodyError::Read(msg) => SilentError::business_error(
+                StatusCode::BAD_REQUEST,
+                format!("Failed to read request body: {}", msg),
+            ),
+        })?;
+    if !compressed {
+        return Ok(bytes);
     }
+    compression::decompress_limited(&bytes, limit)
+        .map(Bytes::from)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid compressed request body: {:#}", e),
+            )
+        })
+}
 
-    Ok(Response::json(&PublicKeyResponse {
-        node_id: state.node_id.clone(),
-        public_key: state.signature_manager.public_key_base64(),
-    }))
+/// 在请求体大小与嵌套深度上限内解析 JSON
+async fn parse_json_limited<T: DeserializeOwned>(req: &mut Request, state: &AppState) -> Result<T> {
+    let bytes = read_body_bytes(req, state).await?;
+
+    check_crdt_depth(&bytes, state.max_crdt_depth)
+        .map_err(|e| SilentError::business_error(StatusCode::BAD_REQUEST, e))?;
+
+    serde_json::from_slice(&bytes).map_err(|e| {
+        SilentError::business_error(StatusCode::BAD_REQUEST, format!("Invalid JSON body: {}", e))
+    })
 }
 
-/// 权限验证中间件
-#[derive(Clone)]
-pub struct AuthMiddleware {
-    required_role: Role,
+/// POST /sync 查询参数
+#[derive(Debug, Default, Deserialize)]
+struct SyncQuery {
+    /// 仅校验变更而不应用
+    #[serde(default)]
+    validate_only: bool,
+    /// 持久化级别（async / flush / memory），优先于 `X-Durability` 请求头
+    durability: Option<String>,
 }
 
-impl AuthMiddleware {
-    pub fn new(required_role: Role) -> Self {
-        Self { required_role }
-    }
+/// 持久化级别请求头
+const DURABILITY_HEADER: &str = "X-Durability";
+
+/// 幂等键请求头：重试携带相同键的 `/sync` 请求时返回首次的响应而不重复应用
+const IDEMPOTENCY_HEADER: &str = "Idempotency-Key";
+
+/// 幂等键对应的首次响应：直接应用的结果，或批量写入的受理票据
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum IdempotentResponse {
+    Applied(SyncResponse),
+    Accepted(AcceptedResponse),
 }
 
-#[async_trait::async_trait]
-impl MiddleWareHandler for AuthMiddleware {
-    async fn handle(&self, req: Request, next: &Next) -> Result<Response> {
-        let state = req.extensions().get::<AppState>().unwrap().clone();
+/// 幂等键缓存中的一条记录
+#[derive(Debug)]
+struct IdempotencyEntry {
+    response: IdempotentResponse,
+    inserted: Instant,
+    last_used: u64,
+}
 
-        // 如果未启用权限控制，直接放行
-        if !state.auth_enabled {
-            return next.call(req).await;
+#[derive(Debug, Default)]
+struct IdempotencyEntries {
+    entries: HashMap<String, IdempotencyEntry>,
+    recency: BTreeMap<u64, String>, // 最近使用序号 -> 键
+    tick: u64,
+}
+
+/// 最近处理过的幂等键及其响应：超过保留时间视为未见过，超出容量时淘汰最久未使用的键
+#[derive(Debug)]
+pub struct IdempotencyCache {
+    ttl: Duration,
+    capacity: usize,
+    inner: Mutex<IdempotencyEntries>,
+}
+
+impl IdempotencyCache {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            inner: Mutex::new(IdempotencyEntries::default()),
         }
+    }
 
-        // 获取 Authorization header
-        let auth_header = req
-            .headers()
-            .get("Authorization")
-            .and_then(|v| v.to_str().ok())
-            .ok_or_else(|| {
-                SilentError::business_error(
-                    StatusCode::UNAUTHORIZED,
-                    "Missing authorization header",
-                )
-            })?;
+    /// 查找未过期的键对应的响应
+    pub fn get(&self, key: &str, now: Instant) -> Option<IdempotentResponse> {
+        self.inner.lock().unwrap().lookup(key, now, self.ttl)
+    }
 
-        // 提取 token
-        let token = JwtManager::extract_token(auth_header).map_err(|e| {
-            SilentError::business_error(StatusCode::UNAUTHORIZED, format!("Invalid token: {}", e))
-        })?;
+    /// 记录键对应的响应
+    pub fn insert(&self, key: String, response: IdempotentResponse, now: Instant) {
+        self.inner
+            .lock()
+            .unwrap()
+            .store(key, response, now, self.capacity);
+    }
 
-        // 验证 token
-        let claims = state.jwt_manager.verify_token(token).map_err(|e| {
-            SilentError::business_error(StatusCode::UNAUTHORIZED, format!("Invalid token: {}", e))
-        })?;
+    /// 键未见过时生成并记录响应，返回响应以及是否为本次生成
+    ///
+    /// 生成响应期间持有缓存锁，携带相同键的并发请求只会生成一次。
+    pub fn get_or_insert_with(
+        &self,
+        key: &str,
+        now: Instant,
+        f: impl FnOnce() -> IdempotentResponse,
+    ) -> (IdempotentResponse, bool) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(response) = inner.lookup(key, now, self.ttl) {
+            return (response, false);
+        }
+        let response = f();
+        inner.store(key.to_string(), response.clone(), now, self.capacity);
+        (response, true)
+    }
+}
 
-        // 检查权限
-        if !claims.role.has_permission(&self.required_role) {
-            return Err(SilentError::business_error(
-                StatusCode::FORBIDDEN,
-                "Insufficient permissions",
-            ));
+impl IdempotencyEntries {
+    fn lookup(&mut self, key: &str, now: Instant, ttl: Duration) -> Option<IdempotentResponse> {
+        let entry = self.entries.get_mut(key)?;
+        if now.saturating_duration_since(entry.inserted) > ttl {
+            self.recency.remove(&entry.last_used);
+            self.entries.remove(key);
+            return None;
         }
+        self.tick += 1;
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(self.tick, key.to_string());
+        entry.last_used = self.tick;
+        Some(entry.response.clone())
+    }
 
-        next.call(req).await
+    fn store(&mut self, key: String, response: IdempotentResponse, now: Instant, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        self.recency.insert(self.tick, key.clone());
+        let previous = self.entries.insert(
+            key,
+            IdempotencyEntry {
+                response,
+                inserted: now,
+                last_used: self.tick,
+            },
+        );
+        if let Some(previous) = previous {
+            self.recency.remove(&previous.last_used);
+        }
+        while self.entries.len() > capacity
+            && let Some((_, oldest)) = self.recency.pop_first()
+        {
+            self.entries.remove(&oldest);
+        }
     }
 }
 
-/// 构建 API 路由
-pub fn build_routes(app_state: AppState) -> Route {
-    Route::new_root()
-        .hook(app_state)
-        // 认证相关路由（无需权限）
-        .append(Route::new("auth/token").post(generate_token_handler))
-        .append(Route::new("auth/public-key").get(get_public_key_handler))
-        // 需要 Writer 权限的路由
-        .append(
-            Route::new("sync")
-                .hook(AuthMiddleware::new(Role::Writer))
-                .post(sync_handler),
-        )
-        .append(
-            Route::new("sync-peer")
-                .hook(AuthMiddleware::new(Role::Writer))
-                .post(sync_peer_handler),
-        )
-        .append(
-            Route::new("merge")
-                .hook(AuthMiddleware::new(Role::Writer))
-                .post(merge_handler),
-        )
-        // 需要 Reader 权限的路由
-        .append(
-            Route::new("state")
-                .hook(AuthMiddleware::new(Role::Reader))
-                .get(get_state_handler),
-        )
-        .append(
-            Route::new("state-hash")
-                .hook(AuthMiddleware::new(Role::Reader))
-                .get(get_state_hash_handler),
-        )
-        .append(
-            Route::new("oplog")
-                .hook(AuthMiddleware::new(Role::Reader))
-                .get(get_oplog_handler),
-        )
-        .append(
+/// 解析请求的持久化级别：查询参数优先，其次为请求头，默认 flush
+fn requested_durability(query: Option<&str>, header: Option<&str>) -> Result<Durability> {
+    query
+        .or(header)
+        .map(|level| level.parse())
+        .transpose()
+        .map(Option::unwrap_or_default)
+        .map_err(|e: String| SilentError::business_error(StatusCode::BAD_REQUEST, e))
+}
+
+/// POST /sync?validate_only=true 的响应
+#[derive(Debug, Serialize)]
+struct ValidateOnlyResponse {
+    /// 所有变更是否都有效
+    valid: bool,
+    results: Vec<ChangeValidation>,
+}
+
+/// 检查变更的键是否都在调用方 token 允许的范围内，越权时返回 403 且不应用任何变更
+fn check_key_scope(scope: Option<&KeyScope>, change_request: &ChangeRequest) -> Result<()> {
+    let Some(scope) = scope else {
+        return Ok(());
+    };
+    match change_request
+        .changes
+        .iter()
+        .find(|change| !scope.allows(&change.key))
+    {
+        Some(change) => Err(SilentError::business_error(
+            StatusCode::FORBIDDEN,
+            format!("Key not permitted by token: {}", change.key),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// 合并整份状态无法按键限制，限定键范围的 token 只能通过 /sync 写入
+fn require_unscoped(req: &Request) -> Result<()> {
+    if req
+        .extensions()
+        .get::<KeyScope>()
+        .is_some_and(KeyScope::is_restricted)
+    {
+        return Err(SilentError::business_error(
+            StatusCode::FORBIDDEN,
+            "Key-scoped tokens cannot merge full states",
+        ));
+    }
+    Ok(())
+}
+
+/// POST /sync - 接收变更请求
+async fn sync_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+    let query: SyncQuery = req.params_parse().unwrap_or_default();
+    let durability = requested_durability(
+        query.durability.as_deref(),
+        req.headers()
+            .get(DURABILITY_HEADER)
+            .and_then(|v| v.to_str().ok()),
+    )?;
+
+    // 解析请求体
+    let change_request: ChangeRequest = parse_json_limited(&mut req, &state).await?;
+    check_key_scope(req.extensions().get::<KeyScope>(), &change_request)?;
+
+    // 试运行：只返回每条变更的校验结果，不修改状态（也不创建文档）
+    if query.validate_only {
+        let doc_id = request_document_id(&req)?;
+        let results = match state.document(&doc_id).await.map_err(document_load_error)? {
+            Some(document) => document.read().await.validate_changes(&change_request),
+            None => {
+                let mut empty = SyncState::new(state.node_id.clone());
+                empty.inherit_local_config(&*state.sync_state.read().await);
+                empty.validate_changes(&change_request)
+            }
+        };
+        return json_response(
+            &req,
+            &ValidateOnlyResponse {
+                valid: results.iter().all(|r| r.valid),
+                results,
+            },
+        );
+    }
+
+    change_request.validate().map_err(validation_failed)?;
+    let (doc_id, document) = write_document(&req, &state).await?;
+
+    // 幂等键在入队或应用之前检查，重试的请求返回首次的响应（含受理票据）
+    let idempotency_key = req
+        .headers()
+        .get(IDEMPOTENCY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|key| format!("{}/{}", doc_id, key));
+
+    // 启用写入合并窗口时仅入队并立即确认（memory 级别的写入与命名文档不经过持久化队列）
+    if durability != Durability::Memory
+        && doc_id == DEFAULT_DOCUMENT
+        && let Some(batcher) = &state.apply_batcher
+    {
+        // 指标在批量应用成功后才记录
+        let enqueue = || {
+            IdempotentResponse::Accepted(AcceptedResponse {
+                success: true,
+                status: "accepted".to_string(),
+                ticket: batcher.enqueue(change_request),
+                message: "Changes accepted for batched apply".to_string(),
+            })
+        };
+        // 在缓存锁内入队，并发的重试请求只会入队一次
+        let response = match &idempotency_key {
+            Some(key) => {
+                state
+                    .idempotency
+                    .get_or_insert_with(key, Instant::now(), enqueue)
+                    .0
+            }
+            None => enqueue(),
+        };
+        return json_response(&req, &response);
+    }
+
+    // 应用变更
+    let op_count = change_request.changes.len();
+    let touched_keys: Vec<String> = change_request
+        .touched_keys()
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let ops: Vec<String> = change_request
+        .changes
+        .iter()
+        .map(|change| change.op.clone())
+        .collect();
+    // 在写锁内检查幂等键，并发的重试请求只会应用一次
+    let mut sync_state = document.write().await;
+    if let Some(key) = &idempotency_key
+        && let Some(response) = state.idempotency.get(key, Instant::now())
+    {
+        return json_response(&req, &response);
+    }
+    let applied = state.guarded_document(&doc_id, &mut sync_state, |sync_state| {
+        state.slow_ops.time(
+            "apply_changes",
+            op_count,
+            || touched_keys.clone(),
+            || {
+                if durability == Durability::Memory {
+                    sync_state.apply_changes_in_memory(change_request)
+                } else {
+                    sync_state.apply_changes(change_request)
+                }
+            },
+        )
+    })?;
+    applied.map_err(change_failed)?;
+    state.metrics.record_ops(ops.iter().map(String::as_str));
+    state.record_applied_ops(&doc_id, &sync_state, op_count);
+
+    // 按请求的持久化级别保存状态
+    state
+        .storage
+        .save_document_state(&state.node_id, &doc_id, &sync_state, durability)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save state: {}", e),
+            )
+        })?;
+
+    let state_hash = state.timed_state_hash(&sync_state);
+    let response = SyncResponse {
+        success: true,
+        state_hash,
+        message: "Changes applied successfully".to_string(),
+    };
+    if let Some(key) = idempotency_key {
+        state.idempotency.insert(
+            key,
+            IdempotentResponse::Applied(response.clone()),
+            Instant::now(),
+        );
+    }
+    let last_op_id = sync_state.op_log.ops.last().map(|entry| entry.id.clone());
+    drop(sync_state);
+
+    let mut changed_keys = touched_keys;
+    changed_keys.sort();
+    changed_keys.dedup();
+    state.publish_change(
+        &doc_id,
+        "sync",
+        changed_keys,
+        &response.state_hash,
+        last_op_id,
+    );
+
+    json_response(&req, &response)
+}
+
+/// GET /metrics - Prometheus 文本格式的指标
+async fn metrics_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+    let body = state.render_metrics().await.map_err(document_load_error)?;
+    Ok(Response::text(&body))
+}
+
+/// GET /sync/status?ticket= - 查询批量变更是否已持久化（含应用失败的原因）
+#[derive(Debug, Deserialize)]
+struct DurabilityQuery {
+    ticket: u64,
+}
+
+async fn sync_status_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+    let query: DurabilityQuery = req.params_parse()?;
+
+    // 未启用批量写入时，所有已确认的变更都已持久化
+    let outcome = match &state.apply_batcher {
+        Some(batcher) => batcher.outcome(query.ticket),
+        None => TicketOutcome {
+            status: TicketStatus::Durable,
+            error: None,
+        },
+    };
+
+    json_response(&req, &DurabilityResponse::new(query.ticket, outcome))
+}
+
+/// POST /sync-peer - 触发与其他节点的同步
+#[derive(Debug, Deserialize)]
+struct SyncPeerRequest {
+    peer: String,
+}
+
+async fn sync_peer_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    require_unscoped(&req)?;
+
+    // 解析请求体
+    let peer_req: SyncPeerRequest = parse_json_limited(&mut req, &state).await?;
+    let doc_id = request_document_id(&req)?;
+    let document = read_document(&req, &state).await?;
+
+    // 获取当前状态
+    let current_state = {
+        let sync_state = document.read().await;
+        sync_state.clone()
+    };
+
+    // 构建同步请求
+    let sync_request = SyncRequest {
+        from_node: state.node_id.clone(),
+        state: current_state,
+    };
+
+    // 发送同步请求到对等节点上的同一文档（复用共享客户端的连接池）
+    let peer_url = state.peer_url(&peer_req.peer, &document_path(&doc_id, "merge"));
+
+    let result = post_to_peer(&state, &peer_url, &sync_request).await;
+    state.metrics.record_sync_peer(result.is_ok());
+    json_response(&req, &result?)
+}
+
+/// 向对等节点的 `/merge` 发送状态并解析其响应
+pub async fn post_to_peer(
+    state: &AppState,
+    peer_url: &str,
+    sync_request: &SyncRequest,
+) -> Result<SyncResponse> {
+    let body = serde_json::to_vec(sync_request).map_err(|e| {
+        SilentError::business_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to serialize sync request: {}", e),
+        )
+    })?;
+    let mut request = state
+        .peer_client
+        .post(peer_url)
+        .header("Content-Type", "application/json");
+    let body = if state.compress_peer_sync {
+        request = request.header("Content-Encoding", SYNC_CONTENT_ENCODING);
+        compression::compress(&body).map_err(|e| {
+            SilentError::business_error(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e))
+        })?
+    } else {
+        body
+    };
+
+    let response = request.body(body).send().await.map_err(|e| {
+        SilentError::business_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to sync with peer: {}", e),
+        )
+    })?;
+
+    if response.status().is_success() {
+        response.json().await.map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to parse peer response: {}", e),
+            )
+        })
+    } else {
+        Err(SilentError::business_error(
+            StatusCode::BAD_GATEWAY,
+            format!("Peer returned error: {}", response.status()),
+        ))
+    }
+}
+
+/// POST /merge - 接收来自其他节点的同步请求
+async fn merge_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    // 解析请求体
+    require_unscoped(&req)?;
+
+    let sync_request: SyncRequest = parse_json_limited(&mut req, &state).await?;
+    sync_request.validate().map_err(validation_failed)?;
+    verify_incoming_signatures(
+        &sync_request.state,
+        &state.signature_manager,
+        state.require_signatures,
+    )
+    .map_err(|e| SilentError::business_error(StatusCode::BAD_REQUEST, e))?;
+    let (doc_id, document) = write_document(&req, &state).await?;
+
+    // 合并状态
+    // 仅在有订阅方时比较合并前后的值
+    let track_changes = state.change_events.receiver_count() > 0;
+    let mut sync_state = document.write().await;
+    let ops_before = sync_state.op_log.ops.len();
+    let changed_keys = state.guarded_document(&doc_id, &mut sync_state, |sync_state| {
+        let incoming = &sync_request.state;
+        state.slow_ops.time(
+            "merge",
+            incoming.op_log.ops.len(),
+            || incoming.crdt_map.entries.keys().cloned().collect(),
+            || {
+                if track_changes {
+                    sync_state.merge_tracking_changes(incoming)
+                } else {
+                    sync_state.merge(incoming);
+                    Vec::new()
+                }
+            },
+        )
+    })?;
+
+    // 保存状态
+    state
+        .storage
+        .save_document_state(&state.node_id, &doc_id, &sync_state, Durability::Flush)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save state: {}", e),
+            )
+        })?;
+
+    let state_hash = state.timed_state_hash(&sync_state);
+    let last_op_id = sync_state.op_log.ops.last().map(|entry| entry.id.clone());
+    let merged_ops = sync_state.op_log.ops.len().saturating_sub(ops_before);
+    state.record_applied_ops(&doc_id, &sync_state, merged_ops);
+    drop(sync_state);
+    state.metrics.record_merge();
+
+    tracing::info!(
+        "Merged state from node: {} into document: {}",
+        sync_request.from_node,
+        doc_id
+    );
+    if !changed_keys.is_empty() {
+        state.publish_change(&doc_id, "merge", changed_keys, &state_hash, last_op_id);
+    }
+
+    let response = SyncResponse {
+        success: true,
+        state_hash,
+        message: format!("Merged state from {}", sync_request.from_node),
+    };
+
+    json_response(&req, &response)
+}
+
+/// 验证传入状态中操作日志的签名，存在无效签名、公钥不受信任（或要求签名时存在未签名条目）时拒绝整个合并
+///
+/// 签名须由各条目 `origin` 节点受信任的公钥签发：本节点使用自己的当前及宽限期内的旧公钥，
+/// 其他节点使用从其 `/auth/public-key` 获取的公钥。传入状态中的 CRDT 值已包含这些操作的效果，
+/// 无法只剔除个别条目，因此整体拒绝。
+pub fn verify_incoming_signatures(
+    incoming: &SyncState,
+    trust: &SignatureManager,
+    require_signatures: bool,
+) -> std::result::Result<(), String> {
+    let invalid = incoming.op_log.verify_signatures(trust, require_signatures);
+    if invalid.is_empty() {
+        return Ok(());
+    }
+    for (id, reason) in &invalid {
+        tracing::warn!("Rejected op {} from {}: {}", id, incoming.node_id, reason);
+    }
+    let details: Vec<String> = invalid
+        .iter()
+        .map(|(id, reason)| format!("{}: {}", id, reason))
+        .collect();
+    Err(format!(
+        "Rejected merge: {} op(s) failed signature verification ({})",
+        invalid.len(),
+        details.join("; ")
+    ))
+}
+
+/// POST /admin/recover - 重新加载持久化状态（或由操作日志重建）并解除待恢复标记
+async fn recover_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let mut sync_state = state.sync_state.write().await;
+    let report = state.recover(&mut sync_state).map_err(|e| {
+        SilentError::business_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to recover state: {}", e),
+        )
+    })?;
+    drop(sync_state);
+
+    json_response(&req, &report)
+}
+
+/// 在高风险管理操作前保存带标签的快照（"pre-<operation>"），保存失败时中止操作
+fn snapshot_before(
+    state: &AppState,
+    sync_state: &SyncState,
+    operation: &str,
+) -> Result<SnapshotMeta> {
+    let label = format!("pre-{}", operation);
+    let meta = state
+        .storage
+        .save_labeled_snapshot(&state.node_id, sync_state, &label)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!(
+                    "Failed to save {} snapshot, operation aborted: {}",
+                    label, e
+                ),
+            )
+        })?;
+    tracing::info!("Saved snapshot {} (version {})", label, meta.version);
+    Ok(meta)
+}
+
+/// 管理操作的响应（附带操作前快照）
+#[derive(Serialize)]
+struct AdminOperationResponse {
+    success: bool,
+    state_hash: String,
+    message: String,
+    snapshot: SnapshotMeta,
+}
+
+/// POST /admin/reset - 清空本地 CRDT 数据与操作日志
+#[derive(Debug, Deserialize)]
+struct ResetRequest {
+    #[serde(default)]
+    confirm: bool,
+}
+
+async fn reset_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let reset_req: ResetRequest = req.json_parse().await?;
+    if !reset_req.confirm {
+        return Err(SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            "Reset discards local state; set \"confirm\": true to proceed",
+        ));
+    }
+
+    let mut sync_state = state.sync_state.write().await;
+    let snapshot = snapshot_before(&state, &sync_state, "reset")?;
+    let discarded_state_hash = sync_state.reset();
+
+    state
+        .storage
+        .save_state(&state.node_id, &sync_state)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save state: {}", e),
+            )
+        })?;
+
+    let state_hash = sync_state.state_hash();
+    drop(sync_state);
+
+    tracing::warn!(
+        "Reset local state, discarded state: {} (snapshot version {})",
+        discarded_state_hash,
+        snapshot.version
+    );
+
+    json_response(
+        &req,
+        &AdminOperationResponse {
+            success: true,
+            state_hash,
+            message: format!(
+                "Reset local state; previous state saved as snapshot {}",
+                snapshot.version
+            ),
+            snapshot,
+        },
+    )
+}
+
+/// 在请求体大小与嵌套深度上限内读取对等节点返回的完整状态，并校验其操作签名
+async fn read_peer_state(mut response: reqwest::Response, state: &AppState) -> Result<SyncState> {
+    let limit = state.max_body_bytes;
+    let compressed = match response
+        .headers()
+        .get("Content-Encoding")
+        .and_then(|v| v.to_str().ok())
+    {
+        None | Some("identity") => false,
+        Some(SYNC_CONTENT_ENCODING) => true,
+        Some(other) => {
+            return Err(SilentError::business_error(
+                StatusCode::BAD_GATEWAY,
+                format!("Peer returned unsupported Content-Encoding: {}", other),
+            ));
+        }
+    };
+    let too_large = || {
+        SilentError::business_error(
+            StatusCode::BAD_GATEWAY,
+            format!("Peer state exceeds limit of {} bytes", limit),
+        )
+    };
+    if response
+        .content_length()
+        .is_some_and(|len| len > limit as u64)
+    {
+        return Err(too_large());
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| {
+        SilentError::business_error(
+            StatusCode::BAD_GATEWAY,
+            format!("Failed to read peer state: {}", e),
+        )
+    })? {
+        if body.len() + chunk.len() > limit {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    if compressed {
+        body = compression::decompress_limited(&body, limit).map_err(|e| {
+            SilentError::business_error(
+                StatusCode::BAD_GATEWAY,
+                format!("Invalid compressed peer state: {:#}", e),
+            )
+        })?;
+    }
+
+    check_crdt_depth(&body, state.max_crdt_depth)
+        .map_err(|e| SilentError::business_error(StatusCode::BAD_GATEWAY, e))?;
+    let peer_state: SyncState = serde_json::from_slice(&body).map_err(|e| {
+        SilentError::business_error(
+            StatusCode::BAD_GATEWAY,
+            format!("Failed to parse peer state: {}", e),
+        )
+    })?;
+    verify_incoming_signatures(
+        &peer_state,
+        &state.signature_manager,
+        state.require_signatures,
+    )
+    .map_err(|e| SilentError::business_error(StatusCode::BAD_GATEWAY, e))?;
+    Ok(peer_state)
+}
+
+/// POST /admin/force-pull - 从对等节点拉取完整状态并替换本地状态
+#[derive(Debug, Deserialize)]
+struct ForcePullRequest {
+    peer: String,
+    #[serde(default)]
+    confirm: bool,
+}
+
+async fn force_pull_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let pull_req: ForcePullRequest = parse_json_limited(&mut req, &state).await?;
+    if !pull_req.confirm {
+        return Err(SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            "Force pull discards local state; set \"confirm\": true to proceed",
+        ));
+    }
+
+    // 先获取对等节点的公钥，用于验证其状态中的签名
+    state
+        .refresh_peer_keys(&pull_req.peer)
+        .await
+        .map_err(|e| SilentError::business_error(StatusCode::BAD_GATEWAY, format!("{:#}", e)))?;
+
+    // 获取对等节点的完整状态
+    let peer_url = state.peer_url(&pull_req.peer, "/state");
+
+    let response = state.peer_client.get(&peer_url).send().await.map_err(|e| {
+        SilentError::business_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to fetch state from peer: {}", e),
+        )
+    })?;
+
+    if !response.status().is_success() {
+        return Err(SilentError::business_error(
+            StatusCode::BAD_GATEWAY,
+            format!("Peer returned error: {}", response.status()),
+        ));
+    }
+
+    let peer_state = read_peer_state(response, &state).await?;
+
+    // 先结算批量队列中已确认的请求，避免其结果被替换后的状态悄悄覆盖
+    state.flush_apply_batch().await.map_err(|e| {
+        SilentError::business_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to flush apply batch: {:#}", e),
+        )
+    })?;
+
+    // 替换本地状态
+    let mut sync_state = state.sync_state.write().await;
+    let snapshot = snapshot_before(&state, &sync_state, "force-pull")?;
+    let mut changed_keys: Vec<String> = sync_state
+        .crdt_map
+        .entries
+        .keys()
+        .chain(peer_state.crdt_map.entries.keys())
+        .cloned()
+        .collect();
+    let discarded_state_hash = sync_state.replace_with(peer_state);
+
+    state
+        .storage
+        .save_state(&state.node_id, &sync_state)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save state: {}", e),
+            )
+        })?;
+
+    let state_hash = state.timed_state_hash(&sync_state);
+    let last_op_id = sync_state.op_log.ops.last().map(|entry| entry.id.clone());
+    drop(sync_state);
+
+    changed_keys.sort();
+    changed_keys.dedup();
+    if !changed_keys.is_empty() {
+        state.publish_change(
+            DEFAULT_DOCUMENT,
+            "force-pull",
+            changed_keys,
+            &state_hash,
+            last_op_id,
+        );
+    }
+
+    tracing::warn!(
+        "Force pulled state from {}, discarded local state: {}",
+        pull_req.peer,
+        discarded_state_hash
+    );
+
+    #[derive(Serialize)]
+    struct ForcePullResponse {
+        success: bool,
+        state_hash: String,
+        discarded_state_hash: String,
+        message: String,
+        snapshot: SnapshotMeta,
+    }
+
+    json_response(
+        &req,
+        &ForcePullResponse {
+            success: true,
+            state_hash,
+            discarded_state_hash,
+            message: format!("Replaced local state with state from {}", pull_req.peer),
+            snapshot,
+        },
+    )
+}
+
+/// POST /admin/merge-file - 从本地文件合并导出的状态（离线同步）
+#[derive(Debug, Deserialize)]
+struct MergeFileRequest {
+    path: String,
+}
+
+async fn merge_file_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let merge_req: MergeFileRequest = req.json_parse().await?;
+    let file_state = read_state_file(&merge_req.path).map_err(|e| {
+        SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            format!("Failed to load state file: {:#}", e),
+        )
+    })?;
+    verify_incoming_signatures(
+        &file_state,
+        &state.signature_manager,
+        state.require_signatures,
+    )
+    .map_err(|e| SilentError::business_error(StatusCode::BAD_REQUEST, e))?;
+
+    let mut sync_state = state.sync_state.write().await;
+    sync_state.merge(&file_state);
+
+    state
+        .storage
+        .save_state(&state.node_id, &sync_state)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save state: {}", e),
+            )
+        })?;
+
+    let state_hash = sync_state.state_hash();
+    drop(sync_state);
+
+    tracing::info!(
+        "Merged state of node {} from file {}",
+        file_state.node_id,
+        merge_req.path
+    );
+
+    json_response(
+        &req,
+        &SyncResponse {
+            success: true,
+            state_hash,
+            message: format!(
+                "Merged state of {} from {}",
+                file_state.node_id, merge_req.path
+            ),
+        },
+    )
+}
+
+/// POST /admin/import - 逐条导入 JSON Lines 归档，可断点续传
+#[derive(Debug, Deserialize)]
+struct ImportRequest {
+    /// 归档 ID，续传时需与首次导入相同
+    archive_id: String,
+    /// 服务器本地的归档文件路径
+    path: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ImportResponse {
+    success: bool,
+    state_hash: String,
+    progress: ImportProgress,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snapshot: Option<SnapshotMeta>,
+}
+
+async fn import_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let import_req: ImportRequest = req.json_parse().await?;
+    if import_req.archive_id.is_empty() {
+        return Err(SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            "archive_id must not be empty",
+        ));
+    }
+
+    let mut sync_state = state.sync_state.write().await;
+
+    // 仅在首次导入时保存快照，续传时不重复保存
+    let started = state
+        .storage
+        .load_import_progress(&state.node_id, &import_req.archive_id)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to load import progress: {}", e),
+            )
+        })?
+        .is_some();
+    let snapshot = if started {
+        None
+    } else {
+        Some(snapshot_before(&state, &sync_state, "import")?)
+    };
+
+    let progress = state
+        .storage
+        .import_archive(
+            &state.node_id,
+            &mut sync_state,
+            &import_req.archive_id,
+            &import_req.path,
+            &state.signature_manager,
+            state.require_signatures,
+        )
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Import interrupted, retry to resume: {:#}", e),
+            )
+        })?;
+
+    let state_hash = sync_state.state_hash();
+    drop(sync_state);
+
+    json_response(
+        &req,
+        &ImportResponse {
+            success: true,
+            state_hash,
+            progress,
+            snapshot,
+        },
+    )
+}
+
+/// GET /export 与 POST /import 的查询参数
+#[derive(Debug, Default, Deserialize)]
+struct BundleQuery {
+    /// `json`（默认）或 `cbor`
+    format: Option<String>,
+    /// 导入方式：`merge`（默认）或 `replace`
+    mode: Option<String>,
+}
+
+impl BundleQuery {
+    /// 未指定 `format` 时按 Content-Type 判断
+    fn format(&self, content_type: Option<&str>) -> Result<BundleFormat> {
+        match &self.format {
+            Some(format) => format
+                .parse()
+                .map_err(|e: String| SilentError::business_error(StatusCode::BAD_REQUEST, e)),
+            None if content_type
+                .is_some_and(|ct| ct.starts_with(BundleFormat::Cbor.content_type())) =>
+            {
+                Ok(BundleFormat::Cbor)
+            }
+            None => Ok(BundleFormat::Json),
+        }
+    }
+}
+
+/// GET /export?format=json|cbor - 导出带格式版本的完整状态包
+async fn export_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let query: BundleQuery = req.params_parse().map_err(|e| {
+        SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid export query: {}", e),
+        )
+    })?;
+    let format = query.format(None)?;
+
+    let sync_state = state.sync_state.read().await;
+    let bytes = StateBundle::new(&sync_state).encode(format).map_err(|e| {
+        SilentError::business_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to encode state bundle: {}", e),
+        )
+    })?;
+    drop(sync_state);
+
+    let mut res = Response::empty();
+    res.set_body(full(bytes));
+    res.headers_mut().insert(
+        "Content-Type",
+        silent::header::HeaderValue::from_static(format.content_type()),
+    );
+    Ok(res)
+}
+
+/// POST /import?mode=merge|replace&format=json|cbor - 从状态包合并或替换本地状态
+async fn import_bundle_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let query: BundleQuery = req.params_parse().map_err(|e| {
+        SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid import query: {}", e),
+        )
+    })?;
+    let content_type = req
+        .headers()
+        .get("Content-Type")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let format = query.format(content_type.as_deref())?;
+    let replace = match query.mode.as_deref() {
+        None | Some("merge") => false,
+        Some("replace") => true,
+        Some(other) => {
+            return Err(SilentError::business_error(
+                StatusCode::BAD_REQUEST,
+                format!("Unknown import mode: {} (expected merge or replace)", other),
+            ));
+        }
+    };
+
+    let bytes = read_body_bytes(&mut req, &state).await?;
+    if format == BundleFormat::Json {
+        check_crdt_depth(&bytes, state.max_crdt_depth)
+            .map_err(|e| SilentError::business_error(StatusCode::BAD_REQUEST, e))?;
+    }
+    let bundle = StateBundle::decode(&bytes, format)
+        .map_err(|e| SilentError::business_error(StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    verify_incoming_signatures(
+        &bundle.state,
+        &state.signature_manager,
+        state.require_signatures,
+    )
+    .map_err(|e| SilentError::business_error(StatusCode::BAD_REQUEST, e))?;
+
+    let mut sync_state = state.sync_state.write().await;
+    let snapshot = snapshot_before(&state, &sync_state, "import-bundle")?;
+    let message = if replace {
+        let discarded_state_hash = sync_state.replace_with(bundle.state);
+        format!(
+            "Replaced local state with bundle from {} (discarded state: {})",
+            bundle.node_id, discarded_state_hash
+        )
+    } else {
+        sync_state.merge(&bundle.state);
+        format!("Merged bundle from {}", bundle.node_id)
+    };
+
+    state
+        .storage
+        .save_state(&state.node_id, &sync_state)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save state: {}", e),
+            )
+        })?;
+
+    let state_hash = sync_state.state_hash();
+    drop(sync_state);
+    tracing::warn!("{} (snapshot version {})", message, snapshot.version);
+
+    json_response(
+        &req,
+        &AdminOperationResponse {
+            success: true,
+            state_hash,
+            message,
+            snapshot,
+        },
+    )
+}
+
+/// POST /admin/forget-node - 遗忘已永久离开集群的节点
+#[derive(Debug, Deserialize)]
+struct ForgetNodeRequest {
+    node_id: String,
+}
+
+async fn forget_node_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let forget_req: ForgetNodeRequest = req.json_parse().await?;
+
+    let mut sync_state = state.sync_state.write().await;
+    if forget_req.node_id == state.node_id {
+        return Err(SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            "Cannot forget the local node",
+        ));
+    }
+    let snapshot = snapshot_before(&state, &sync_state, "forget-node")?;
+    sync_state
+        .forget_node(&forget_req.node_id)
+        .map_err(|e| SilentError::business_error(StatusCode::BAD_REQUEST, e))?;
+
+    state
+        .storage
+        .save_state(&state.node_id, &sync_state)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save state: {}", e),
+            )
+        })?;
+
+    let state_hash = sync_state.state_hash();
+    drop(sync_state);
+
+    tracing::warn!("Forgot departed node: {}", forget_req.node_id);
+
+    json_response(
+        &req,
+        &AdminOperationResponse {
+            success: true,
+            state_hash,
+            message: format!("Forgot node {}", forget_req.node_id),
+            snapshot,
+        },
+    )
+}
+
+/// POST /admin/counter/{key}/compact - 将计数器明细压缩为基线
+async fn compact_counter_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let key: String = req.get_path_params("key")?;
+
+    let mut sync_state = state.sync_state.write().await;
+    let snapshot = snapshot_before(&state, &sync_state, "compact-counter")?;
+    let value = sync_state
+        .compact_counter(&key)
+        .map_err(|e| SilentError::business_error(StatusCode::CONFLICT, e))?;
+
+    state
+        .storage
+        .save_state(&state.node_id, &sync_state)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save state: {}", e),
+            )
+        })?;
+
+    let state_hash = sync_state.state_hash();
+    drop(sync_state);
+
+    tracing::info!("Compacted counter {} to baseline {}", key, value);
+
+    json_response(
+        &req,
+        &AdminOperationResponse {
+            success: true,
+            state_hash,
+            message: format!("Compacted counter {} (value {})", key, value),
+            snapshot,
+        },
+    )
+}
+
+/// POST /admin/compact - 压缩操作日志
+///
+/// 未指定 `clock` 时使用本地与所有已知对等节点都已观察到的时钟，
+/// 对端仍可能请求的操作保留在日志中。压缩后的状态保存为带标签的快照（含压缩前沿）。
+#[derive(Debug, Default, Deserialize)]
+struct CompactRequest {
+    clock: Option<VectorClock>,
+}
+
+#[derive(Serialize)]
+struct CompactResponse {
+    success: bool,
+    state_hash: String,
+    truncated: usize,
+    remaining: usize,
+    frontier: VectorClock,
+    snapshot: SnapshotMeta,
+}
+
+async fn compact_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let compact_req: CompactRequest = req.json_parse().await.unwrap_or_default();
+
+    let mut sync_state = state.sync_state.write().await;
+    let up_to_clock = compact_req
+        .clock
+        .unwrap_or_else(|| sync_state.stable_clock());
+    let truncated = sync_state.compact(&up_to_clock);
+
+    state
+        .storage
+        .save_state(&state.node_id, &sync_state)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save state: {}", e),
+            )
+        })?;
+    let snapshot = state
+        .storage
+        .save_labeled_snapshot(&state.node_id, &sync_state, "compact")
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save compaction snapshot: {}", e),
+            )
+        })?;
+
+    let response = CompactResponse {
+        success: true,
+        state_hash: sync_state.state_hash(),
+        truncated,
+        remaining: sync_state.op_log.ops.len(),
+        frontier: sync_state.op_log.compacted.clone(),
+        snapshot,
+    };
+    drop(sync_state);
+
+    tracing::info!(
+        "Compacted oplog: truncated {} ops, {} remaining (snapshot version {})",
+        response.truncated,
+        response.remaining,
+        response.snapshot.version
+    );
+
+    json_response(&req, &response)
+}
+
+/// POST /admin/schema - 注册键的取值约束
+#[derive(Debug, Deserialize)]
+struct RegisterSchemaRequest {
+    key: String,
+    schema: KeySchema,
+}
+
+async fn register_schema_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let schema_req: RegisterSchemaRequest = req.json_parse().await?;
+
+    let mut sync_state = state.sync_state.write().await;
+    sync_state
+        .schemas
+        .register(schema_req.key.clone(), schema_req.schema);
+
+    state
+        .storage
+        .save_schemas(&state.node_id, &sync_state.schemas)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save schemas: {}", e),
+            )
+        })?;
+
+    tracing::info!("Registered schema for key: {}", schema_req.key);
+
+    json_response(&req, &sync_state.schemas)
+}
+
+/// GET /admin/schema - 查看已注册的取值约束
+async fn get_schemas_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+    let sync_state = state.sync_state.read().await;
+
+    json_response(&req, &sync_state.schemas)
+}
+
+/// POST /admin/policy - 设置键的冲突解决策略（随状态同步到其他节点）
+#[derive(Debug, Deserialize)]
+struct SetPolicyRequest {
+    key: String,
+    policy: ConflictPolicy,
+}
+
+async fn set_policy_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let policy_req: SetPolicyRequest = req.json_parse().await?;
+
+    let mut sync_state = state.sync_state.write().await;
+    sync_state
+        .set_policy(&policy_req.key, policy_req.policy)
+        .map_err(|e| SilentError::business_error(StatusCode::CONFLICT, e))?;
+
+    state
+        .storage
+        .save_state(&state.node_id, &sync_state)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save state: {}", e),
+            )
+        })?;
+
+    tracing::info!(
+        "Set conflict policy for key {}: {}",
+        policy_req.key,
+        policy_req.policy
+    );
+
+    json_response(&req, &sync_state.policies.listing())
+}
+
+/// GET /admin/policy - 查看已设置的冲突解决策略
+async fn get_policies_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+    let sync_state = state.sync_state.read().await;
+
+    json_response(&req, &sync_state.policies.listing())
+}
+
+/// POST /convert - 将已有键转换为兼容的 CRDT 类型
+#[derive(Debug, Deserialize)]
+struct ConvertRequest {
+    key: String,
+    to_type: String,
+}
+
+async fn convert_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let convert_req: ConvertRequest = req.json_parse().await?;
+
+    let mut sync_state = state.sync_state.write().await;
+    if sync_state.crdt_map.get(&convert_req.key).is_none() {
+        return Err(SilentError::business_error(
+            StatusCode::NOT_FOUND,
+            format!("Key not found: {}", convert_req.key),
+        ));
+    }
+    let snapshot = snapshot_before(&state, &sync_state, "convert")?;
+    sync_state
+        .convert_key(&convert_req.key, &convert_req.to_type)
+        .map_err(|e| SilentError::business_error(StatusCode::BAD_REQUEST, e))?;
+
+    state
+        .storage
+        .save_state(&state.node_id, &sync_state)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save state: {}", e),
+            )
+        })?;
+
+    let state_hash = sync_state.state_hash();
+    drop(sync_state);
+
+    tracing::info!(
+        "Converted key {} to {}",
+        convert_req.key,
+        convert_req.to_type
+    );
+
+    json_response(
+        &req,
+        &AdminOperationResponse {
+            success: true,
+            state_hash,
+            message: format!(
+                "Converted key {} to {}",
+                convert_req.key, convert_req.to_type
+            ),
+            snapshot,
+        },
+    )
+}
+
+/// GET /state - 获取当前状态
+async fn get_state_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+    json_response(&req, &*sync_state)
+}
+
+/// GET /state/as-of 查询参数
+#[derive(Debug, Deserialize)]
+struct AsOfQuery {
+    /// 毫秒时间戳（含）
+    ts: i64,
+}
+
+/// GET /state/as-of 响应
+#[derive(Debug, Serialize)]
+struct AsOfResponse {
+    ts: i64,
+    ts_iso: String,
+    ops_applied: usize,
+    state_hash: String,
+    document: BTreeMap<String, serde_json::Value>,
+}
+
+/// GET /state/as-of - 重放时间戳不晚于 `ts` 的操作，返回当时的物化文档
+///
+/// 按各节点记录的时间戳过滤，存在时钟偏差时并非因果精确的历史视图。
+async fn get_state_as_of_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let query: AsOfQuery = req.params_parse().map_err(|e| {
+        SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid as-of query: {}", e),
+        )
+    })?;
+
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+    let (crdt_map, ops_applied) = sync_state.state_as_of(query.ts);
+    let mut document = crdt_map.document();
+
+    // 加密字段仅对 Writer 及以上角色解密
+    let authorized = req
+        .extensions()
+        .get::<Role>()
+        .is_some_and(|role| role.has_permission(&Role::Writer));
+    if authorized && let Some(encryptor) = &sync_state.encryptor {
+        encryptor.decrypt_document(&mut document);
+    }
+
+    json_response(
+        &req,
+        &AsOfResponse {
+            ts: query.ts,
+            ts_iso: format_ts_iso(query.ts),
+            ops_applied,
+            state_hash: crdt_map.state_hash(),
+            document,
+        },
+    )
+}
+
+/// GET /keys 查询参数
+#[derive(Debug, Default, Deserialize)]
+struct KeysQuery {
+    /// 展示排序方式（byte / case-insensitive），不影响 state_hash
+    #[serde(default)]
+    collation: KeyCollation,
+    /// 只列出以此开头的键
+    prefix: Option<String>,
+    /// 只列出此类型的键
+    #[serde(rename = "type")]
+    crdt_type: Option<String>,
+}
+
+/// GET /keys - 列出所有键及其 CRDT 类型
+async fn get_keys_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+    let query: KeysQuery = req.params_parse().map_err(|e| {
+        SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid keys query: {}", e),
+        )
+    })?;
+
+    if let Some(crdt_type) = &query.crdt_type
+        && !CRDT_TYPES.contains(&crdt_type.as_str())
+    {
+        return Err(SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            format!("Unknown CRDT type: {}", crdt_type),
+        ));
+    }
+
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+    let filter = KeyFilter {
+        prefix: query.prefix,
+        crdt_type: query.crdt_type,
+    };
+    json_response(
+        &req,
+        &sync_state.crdt_map.key_listing(query.collation, &filter),
+    )
+}
+
+/// GET /replication-status - 各对等节点相对本地的复制状态
+async fn replication_status_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+    json_response(&req, &sync_state.replication_status())
+}
+
+/// 跨文档一致的时间点快照
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsistentSnapshot {
+    /// 逻辑时间戳：所有文档的读锁均已持有时的时间（毫秒）
+    pub taken_at: i64,
+    pub taken_at_iso: String,
+    pub documents: BTreeMap<String, SyncState>,
+}
+
+/// 同时持有所有文档的读锁并复制其状态，得到一致的时间点视图
+///
+/// 读锁按文档名排序后依次获取，同时修改多个文档的写入方须遵循相同顺序以避免死锁。
+/// 锁内只做复制，序列化等耗时工作在释放后进行。
+pub async fn capture_consistent(
+    documents: &[(String, Arc<RwLock<SyncState>>)],
+) -> ConsistentSnapshot {
+    let mut ordered: Vec<_> = documents.iter().collect();
+    ordered.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut guards = Vec::with_capacity(ordered.len());
+    for (name, state) in ordered {
+        guards.push((name, state.read().await));
+    }
+    let taken_at = chrono::Local::now()
+        .naive_local()
+        .and_utc()
+        .timestamp_millis();
+    let documents = guards
+        .iter()
+        .map(|(name, guard)| ((*name).clone(), (**guard).clone()))
+        .collect();
+    drop(guards);
+
+    ConsistentSnapshot {
+        taken_at,
+        taken_at_iso: format_ts_iso(taken_at),
+        documents,
+    }
+}
+
+/// GET /admin/consistent-snapshot - 导出所有文档的一致时间点快照
+async fn consistent_snapshot_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let documents = state.documents().await.map_err(document_load_error)?;
+    let snapshot = capture_consistent(&documents).await;
+    json_response(&req, &snapshot)
+}
+
+/// GET /admin/repro - 导出用于复现问题的重放包
+///
+/// 以最早的已保存快照为重放起点（没有快照时从空状态开始），附带其后的全部操作日志。
+async fn export_repro_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let storage_error = |e: anyhow::Error| {
+        SilentError::business_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to load snapshot: {}", e),
+        )
+    };
+    let initial = match state
+        .storage
+        .list_snapshots(&state.node_id)
+        .map_err(storage_error)?
+        .first()
+    {
+        Some(version) => state
+            .storage
+            .load_snapshot(&state.node_id, *version)
+            .map_err(storage_error)?,
+        None => None,
+    };
+
+    let sync_state = state.sync_state.read().await;
+    let bundle = sync_state.repro_bundle(initial);
+    drop(sync_state);
+
+    tracing::info!(
+        "Exported repro bundle with {} ops (state hash {})",
+        bundle.ops.len(),
+        bundle.expected_state_hash
+    );
+    json_response(&req, &bundle)
+}
+
+/// POST /admin/repro - 在临时状态中重放重放包并比较状态哈希（不修改本地状态）
+async fn replay_repro_handler(mut req: Request) -> Result<Response> {
+    #[derive(Serialize)]
+    struct ReplayResponse {
+        expected_state_hash: String,
+        replayed_state_hash: String,
+        matches: bool,
+        ops_replayed: usize,
+    }
+
+    let bundle: ReproBundle = req.json_parse().await?;
+    let replayed_state_hash = bundle.replay().state_hash();
+
+    json_response(
+        &req,
+        &ReplayResponse {
+            matches: replayed_state_hash == bundle.expected_state_hash,
+            expected_state_hash: bundle.expected_state_hash,
+            replayed_state_hash,
+            ops_replayed: bundle.ops.len(),
+        },
+    )
+}
+
+/// POST /admin/self-test 查询参数
+#[derive(Debug, Deserialize)]
+struct SelfTestQuery {
+    #[serde(default = "default_self_test_rounds")]
+    rounds: usize,
+}
+
+fn default_self_test_rounds() -> usize {
+    DEFAULT_SELF_TEST_ROUNDS
+}
+
+/// POST /admin/self-test - 在当前状态的内存副本上运行收敛自检（不修改持久化状态）
+async fn self_test_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let query: SelfTestQuery = req.params_parse().map_err(|e| {
+        SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid self-test query: {}", e),
+        )
+    })?;
+
+    let snapshot = state.sync_state.read().await.clone();
+    let report = run_self_test(&snapshot, query.rounds);
+    if report.passed {
+        tracing::info!("Convergence self-test passed ({} rounds)", report.rounds);
+    } else {
+        tracing::error!("Convergence self-test failed: {:?}", report.failures);
+    }
+
+    json_response(&req, &report)
+}
+
+/// GET /schema/operations - 列出支持的变更操作及其字段
+async fn list_operations_handler(req: Request) -> Result<Response> {
+    json_response(&req, CHANGE_OPS)
+}
+
+/// GET /stats 查询参数
+#[derive(Debug, Deserialize)]
+struct StatsQuery {
+    /// 列出的最大键数量
+    #[serde(default = "default_stats_top")]
+    top: usize,
+}
+
+fn default_stats_top() -> usize {
+    DEFAULT_STATS_TOP_KEYS
+}
+
+/// GET /stats - CRDT 组成统计（类型分布、集合元素、操作日志长度、最大键、近似内存）
+async fn get_stats_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let query: StatsQuery = req.params_parse().map_err(|e| {
+        SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid stats query: {}", e),
+        )
+    })?;
+
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+    json_response(&req, &sync_state.stats(query.top))
+}
+
+/// GET /document - 以普通 JSON 返回物化后的逻辑文档
+async fn get_document_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+    let mut document = sync_state.crdt_map.document();
+
+    // 加密字段仅对 Writer 及以上角色解密
+    let authorized = req
+        .extensions()
+        .get::<Role>()
+        .is_some_and(|role| role.has_permission(&Role::Writer));
+    if authorized && let Some(encryptor) = &sync_state.encryptor {
+        encryptor.decrypt_document(&mut document);
+    }
+
+    json_response(&req, &document)
+}
+
+/// GET /key/{key} - 单个键的类型化物化值
+async fn get_key_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+    let key: String = req.get_path_params("key")?;
+
+    if let Some(scope) = req.extensions().get::<KeyScope>()
+        && !scope.allows(&key)
+    {
+        return Err(SilentError::business_error(
+            StatusCode::FORBIDDEN,
+            format!("Key not permitted by token: {}", key),
+        ));
+    }
+
+    // 加密字段仅对 Writer 及以上角色解密
+    let authorized = req
+        .extensions()
+        .get::<Role>()
+        .is_some_and(|role| role.has_permission(&Role::Writer));
+
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+    json_response(&req, &key_view(&sync_state, &key, authorized)?)
+}
+
+/// 投影单个键的值，键不存在时返回 404
+fn key_view(sync_state: &SyncState, key: &str, decrypt: bool) -> Result<serde_json::Value> {
+    let value = sync_state.crdt_map.get(key).ok_or_else(|| {
+        SilentError::business_error(StatusCode::NOT_FOUND, format!("Key not found: {}", key))
+    })?;
+    let mut view = serde_json::to_value(value.view()).map_err(|e| {
+        SilentError::business_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to serialize value: {}", e),
+        )
+    })?;
+
+    if decrypt
+        && let Some(encryptor) = &sync_state.encryptor
+        && let Some(fields) = view.as_object_mut()
+    {
+        for field in fields.values_mut() {
+            encryptor.decrypt_value(key, field);
+        }
+    }
+    Ok(view)
+}
+
+/// GET /snapshots - 列出已保存的快照（按版本升序）与当前最新版本
+#[derive(Serialize)]
+struct SnapshotListEntry {
+    version: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SnapshotListResponse {
+    current_version: Option<u64>,
+    snapshots: Vec<SnapshotListEntry>,
+}
+
+async fn list_snapshots_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let storage_error = |e: anyhow::Error| {
+        SilentError::business_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to list snapshots: {}", e),
+        )
+    };
+    let mut snapshots = Vec::new();
+    for version in state
+        .storage
+        .list_snapshots(&state.node_id)
+        .map_err(storage_error)?
+    {
+        // 旧版本快照可能没有元数据
+        let meta = state
+            .storage
+            .snapshot_meta(&state.node_id, version)
+            .map_err(storage_error)?;
+        snapshots.push(SnapshotListEntry {
+            version,
+            created_at: meta.as_ref().map(|meta| meta.created_at),
+            label: meta.and_then(|meta| meta.label),
+        });
+    }
+
+    json_response(
+        &req,
+        &SnapshotListResponse {
+            current_version: snapshots.last().map(|entry| entry.version),
+            snapshots,
+        },
+    )
+}
+
+/// POST /snapshots/<version>/restore?keep_oplog= - 将默认文档回滚到指定快照
+#[derive(Debug, Default, Deserialize)]
+struct RestoreQuery {
+    #[serde(default)]
+    keep_oplog: bool,
+}
+
+#[derive(Serialize)]
+struct RestoreResponse {
+    success: bool,
+    state_hash: String,
+    restored_version: u64,
+    #[serde(flatten)]
+    report: RestoreReport,
+    /// 回滚前自动保存的快照
+    snapshot: SnapshotMeta,
+}
+
+async fn restore_snapshot_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let version: u64 = req.get_path_params("version")?;
+    let query: RestoreQuery = req.params_parse().map_err(|e| {
+        SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid restore query: {}", e),
+        )
+    })?;
+    let restored = state
+        .storage
+        .load_snapshot(&state.node_id, version)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to load snapshot: {}", e),
+            )
+        })?
+        .ok_or_else(|| {
+            SilentError::business_error(
+                StatusCode::NOT_FOUND,
+                format!("Snapshot version {} not found", version),
+            )
+        })?;
+
+    let mut sync_state = state.sync_state.write().await;
+    let snapshot = snapshot_before(&state, &sync_state, "restore")?;
+    let report = sync_state.restore_snapshot(restored, query.keep_oplog);
+
+    state
+        .storage
+        .save_state(&state.node_id, &sync_state)
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to save state: {}", e),
+            )
+        })?;
+
+    let state_hash = sync_state.state_hash();
+    drop(sync_state);
+
+    tracing::warn!(
+        "Restored snapshot {}, dropped {} op(s), discarded state: {}",
+        version,
+        report.dropped_ops,
+        report.discarded_state_hash
+    );
+
+    json_response(
+        &req,
+        &RestoreResponse {
+            success: true,
+            state_hash,
+            restored_version: version,
+            report,
+            snapshot,
+        },
+    )
+}
+
+/// GET /snapshots/diff - 比较两个快照版本的键级差异
+#[derive(Debug, Deserialize)]
+struct SnapshotDiffQuery {
+    from: u64,
+    to: u64,
+}
+
+async fn snapshot_diff_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let query: SnapshotDiffQuery = req.params_parse().map_err(|e| {
+        SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid diff query: {}", e),
+        )
+    })?;
+
+    let load = |version: u64| -> Result<SyncState> {
+        state
+            .storage
+            .load_snapshot(&state.node_id, version)
+            .map_err(|e| {
+                SilentError::business_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to load snapshot: {}", e),
+                )
+            })?
+            .ok_or_else(|| {
+                SilentError::business_error(
+                    StatusCode::NOT_FOUND,
+                    format!("Snapshot version {} not found", version),
+                )
+            })
+    };
+    let from = load(query.from)?;
+    let to = load(query.to)?;
+
+    #[derive(Serialize)]
+    struct SnapshotDiffResponse {
+        from: u64,
+        to: u64,
+        #[serde(flatten)]
+        diff: MapDiff,
+    }
+
+    json_response(
+        &req,
+        &SnapshotDiffResponse {
+            from: query.from,
+            to: query.to,
+            diff: from.crdt_map.diff(&to.crdt_map),
+        },
+    )
+}
+
+/// GET /frontier - 获取操作日志的因果前沿（每个节点的最新操作 ID 与序号）
+async fn get_frontier_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+    let frontier = sync_state.op_log.frontier();
+
+    json_response(&req, &frontier)
+}
+
+/// POST /plan-sync - 计算与目标状态收敛所需接收的操作（只读）
+#[derive(Debug, Deserialize)]
+struct PlanSyncRequest {
+    /// 目标状态（如对等节点 GET /state 的结果）
+    state: SyncState,
+}
+
+#[derive(Serialize)]
+struct PlanSyncResponse {
+    /// 本地缺少的操作数
+    missing_count: usize,
+    /// 本地缺少的操作 ID（按目标日志顺序）
+    missing_ids: Vec<String>,
+    /// 本地缺少的操作
+    missing_ops: Vec<OpLogEntry>,
+    /// 目标缺少的本地操作数（反方向同步将传输的操作数）
+    local_only_count: usize,
+}
+
+async fn plan_sync_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let plan_req: PlanSyncRequest = parse_json_limited(&mut req, &state).await?;
+    let target = &plan_req.state.op_log;
+
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+    let missing_ops: Vec<OpLogEntry> = sync_state
+        .op_log
+        .missing_ops(target)
+        .into_iter()
+        .cloned()
+        .collect();
+    let local_only_count = target.missing_ops(&sync_state.op_log).len();
+    drop(sync_state);
+
+    json_response(
+        &req,
+        &PlanSyncResponse {
+            missing_count: missing_ops.len(),
+            missing_ids: missing_ops.iter().map(|e| e.id.clone()).collect(),
+            missing_ops,
+            local_only_count,
+        },
+    )
+}
+
+/// GET /merkle-root - 按键哈希分桶的 Merkle 树根哈希
+#[derive(Serialize)]
+struct MerkleRootResponse {
+    root: String,
+    depth: usize,
+    keys: usize,
+}
+
+async fn merkle_root_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+    let response = MerkleRootResponse {
+        root: sync_state.crdt_map.merkle_root(),
+        depth: MERKLE_DEPTH,
+        keys: sync_state.crdt_map.entries.len(),
+    };
+    drop(sync_state);
+
+    json_response(&req, &response)
+}
+
+/// POST /diff - 按对端的 Merkle 节点哈希返回本地不一致的子节点或键
+#[derive(Debug, Deserialize)]
+struct MerkleDiffRequest {
+    /// 节点路径（十六进制前缀，根为 ""）→ 对端哈希（空子树为 ""）
+    nodes: BTreeMap<String, String>,
+}
+
+async fn merkle_diff_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let diff_req: MerkleDiffRequest = parse_json_limited(&mut req, &state).await?;
+
+    let document = read_document(&req, &state).await?;
+    let tree = document.read().await.crdt_map.merkle_tree();
+    let diff = tree
+        .compare(&diff_req.nodes)
+        .map_err(|e| SilentError::business_error(StatusCode::BAD_REQUEST, e))?;
+
+    json_response(&req, &diff)
+}
+
+/// POST /sync-delta - 返回对端（以其向量时钟表示）尚未观察到的增量状态
+#[derive(Debug, Deserialize)]
+struct SyncDeltaRequest {
+    /// 对端已知的向量时钟（如对端 GET /state 结果中的 `crdt_map.vector_clock`）
+    clock: VectorClock,
+    /// 对端各 OR-Set 的因果上下文（键 -> 集合的 `context`），给出时这些集合只返回对端缺失的点
+    #[serde(default)]
+    sets: HashMap<String, VectorClock>,
+}
+
+async fn sync_delta_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let delta_req: SyncDeltaRequest = parse_json_limited(&mut req, &state).await?;
+    let document = read_document(&req, &state).await?;
+    let delta = document
+        .read()
+        .await
+        .delta_since_with_sets(&delta_req.clock, &delta_req.sets);
+
+    // 响应体可直接作为对端 POST /merge 的请求体
+    json_response(
+        &req,
+        &SyncRequest {
+            from_node: state.node_id.clone(),
+            state: delta,
+        },
+    )
+}
+
+/// GET /state-hash - 获取状态哈希
+async fn get_state_hash_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+    let state_hash = state.timed_state_hash(&sync_state);
+
+    #[derive(Serialize)]
+    struct StateHashResponse {
+        hash: String,
+    }
+
+    json_response(&req, &StateHashResponse { hash: state_hash })
+}
+
+/// 解析操作日志的过滤与分页查询参数
+fn parse_oplog_query(req: &mut Request) -> Result<(OpLogFilter, OpLogPage)> {
+    let invalid = |e: String| {
+        SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid oplog filter: {}", e),
+        )
+    };
+    let filter: OpLogFilter = req.params_parse().map_err(|e| invalid(e.to_string()))?;
+    let page: OpLogPage = req.params_parse().map_err(|e| invalid(e.to_string()))?;
+    Ok((filter, page))
+}
+
+/// GET /oplog 的一页结果
+#[derive(Debug, Serialize)]
+struct OpLogPageResponse {
+    #[serde(flatten)]
+    oplog: OpLog,
+    /// 下一页的 `after_id`，已是最后一页时为 null
+    next_after_id: Option<String>,
+}
+
+/// GET /oplog - 分页导出操作日志（支持 key / node / op_type / since_ts / until_ts 过滤与 limit / after_id 分页）
+async fn get_oplog_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+    let (filter, page) = parse_oplog_query(&mut req)?;
+
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+    let (oplog, next_after_id) = sync_state
+        .op_log
+        .page(&filter, &page)
+        .map_err(|e| SilentError::business_error(StatusCode::BAD_REQUEST, e))?;
+    json_response(
+        &req,
+        &OpLogPageResponse {
+            oplog,
+            next_after_id,
+        },
+    )
+}
+
+/// GET /history 查询参数
+#[derive(Debug, Default, Deserialize)]
+struct HistoryQuery {
+    /// 时间戳格式，`iso` 时额外输出 RFC 3339 字符串
+    ts_format: Option<String>,
+}
+
+/// GET /history - 分页获取操作历史（带详细信息，过滤与分页参数同 `/oplog`）
+async fn get_history_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+    let query: HistoryQuery = req.params_parse().unwrap_or_default();
+    let iso = query.ts_format.as_deref() == Some("iso");
+    let (filter, page) = parse_oplog_query(&mut req)?;
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+
+    #[derive(Serialize)]
+    struct HistoryEntry {
+        id: String,
+        timestamp: i64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        timestamp_iso: Option<String>,
+        operation_type: String,
+        key: String,
+        details: String,
+        node_id: String,
+        causal_context: std::collections::HashMap<String, i64>,
+    }
+
+    #[derive(Serialize)]
+    struct HistoryPage {
+        entries: Vec<HistoryEntry>,
+        next_after_id: Option<String>,
+    }
+
+    let (oplog, next_after_id) = sync_state
+        .op_log
+        .page(&filter, &page)
+        .map_err(|e| SilentError::business_error(StatusCode::BAD_REQUEST, e))?;
+    let mut history: Vec<HistoryEntry> = Vec::new();
+
+    for entry in &oplog.ops {
+        history.push(HistoryEntry {
+            id: entry.id.clone(),
+            timestamp: entry.ts,
+            timestamp_iso: iso.then(|| entry.ts_iso()),
+            operation_type: entry.op.op_type().to_string(),
+            key: entry.op.key().to_string(),
+            details: entry.op.details(),
+            node_id: oplog.node_id.clone(),
+            causal_context: entry
+                .causal
+                .clocks
+                .iter()
+                .map(|(k, v)| (k.clone(), *v as i64))
+                .collect(),
+        });
+    }
+
+    json_response(
+        &req,
+        &HistoryPage {
+            entries: history,
+            next_after_id,
+        },
+    )
+}
+
+/// GET /conflicts - 检测并返回可能的冲突
+async fn get_conflicts_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+
+    #[derive(Serialize)]
+    struct Conflict {
+        key: String,
+        conflict_type: String,
+        operations: Vec<ConflictOperation>,
+        resolution: String,
+    }
+
+    #[derive(Serialize)]
+    struct ConflictOperation {
+        id: String,
+        timestamp: i64,
+        node_id: String,
+        details: String,
+    }
+
+    let mut conflicts: Vec<Conflict> = Vec::new();
+    let oplog = &sync_state.op_log;
+
+    // 检测 LWWRegister 的并发写入
+    let mut lww_writes: std::collections::HashMap<String, Vec<&crate::sync::OpLogEntry>> =
+        std::collections::HashMap::new();
+
+    for entry in &oplog.ops {
+        if let crate::sync::Operation::LwwRegisterSet { key, .. } = &entry.op {
+            lww_writes.entry(key.clone()).or_default().push(entry);
+        }
+    }
+
+    for (key, entries) in lww_writes {
+        if entries.len() > 1 {
+            // 检查是否有并发写入（向量时钟无法比较）
+            let mut concurrent_writes = Vec::new();
+            for i in 0..entries.len() {
+                for j in (i + 1)..entries.len() {
+                    let clock1 = &entries[i].causal;
+                    let clock2 = &entries[j].causal;
+
+                    if !clock1.happens_before(clock2) && !clock2.happens_before(clock1) {
+                        if concurrent_writes.is_empty()
+                            && let crate::sync::Operation::LwwRegisterSet {
+                                value,
+                                timestamp,
+                                node_id,
+                                ..
+                            } = &entries[i].op
+                        {
+                            concurrent_writes.push(ConflictOperation {
+                                id: entries[i].id.clone(),
+                                timestamp: *timestamp,
+                                node_id: node_id.clone(),
+                                details: format!("设置为 '{}'", value),
+                            });
+                        }
+
+                        if let crate::sync::Operation::LwwRegisterSet {
+                            value,
+                            timestamp,
+                            node_id,
+                            ..
+                        } = &entries[j].op
+                        {
+                            concurrent_writes.push(ConflictOperation {
+                                id: entries[j].id.clone(),
+                                timestamp: *timestamp,
+                                node_id: node_id.clone(),
+                                details: format!("设置为 '{}'", value),
+                            });
+                        }
+                    }
+                }
+            }
+
+            if !concurrent_writes.is_empty() {
+                // 找出最终胜出的值
+                let winner_node = concurrent_writes
+                    .iter()
+                    .max_by(|a, b| {
+                        a.timestamp
+                            .cmp(&b.timestamp)
+                            .then_with(|| a.node_id.cmp(&b.node_id))
+                    })
+                    .map(|w| w.node_id.clone())
+                    .unwrap();
+
+                conflicts.push(Conflict {
+                    key: key.clone(),
+                    conflict_type: "LWWRegister 并发写入".to_string(),
+                    operations: concurrent_writes,
+                    resolution: format!(
+                        "根据 LWW 规则，时间戳较大的操作胜出 (节点: {})",
+                        winner_node
+                    ),
+                });
+            }
+        }
+    }
+
+    // OR-Set 上对同一元素的并发添加与移除（添加胜出）
+    for race in sync_state.set_add_remove_races() {
+        conflicts.push(Conflict {
+            key: race.key.to_string(),
+            conflict_type: SetRace::CONFLICT_TYPE.to_string(),
+            operations: race
+                .entries()
+                .map(|entry| ConflictOperation {
+                    id: entry.id.clone(),
+                    timestamp: entry.ts,
+                    node_id: entry.origin_node().unwrap_or_default().to_string(),
+                    details: entry.op.details(),
+                })
+                .collect(),
+            resolution: race.resolution(),
+        });
+    }
+
+    // 多值寄存器保留的并发写入（未自动解决，由客户端处理）
+    for (key, writes) in sync_state.unresolved_writes() {
+        let operations: Vec<ConflictOperation> = writes
+            .iter()
+            .map(|entry| ConflictOperation {
+                id: entry.id.clone(),
+                timestamp: entry.ts,
+                node_id: entry.origin_node().unwrap_or_default().to_string(),
+                details: entry.op.details(),
+            })
+            .collect();
+        conflicts.push(Conflict {
+            key: key.to_string(),
+            conflict_type: "MVRegister 并发写入".to_string(),
+            resolution: format!(
+                "多值寄存器保留全部 {} 个并发值，待客户端解决",
+                operations.len()
+            ),
+            operations,
+        });
+    }
+
+    // 合并时 CRDT 类型不一致的键（保留本地值，需人工处理）
+    for conflict in &sync_state.type_conflicts {
+        conflicts.push(Conflict {
+            key: conflict.key.clone(),
+            conflict_type: "Type mismatch".to_string(),
+            operations: Vec::new(),
+            resolution: format!(
+                "本地类型 {} 与对端类型 {} 不一致，保留本地值",
+                conflict.local_type, conflict.remote_type
+            ),
+        });
+    }
+
+    json_response(&req, &conflicts)
+}
+
+/// GET /conflicts/stats - 获取各键的累计冲突次数
+async fn get_conflict_stats_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+    let document = read_document(&req, &state).await?;
+    let sync_state = document.read().await;
+
+    #[derive(Serialize)]
+    struct ConflictStat {
+        key: String,
+        count: u64,
+    }
+
+    let stats: Vec<ConflictStat> = sync_state
+        .conflict_stats_sorted()
+        .into_iter()
+        .map(|(key, count)| ConflictStat { key, count })
+        .collect();
+
+    json_response(&req, &stats)
+}
+
+/// GET /health - 健康检查
+async fn health_handler(req: Request) -> Result<Response> {
+    #[derive(Serialize)]
+    struct HealthResponse {
+        status: String,
+        timestamp: i64,
+    }
+
+    let response = HealthResponse {
+        status: "ok".to_string(),
+        timestamp: chrono::Local::now()
+            .naive_local()
+            .and_utc()
+            .timestamp_millis(),
+    };
+
+    json_response(&req, &response)
+}
+
+/// POST /auth/token - 生成 JWT token
+async fn generate_token_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    #[derive(Deserialize)]
+    struct TokenRequest {
+        node_id: String,
+        role: Role,
+        expires_in_secs: Option<u64>,
+        allowed_keys: Option<Vec<String>>,
+    }
+
+    #[derive(Serialize)]
+    struct TokenResponse {
+        token: String,
+        expires_in: u64,
+    }
+
+    // 仅持有公钥的节点只验证 token，签发需通过持有私钥的节点
+    if !state.jwt_manager.can_issue() {
+        return Err(SilentError::business_error(
+            StatusCode::FORBIDDEN,
+            "This node holds no JWT private key and cannot issue tokens".to_string(),
+        ));
+    }
+
+    let token_req: TokenRequest = req.json_parse().await?;
+    let expires_in = token_req.expires_in_secs.unwrap_or(3600); // 默认 1 小时
+
+    let token = state
+        .jwt_manager
+        .generate_scoped_token(
+            token_req.node_id,
+            token_req.role,
+            expires_in,
+            token_req.allowed_keys,
+        )
+        .map_err(|e| {
+            SilentError::business_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to generate token: {}", e),
+            )
+        })?;
+
+    json_response(&req, &TokenResponse { token, expires_in })
+}
+
+/// POST /auth/revoke - 吊销 token
+///
+/// 未提供 `exp` 时记录保留 `DEFAULT_REVOCATION_RETENTION_SECS`，
+/// 有效期更长的 token 应传入其 `exp` 以免记录提前被清理。
+async fn revoke_token_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    #[derive(Deserialize)]
+    struct RevokeRequest {
+        jti: String,
+        exp: Option<u64>,
+    }
+
+    #[derive(Serialize)]
+    struct RevokeResponse {
+        jti: String,
+        revoked_until: u64,
+    }
+
+    let revoke_req: RevokeRequest = req.json_parse().await?;
+    if revoke_req.jti.is_empty() {
+        return Err(SilentError::business_error(
+            StatusCode::BAD_REQUEST,
+            "Missing jti",
+        ));
+    }
+    let revoked_until = revoke_req.exp.unwrap_or_else(|| {
+        chrono::Utc::now().timestamp() as u64 + DEFAULT_REVOCATION_RETENTION_SECS
+    });
+
+    state
+        .revocations
+        .revoke(revoke_req.jti.clone(), revoked_until);
+    state
+        .jwt_manager
+        .cache()
+        .invalidate_where(|claims| claims.jti == revoke_req.jti);
+    tracing::warn!("Revoked token jti={}", revoke_req.jti);
+
+    json_response(
+        &req,
+        &RevokeResponse {
+            jti: revoke_req.jti,
+            revoked_until,
+        },
+    )
+}
+
+/// GET /auth/public-key - 获取节点的公钥
+async fn get_public_key_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    json_response(&req, &state.signature_manager.public_keys())
+}
+
+/// POST /admin/peer-keys - 从对等节点的 /auth/public-key 获取并信任其公钥
+#[derive(Debug, Deserialize)]
+struct TrustPeerRequest {
+    peer: String,
+}
+
+async fn trust_peer_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let trust_req: TrustPeerRequest = parse_json_limited(&mut req, &state).await?;
+    let keys = state
+        .refresh_peer_keys(&trust_req.peer)
+        .await
+        .map_err(|e| SilentError::business_error(StatusCode::BAD_GATEWAY, format!("{:#}", e)))?;
+
+    json_response(&req, &keys)
+}
+
+/// POST /admin/rotate-key - 轮换节点签名密钥
+///
+/// 新密钥与旧公钥一并持久化后才生效（持久化失败时仍使用原密钥）；旧公钥在宽限期内仍出现在 /auth/public-key 中，
+/// 对等节点据此继续验证轮换前签发的操作。
+async fn rotate_key_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    state.rotate_signing_key().map_err(|e| {
+        SilentError::business_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to save keypair: {}", e),
+        )
+    })?;
+
+    let response = state.signature_manager.public_keys();
+    tracing::warn!(
+        "Rotated signing key for node {}, new public key: {}",
+        state.node_id,
+        response.public_key
+    );
+
+    json_response(&req, &response)
+}
+
+/// POST /sign - 由节点代为签名操作
+#[derive(Debug, Deserialize)]
+struct SignRequest {
+    id: Option<String>,
+    timestamp: Option<i64>,
+    operation_type: String,
+    operation_data: String,
+    causal_context: Option<String>,
+}
+
+/// 使用节点密钥签名操作，缺省字段自动补全
+fn sign_operation_request(
+    manager: &SignatureManager,
+    sign_req: SignRequest,
+) -> anyhow::Result<SignedOperation> {
+    let id = sign_req.id.unwrap_or_else(scru128::new_string);
+    let timestamp = sign_req.timestamp.unwrap_or_else(|| {
+        chrono::Local::now()
+            .naive_local()
+            .and_utc()
+            .timestamp_millis()
+    });
+    let causal_context = sign_req.causal_context.unwrap_or_else(|| "{}".to_string());
+
+    manager.sign_operation(
+        id,
+        timestamp,
+        sign_req.operation_type,
+        sign_req.operation_data,
+        causal_context,
+    )
+}
+
+async fn sign_handler(mut req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+
+    let sign_req: SignRequest = req.json_parse().await?;
+    let signed = sign_operation_request(&state.signature_manager, sign_req).map_err(|e| {
+        SilentError::business_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to sign operation: {}", e),
+        )
+    })?;
+
+    json_response(&req, &signed)
+}
+
+/// 权限验证中间件
+#[derive(Clone)]
+pub struct AuthMiddleware {
+    required_role: Role,
+}
+
+impl AuthMiddleware {
+    pub fn new(required_role: Role) -> Self {
+        Self { required_role }
+    }
+}
+
+impl AuthMiddleware {
+    /// 根据 Authorization header 校验请求是否具备所需角色，返回调用方角色与可写键范围
+    ///
+    /// 未携带 header 时回退到默认角色（若已配置），否则返回 401。
+    fn authorize(&self, state: &AppState, auth_header: Option<&str>) -> Result<(Role, KeyScope)> {
+        let (role, scope) = match auth_header {
+            Some(auth_header) => {
+                // 提取 token
+                let token = JwtManager::extract_token(auth_header).map_err(|e| {
+                    SilentError::business_error(
+                        StatusCode::UNAUTHORIZED,
+                        format!("Invalid token: {}", e),
+                    )
+                })?;
+
+                // 验证 token
+                let claims = state.jwt_manager.verify_token(token).map_err(|e| {
+                    SilentError::business_error(
+                        StatusCode::UNAUTHORIZED,
+                        format!("Invalid token: {}", e),
+                    )
+                })?;
+                if state.revocations.is_revoked(&claims.jti) {
+                    return Err(SilentError::business_error(
+                        StatusCode::UNAUTHORIZED,
+                        "Token has been revoked",
+                    ));
+                }
+                (claims.role.clone(), claims.key_scope())
+            }
+            None => {
+                let role = state.default_role.clone().ok_or_else(|| {
+                    SilentError::business_error(
+                        StatusCode::UNAUTHORIZED,
+                        "Missing authorization header",
+                    )
+                })?;
+                (role, KeyScope::default())
+            }
+        };
+
+        // 检查权限
+        if !role.has_permission(&self.required_role) {
+            return Err(SilentError::business_error(
+                StatusCode::FORBIDDEN,
+                "Insufficient permissions",
+            ));
+        }
+
+        Ok((role, scope))
+    }
+}
+
+#[async_trait::async_trait]
+impl MiddleWareHandler for AuthMiddleware {
+    async fn handle(&self, mut req: Request, next: &Next) -> Result<Response> {
+        let state = req.extensions().get::<AppState>().unwrap().clone();
+
+        // 如果未启用权限控制，直接放行
+        if !state.auth_enabled {
+            req.extensions_mut().insert(Role::Admin);
+            return next.call(req).await;
+        }
+
+        let auth_header = req
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok());
+        let (role, scope) = self.authorize(&state, auth_header)?;
+
+        // 记录调用方角色与可写键范围，供处理器做细粒度判断
+        req.extensions_mut().insert(role);
+        req.extensions_mut().insert(scope);
+        next.call(req).await
+    }
+}
+
+/// 单个调用方的令牌桶
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// 按调用方限流的令牌桶集合
+#[derive(Debug)]
+pub struct RateLimiter {
+    rps: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(rps: f64, burst: u32) -> Self {
+        Self {
+            rps,
+            burst: f64::from(burst.max(1)),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 为调用方 `key` 消耗一个令牌，令牌不足时返回需要等待的时长
+    pub fn check(&self, key: &str, now: Instant) -> std::result::Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > RATE_LIMIT_PRUNE_THRESHOLD {
+            // 已补满的桶与新建的桶等价，可以丢弃
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.burst);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(TokenBucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rps))
+        }
+    }
+
+    fn refilled(&self, bucket: &TokenBucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.rps).min(self.burst)
+    }
+}
+
+/// 限流的调用方标识：携带有效 token 时为其 `sub`，否则（含未启用权限控制时）为客户端 IP
+fn rate_limit_key(state: &AppState, auth_header: Option<&str>, remote: IpAddr) -> String {
+    if state.auth_enabled
+        && let Some(auth_header) = auth_header
+        && let Ok(token) = JwtManager::extract_token(auth_header)
+        && let Ok(claims) = state.jwt_manager.verify_token(token)
+    {
+        return format!("sub:{}", claims.sub);
+    }
+    format!("ip:{}", remote)
+}
+
+/// 不受限流影响的路径
+const RATE_LIMIT_EXEMPT_PATHS: &[&str] = &["/health"];
+
+/// 限流中间件：超出调用方的令牌桶时返回 429 与 `Retry-After`（秒）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RateLimitMiddleware;
+
+#[async_trait::async_trait]
+impl MiddleWareHandler for RateLimitMiddleware {
+    async fn handle(&self, req: Request, next: &Next) -> Result<Response> {
+        let state = req.extensions().get::<AppState>().unwrap().clone();
+        let Some(limiter) = &state.rate_limiter else {
+            return next.call(req).await;
+        };
+        if RATE_LIMIT_EXEMPT_PATHS.contains(&req.uri().path()) {
+            return next.call(req).await;
+        }
+
+        let auth_header = req
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok());
+        let key = rate_limit_key(&state, auth_header, req.remote().ip());
+        match limiter.check(&key, Instant::now()) {
+            Ok(()) => next.call(req).await,
+            Err(wait) => {
+                let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+                tracing::debug!("Rate limited {}, retry after {}s", key, retry_after);
+                let mut res = Response::json(&serde_json::json!({
+                    "code": StatusCode::TOO_MANY_REQUESTS.as_u16(),
+                    "msg": "Too many requests",
+                }));
+                res.set_status(StatusCode::TOO_MANY_REQUESTS);
+                res.headers_mut().insert(
+                    silent::header::RETRY_AFTER,
+                    silent::header::HeaderValue::from(retry_after),
+                );
+                Ok(res)
+            }
+        }
+    }
+}
+
+/// 预检响应允许的方法
+const CORS_ALLOW_METHODS: &str = "GET, POST, PUT, DELETE, OPTIONS";
+
+/// 预检响应允许的请求头
+const CORS_ALLOW_HEADERS: &str =
+    "Authorization, Content-Type, Accept, Idempotency-Key, X-Durability";
+
+/// 浏览器缓存预检结果的时间（秒）
+const CORS_MAX_AGE_SECS: u64 = 600;
+
+/// 跨域访问配置
+#[derive(Debug, Clone, Default)]
+pub struct CorsConfig {
+    origins: Vec<String>,
+}
+
+impl CorsConfig {
+    pub fn new(origins: Vec<String>) -> Self {
+        Self { origins }
+    }
+
+    /// 请求来源允许跨域时返回 `Access-Control-Allow-Origin` 的取值
+    fn allow_origin<'a>(&'a self, origin: &'a str) -> Option<&'a str> {
+        if self.origins.iter().any(|allowed| allowed == "*") {
+            Some("*")
+        } else {
+            self.origins
+                .iter()
+                .any(|allowed| allowed == origin)
+                .then_some(origin)
+        }
+    }
+
+    /// 需要添加到响应中的跨域头，预检请求额外包含允许的方法、请求头与缓存时间
+    ///
+    /// 未携带 `Origin` 或来源不在允许列表中时返回空列表。
+    pub fn headers(&self, origin: Option<&str>, preflight: bool) -> Vec<(&'static str, String)> {
+        let Some(allow_origin) = origin.and_then(|origin| self.allow_origin(origin)) else {
+            return Vec::new();
+        };
+        let mut headers = vec![("Access-Control-Allow-Origin", allow_origin.to_string())];
+        if allow_origin != "*" {
+            headers.push(("Vary", "Origin".to_string()));
+        }
+        if preflight {
+            headers.push((
+                "Access-Control-Allow-Methods",
+                CORS_ALLOW_METHODS.to_string(),
+            ));
+            headers.push((
+                "Access-Control-Allow-Headers",
+                CORS_ALLOW_HEADERS.to_string(),
+            ));
+            headers.push(("Access-Control-Max-Age", CORS_MAX_AGE_SECS.to_string()));
+        }
+        headers
+    }
+}
+
+/// 跨域中间件：直接应答 `OPTIONS` 预检请求，并为允许的来源添加 `Access-Control-Allow-Origin`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CorsMiddleware;
+
+#[async_trait::async_trait]
+impl MiddleWareHandler for CorsMiddleware {
+    async fn handle(&self, req: Request, next: &Next) -> Result<Response> {
+        let state = req.extensions().get::<AppState>().unwrap().clone();
+        let Some(cors) = &state.cors else {
+            return next.call(req).await;
+        };
+
+        let origin = req
+            .headers()
+            .get("Origin")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let preflight = req.method() == Method::OPTIONS
+            && req.headers().contains_key("Access-Control-Request-Method");
+        let mut res = if preflight {
+            let mut res = Response::empty();
+            res.set_status(StatusCode::NO_CONTENT);
+            res
+        } else {
+            next.call(req).await?
+        };
+
+        for (name, value) in cors.headers(origin.as_deref(), preflight) {
+            if let Ok(value) = silent::header::HeaderValue::from_str(&value) {
+                res.headers_mut().append(name, value);
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// 将状态变更事件转发给订阅方，直到订阅方断开（`send` 返回 false）或广播通道关闭
+///
+/// 订阅方消费过慢、事件在广播通道中被覆盖时，发送一条重新同步提示并继续转发后续事件，
+/// 而不是静默断开连接。
+pub async fn forward_change_events(
+    mut events: broadcast::Receiver<ChangeEvent>,
+    mut send: impl FnMut(SubscribeMessage) -> bool,
+) {
+    loop {
+        let message = match events.recv().await {
+            Ok(event) => SubscribeMessage::Change(event),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!(
+                    "Subscriber lagged behind, {} change events dropped",
+                    skipped
+                );
+                SubscribeMessage::ResyncRecommended {
+                    skipped,
+                    message: "Change events were dropped, resync recommended".to_string(),
+                }
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        if !send(message) {
+            return;
+        }
+    }
+}
+
+/// GET /subscribe - 以 WebSocket 推送状态变更
+///
+/// 连接建立后先发送携带当前状态哈希的 hello 消息，之后每次 `/sync` 或 `/merge` 修改状态时
+/// 推送变更的键与新的状态哈希（含所有文档，以 `doc_id` 区分）。
+fn subscribe_route(app_state: AppState) -> Route {
+    let handler =
+        WebSocketHandler::new().on_connect(move |_parts, sender: UnboundedSender<Message>| {
+            let state = app_state.clone();
+            async move {
+                // 先订阅再读取状态哈希，避免遗漏两者之间发生的变更
+                let events = state.change_events.subscribe();
+                let state_hash = state.sync_state.read().await.state_hash();
+                let send = move |message: SubscribeMessage| {
+                    serde_json::to_string(&message)
+                        .is_ok_and(|json| sender.send(Message::text(json)).is_ok())
+                };
+                if send(SubscribeMessage::Hello { state_hash }) {
+                    tokio::spawn(forward_change_events(events, send));
+                }
+                Ok::<(), SilentError>(())
+            }
+        });
+
+    Route::new("subscribe")
+        .hook(AuthMiddleware::new(Role::Reader))
+        .ws(None, handler)
+}
+
+/// 将文档的状态变更事件转换为 SSE 消息（忽略其他文档的事件）
+fn state_change_stream(
+    events: broadcast::Receiver<ChangeEvent>,
+    doc_id: String,
+) -> impl Stream<Item = SseMessage> {
+    BroadcastStream::new(events).filter_map(move |event| match event {
+        Ok(event) if event.doc_id == doc_id => Some(SseMessage::Change(StateChange {
+            changed_keys: event.keys,
+            state_hash: event.state_hash,
+            ts: chrono::Utc::now().timestamp_millis(),
+            id: event.last_op_id,
+        })),
+        Ok(_) => None,
+        Err(BroadcastStreamRecvError::Lagged(skipped)) => Some(SseMessage::Resync { skipped }),
+    })
+}
+
+/// 客户端重连时需要补发的变更：`last_event_id` 之后的操作涉及的键
+///
+/// 该 ID 不在操作日志中（如已被压缩）时视为所有键都已变化；之后没有新操作时返回 `None`。
+fn changes_since(
+    sync_state: &SyncState,
+    last_event_id: &str,
+    state_hash: String,
+) -> Option<StateChange> {
+    let ops = &sync_state.op_log.ops;
+    let mut changed_keys: Vec<String> = match ops.iter().position(|e| e.id == last_event_id) {
+        Some(position) => ops[position + 1..]
+            .iter()
+            .flat_map(|entry| entry.op.keys())
+            .map(str::to_string)
+            .collect(),
+        None => sync_state.crdt_map.entries.keys().cloned().collect(),
+    };
+    if changed_keys.is_empty() {
+        return None;
+    }
+    changed_keys.sort();
+    changed_keys.dedup();
+    Some(StateChange {
+        changed_keys,
+        state_hash,
+        ts: chrono::Utc::now().timestamp_millis(),
+        id: ops.last().map(|entry| entry.id.clone()),
+    })
+}
+
+impl SseMessage {
+    fn into_sse_event(self) -> SSEEvent {
+        match self {
+            SseMessage::Change(change) => {
+                let event =
+                    SSEEvent::default().data(serde_json::to_string(&change).unwrap_or_default());
+                match change.id {
+                    Some(id) => event.id(id),
+                    None => event,
+                }
+            }
+            SseMessage::Resync { skipped } => SSEEvent::default()
+                .event("resync")
+                .data(serde_json::json!({ "skipped": skipped }).to_string()),
+        }
+    }
+}
+
+/// GET /events - 以 Server-Sent Events 推送状态变更
+///
+/// 每次 `/sync` 或 `/merge` 修改文档时推送 `{changed_keys, state_hash, ts}`，事件 ID 为变更后的
+/// 最后一条操作 ID；重连时携带 `Last-Event-ID` 会先补发该操作之后的变更。
+async fn events_handler(req: Request) -> Result<Response> {
+    let state = req.extensions().get::<AppState>().unwrap().clone();
+    let doc_id = request_document_id(&req)?;
+    let document = read_document(&req, &state).await?;
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    // 先订阅再读取状态，避免遗漏两者之间发生的变更
+    let events = state.change_events.subscribe();
+    let catch_up = match last_event_id {
+        Some(last_event_id) => {
+            let sync_state = document.read().await;
+            let state_hash = state.timed_state_hash(&sync_state);
+            changes_since(&sync_state, &last_event_id, state_hash)
+        }
+        None => None,
+    };
+
+    let stream = tokio_stream::iter(catch_up.map(SseMessage::Change))
+        .chain(state_change_stream(events, doc_id))
+        .map(|message| Ok::<_, Infallible>(message.into_sse_event()));
+    Ok(sse_reply(
+        KeepAlive::new()
+            .interval(Duration::from_secs(SSE_KEEP_ALIVE_SECS))
+            .stream(stream),
+    ))
+}
+
+/// 挂载作用于单个文档的路由（根路径下作用于默认文档，`/doc/<doc_id>` 下作用于命名文档）
+fn document_routes(route: Route) -> Route {
+    route
+        // 需要 Writer 权限的路由
+        .append(
+            Route::new("sync")
+                .hook(AuthMiddleware::new(Role::Writer))
+                .post(sync_handler)
+                .append(Route::new("status").get(sync_status_handler)),
+        )
+        .append(
+            Route::new("sync-peer")
+                .hook(AuthMiddleware::new(Role::Writer))
+                .post(sync_peer_handler),
+        )
+        .append(
+            Route::new("merge")
+                .hook(AuthMiddleware::new(Role::Writer))
+                .post(merge_handler),
+        )
+        // 需要 Reader 权限的路由
+        .append(
+            Route::new("state")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .get(get_state_handler)
+                .append(Route::new("as-of").get(get_state_as_of_handler)),
+        )
+        .append(
+            Route::new("keys")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .get(get_keys_handler),
+        )
+        .append(
+            Route::new("key/<key>")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .get(get_key_handler),
+        )
+        .append(
+            Route::new("replication-status")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .get(replication_status_handler),
+        )
+        .append(
+            Route::new("stats")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .get(get_stats_handler),
+        )
+        .append(
+            Route::new("document")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .get(get_document_handler),
+        )
+        .append(
+            Route::new("frontier")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .get(get_frontier_handler),
+        )
+        .append(
+            Route::new("plan-sync")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .post(plan_sync_handler),
+        )
+        .append(
+            Route::new("merkle-root")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .get(merkle_root_handler),
+        )
+        .append(
+            Route::new("diff")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .post(merkle_diff_handler),
+        )
+        .append(
+            Route::new("sync-delta")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .post(sync_delta_handler),
+        )
+        .append(
+            Route::new("state-hash")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .get(get_state_hash_handler),
+        )
+        .append(
+            Route::new("oplog")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .get(get_oplog_handler),
+        )
+        .append(
             Route::new("history")
                 .hook(AuthMiddleware::new(Role::Reader))
                 .get(get_history_handler),
         )
+        .append(
+            Route::new("events")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .get(events_handler),
+        )
         .append(
             Route::new("conflicts")
                 .hook(AuthMiddleware::new(Role::Reader))
-                .get(get_conflicts_handler),
+                .get(get_conflicts_handler)
+                .append(Route::new("stats").get(get_conflict_stats_handler)),
+        )
+}
+
+/// 构建 API 路由
+pub fn build_routes(app_state: AppState) -> Route {
+    let root = Route::new_root()
+        .hook(app_state.clone())
+        .hook(RequestTelemetry)
+        .hook(CorsMiddleware)
+        .hook(RateLimitMiddleware)
+        // 认证相关路由（无需权限）
+        .append(Route::new("auth/token").post(generate_token_handler))
+        .append(Route::new("auth/public-key").get(get_public_key_handler))
+        .append(
+            Route::new("auth/revoke")
+                .hook(AuthMiddleware::new(Role::Admin))
+                .post(revoke_token_handler),
+        );
+
+    document_routes(root)
+        // 命名文档：首次写入时创建，读取未知文档返回 404
+        .append(document_routes(Route::new("doc/<doc_id>")))
+        // 需要 Writer 权限的路由
+        .append(
+            Route::new("sign")
+                .hook(AuthMiddleware::new(Role::Writer))
+                .post(sign_handler),
+        )
+        // 需要 Admin 权限的路由
+        .append(
+            Route::new("convert")
+                .hook(AuthMiddleware::new(Role::Admin))
+                .post(convert_handler),
+        )
+        .append(
+            Route::new("import")
+                .hook(AuthMiddleware::new(Role::Admin))
+                .post(import_bundle_handler),
+        )
+        .append(
+            Route::new("admin")
+                .hook(AuthMiddleware::new(Role::Admin))
+                .append(Route::new("reset").post(reset_handler))
+                .append(Route::new("force-pull").post(force_pull_handler))
+                .append(Route::new("merge-file").post(merge_file_handler))
+                .append(Route::new("import").post(import_handler))
+                .append(Route::new("forget-node").post(forget_node_handler))
+                .append(Route::new("rotate-key").post(rotate_key_handler))
+                .append(Route::new("peer-keys").post(trust_peer_handler))
+                .append(Route::new("self-test").post(self_test_handler))
+                .append(Route::new("recover").post(recover_handler))
+                .append(
+                    Route::new("repro")
+                        .get(export_repro_handler)
+                        .post(replay_repro_handler),
+                )
+                .append(Route::new("compact").post(compact_handler))
+                .append(Route::new("counter/<key>/compact").post(compact_counter_handler))
+                .append(Route::new("consistent-snapshot").get(consistent_snapshot_handler))
+                .append(
+                    Route::new("schema")
+                        .get(get_schemas_handler)
+                        .post(register_schema_handler),
+                )
+                .append(
+                    Route::new("policy")
+                        .get(get_policies_handler)
+                        .post(set_policy_handler),
+                ),
+        )
+        // 需要 Reader 权限的路由
+        .append(subscribe_route(app_state))
+        .append(
+            Route::new("schema")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .append(Route::new("operations").get(list_operations_handler)),
+        )
+        .append(
+            Route::new("snapshots")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .get(list_snapshots_handler)
+                .append(
+                    Route::new("<version>/restore")
+                        .hook(AuthMiddleware::new(Role::Admin))
+                        .post(restore_snapshot_handler),
+                )
+                .append(Route::new("diff").get(snapshot_diff_handler)),
+        )
+        .append(
+            Route::new("metrics")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .get(metrics_handler),
+        )
+        .append(
+            Route::new("export")
+                .hook(AuthMiddleware::new(Role::Reader))
+                .get(export_handler),
         )
         // 健康检查（无需权限）
         .append(Route::new("health").get(health_handler))
         // 静态文件服务（无需权限）
         .with_static("./static")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use http_body_util::Full;
+
+    #[tokio::test]
+    async fn test_read_body_limited() {
+        let body = Full::new(Bytes::from(vec![b'a'; 100]));
+        let bytes = read_body_limited(body, 100).await.unwrap();
+        assert_eq!(bytes.len(), 100);
+
+        let body = Full::new(Bytes::from(vec![b'a'; 101]));
+        let result = read_body_limited(body, 100).await;
+        assert!(matches!(result, Err(BodyError::TooLarge { limit: 100 })));
+    }
+
+    #[tokio::test]
+    async fn test_peer_client_reuses_connections() -> anyhow::Result<()> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // 最小的 HTTP/1.1 keep-alive 服务端，统计建立的连接数
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 4096];
+                    let mut pending = Vec::new();
+                    loop {
+                        let n = match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => n,
+                        };
+                        pending.extend_from_slice(&buf[..n]);
+                        while let Some(end) = pending.windows(4).position(|w| w == b"\r\n\r\n") {
+                            pending.drain(..end + 4);
+                            let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n{}";
+                            if socket.write_all(response.as_bytes()).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        let client = PeerClientConfig::default().build()?;
+        for _ in 0..5 {
+            let response = client.get(format!("http://{}/state", addr)).send().await?;
+            assert!(response.status().is_success());
+            response.bytes().await?;
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    /// 启动只返回一次固定响应体的 HTTP 服务端，返回其地址
+    async fn serve_body_once(body: Vec<u8>) -> anyhow::Result<std::net::SocketAddr> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            if let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = vec![0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = socket.write_all(head.as_bytes()).await;
+                let _ = socket.write_all(&body).await;
+            }
+        });
+        Ok(addr)
+    }
+
+    #[tokio::test]
+    async fn test_read_peer_state_enforces_limits() -> anyhow::Result<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let state = AppState::new("node1".to_string(), storage, "secret".to_string(), false)?;
+
+        let mut peer = SyncState::new("node2".to_string());
+        peer.apply_changes(ChangeRequest {
+            changes: vec![crate::sync::Change {
+                op: "set".to_string(),
+                key: "title".to_string(),
+                value: Some("hello".to_string()),
+                ..Default::default()
+            }],
+        })?;
+        let body = serde_json::to_vec(&peer)?;
+
+        // 正常状态可被读取
+        let addr = serve_body_once(body.clone()).await?;
+        let response = state
+            .peer_client
+            .get(format!("http://{}/state", addr))
+            .send()
+            .await?;
+        let pulled = read_peer_state(response, &state).await.unwrap();
+        assert_eq!(pulled.state_hash(), peer.state_hash());
+
+        // 超过请求体上限的状态被拒绝
+        let small = state.clone().with_max_body_bytes(body.len() - 1);
+        let addr = serve_body_once(body).await?;
+        let response = small
+            .peer_client
+            .get(format!("http://{}/state", addr))
+            .send()
+            .await?;
+        let err = read_peer_state(response, &small).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_GATEWAY);
+
+        // 嵌套过深的状态被拒绝
+        let nested = format!("{}{}", "[".repeat(10_000), "]".repeat(10_000));
+        let addr = serve_body_once(nested.into_bytes()).await?;
+        let response = state
+            .peer_client
+            .get(format!("http://{}/state", addr))
+            .send()
+            .await?;
+        let err = read_peer_state(response, &state).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_GATEWAY);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_guarded_panic_recovers_state() -> anyhow::Result<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let state = AppState::new("node1".to_string(), storage, "secret".to_string(), false)?;
+        let change = |key: &str| ChangeRequest {
+            changes: vec![crate::sync::Change {
+                op: "increment".to_string(),
+                key: key.to_string(),
+                ..Default::default()
+            }],
+        };
+
+        let mut sync_state = state.sync_state.write().await;
+        sync_state.apply_changes(change("a")).unwrap();
+        state.storage.save_state("node1", &sync_state)?;
+        let persisted_hash = sync_state.state_hash();
+
+        // 修改到一半时 panic：返回错误并回到最近持久化的状态
+        let result = state.guarded(&mut sync_state, |s| {
+            s.apply_changes(change("b")).unwrap();
+            panic!("simulated panic");
+        });
+        assert!(result.is_err());
+        assert!(!state.needs_recovery.load(Ordering::SeqCst));
+        assert_eq!(sync_state.state_hash(), persisted_hash);
+        drop(sync_state);
+
+        // 写锁可以继续获取，后续写入正常
+        let mut sync_state = state.sync_state.write().await;
+        state
+            .guarded(&mut sync_state, |s| s.apply_changes(change("c")))?
+            .unwrap();
+        assert!(sync_state.crdt_map.get("c").is_some());
+        assert!(sync_state.crdt_map.get("b").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rebuild_from_oplog_without_persisted_state() -> anyhow::Result<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let state = AppState::new("node1".to_string(), storage, "secret".to_string(), false)?;
+
+        let mut sync_state = SyncState::new("node1".to_string());
+        sync_state
+            .apply_changes(ChangeRequest {
+                changes: vec![crate::sync::Change {
+                    op: "set".to_string(),
+                    key: "name".to_string(),
+                    value: Some("alice".to_string()),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        let hash = sync_state.state_hash();
+        // 模拟被破坏的内存状态
+        sync_state.crdt_map.entries.clear();
+
+        let report = state.recover(&mut sync_state)?;
+        assert_eq!(report.source, "oplog rebuild");
+        assert_eq!(report.ops_replayed, 1);
+        assert_eq!(sync_state.state_hash(), hash);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_metrics_track_operations_and_state_size() -> anyhow::Result<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let state = AppState::new("node1".to_string(), storage, "secret".to_string(), false)?;
+        let scrape = |body: &str, metric: &str| -> u64 {
+            body.lines()
+                .find_map(|line| line.strip_prefix(metric)?.trim().parse().ok())
+                .unwrap_or_else(|| panic!("metric {} missing", metric))
+        };
+
+        let before = state.render_metrics().await?;
+        assert_eq!(
+            scrape(&before, "crdt_ops_applied_total{op=\"increment\"}"),
+            0
+        );
+        assert_eq!(scrape(&before, "crdt_keys{document=\"default\"}"), 0);
+
+        let request = ChangeRequest {
+            changes: vec![
+                crate::sync::Change {
+                    op: "increment".to_string(),
+                    key: "counter".to_string(),
+                    ..Default::default()
+                },
+                crate::sync::Change {
+                    op: "set".to_string(),
+                    key: "name".to_string(),
+                    value: Some("alice".to_string()),
+                    ..Default::default()
+                },
+            ],
+        };
+        state
+            .sync_state
+            .write()
+            .await
+            .apply_changes(request.clone())
+            .unwrap();
+        state
+            .metrics
+            .record_ops(request.changes.iter().map(|change| change.op.as_str()));
+        state.metrics.record_merge();
+        state.metrics.record_sync_peer(true);
+        state.metrics.record_sync_peer(false);
+
+        let after = state.render_metrics().await?;
+        assert!(after.contains("# TYPE crdt_ops_applied_total counter"));
+        assert_eq!(
+            scrape(&after, "crdt_ops_applied_total{op=\"increment\"}"),
+            1
+        );
+        assert_eq!(scrape(&after, "crdt_ops_applied_total{op=\"set\"}"), 1);
+        assert_eq!(scrape(&after, "crdt_merges_received_total"), 1);
+        assert_eq!(scrape(&after, "crdt_sync_peer_attempts_total"), 2);
+        assert_eq!(scrape(&after, "crdt_sync_peer_failures_total"), 1);
+        assert_eq!(scrape(&after, "crdt_keys{document=\"default\"}"), 2);
+        assert_eq!(scrape(&after, "crdt_oplog_length{document=\"default\"}"), 2);
+        assert!(
+            scrape(&after, "crdt_state_bytes{document=\"default\"}")
+                > scrape(&before, "crdt_state_bytes{document=\"default\"}")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_signing_key_stable_across_restarts() -> anyhow::Result<()> {
+        let storage: Arc<dyn StorageBackend> = Arc::new(MemoryStorage::new());
+        let open = || {
+            AppState::new(
+                "node1".to_string(),
+                storage.clone(),
+                "secret".to_string(),
+                false,
+            )
+        };
+
+        let public_key = open()?.signature_manager.public_key_base64();
+        assert_eq!(open()?.signature_manager.public_key_base64(), public_key);
+
+        // 密钥文件优先于存储中的密钥，且同样在重启后保持不变
+        let dir = tempfile::tempdir()?;
+        let key_path = dir.path().join("node.key");
+        let from_file = open()?
+            .with_key_file(&key_path)?
+            .signature_manager
+            .public_key_base64();
+        assert_ne!(from_file, public_key);
+        assert_eq!(
+            open()?
+                .with_key_file(&key_path)?
+                .signature_manager
+                .public_key_base64(),
+            from_file
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_merge_signature_verification() -> anyhow::Result<()> {
+        let open = |node_id: &str| {
+            AppState::new(
+                node_id.to_string(),
+                Arc::new(MemoryStorage::new()),
+                "secret".to_string(),
+                false,
+            )
+        };
+        let node1 = open("node1")?;
+        let change = ChangeRequest {
+            changes: vec![crate::sync::Change {
+                op: "increment".to_string(),
+                key: "hits".to_string(),
+                ..Default::default()
+            }],
+        };
+        node1
+            .sync_state
+            .write()
+            .await
+            .apply_changes(change)
+            .unwrap();
+        let signed = node1.sync_state.read().await.clone();
+        assert!(signed.op_log.ops[0].signature.is_some());
+
+        let node2 = open("node2")?;
+        let trust = &node2.signature_manager;
+
+        // 未登记 node1 的公钥：拒绝
+        let err = verify_incoming_signatures(&signed, trust, false).unwrap_err();
+        assert!(err.contains("No trusted public key"));
+
+        // 登记后有效签名无论是否要求签名都接受
+        trust.trust_peer_keys(node1.signature_manager.public_keys())?;
+        assert!(verify_incoming_signatures(&signed, trust, false).is_ok());
+        assert!(verify_incoming_signatures(&signed, trust, true).is_ok());
+
+        // 以其他密钥重新签名并冒充 node1：公钥与登记的不符
+        let mut forged = signed.clone();
+        let impostor = SignatureManager::new("node1".to_string());
+        let op = forged.op_log.ops[0].signed_operation().unwrap();
+        let resigned = impostor.sign_operation(
+            op.id,
+            op.timestamp,
+            op.operation_type,
+            op.operation_data,
+            op.causal_context,
+        )?;
+        forged.op_log.ops[0].signature = Some(resigned.signature);
+        forged.op_log.ops[0].public_key = Some(resigned.public_key);
+        let err = verify_incoming_signatures(&forged, trust, false).unwrap_err();
+        assert!(err.contains("not trusted"));
+
+        // 篡改操作内容：拒绝
+        let mut tampered = signed.clone();
+        if let crate::sync::Operation::PNCounterIncrement { delta, .. } =
+            &mut tampered.op_log.ops[0].op
+        {
+            *delta = 1000;
+        }
+        let err = verify_incoming_signatures(&tampered, trust, false).unwrap_err();
+        assert!(err.contains(&tampered.op_log.ops[0].id));
+
+        // 未签名的旧条目：仅在要求签名时拒绝
+        let mut legacy = signed.clone();
+        legacy.op_log.ops[0].signature = None;
+        legacy.op_log.ops[0].public_key = None;
+        assert!(verify_incoming_signatures(&legacy, trust, false).is_ok());
+        assert!(verify_incoming_signatures(&legacy, trust, true).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rotated_key_and_retiring_keys_survive_restart() -> anyhow::Result<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let open = || {
+            AppState::new(
+                "node1".to_string(),
+                storage.clone(),
+                "secret".to_string(),
+                false,
+            )
+        };
+        let state = open()?;
+        let old_key = state.signature_manager.public_key_base64();
+        state.rotate_signing_key()?;
+        let new_key = state.signature_manager.public_key_base64();
+        assert_ne!(old_key, new_key);
+
+        // 重启后沿用新密钥，旧公钥仍在宽限期内受信任
+        let restarted = open()?;
+        assert_eq!(restarted.signature_manager.public_key_base64(), new_key);
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        assert!(
+            restarted
+                .signature_manager
+                .is_trusted_key_at("node1", &old_key, now_ms)
+        );
+        assert_eq!(restarted.signature_manager.retiring_keys().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_named_documents_created_on_first_write() -> anyhow::Result<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let state = AppState::new(
+            "node1".to_string(),
+            storage.clone(),
+            "secret".to_string(),
+            false,
+        )?
+        .with_max_keys(Some(1));
+        let set = |key: &str| ChangeRequest {
+            changes: vec![crate::sync::Change {
+                op: "set".to_string(),
+                key: key.to_string(),
+                value: Some("v".to_string()),
+                ..Default::default()
+            }],
+        };
+
+        // 读取未知文档不会创建文档
+        assert!(state.document("notes").await?.is_none());
+        assert!(state.document("notes").await?.is_none());
+
+        let notes = state.document_or_create("notes").await?;
+        {
+            let mut doc = notes.write().await;
+            doc.apply_changes(set("a")).unwrap();
+            // 新文档继承默认文档的本地配置
+            assert!(doc.apply_changes(set("b")).is_err());
+            state
+                .storage
+                .save_document_state("node1", "notes", &doc, Durability::Flush)?;
+        }
+        assert!(state.sync_state.read().await.crdt_map.get("a").is_none());
+        let notes_hash = notes.read().await.state_hash();
+
+        // 重启后按需从存储加载
+        let restarted = AppState::new("node1".to_string(), storage, "secret".to_string(), false)?;
+        let loaded = restarted.document("notes").await?.unwrap();
+        assert_eq!(loaded.read().await.state_hash(), notes_hash);
+        let ids: Vec<String> = restarted
+            .documents()
+            .await?
+            .into_iter()
+            .map(|(doc_id, _)| doc_id)
+            .collect();
+        assert_eq!(ids, vec![DEFAULT_DOCUMENT.to_string(), "notes".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_document_id_validation() {
+        assert!(validate_document_id("notes-2024_v1.json").is_ok());
+        assert!(validate_document_id("").is_err());
+        assert!(validate_document_id("a:b").is_err());
+        assert!(validate_document_id("a/b").is_err());
+        assert!(validate_document_id(&"x".repeat(MAX_DOCUMENT_ID_LEN + 1)).is_err());
+
+        assert_eq!(document_path(DEFAULT_DOCUMENT, "merge"), "/merge");
+        assert_eq!(document_path("notes", "merge"), "/doc/notes/merge");
+    }
+
+    #[tokio::test]
+    async fn test_forward_change_events_recommends_resync_on_lag() {
+        let (sender, receiver) = broadcast::channel(2);
+        let event = |n: usize| ChangeEvent {
+            doc_id: DEFAULT_DOCUMENT.to_string(),
+            source: "sync",
+            keys: vec![format!("k{}", n)],
+            state_hash: format!("hash{}", n),
+            last_op_id: None,
+        };
+        for n in 0..4 {
+            sender.send(event(n)).unwrap();
+        }
+        drop(sender);
+
+        let mut received = Vec::new();
+        forward_change_events(receiver, |message| {
+            received.push(message);
+            true
+        })
+        .await;
+
+        // 最早的两个事件被覆盖：先提示重新同步，再继续推送其余事件，通道关闭后结束
+        assert_eq!(
+            received,
+            vec![
+                SubscribeMessage::ResyncRecommended {
+                    skipped: 2,
+                    message: "Change events were dropped, resync recommended".to_string(),
+                },
+                SubscribeMessage::Change(event(2)),
+                SubscribeMessage::Change(event(3)),
+            ]
+        );
+        let json = serde_json::to_value(&received[1]).unwrap();
+        assert_eq!(json["type"], "change");
+        assert_eq!(json["state_hash"], "hash2");
+
+        // 订阅方断开后停止转发
+        let (sender, receiver) = broadcast::channel(2);
+        sender.send(event(0)).unwrap();
+        sender.send(event(1)).unwrap();
+        let mut sent = 0;
+        forward_change_events(receiver, |_| {
+            sent += 1;
+            false
+        })
+        .await;
+        assert_eq!(sent, 1);
+    }
+
+    #[tokio::test]
+    async fn test_events_stream_reports_changed_keys() -> anyhow::Result<()> {
+        let state = AppState::new(
+            "node1".to_string(),
+            Arc::new(MemoryStorage::new()),
+            "secret".to_string(),
+            false,
+        )?;
+        let mut stream = Box::pin(state_change_stream(
+            state.change_events.subscribe(),
+            DEFAULT_DOCUMENT.to_string(),
+        ));
+
+        let (state_hash, last_op_id) = {
+            let mut sync_state = state.sync_state.write().await;
+            sync_state.apply_changes(ChangeRequest {
+                changes: vec![crate::sync::Change {
+                    op: "increment".to_string(),
+                    key: "visits".to_string(),
+                    ..Default::default()
+                }],
+            })?;
+            let last_op_id = sync_state.op_log.ops.last().map(|e| e.id.clone());
+            (sync_state.state_hash(), last_op_id)
+        };
+        // 其他文档的事件不会推送
+        state.publish_change("notes", "sync", vec!["title".to_string()], "h", None);
+        state.publish_change(
+            DEFAULT_DOCUMENT,
+            "sync",
+            vec!["visits".to_string()],
+            &state_hash,
+            last_op_id.clone(),
+        );
+
+        let Some(SseMessage::Change(change)) = stream.next().await else {
+            panic!("expected a change event");
+        };
+        assert_eq!(change.changed_keys, vec!["visits".to_string()]);
+        assert_eq!(change.state_hash, state_hash);
+        assert_eq!(change.id, last_op_id);
+        let json = serde_json::to_value(&change)?;
+        assert_eq!(json["changed_keys"], serde_json::json!(["visits"]));
+        assert!(json["ts"].is_i64());
+        Ok(())
+    }
+
+    #[test]
+    fn test_changes_since_resumes_after_last_event_id() {
+        let mut sync_state = SyncState::new("node1".to_string());
+        let change = |key: &str| ChangeRequest {
+            changes: vec![crate::sync::Change {
+                op: "increment".to_string(),
+                key: key.to_string(),
+                ..Default::default()
+            }],
+        };
+        sync_state.apply_changes(change("a")).unwrap();
+        let seen = sync_state.op_log.ops.last().unwrap().id.clone();
+        assert!(changes_since(&sync_state, &seen, "h".to_string()).is_none());
+
+        sync_state.apply_changes(change("c")).unwrap();
+        sync_state.apply_changes(change("b")).unwrap();
+        let resumed = changes_since(&sync_state, &seen, "h".to_string()).unwrap();
+        assert_eq!(resumed.changed_keys, vec!["b".to_string(), "c".to_string()]);
+        assert_eq!(
+            resumed.id.as_ref(),
+            sync_state.op_log.ops.last().map(|e| &e.id)
+        );
+
+        // 未知的事件 ID 视为所有键都已变化
+        let unknown = changes_since(&sync_state, "missing", "h".to_string()).unwrap();
+        assert_eq!(unknown.changed_keys.len(), 3);
+    }
+
+    #[test]
+    fn test_sign_operation_request() {
+        let manager = SignatureManager::new("node1".to_string());
+        let sign_req: SignRequest = serde_json::from_str(
+            r#"{"operation_type":"LWWRegister.Set","operation_data":"name=alice"}"#,
+        )
+        .unwrap();
+
+        let signed = sign_operation_request(&manager, sign_req).unwrap();
+        assert_eq!(signed.node_id, "node1");
+        assert_eq!(signed.public_key, manager.public_key_base64());
+
+        // 经过 JSON 往返后仍可验证
+        let json = serde_json::to_string(&signed).unwrap();
+        let mut received: SignedOperation = serde_json::from_str(&json).unwrap();
+        assert!(received.verify().is_ok());
+
+        // 篡改后验证失败
+        received.operation_data = "name=mallory".to_string();
+        assert!(received.verify().is_err());
+    }
+
+    fn sync_response(state_hash: &str) -> IdempotentResponse {
+        IdempotentResponse::Applied(SyncResponse {
+            success: true,
+            state_hash: state_hash.to_string(),
+            message: "Changes applied successfully".to_string(),
+        })
+    }
+
+    #[test]
+    fn test_idempotency_cache_returns_prior_response() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60), 10);
+        let now = Instant::now();
+        assert!(cache.get("default/retry-1", now).is_none());
+
+        cache.insert("default/retry-1".to_string(), sync_response("h1"), now);
+        let Some(IdempotentResponse::Applied(cached)) = cache.get("default/retry-1", now) else {
+            panic!("expected cached applied response");
+        };
+        assert_eq!(cached.state_hash, "h1");
+        // 不同的键不受影响
+        assert!(cache.get("default/retry-2", now).is_none());
+
+        // 超过保留时间后视为未见过
+        assert!(
+            cache
+                .get("default/retry-1", now + Duration::from_secs(61))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_idempotency_cache_evicts_least_recently_used() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60), 2);
+        let now = Instant::now();
+        cache.insert("a".to_string(), sync_response("ha"), now);
+        cache.insert("b".to_string(), sync_response("hb"), now);
+        // 访问 a 后 b 成为最久未使用的键
+        assert!(cache.get("a", now).is_some());
+        cache.insert("c".to_string(), sync_response("hc"), now);
+
+        assert!(cache.get("a", now).is_some());
+        assert!(cache.get("b", now).is_none());
+        assert!(cache.get("c", now).is_some());
+    }
+
+    #[test]
+    fn test_idempotency_cache_enqueues_batched_retry_once() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60), 10);
+        let batcher = ApplyBatcher::new();
+        let now = Instant::now();
+        let accept = || {
+            let ticket = batcher.enqueue(ChangeRequest {
+                changes: vec![crate::sync::Change {
+                    op: "increment".to_string(),
+                    key: "hits".to_string(),
+                    ..Default::default()
+                }],
+            });
+            IdempotentResponse::Accepted(AcceptedResponse {
+                success: true,
+                status: "accepted".to_string(),
+                ticket,
+                message: "Changes accepted for batched apply".to_string(),
+            })
+        };
+
+        let (first, fresh) = cache.get_or_insert_with("default/retry-1", now, accept);
+        assert!(fresh);
+        let (retry, fresh) = cache.get_or_insert_with("default/retry-1", now, accept);
+        assert!(!fresh);
+        // 重试返回首次受理的票据，变更只入队一次
+        let (IdempotentResponse::Accepted(first), IdempotentResponse::Accepted(retry)) =
+            (first, retry)
+        else {
+            panic!("expected accepted responses");
+        };
+        assert_eq!(retry.ticket, first.ticket);
+        assert_eq!(batcher.pending_len(), 1);
+
+        // 直接应用路径的重试同样命中受理响应
+        assert!(matches!(
+            cache.get("default/retry-1", now),
+            Some(IdempotentResponse::Accepted(_))
+        ));
+    }
+
+    #[test]
+    fn test_cors_preflight_headers() {
+        let cors = CorsConfig::new(vec!["http://localhost:5173".to_string()]);
+        let headers: HashMap<_, _> = cors
+            .headers(Some("http://localhost:5173"), true)
+            .into_iter()
+            .collect();
+        assert_eq!(
+            headers
+                .get("Access-Control-Allow-Origin")
+                .map(String::as_str),
+            Some("http://localhost:5173")
+        );
+        assert_eq!(headers.get("Vary").map(String::as_str), Some("Origin"));
+        let methods = &headers["Access-Control-Allow-Methods"];
+        for method in ["POST", "PUT", "DELETE"] {
+            assert!(methods.contains(method));
+        }
+        let allowed = &headers["Access-Control-Allow-Headers"];
+        for header in ["Authorization", IDEMPOTENCY_HEADER, DURABILITY_HEADER] {
+            assert!(allowed.contains(header));
+        }
+
+        // 不在允许列表中的来源与未携带 Origin 的请求不添加跨域头
+        assert!(cors.headers(Some("http://evil.example"), true).is_empty());
+        assert!(cors.headers(None, true).is_empty());
+    }
+
+    #[test]
+    fn test_cors_simple_request_headers() {
+        let cors = CorsConfig::new(vec!["*".to_string()]);
+        // 普通 GET 只需 Allow-Origin，通配时不需要 Vary
+        assert_eq!(
+            cors.headers(Some("http://localhost:3000"), false),
+            vec![("Access-Control-Allow-Origin", "*".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_rate_limiter_rejects_burst_until_refilled() {
+        let limiter = RateLimiter::new(2.0, 3);
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check("ip:10.0.0.1", start).is_ok());
+        }
+        let wait = limiter.check("ip:10.0.0.1", start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+        // 其他调用方不受影响
+        assert!(limiter.check("ip:10.0.0.2", start).is_ok());
+
+        // 补充一个令牌后恰好放行一次
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.check("ip:10.0.0.1", later).is_ok());
+        assert!(limiter.check("ip:10.0.0.1", later).is_err());
+
+        // 长时间空闲后最多积累 burst 个令牌
+        let idle = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.check("ip:10.0.0.1", idle).is_ok());
+        }
+        assert!(limiter.check("ip:10.0.0.1", idle).is_err());
+    }
+
+    #[test]
+    fn test_rate_limit_key_uses_token_subject() -> anyhow::Result<()> {
+        let remote: IpAddr = "10.0.0.1".parse()?;
+        let state = AppState::new(
+            "node1".to_string(),
+            Arc::new(MemoryStorage::new()),
+            "secret".to_string(),
+            true,
+        )?;
+        let token = state
+            .jwt_manager
+            .generate_token("alice".to_string(), Role::Writer, 3600)?;
+        let header = format!("Bearer {}", token);
+
+        assert_eq!(rate_limit_key(&state, Some(&header), remote), "sub:alice");
+        // 缺少或无效 token 时按客户端 IP 限流
+        assert_eq!(rate_limit_key(&state, None, remote), "ip:10.0.0.1");
+        assert_eq!(
+            rate_limit_key(&state, Some("Bearer invalid"), remote),
+            "ip:10.0.0.1"
+        );
+
+        // 未启用权限控制时不解析 token
+        let state = AppState::new(
+            "node1".to_string(),
+            Arc::new(MemoryStorage::new()),
+            "secret".to_string(),
+            false,
+        )?;
+        assert_eq!(rate_limit_key(&state, Some(&header), remote), "ip:10.0.0.1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_role_fallback() -> anyhow::Result<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let state = AppState::new("node1".to_string(), storage, "secret".to_string(), true)?;
+
+        let read = AuthMiddleware::new(Role::Reader);
+        let write = AuthMiddleware::new(Role::Writer);
+
+        // 未配置默认角色时缺少 token 一律拒绝
+        assert!(read.authorize(&state, None).is_err());
+
+        // 默认角色为 reader：/state 等只读接口无需 token，写入仍需 Writer
+        let state = state.with_default_role(Some(Role::Reader));
+        assert!(read.authorize(&state, None).is_ok());
+        assert!(write.authorize(&state, None).is_err());
+
+        let token = state
+            .jwt_manager
+            .generate_token("node1".to_string(), Role::Writer, 3600)?;
+        let header = format!("Bearer {}", token);
+        assert!(write.authorize(&state, Some(&header)).is_ok());
+
+        // 携带无效 token 时不回退到默认角色
+        assert!(read.authorize(&state, Some("Bearer invalid")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_scoped_token_limits_sync_keys() -> anyhow::Result<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let state = AppState::new("node1".to_string(), storage, "secret".to_string(), true)?;
+        let token = state.jwt_manager.generate_scoped_token(
+            "node1".to_string(),
+            Role::Writer,
+            3600,
+            Some(vec!["profile".to_string(), "cart:*".to_string()]),
+        )?;
+        let header = format!("Bearer {}", token);
+        let (_, scope) = AuthMiddleware::new(Role::Writer).authorize(&state, Some(&header))?;
+
+        let request = |keys: &[&str]| ChangeRequest {
+            changes: keys
+                .iter()
+                .map(|key| crate::sync::Change {
+                    op: "increment".to_string(),
+                    key: key.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+        };
+        assert!(check_key_scope(Some(&scope), &request(&["profile", "cart:1"])).is_ok());
+
+        // 批次中任一键越权即整体拒绝
+        let err = check_key_scope(Some(&scope), &request(&["cart:1", "orders"])).unwrap_err();
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+
+        // 未限定键的 token 或关闭权限控制时不受影响
+        assert!(check_key_scope(Some(&KeyScope::default()), &request(&["orders"])).is_ok());
+        assert!(check_key_scope(None, &request(&["orders"])).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_view_projects_value_or_not_found() {
+        let mut sync_state = SyncState::new("node1".to_string());
+        sync_state
+            .apply_changes(ChangeRequest {
+                changes: vec![
+                    crate::sync::Change {
+                        op: "increment".to_string(),
+                        key: "visits".to_string(),
+                        delta: Some(7),
+                        ..Default::default()
+                    },
+                    crate::sync::Change {
+                        op: "set".to_string(),
+                        key: "title".to_string(),
+                        value: Some("foo".to_string()),
+                        ..Default::default()
+                    },
+                ],
+            })
+            .unwrap();
+
+        assert_eq!(
+            key_view(&sync_state, "visits", false).unwrap(),
+            serde_json::json!({"type": "pn-counter", "value": 7})
+        );
+        assert_eq!(
+            key_view(&sync_state, "title", false).unwrap(),
+            serde_json::json!({"type": "lww-register", "value": "foo"})
+        );
+        let err = key_view(&sync_state, "missing", false).unwrap_err();
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_change_errors_map_to_code_and_status() {
+        let mut sync_state = SyncState::new("node1".to_string());
+        let mut apply = |change: crate::sync::Change| {
+            sync_state
+                .apply_changes(ChangeRequest {
+                    changes: vec![change],
+                })
+                .err()
+        };
+        apply(crate::sync::Change {
+            op: "increment".to_string(),
+            key: "visits".to_string(),
+            ..Default::default()
+        });
+
+        let cases = [
+            (
+                crate::sync::Change {
+                    op: "rename".to_string(),
+                    key: "visits".to_string(),
+                    ..Default::default()
+                },
+                "unknown_op",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                crate::sync::Change {
+                    op: "add".to_string(),
+                    key: "tags".to_string(),
+                    ..Default::default()
+                },
+                "missing_value",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                crate::sync::Change {
+                    op: "set".to_string(),
+                    key: "visits".to_string(),
+                    value: Some("foo".to_string()),
+                    ..Default::default()
+                },
+                "type_mismatch",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                crate::sync::Change {
+                    op: "move".to_string(),
+                    value: Some("a".to_string()),
+                    from_key: Some("missing".to_string()),
+                    to_key: Some("tags".to_string()),
+                    ..Default::default()
+                },
+                "invalid_key",
+                StatusCode::NOT_FOUND,
+            ),
+        ];
+        for (change, code, status) in cases {
+            let error = apply(change).expect(code);
+            assert_eq!(error.code(), code);
+            assert_eq!(change_failed(error).status(), status, "{}", code);
+        }
+
+        let error = apply(crate::sync::Change {
+            op: "set".to_string(),
+            key: "visits".to_string(),
+            value: Some("foo".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            error,
+            ChangeError::TypeMismatch {
+                key: "visits".to_string(),
+                expected: "lww-register".to_string(),
+                found: "pn-counter".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_revoked_token_rejected() -> anyhow::Result<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let state = AppState::new("node1".to_string(), storage, "secret".to_string(), true)?;
+        let write = AuthMiddleware::new(Role::Writer);
+
+        let bearer = |token: String| format!("Bearer {}", token);
+        let leaked = bearer(state.jwt_manager.generate_token(
+            "node1".to_string(),
+            Role::Writer,
+            3600,
+        )?);
+        let other = bearer(state.jwt_manager.generate_token(
+            "node1".to_string(),
+            Role::Writer,
+            3600,
+        )?);
+        assert!(write.authorize(&state, Some(&leaked)).is_ok());
+
+        // 吊销后即使命中验证缓存也会被拒绝，其他 token 不受影响
+        let claims = state
+            .jwt_manager
+            .verify_token(leaked.strip_prefix("Bearer ").unwrap())?;
+        state.revocations.revoke(claims.jti, claims.exp);
+        assert!(write.authorize(&state, Some(&leaked)).is_err());
+        assert!(write.authorize(&state, Some(&other)).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pretty_requested() {
+        assert!(!pretty_requested(None, None));
+        assert!(!pretty_requested(Some("key=a"), Some("application/json")));
+        assert!(pretty_requested(Some("pretty=true"), None));
+        assert!(pretty_requested(Some("key=a&pretty=1"), None));
+        assert!(!pretty_requested(Some("pretty=false"), None));
+        assert!(pretty_requested(
+            None,
+            Some("application/json; pretty=true")
+        ));
+    }
+
+    #[test]
+    fn test_state_json_compact_by_default() {
+        let mut sync_state = SyncState::new("node1".to_string());
+        sync_state
+            .apply_changes(crate::sync::ChangeRequest {
+                changes: vec![crate::sync::Change {
+                    op: "increment".to_string(),
+                    key: "counter".to_string(),
+                    delta: Some(1),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+
+        let compact = render_json(&sync_state, pretty_requested(None, None)).unwrap();
+        assert!(!compact.contains('\n'));
+
+        let pretty = render_json(&sync_state, pretty_requested(Some("pretty=true"), None)).unwrap();
+        assert!(pretty.contains('\n'));
+
+        let a: serde_json::Value = serde_json::from_str(&compact).unwrap();
+        let b: serde_json::Value = serde_json::from_str(&pretty).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[tokio::test]
+    async fn test_consistent_snapshot_under_concurrent_writes() {
+        let increment = || ChangeRequest {
+            changes: vec![crate::sync::Change {
+                op: "increment".to_string(),
+                key: "counter".to_string(),
+                ..Default::default()
+            }],
+        };
+        let counter = |state: &SyncState| match state.crdt_map.get("counter") {
+            Some(crate::crdt::CRDTValue::PNCounter(c)) => c.value(),
+            _ => 0,
+        };
+
+        let doc_a = Arc::new(RwLock::new(SyncState::new("node1".to_string())));
+        let doc_b = Arc::new(RwLock::new(SyncState::new("node1".to_string())));
+        let documents = vec![
+            ("b".to_string(), doc_b.clone()),
+            ("a".to_string(), doc_a.clone()),
+        ];
+
+        // 写入方按相同顺序（a → b）同时修改两个文档
+        let writer = tokio::spawn(async move {
+            for _ in 0..200 {
+                let mut a = doc_a.write().await;
+                let mut b = doc_b.write().await;
+                a.apply_changes(increment()).unwrap();
+                b.apply_changes(increment()).unwrap();
+                drop(b);
+                drop(a);
+                tokio::task::yield_now().await;
+            }
+        });
+
+        for _ in 0..50 {
+            let snapshot = capture_consistent(&documents).await;
+            let values: Vec<i64> = snapshot.documents.values().map(counter).collect();
+            assert_eq!(values.len(), 2);
+            assert_eq!(values[0], values[1]);
+            tokio::task::yield_now().await;
+        }
+        writer.await.unwrap();
+
+        let snapshot = capture_consistent(&documents).await;
+        assert!(snapshot.documents.values().all(|s| counter(s) == 200));
+        assert!(snapshot.taken_at > 0);
+    }
+}
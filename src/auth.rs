@@ -1,7 +1,12 @@
 use anyhow::{Result, anyhow};
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode};
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 默认 token 缓存容量
+pub const DEFAULT_TOKEN_CACHE_CAPACITY: usize = 1024;
 
 /// 用户角色
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -12,6 +17,19 @@ pub enum Role {
     Reader,
 }
 
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "admin" => Ok(Role::Admin),
+            "writer" => Ok(Role::Writer),
+            "reader" => Ok(Role::Reader),
+            other => Err(format!("Unknown role: {}", other)),
+        }
+    }
+}
+
 impl Role {
     /// 检查是否有足够的权限
     pub fn has_permission(&self, required: &Role) -> bool {
@@ -26,36 +44,323 @@ impl Role {
 }
 
 /// JWT Claims
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,     // 主体（用户ID或节点ID）
     pub role: Role,      // 角色
     pub exp: u64,        // 过期时间
     pub iat: u64,        // 签发时间
     pub node_id: String, // 节点ID
+    #[serde(default)]
+    pub jti: String, // token 唯一标识（用于吊销，旧 token 为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_keys: Option<Vec<String>>, // 允许写入的键（支持末尾 `*` 前缀通配），缺省时不限
+}
+
+impl Claims {
+    pub fn key_scope(&self) -> KeyScope {
+        KeyScope(self.allowed_keys.clone())
+    }
+}
+
+/// 调用方允许写入的键范围，`None` 表示不限
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyScope(pub Option<Vec<String>>);
+
+impl KeyScope {
+    /// 检查键是否在允许范围内，以 `*` 结尾的模式按前缀匹配
+    pub fn allows(&self, key: &str) -> bool {
+        match &self.0 {
+            None => true,
+            Some(patterns) => patterns
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => key.starts_with(prefix),
+                    None => key == pattern,
+                }),
+        }
+    }
+
+    pub fn is_restricted(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+/// 当前 Unix 时间（秒）
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// 已验证 token 的缓存
+///
+/// 以原始 token 字符串为键缓存验证结果，条目在 `exp` 到期后不再返回。
+/// 容量满时先清理过期条目，仍然满则清空缓存。
+pub struct TokenCache {
+    entries: Mutex<HashMap<String, Claims>>,
+    capacity: usize,
+}
+
+impl TokenCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            capacity,
+        }
+    }
+
+    /// 获取未过期的缓存结果
+    pub fn get(&self, token: &str) -> Option<Claims> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(token) {
+            Some(claims) if claims.exp > now_secs() => Some(claims.clone()),
+            Some(_) => {
+                entries.remove(token);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, token: String, claims: Claims) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity {
+            let now = now_secs();
+            entries.retain(|_, c| c.exp > now);
+            if entries.len() >= self.capacity {
+                entries.clear();
+            }
+        }
+        entries.insert(token, claims);
+    }
+
+    /// 移除满足条件的缓存条目（用于吊销等场景）
+    pub fn invalidate_where(&self, predicate: impl Fn(&Claims) -> bool) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|_, claims| !predicate(claims));
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// 未指定过期时间时，吊销记录的保留时长（秒）
+pub const DEFAULT_REVOCATION_RETENTION_SECS: u64 = 7 * 24 * 3600;
+
+/// 过期吊销记录的清理间隔（秒）
+pub const REVOCATION_PURGE_INTERVAL_SECS: u64 = 300;
+
+/// 已吊销 token 的 `jti` 集合
+///
+/// 每条记录保存 token 的过期时间：token 过期后本身已无法通过验证，
+/// 记录可由 `purge_expired` 清理，避免集合无限增长。
+#[derive(Default)]
+pub struct RevocationStore {
+    revoked: Mutex<HashMap<String, u64>>,
+}
+
+impl RevocationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 吊销 `jti`，记录保留到 `exp`
+    pub fn revoke(&self, jti: String, exp: u64) {
+        let mut revoked = self.revoked.lock().unwrap();
+        let entry = revoked.entry(jti).or_insert(exp);
+        *entry = (*entry).max(exp);
+    }
+
+    pub fn is_revoked(&self, jti: &str) -> bool {
+        !jti.is_empty() && self.revoked.lock().unwrap().contains_key(jti)
+    }
+
+    /// 清理已过期的记录，返回清理数量
+    pub fn purge_expired(&self) -> usize {
+        let now = now_secs();
+        let mut revoked = self.revoked.lock().unwrap();
+        let before = revoked.len();
+        revoked.retain(|_, exp| *exp > now);
+        before - revoked.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.revoked.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// 启动定期清理过期吊销记录的后台任务
+pub fn spawn_revocation_purge_task(
+    store: Arc<RevocationStore>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval.max(Duration::from_secs(1)));
+        loop {
+            ticker.tick().await;
+            let purged = store.purge_expired();
+            if purged > 0 {
+                tracing::debug!("Purged {} expired token revocations", purged);
+            }
+        }
+    })
+}
+
+/// JWT 签名密钥
+pub enum JwtKeys {
+    /// HS256 共享密钥：持有者均可签发与验证 token
+    Secret(String),
+    /// 非对称密钥（RS256 / EdDSA）：仅持有私钥的节点可签发，其余节点只需公钥即可验证
+    KeyPair {
+        algorithm: Algorithm,
+        private_key: Option<EncodingKey>,
+        public_key: DecodingKey,
+    },
+}
+
+impl From<&str> for JwtKeys {
+    fn from(secret: &str) -> Self {
+        JwtKeys::Secret(secret.to_string())
+    }
+}
+
+impl From<&String> for JwtKeys {
+    fn from(secret: &String) -> Self {
+        JwtKeys::Secret(secret.clone())
+    }
+}
+
+impl From<String> for JwtKeys {
+    fn from(secret: String) -> Self {
+        JwtKeys::Secret(secret)
+    }
 }
 
 /// JWT 管理器
+///
+/// 新 token 始终以主密钥签名；验证时依次尝试主密钥与仅用于验证的备用密钥，
+/// 以便轮换密钥期间旧密钥签发的 token 仍然有效。
 pub struct JwtManager {
-    encoding_key: EncodingKey,
-    decoding_key: DecodingKey,
+    /// 仅持有公钥时为 None，此时只能验证不能签发
+    encoding_key: Option<EncodingKey>,
+    /// 主密钥在前，其后为备用密钥
+    decoding_keys: Vec<DecodingKey>,
+    algorithm: Algorithm,
     validation: Validation,
+    cache: TokenCache,
 }
 
 impl JwtManager {
-    /// 创建新的 JWT 管理器
-    pub fn new(secret: &str) -> Self {
-        let encoding_key = EncodingKey::from_secret(secret.as_bytes());
-        let decoding_key = DecodingKey::from_secret(secret.as_bytes());
-        let validation = Validation::default();
+    /// 创建新的 JWT 管理器（传入字符串时使用 HS256 共享密钥）
+    pub fn new(keys: impl Into<JwtKeys>) -> Self {
+        let (algorithm, encoding_key, decoding_key) = match keys.into() {
+            JwtKeys::Secret(secret) => (
+                Algorithm::HS256,
+                Some(EncodingKey::from_secret(secret.as_bytes())),
+                DecodingKey::from_secret(secret.as_bytes()),
+            ),
+            JwtKeys::KeyPair {
+                algorithm,
+                private_key,
+                public_key,
+            } => (algorithm, private_key, public_key),
+        };
 
         Self {
             encoding_key,
-            decoding_key,
-            validation,
+            decoding_keys: vec![decoding_key],
+            algorithm,
+            validation: Validation::new(algorithm),
+            cache: TokenCache::new(DEFAULT_TOKEN_CACHE_CAPACITY),
         }
     }
 
+    /// 从 PEM 编码的 RSA 密钥创建 RS256 管理器，省略私钥时仅用于验证
+    pub fn from_rsa_pem(private_pem: Option<&[u8]>, public_pem: &[u8]) -> Result<Self> {
+        let private_key = private_pem
+            .map(EncodingKey::from_rsa_pem)
+            .transpose()
+            .map_err(|e| anyhow!("Invalid RSA private key: {}", e))?;
+        let public_key = DecodingKey::from_rsa_pem(public_pem)
+            .map_err(|e| anyhow!("Invalid RSA public key: {}", e))?;
+        Ok(Self::new(JwtKeys::KeyPair {
+            algorithm: Algorithm::RS256,
+            private_key,
+            public_key,
+        }))
+    }
+
+    /// 从 PEM 编码的 Ed25519 密钥创建 EdDSA 管理器，省略私钥时仅用于验证
+    pub fn from_ed_pem(private_pem: Option<&[u8]>, public_pem: &[u8]) -> Result<Self> {
+        let private_key = private_pem
+            .map(EncodingKey::from_ed_pem)
+            .transpose()
+            .map_err(|e| anyhow!("Invalid Ed25519 private key: {}", e))?;
+        let public_key = DecodingKey::from_ed_pem(public_pem)
+            .map_err(|e| anyhow!("Invalid Ed25519 public key: {}", e))?;
+        Ok(Self::new(JwtKeys::KeyPair {
+            algorithm: Algorithm::EdDSA,
+            private_key,
+            public_key,
+        }))
+    }
+
+    /// 签名算法
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// 是否持有签发 token 所需的密钥
+    pub fn can_issue(&self) -> bool {
+        self.encoding_key.is_some()
+    }
+
+    /// 添加仅用于验证的备用密钥（如轮换前的旧密钥，仅适用于 HS256）
+    pub fn with_fallback_secrets(mut self, secrets: &[String]) -> Self {
+        self.add_fallback_secrets(secrets);
+        self
+    }
+
+    /// 追加仅用于验证的备用密钥
+    pub fn add_fallback_secrets(&mut self, secrets: &[String]) {
+        self.decoding_keys.extend(
+            secrets
+                .iter()
+                .map(|secret| DecodingKey::from_secret(secret.as_bytes())),
+        );
+    }
+
+    /// 设置验证结果缓存容量（0 表示禁用缓存）
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache = TokenCache::new(capacity);
+        self
+    }
+
+    /// 验证结果缓存
+    pub fn cache(&self) -> &TokenCache {
+        &self.cache
+    }
+
     /// 生成 JWT token
     pub fn generate_token(
         &self,
@@ -63,10 +368,18 @@ impl JwtManager {
         role: Role,
         expires_in_secs: u64,
     ) -> Result<String> {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        self.generate_scoped_token(node_id, role, expires_in_secs, None)
+    }
+
+    /// 生成只允许写入指定键的 JWT token（`None` 时不限）
+    pub fn generate_scoped_token(
+        &self,
+        node_id: String,
+        role: Role,
+        expires_in_secs: u64,
+        allowed_keys: Option<Vec<String>>,
+    ) -> Result<String> {
+        let now = now_secs();
 
         let claims = Claims {
             sub: node_id.clone(),
@@ -74,17 +387,40 @@ impl JwtManager {
             exp: now + expires_in_secs,
             iat: now,
             node_id,
+            jti: scru128::new_string(),
+            allowed_keys,
         };
 
-        encode(&Header::default(), &claims, &self.encoding_key)
+        let encoding_key = self.encoding_key.as_ref().ok_or_else(|| {
+            anyhow!("No private key configured, this node can only verify tokens")
+        })?;
+        encode(&Header::new(self.algorithm), &claims, encoding_key)
             .map_err(|e| anyhow!("Failed to generate token: {}", e))
     }
 
-    /// 验证并解析 JWT token
+    /// 验证并解析 JWT token（命中缓存时跳过签名验证）
     pub fn verify_token(&self, token: &str) -> Result<Claims> {
-        decode::<Claims>(token, &self.decoding_key, &self.validation)
-            .map(|data| data.claims)
-            .map_err(|e| anyhow!("Invalid token: {}", e))
+        if let Some(claims) = self.cache.get(token) {
+            return Ok(claims);
+        }
+
+        // 依次尝试各密钥，全部失败时报告主密钥的错误
+        let mut first_error = None;
+        for decoding_key in &self.decoding_keys {
+            match decode::<Claims>(token, decoding_key, &self.validation) {
+                Ok(data) => {
+                    self.cache.insert(token.to_string(), data.claims.clone());
+                    return Ok(data.claims);
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(anyhow!(
+            "Invalid token: {}",
+            first_error.expect("at least one decoding key")
+        ))
     }
 
     /// 从 Authorization header 中提取 token
@@ -128,6 +464,229 @@ mod tests {
         assert_eq!(claims.role, Role::Writer);
     }
 
+    #[test]
+    fn test_fallback_secret_verifies_during_rotation() {
+        let old = JwtManager::new("old_secret");
+        let old_token = old
+            .generate_token("node1".to_string(), Role::Writer, 3600)
+            .unwrap();
+
+        let rotated =
+            JwtManager::new("new_secret").with_fallback_secrets(&["old_secret".to_string()]);
+        let claims = rotated.verify_token(&old_token).unwrap();
+        assert_eq!(claims.role, Role::Writer);
+
+        // 新 token 使用当前密钥签名，旧节点无法验证
+        let new_token = rotated
+            .generate_token("node1".to_string(), Role::Reader, 3600)
+            .unwrap();
+        assert!(rotated.verify_token(&new_token).is_ok());
+        assert!(old.verify_token(&new_token).is_err());
+        assert!(
+            JwtManager::new("new_secret")
+                .verify_token(&new_token)
+                .is_ok()
+        );
+
+        // 移除备用密钥后旧 token 失效
+        assert!(
+            JwtManager::new("new_secret")
+                .verify_token(&old_token)
+                .is_err()
+        );
+    }
+
+    const RSA_PRIVATE_PEM: &[u8] = include_bytes!("../tests/fixtures/jwt_rs256_private.pem");
+    const RSA_PUBLIC_PEM: &[u8] = include_bytes!("../tests/fixtures/jwt_rs256_public.pem");
+
+    #[test]
+    fn test_rs256_sign_with_private_verify_with_public() {
+        let gateway = JwtManager::from_rsa_pem(Some(RSA_PRIVATE_PEM), RSA_PUBLIC_PEM).unwrap();
+        assert_eq!(gateway.algorithm(), Algorithm::RS256);
+        let token = gateway
+            .generate_token("node1".to_string(), Role::Writer, 3600)
+            .unwrap();
+
+        // 工作节点只持有公钥即可验证
+        let worker = JwtManager::from_rsa_pem(None, RSA_PUBLIC_PEM).unwrap();
+        let claims = worker.verify_token(&token).unwrap();
+        assert_eq!(claims.node_id, "node1");
+        assert_eq!(claims.role, Role::Writer);
+
+        // 但无法签发 token
+        assert!(!worker.can_issue());
+        assert!(
+            worker
+                .generate_token("node2".to_string(), Role::Admin, 3600)
+                .is_err()
+        );
+
+        // 共享密钥签发的 token 不被 RS256 节点接受
+        let hs256 = JwtManager::new("test_secret_key")
+            .generate_token("node1".to_string(), Role::Admin, 3600)
+            .unwrap();
+        assert!(worker.verify_token(&hs256).is_err());
+        assert!(
+            JwtManager::new("test_secret_key")
+                .verify_token(&token)
+                .is_err()
+        );
+
+        assert!(JwtManager::from_rsa_pem(None, b"not a pem").is_err());
+    }
+
+    #[test]
+    fn test_role_from_str() {
+        assert_eq!("reader".parse::<Role>(), Ok(Role::Reader));
+        assert_eq!("Writer".parse::<Role>(), Ok(Role::Writer));
+        assert_eq!("ADMIN".parse::<Role>(), Ok(Role::Admin));
+        assert!("guest".parse::<Role>().is_err());
+    }
+
+    #[test]
+    fn test_verified_token_is_cached() {
+        let manager = JwtManager::new("test_secret_key");
+        let token = manager
+            .generate_token("node1".to_string(), Role::Reader, 3600)
+            .unwrap();
+
+        assert!(manager.cache().is_empty());
+        manager.verify_token(&token).unwrap();
+        assert_eq!(manager.cache().len(), 1);
+
+        // 第二次验证命中缓存
+        let claims = manager.verify_token(&token).unwrap();
+        assert_eq!(claims.role, Role::Reader);
+        assert_eq!(manager.cache().len(), 1);
+
+        // 无效 token 不会进入缓存
+        assert!(manager.verify_token("invalid").is_err());
+        assert_eq!(manager.cache().len(), 1);
+
+        manager.cache().invalidate_where(|c| c.node_id == "node1");
+        assert!(manager.cache().is_empty());
+    }
+
+    #[test]
+    fn test_expired_cache_entry_not_served() {
+        let cache = TokenCache::new(16);
+        let now = now_secs();
+        let claims = |exp| Claims {
+            sub: "node1".to_string(),
+            role: Role::Writer,
+            exp,
+            iat: now - 10,
+            node_id: "node1".to_string(),
+            jti: String::new(),
+            allowed_keys: None,
+        };
+
+        cache.insert("expired".to_string(), claims(now - 1));
+        cache.insert("valid".to_string(), claims(now + 3600));
+
+        assert!(cache.get("expired").is_none());
+        assert!(cache.get("valid").is_some());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_cache_capacity_is_bounded() {
+        let cache = TokenCache::new(2);
+        let claims = Claims {
+            sub: "node1".to_string(),
+            role: Role::Reader,
+            exp: now_secs() + 3600,
+            iat: now_secs(),
+            node_id: "node1".to_string(),
+            jti: String::new(),
+            allowed_keys: None,
+        };
+
+        for i in 0..5 {
+            cache.insert(format!("token-{}", i), claims.clone());
+            assert!(cache.len() <= 2);
+        }
+
+        let disabled = TokenCache::new(0);
+        disabled.insert("token".to_string(), claims);
+        assert!(disabled.is_empty());
+    }
+
+    #[test]
+    fn test_revocation_store_purges_expired() {
+        let store = RevocationStore::new();
+        let now = now_secs();
+        store.revoke("expired".to_string(), now - 1);
+        store.revoke("active".to_string(), now + 3600);
+        assert!(store.is_revoked("expired"));
+        assert!(store.is_revoked("active"));
+        assert!(!store.is_revoked("other"));
+        // 不带 jti 的旧 token 无法被吊销
+        assert!(!store.is_revoked(""));
+
+        assert_eq!(store.purge_expired(), 1);
+        assert!(!store.is_revoked("expired"));
+        assert!(store.is_revoked("active"));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_generated_tokens_have_unique_jti() {
+        let manager = JwtManager::new("test_secret_key");
+        let jti = |manager: &JwtManager| {
+            let token = manager
+                .generate_token("node1".to_string(), Role::Reader, 3600)
+                .unwrap();
+            manager.verify_token(&token).unwrap().jti
+        };
+        let first = jti(&manager);
+        assert!(!first.is_empty());
+        assert_ne!(first, jti(&manager));
+    }
+
+    #[test]
+    fn test_key_scope_matching() {
+        let scope = KeyScope(Some(vec!["profile".to_string(), "cart:*".to_string()]));
+        assert!(scope.allows("profile"));
+        assert!(!scope.allows("profile2"));
+        assert!(scope.allows("cart:1"));
+        assert!(scope.allows("cart:"));
+        assert!(!scope.allows("cart"));
+        assert!(!scope.allows("orders"));
+
+        assert!(KeyScope(None).allows("anything"));
+        assert!(!KeyScope(Some(Vec::new())).allows("anything"));
+        assert!(KeyScope(Some(vec!["*".to_string()])).allows("anything"));
+    }
+
+    #[test]
+    fn test_scoped_token_round_trip() {
+        let manager = JwtManager::new("test_secret_key");
+        let token = manager
+            .generate_scoped_token(
+                "node1".to_string(),
+                Role::Writer,
+                3600,
+                Some(vec!["cart:*".to_string()]),
+            )
+            .unwrap();
+        let scope = manager.verify_token(&token).unwrap().key_scope();
+        assert!(scope.is_restricted());
+        assert!(scope.allows("cart:1"));
+
+        // 未限定键的 token 不携带该字段
+        let token = manager
+            .generate_token("node1".to_string(), Role::Writer, 3600)
+            .unwrap();
+        assert!(
+            !manager
+                .verify_token(&token)
+                .unwrap()
+                .key_scope()
+                .is_restricted()
+        );
+    }
+
     #[test]
     fn test_token_extraction() {
         let header = "Bearer eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9...";
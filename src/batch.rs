@@ -0,0 +1,460 @@
+use crate::api::AppState;
+use crate::storage::DEFAULT_DOCUMENT;
+use crate::sync::ChangeRequest;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// 保留的票据处理结果数量，超出后淘汰最旧的票据
+const TICKET_OUTCOME_CAPACITY: usize = 10_000;
+
+/// 批量写入的受理响应
+#[derive(Debug, Clone, Serialize)]
+pub struct AcceptedResponse {
+    pub success: bool,
+    pub status: String, // "accepted"：已受理，尚未持久化
+    pub ticket: u64,    // 用于查询是否已持久化
+    pub message: String,
+}
+
+/// 批量变更票据的处理状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TicketStatus {
+    Pending,  // 排队中，或已应用但尚未持久化
+    Durable,  // 已应用并持久化
+    Rejected, // 应用失败，变更未写入
+    Unknown,  // 未签发、处理结果已被淘汰，或已应用的变更在持久化前因状态恢复而丢失
+}
+
+/// 票据的处理结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TicketOutcome {
+    pub status: TicketStatus,
+    pub error: Option<String>, // 应用失败的原因
+}
+
+impl TicketOutcome {
+    fn status(status: TicketStatus) -> Self {
+        Self {
+            status,
+            error: None,
+        }
+    }
+}
+
+/// 持久化状态查询响应
+#[derive(Debug, Clone, Serialize)]
+pub struct DurabilityResponse {
+    pub ticket: u64,
+    pub durable: bool,
+    pub status: TicketStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DurabilityResponse {
+    pub fn new(ticket: u64, outcome: TicketOutcome) -> Self {
+        Self {
+            ticket,
+            durable: outcome.status == TicketStatus::Durable,
+            status: outcome.status,
+            error: outcome.error,
+        }
+    }
+}
+
+/// 写入合并缓冲区
+///
+/// 客户端的变更请求先进入内存队列并立即确认，由后台任务按固定窗口
+/// 批量应用并持久化，从而将多次加锁与 flush 合并为一次。
+/// 每个票据单独记录处理结果：只有成功应用且包含它的状态已写入存储时才视为已持久化。
+pub struct ApplyBatcher {
+    pending: Mutex<Vec<(u64, ChangeRequest)>>,
+    next_ticket: AtomicU64,
+    outcomes: Mutex<BTreeMap<u64, TicketOutcome>>,
+    unsaved: Mutex<Vec<u64>>,           // 已应用但尚未持久化的票据
+    flush_lock: tokio::sync::Mutex<()>, // 串行化批量持久化
+    flushes: AtomicU64,
+}
+
+impl ApplyBatcher {
+    pub fn new() -> Self {
+        Self {
+            pending: Mutex::new(Vec::new()),
+            next_ticket: AtomicU64::new(1),
+            outcomes: Mutex::new(BTreeMap::new()),
+            unsaved: Mutex::new(Vec::new()),
+            flush_lock: tokio::sync::Mutex::new(()),
+            flushes: AtomicU64::new(0),
+        }
+    }
+
+    /// 将变更请求加入队列，返回用于确认持久化的票据
+    pub fn enqueue(&self, request: ChangeRequest) -> u64 {
+        let mut pending = self.pending.lock().unwrap();
+        let ticket = self.next_ticket.fetch_add(1, Ordering::SeqCst);
+        self.settle(ticket, TicketOutcome::status(TicketStatus::Pending));
+        pending.push((ticket, request));
+        ticket
+    }
+
+    /// 记录票据的处理结果
+    fn settle(&self, ticket: u64, outcome: TicketOutcome) {
+        let mut outcomes = self.outcomes.lock().unwrap();
+        outcomes.insert(ticket, outcome);
+        while outcomes.len() > TICKET_OUTCOME_CAPACITY {
+            outcomes.pop_first();
+        }
+    }
+
+    /// 当前排队中的请求数
+    pub fn pending_len(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// 票据的处理结果
+    pub fn outcome(&self, ticket: u64) -> TicketOutcome {
+        self.outcomes
+            .lock()
+            .unwrap()
+            .get(&ticket)
+            .cloned()
+            .unwrap_or_else(|| TicketOutcome::status(TicketStatus::Unknown))
+    }
+
+    /// 票据对应的变更是否已应用并持久化
+    pub fn is_durable(&self, ticket: u64) -> bool {
+        self.outcome(ticket).status == TicketStatus::Durable
+    }
+
+    /// 内存状态被替换为持久化版本时调用：已应用但尚未持久化的变更随之丢失，
+    /// 其票据标记为结果未知，不会在之后成功的 flush 中被报告为已持久化。返回受影响的票据数
+    pub fn discard_unsaved(&self) -> usize {
+        let lost = std::mem::take(&mut *self.unsaved.lock().unwrap());
+        for &ticket in &lost {
+            self.settle(
+                ticket,
+                TicketOutcome {
+                    status: TicketStatus::Unknown,
+                    error: Some("Applied changes were lost by state recovery".to_string()),
+                },
+            );
+        }
+        lost.len()
+    }
+
+    /// 已执行的批量持久化次数
+    pub fn flush_count(&self) -> u64 {
+        self.flushes.load(Ordering::Relaxed)
+    }
+
+    /// 应用所有排队的变更并持久化一次，返回成功应用的变更数
+    ///
+    /// 取出队列、应用与保存在同一把锁内完成，并发的 flush 依次执行。每个请求整体应用或整体拒绝，
+    /// 与直接写入一样在 `guarded` 中执行并计入指标、慢操作与自动快照；保存成功后发布变更事件。
+    /// 保存失败时已应用的票据保持待持久化，在之后成功的 flush 中标记为已持久化。
+    pub async fn flush(&self, app_state: &AppState) -> Result<usize> {
+        let _flush = self.flush_lock.lock().await;
+        let batch = std::mem::take(&mut *self.pending.lock().unwrap());
+        if batch.is_empty() && self.unsaved.lock().unwrap().is_empty() {
+            return Ok(0);
+        }
+
+        let mut state = app_state.sync_state.write().await;
+        let mut applied = 0;
+        let mut changed_keys = Vec::new();
+        for (ticket, request) in batch {
+            let count = request.changes.len();
+            let ops: Vec<String> = request.changes.iter().map(|c| c.op.clone()).collect();
+            let touched_keys: Vec<String> = request
+                .touched_keys()
+                .into_iter()
+                .map(str::to_string)
+                .collect();
+            let result = app_state.guarded(&mut state, |state| {
+                app_state.slow_ops.time(
+                    "apply_changes",
+                    count,
+                    || touched_keys.clone(),
+                    || state.apply_changes(request),
+                )
+            });
+            let error = match result {
+                Ok(Ok(())) => {
+                    applied += count;
+                    self.unsaved.lock().unwrap().push(ticket);
+                    app_state.metrics.record_ops(ops.iter().map(String::as_str));
+                    changed_keys.extend(touched_keys);
+                    continue;
+                }
+                Ok(Err(e)) => e.to_string(),
+                Err(e) => e.to_string(),
+            };
+            tracing::warn!("Dropped batched change request #{}: {}", ticket, error);
+            self.settle(
+                ticket,
+                TicketOutcome {
+                    status: TicketStatus::Rejected,
+                    error: Some(error),
+                },
+            );
+        }
+        app_state.storage.save_state(&app_state.node_id, &state)?;
+
+        let durable = std::mem::take(&mut *self.unsaved.lock().unwrap());
+        for ticket in durable {
+            self.settle(ticket, TicketOutcome::status(TicketStatus::Durable));
+        }
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+        if applied > 0 {
+            app_state.record_applied_ops(DEFAULT_DOCUMENT, &state, applied);
+        }
+        if !changed_keys.is_empty() {
+            changed_keys.sort();
+            changed_keys.dedup();
+            let state_hash = app_state.timed_state_hash(&state);
+            let last_op_id = state.op_log.ops.last().map(|entry| entry.id.clone());
+            app_state.publish_change(
+                DEFAULT_DOCUMENT,
+                "sync",
+                changed_keys,
+                &state_hash,
+                last_op_id,
+            );
+        }
+        drop(state);
+
+        tracing::debug!("Flushed {} batched changes", applied);
+        Ok(applied)
+    }
+}
+
+impl Default for ApplyBatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 启动后台任务，按固定窗口应用并持久化排队的变更
+pub fn spawn_flush_task(app_state: AppState, window: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        if app_state.apply_batcher.is_none() {
+            return;
+        }
+        let mut ticker = tokio::time::interval(window);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = app_state.flush_apply_batch().await {
+                tracing::warn!("Failed to flush batched changes: {}", e);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::CRDTValue;
+    use crate::storage::{MemoryStorage, StorageBackend};
+    use crate::sync::Change;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, AtomicU64};
+
+    /// 可按需让写入失败的内存存储
+    #[derive(Default)]
+    struct FlakyStorage {
+        inner: MemoryStorage,
+        fail_writes: AtomicBool,
+    }
+
+    impl StorageBackend for FlakyStorage {
+        fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+            self.inner.get(key)
+        }
+
+        fn insert(&self, key: &str, value: Vec<u8>) -> Result<()> {
+            if self.fail_writes.load(Ordering::SeqCst) {
+                anyhow::bail!("disk full");
+            }
+            self.inner.insert(key, value)
+        }
+
+        fn remove(&self, key: &str) -> Result<()> {
+            self.inner.remove(key)
+        }
+
+        fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+            self.inner.keys_with_prefix(prefix)
+        }
+
+        fn flush(&self) -> Result<()> {
+            self.inner.flush()
+        }
+
+        fn clear(&self) -> Result<()> {
+            self.inner.clear()
+        }
+
+        fn corrupt_snapshot_counter(&self) -> &AtomicU64 {
+            self.inner.corrupt_snapshot_counter()
+        }
+    }
+
+    fn batched_state(storage: Arc<dyn StorageBackend>) -> (AppState, Arc<ApplyBatcher>) {
+        let state = AppState::new("node1".to_string(), storage, "secret".to_string(), false)
+            .unwrap()
+            .with_apply_batcher();
+        let batcher = state.apply_batcher.clone().unwrap();
+        (state, batcher)
+    }
+
+    #[tokio::test]
+    async fn test_batched_changes_coalesce_flushes() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let storage = Arc::new(crate::storage::SledStorage::new(
+            temp_dir.path().to_str().unwrap(),
+        )?);
+        let (state, batcher) = batched_state(storage.clone());
+        let mut events = state.change_events.subscribe();
+
+        let mut last_ticket = 0;
+        for _ in 0..100 {
+            last_ticket = batcher.enqueue(ChangeRequest {
+                changes: vec![Change {
+                    op: "increment".to_string(),
+                    key: "counter".to_string(),
+                    value: None,
+                    delta: Some(1),
+                    ..Default::default()
+                }],
+            });
+        }
+        assert_eq!(batcher.pending_len(), 100);
+        assert!(!batcher.is_durable(last_ticket));
+
+        let applied = batcher.flush(&state).await?;
+        assert_eq!(applied, 100);
+        assert_eq!(batcher.flush_count(), 1);
+        assert!(batcher.is_durable(last_ticket));
+
+        // 保存后发布一次变更事件
+        let event = events.try_recv()?;
+        assert_eq!(event.source, "sync");
+        assert_eq!(event.keys, vec!["counter".to_string()]);
+        assert_eq!(event.state_hash, state.sync_state.read().await.state_hash());
+
+        // 空队列不会触发持久化
+        batcher.flush(&state).await?;
+        assert_eq!(batcher.flush_count(), 1);
+        assert!(events.try_recv().is_err());
+
+        let loaded = storage.load_state("node1")?.unwrap();
+        if let Some(CRDTValue::PNCounter(c)) = loaded.crdt_map.get("counter") {
+            assert_eq!(c.value(), 100);
+        } else {
+            panic!("Counter not found or wrong type");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rejected_ticket_is_not_reported_durable() -> Result<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let (state, batcher) = batched_state(storage.clone());
+
+        let change = |op: &str, key: &str, value: Option<&str>| Change {
+            op: op.to_string(),
+            key: key.to_string(),
+            value: value.map(str::to_string),
+            ..Default::default()
+        };
+        let request = |changes: Vec<Change>| ChangeRequest { changes };
+        let ok = batcher.enqueue(request(vec![change("set", "name", Some("alice"))]));
+        let missing_value = batcher.enqueue(request(vec![change("set", "name", None)]));
+        // 第二条变更失败时，第一条变更也不生效
+        let partial = batcher.enqueue(request(vec![
+            change("set", "title", Some("draft")),
+            change("rename", "title", Some("final")),
+        ]));
+        assert_eq!(batcher.outcome(ok).status, TicketStatus::Pending);
+        assert_eq!(batcher.outcome(partial + 1).status, TicketStatus::Unknown);
+
+        assert_eq!(batcher.flush(&state).await?, 1);
+        assert!(batcher.is_durable(ok));
+        // 失败的请求即使排在成功的请求之前或之后，也不会被报告为已持久化
+        for ticket in [missing_value, partial] {
+            let outcome = batcher.outcome(ticket);
+            assert_eq!(outcome.status, TicketStatus::Rejected);
+            assert!(outcome.error.is_some());
+            assert!(!batcher.is_durable(ticket));
+        }
+        assert!(
+            state
+                .sync_state
+                .read()
+                .await
+                .crdt_map
+                .get("title")
+                .is_none()
+        );
+        let loaded = storage.load_state("node1")?.unwrap();
+        assert!(loaded.crdt_map.get("title").is_none());
+        assert_eq!(loaded.op_log.ops.len(), 1);
+
+        let response =
+            serde_json::to_value(DurabilityResponse::new(partial, batcher.outcome(partial)))?;
+        assert_eq!(response["durable"], false);
+        assert_eq!(response["status"], "rejected");
+        assert!(response["error"].is_string());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recovery_discards_unsaved_tickets() -> Result<()> {
+        let storage = Arc::new(FlakyStorage::default());
+        let (state, batcher) = batched_state(storage.clone());
+        let set_name = |value: &str| ChangeRequest {
+            changes: vec![Change {
+                op: "set".to_string(),
+                key: "name".to_string(),
+                value: Some(value.to_string()),
+                ..Default::default()
+            }],
+        };
+
+        let saved = batcher.enqueue(set_name("alice"));
+        batcher.flush(&state).await?;
+        assert!(batcher.is_durable(saved));
+
+        // 应用成功但保存失败：票据保持待持久化
+        storage.fail_writes.store(true, Ordering::SeqCst);
+        let lost = batcher.enqueue(set_name("bob"));
+        assert!(batcher.flush(&state).await.is_err());
+        assert_eq!(batcher.outcome(lost).status, TicketStatus::Pending);
+
+        // 恢复为持久化状态后，未保存的变更已不在内存中
+        {
+            let mut sync_state = state.sync_state.write().await;
+            state.recover(&mut sync_state)?;
+            assert_eq!(sync_state.crdt_map.document()["name"], "alice");
+        }
+        let outcome = batcher.outcome(lost);
+        assert_eq!(outcome.status, TicketStatus::Unknown);
+        assert!(outcome.error.is_some());
+
+        // 之后成功的 flush 不会把丢失的票据报告为已持久化
+        storage.fail_writes.store(false, Ordering::SeqCst);
+        let next = batcher.enqueue(set_name("carol"));
+        batcher.flush(&state).await?;
+        assert!(batcher.is_durable(next));
+        assert!(!batcher.is_durable(lost));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::{Context, Result, bail};
+
+/// 压缩存储值的魔数前缀；未压缩的状态是以 `{` 开头的 JSON，不会与之冲突
+pub const COMPRESSED_MAGIC: &[u8; 4] = b"CRZ\x01";
+
+/// 对等同步请求体压缩后使用的 Content-Encoding
+pub const SYNC_CONTENT_ENCODING: &str = "zstd";
+
+/// zstd 压缩级别（兼顾速度与压缩率）
+const ZSTD_LEVEL: i32 = 3;
+
+/// zstd 压缩
+pub fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    zstd::bulk::compress(data, ZSTD_LEVEL).context("Failed to compress data")
+}
+
+/// zstd 解压，解压后超过 `limit` 字节时报错（防止压缩炸弹）
+pub fn decompress_limited(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    zstd::bulk::decompress(data, limit)
+        .with_context(|| format!("Failed to decompress data (limit: {} bytes)", limit))
+}
+
+/// 压缩并加上魔数前缀
+pub fn encode_value(data: &[u8]) -> Result<Vec<u8>> {
+    let mut value = COMPRESSED_MAGIC.to_vec();
+    value.extend_from_slice(&compress(data)?);
+    Ok(value)
+}
+
+/// 带魔数前缀的值解压，其余值原样返回（兼容压缩前写入的数据）
+pub fn decode_value(value: &[u8]) -> Result<Vec<u8>> {
+    let Some(compressed) = value.strip_prefix(COMPRESSED_MAGIC) else {
+        return Ok(value.to_vec());
+    };
+    let size = zstd::zstd_safe::get_frame_content_size(compressed)
+        .ok()
+        .flatten()
+        .context("Compressed value has no content size")?;
+    let Ok(size) = usize::try_from(size) else {
+        bail!("Compressed value is too large: {} bytes", size);
+    };
+    decompress_limited(compressed, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode_value() {
+        let data = br#"{"node_id":"node1","ops":[1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1]}"#.repeat(50);
+        let encoded = encode_value(&data).unwrap();
+        assert!(encoded.starts_with(COMPRESSED_MAGIC));
+        assert!(encoded.len() < data.len());
+        assert_eq!(decode_value(&encoded).unwrap(), data);
+
+        // 未压缩的旧值原样返回
+        assert_eq!(decode_value(&data).unwrap(), data);
+
+        // 超过上限的解压被拒绝
+        let compressed = compress(&data).unwrap();
+        assert!(decompress_limited(&compressed, data.len() - 1).is_err());
+        assert_eq!(decompress_limited(&compressed, data.len()).unwrap(), data);
+    }
+}
@@ -1,10 +1,61 @@
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 /// 节点 ID 类型
 pub type NodeId = String;
 
+/// 默认允许的 CRDT 最大嵌套深度
+pub const DEFAULT_MAX_CRDT_DEPTH: usize = 16;
+
+/// 序列化同步请求时 CRDT 值之外的固定 JSON 嵌套层数（请求、状态、映射、元数据等）
+const JSON_DEPTH_BASE: usize = 8;
+/// 每层 CRDT 嵌套在 JSON 中占用的层数（映射条目、枚举标签、结构体及余量）
+const JSON_DEPTH_PER_LEVEL: usize = 4;
+
+/// 不递归地计算 JSON 文本的最大嵌套深度（忽略字符串内的括号）
+pub fn json_nesting_depth(bytes: &[u8]) -> usize {
+    let (mut depth, mut max_depth) = (0usize, 0usize);
+    let (mut in_string, mut escaped) = (false, false);
+    for &b in bytes {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    max_depth
+}
+
+/// 在反序列化前拒绝嵌套过深的数据，避免递归合并 / 哈希时栈溢出
+pub fn check_crdt_depth(bytes: &[u8], max_crdt_depth: usize) -> Result<(), String> {
+    let limit = JSON_DEPTH_BASE + JSON_DEPTH_PER_LEVEL * max_crdt_depth;
+    let depth = json_nesting_depth(bytes);
+    if depth > limit {
+        return Err(format!(
+            "Nesting depth {} exceeds limit {} (max CRDT depth {})",
+            depth, limit, max_crdt_depth
+        ));
+    }
+    Ok(())
+}
+
+/// 计数器中保存已遗忘节点累计贡献的保留条目
+pub const FORGOTTEN_BASELINE: &str = "__forgotten__";
+
 /// 向量时钟，用于因果关系追踪
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct VectorClock {
@@ -27,6 +78,34 @@ impl VectorClock {
         self.clocks.get(node_id).copied().unwrap_or(0)
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.clocks.values().all(|&clock| clock == 0)
+    }
+
+    /// 逐分量取较小值（双方都已观察到的部分）
+    pub fn meet(&self, other: &VectorClock) -> VectorClock {
+        VectorClock {
+            clocks: self
+                .clocks
+                .iter()
+                .map(|(node, &clock)| (node.clone(), clock.min(other.get(node))))
+                .filter(|(_, clock)| *clock > 0)
+                .collect(),
+        }
+    }
+
+    /// 每个分量都不超过 `other` 的对应分量
+    pub fn covered_by(&self, other: &VectorClock) -> bool {
+        self.clocks
+            .iter()
+            .all(|(node, &clock)| clock <= other.get(node))
+    }
+
+    /// 移除节点的时钟分量
+    pub fn remove(&mut self, node_id: &str) -> Option<u64> {
+        self.clocks.remove(node_id)
+    }
+
     pub fn merge(&mut self, other: &VectorClock) {
         for (node, &clock) in &other.clocks {
             let entry = self.clocks.entry(node.clone()).or_insert(0);
@@ -60,6 +139,19 @@ impl VectorClock {
     pub fn is_concurrent(&self, other: &VectorClock) -> bool {
         !self.happens_before(other) && !other.happens_before(self) && self != other
     }
+
+    /// 比较因果顺序：`Less` 表示早于 `other`，并发时返回 None
+    pub fn partial_cmp_causal(&self, other: &VectorClock) -> Option<std::cmp::Ordering> {
+        if self.happens_before(other) {
+            Some(std::cmp::Ordering::Less)
+        } else if other.happens_before(self) {
+            Some(std::cmp::Ordering::Greater)
+        } else if self.is_concurrent(other) {
+            None
+        } else {
+            Some(std::cmp::Ordering::Equal)
+        }
+    }
 }
 
 impl Default for VectorClock {
@@ -68,27 +160,44 @@ impl Default for VectorClock {
     }
 }
 
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
 /// GCounter - 增长计数器
 /// 只能递增的计数器，支持分布式环境下的最终一致性
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct GCounter {
     pub counts: HashMap<NodeId, u64>,
+    /// 压缩基线：压缩时的总值，作为计数下限
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub baseline: u64,
+    /// 基线之后各节点的增量
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub since_baseline: HashMap<NodeId, u64>,
 }
 
 impl GCounter {
     pub fn new() -> Self {
         Self {
             counts: HashMap::new(),
+            baseline: 0,
+            since_baseline: HashMap::new(),
         }
     }
 
     pub fn increment(&mut self, node_id: &str, delta: u64) {
         *self.counts.entry(node_id.to_string()).or_insert(0) += delta;
+        if self.baseline > 0 {
+            *self.since_baseline.entry(node_id.to_string()).or_insert(0) += delta;
+        }
     }
 
-    #[allow(dead_code)]
+    /// 计数值：明细之和与「基线 + 基线后增量」中的较大者
     pub fn value(&self) -> u64 {
-        self.counts.values().sum()
+        let detailed: u64 = self.counts.values().sum();
+        let compacted = self.baseline + self.since_baseline.values().sum::<u64>();
+        detailed.max(compacted)
     }
 
     pub fn merge(&mut self, other: &GCounter) {
@@ -96,6 +205,78 @@ impl GCounter {
             let entry = self.counts.entry(node.clone()).or_insert(0);
             *entry = (*entry).max(count);
         }
+
+        // 较新的基线已包含较旧基线之后的增量
+        if other.baseline > self.baseline {
+            self.baseline = other.baseline;
+            self.since_baseline = other.since_baseline.clone();
+        } else if other.baseline == self.baseline {
+            for (node, &count) in &other.since_baseline {
+                let entry = self.since_baseline.entry(node.clone()).or_insert(0);
+                *entry = (*entry).max(count);
+            }
+        }
+    }
+
+    /// 丢弃各节点明细，以当前总值作为基线，返回基线值
+    ///
+    /// 基线是计数下限：与仍持有明细的副本合并时取两者较大值，结果不变且收敛。
+    /// 调用方需保证计数在所有副本间因果稳定（各副本都已观察到全部增量），
+    /// 否则未观察到基线的副本在压缩期间的并发增量可能丢失。
+    pub fn compact_to_baseline(&mut self) -> u64 {
+        self.baseline = self.value();
+        self.since_baseline.clear();
+        self.counts.clear();
+        self.baseline
+    }
+
+    /// 将超过单节点上限的计数截断为上限，返回被截断的节点（遗忘基线不受限制）
+    ///
+    /// 截断与按最大值合并可交换，只要所有节点使用相同的上限，诚实节点之间仍然收敛。
+    pub fn clamp_to(&mut self, cap: u64) -> Vec<NodeId> {
+        let mut clamped = Vec::new();
+        for (node, count) in self.counts.iter_mut() {
+            if node != FORGOTTEN_BASELINE && *count > cap {
+                *count = cap;
+                clamped.push(node.clone());
+            }
+        }
+        clamped.sort();
+        clamped
+    }
+
+    /// 合并时对传入的单节点计数应用上限，返回被截断的节点
+    pub fn merge_capped(&mut self, other: &GCounter, cap: Option<u64>) -> Vec<NodeId> {
+        let Some(cap) = cap else {
+            self.merge(other);
+            return Vec::new();
+        };
+        let mut other = other.clone();
+        let clamped = other.clamp_to(cap);
+        self.merge(&other);
+        clamped
+    }
+
+    /// 将节点的计数并入遗忘基线，计数值保持不变
+    pub fn forget_node(&mut self, node_id: &str) {
+        if let Some(count) = self.counts.remove(node_id) {
+            *self
+                .counts
+                .entry(FORGOTTEN_BASELINE.to_string())
+                .or_insert(0) += count;
+        }
+        if let Some(count) = self.since_baseline.remove(node_id) {
+            *self
+                .since_baseline
+                .entry(FORGOTTEN_BASELINE.to_string())
+                .or_insert(0) += count;
+        }
+    }
+
+    /// 丢弃节点的计数（不并入遗忘基线）
+    pub fn discard_node(&mut self, node_id: &str) {
+        self.counts.remove(node_id);
+        self.since_baseline.remove(node_id);
     }
 
     pub fn state_hash(&self) -> String {
@@ -106,6 +287,17 @@ impl GCounter {
             hasher.update(node.as_bytes());
             hasher.update(count.to_le_bytes());
         }
+        // 未压缩的计数器保持原有哈希
+        if self.baseline > 0 {
+            hasher.update(b"baseline:");
+            hasher.update(self.baseline.to_le_bytes());
+            let mut since: Vec<_> = self.since_baseline.iter().collect();
+            since.sort_by(|a, b| a.0.cmp(b.0));
+            for (node, count) in since {
+                hasher.update(node.as_bytes());
+                hasher.update(count.to_le_bytes());
+            }
+        }
         hex::encode(hasher.finalize())
     }
 }
@@ -150,6 +342,54 @@ impl PNCounter {
         self.negative.merge(&other.negative);
     }
 
+    /// 将节点的计数并入遗忘基线，并入前先抵消该节点的正负计数
+    pub fn forget_node(&mut self, node_id: &str) {
+        self.compact(|node| node == node_id);
+        self.positive.forget_node(node_id);
+        self.negative.forget_node(node_id);
+    }
+
+    /// 对因果稳定节点的正负计数做抵消，只保留较大一侧的差值，返回被压缩的节点数
+    ///
+    /// 调用方需保证所有副本都已观察到 `is_stable` 为真的节点的当前计数，且这些节点不会在本副本上继续写入；
+    /// 此时与未压缩副本合并会恢复为未压缩的计数，`value()` 不变且仍然收敛。
+    /// 已压缩为基线的计数器不做抵消（计数值可能由基线而非明细决定）。
+    pub fn compact(&mut self, is_stable: impl Fn(&str) -> bool) -> usize {
+        if self.positive.baseline > 0 || self.negative.baseline > 0 {
+            return 0;
+        }
+        let nodes: Vec<NodeId> = self
+            .negative
+            .counts
+            .keys()
+            .filter(|node| is_stable(node) && self.positive.counts.contains_key(*node))
+            .cloned()
+            .collect();
+
+        for node in &nodes {
+            let positive = self.positive.counts.remove(node).unwrap_or(0);
+            let negative = self.negative.counts.remove(node).unwrap_or(0);
+            if positive > negative {
+                self.positive
+                    .counts
+                    .insert(node.clone(), positive - negative);
+            } else if negative > positive {
+                self.negative
+                    .counts
+                    .insert(node.clone(), negative - positive);
+            }
+        }
+
+        nodes.len()
+    }
+
+    /// 正负计数分别压缩为基线，返回压缩后的计数值
+    pub fn compact_to_baseline(&mut self) -> i64 {
+        self.positive.compact_to_baseline();
+        self.negative.compact_to_baseline();
+        self.value()
+    }
+
     pub fn state_hash(&self) -> String {
         let mut hasher = Sha256::new();
         hasher.update(b"positive:");
@@ -166,6 +406,82 @@ impl Default for PNCounter {
     }
 }
 
+/// BoundedCounter - 有下界的计数器
+///
+/// 与 PNCounter 一样按节点记录增减，`value()` 永远不会低于 `lower_bound`。
+/// `try_decrement` 只依据本地视图检查下界，属于尽力而为：不同节点并发的递减在合并后
+/// 仍可能越过下界，此时 `value()` 截断为下界，`raw_value()` 反映实际的增减差。
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BoundedCounter {
+    pub counts: PNCounter,
+    pub lower_bound: i64,
+}
+
+impl BoundedCounter {
+    pub fn new(lower_bound: i64) -> Self {
+        Self {
+            counts: PNCounter::new(),
+            lower_bound,
+        }
+    }
+
+    pub fn increment(&mut self, node_id: &str, delta: u64) {
+        self.counts.increment(node_id, delta);
+    }
+
+    /// 检查本地视图下递减 `delta` 后是否仍不低于下界
+    pub fn check_decrement(&self, delta: u64) -> Result<(), String> {
+        let remaining = self
+            .raw_value()
+            .saturating_sub(i64::try_from(delta).unwrap_or(i64::MAX));
+        if remaining < self.lower_bound {
+            return Err(format!(
+                "Decrement by {} would take counter below its lower bound {} (current {})",
+                delta,
+                self.lower_bound,
+                self.value()
+            ));
+        }
+        Ok(())
+    }
+
+    /// 本地视图下递减后不低于下界时才递减，否则返回错误且不修改计数
+    pub fn try_decrement(&mut self, node_id: &str, delta: u64) -> Result<(), String> {
+        self.check_decrement(delta)?;
+        self.counts.decrement(node_id, delta);
+        Ok(())
+    }
+
+    /// 不检查下界的递减（用于重放已被发起节点接受的操作）
+    pub fn decrement(&mut self, node_id: &str, delta: u64) {
+        self.counts.decrement(node_id, delta);
+    }
+
+    /// 实际的增减差（并发递减越界时可能低于下界）
+    pub fn raw_value(&self) -> i64 {
+        self.counts.value()
+    }
+
+    /// 计数值，截断为不低于下界
+    pub fn value(&self) -> i64 {
+        self.raw_value().max(self.lower_bound)
+    }
+
+    /// 合并计数；下界不同时取较严格（较大）的一个，保证合并满足交换律与结合律
+    pub fn merge(&mut self, other: &BoundedCounter) {
+        self.counts.merge(&other.counts);
+        self.lower_bound = self.lower_bound.max(other.lower_bound);
+    }
+
+    pub fn state_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(b"lower_bound:");
+        hasher.update(self.lower_bound.to_le_bytes());
+        hasher.update(self.counts.state_hash().as_bytes());
+        hex::encode(hasher.finalize())
+    }
+}
+
 /// LWW-Register - 最后写入胜出寄存器
 /// 使用时间戳来解决冲突，最新的写入胜出
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -211,12 +527,155 @@ impl<T: Clone> Default for LWWRegister<T> {
     }
 }
 
-/// OR-Set - 观察移除集合
-/// 使用唯一标识符来追踪每个元素的添加和删除
+/// LWW-Map - 每个字段独立按最后写入胜出解决冲突的映射
+///
+/// 字段记录为 `(值, 时间戳, 节点)`，删除写入值为 `None` 的墓碑并与写入按同一规则比较：
+/// 晚于删除的写入使字段重新出现，早于删除的写入被忽略。时间戳相同时节点 ID 较大者胜出。
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LWWMap {
+    pub entries: HashMap<String, (Option<String>, i64, NodeId)>,
+}
+
+impl LWWMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, field: String, value: String, timestamp: i64, node_id: &str) {
+        self.write(field, Some(value), timestamp, node_id);
+    }
+
+    /// 以墓碑删除字段
+    pub fn remove(&mut self, field: String, timestamp: i64, node_id: &str) {
+        self.write(field, None, timestamp, node_id);
+    }
+
+    fn write(&mut self, field: String, value: Option<String>, timestamp: i64, node_id: &str) {
+        match self.entries.get(&field) {
+            Some((_, ts, node)) if (*ts, node.as_str()) >= (timestamp, node_id) => {}
+            _ => {
+                self.entries
+                    .insert(field, (value, timestamp, node_id.to_string()));
+            }
+        }
+    }
+
+    /// 字段当前值（未写入或已删除时为 None）
+    pub fn get(&self, field: &str) -> Option<&String> {
+        self.entries
+            .get(field)
+            .and_then(|(value, _, _)| value.as_ref())
+    }
+
+    /// 未删除的字段及其值
+    pub fn fields(&self) -> BTreeMap<String, String> {
+        self.entries
+            .iter()
+            .filter_map(|(field, (value, _, _))| Some((field.clone(), value.clone()?)))
+            .collect()
+    }
+
+    pub fn merge(&mut self, other: &LWWMap) {
+        for (field, (value, timestamp, node_id)) in &other.entries {
+            self.write(field.clone(), value.clone(), *timestamp, node_id);
+        }
+    }
+
+    /// 状态哈希（字段按字节序，墓碑与空字符串值可区分）
+    pub fn state_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        let mut sorted: Vec<_> = self.entries.iter().collect();
+        sorted.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+        for (field, (value, timestamp, node_id)) in sorted {
+            hasher.update((field.len() as u64).to_le_bytes());
+            hasher.update(field.as_bytes());
+            match value {
+                Some(value) => {
+                    hasher.update([1]);
+                    hasher.update((value.len() as u64).to_le_bytes());
+                    hasher.update(value.as_bytes());
+                }
+                None => hasher.update([0]),
+            }
+            hasher.update(timestamp.to_le_bytes());
+            hasher.update(node_id.as_bytes());
+            hasher.update([0]);
+        }
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// OR-Set 中一次添加的点：(节点, 该节点在此集合上的第 n 次添加)
+///
+/// 文本形式为 `计数@节点`。旧版本以不透明的唯一 ID 标识添加，读取时转换为计数为 0 的点，
+/// 这类点无法被向量时钟覆盖，只能逐个记录在因果上下文的点集中。
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Dot {
+    pub node_id: NodeId,
+    pub counter: u64,
+}
+
+impl Dot {
+    pub fn new(node_id: &str, counter: u64) -> Self {
+        Self {
+            node_id: node_id.to_string(),
+            counter,
+        }
+    }
+}
+
+impl std::fmt::Display for Dot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.counter == 0 {
+            write!(f, "{}", self.node_id)
+        } else {
+            write!(f, "{}@{}", self.counter, self.node_id)
+        }
+    }
+}
+
+impl From<&str> for Dot {
+    fn from(s: &str) -> Self {
+        match s.split_once('@') {
+            Some((counter, node_id)) if !node_id.is_empty() => match counter.parse() {
+                Ok(counter) if counter > 0 => Self::new(node_id, counter),
+                _ => Self::new(s, 0),
+            },
+            _ => Self::new(s, 0),
+        }
+    }
+}
+
+impl Serialize for Dot {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Dot {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Ok(Dot::from(s.as_str()))
+    }
+}
+
+/// OR-Set - 观察移除集合（添加优先）
+///
+/// 每次添加生成一个点，元素存在当且仅当仍有存活的点；因果上下文记录所有已观察到的点（含已移除的），
+/// 移除只需丢弃元素的点而无需保留墓碑。合并时，一方存活而另一方上下文已覆盖的点视为已被移除。
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ORSet<T: Eq + std::hash::Hash> {
-    pub added: HashMap<T, HashSet<String>>, // 元素 -> 唯一标识符集合
-    pub removed: HashSet<String>,           // 已删除的唯一标识符
+    pub entries: HashMap<T, HashSet<Dot>>, // 元素 -> 存活的点（非空）
+    /// 因果上下文的连续部分：每个节点 1..=n 的点均已观察到
+    pub context: VectorClock,
+    /// 因果上下文中不连续的点（乱序到达或旧版本 ID），连续后并入 `context`
+    pub cloud: HashSet<Dot>,
 }
 
 // 手动实现 Serialize 和 Deserialize
@@ -226,16 +685,17 @@ impl<T: Eq + std::hash::Hash + Serialize> Serialize for ORSet<T> {
         S: serde::Serializer,
     {
         use serde::ser::SerializeStruct;
-        let mut state = serializer.serialize_struct("ORSet", 2)?;
-        state.serialize_field("added", &self.added)?;
-        state.serialize_field("removed", &self.removed)?;
+        let mut state = serializer.serialize_struct("ORSet", 3)?;
+        state.serialize_field("entries", &self.entries)?;
+        state.serialize_field("context", &self.context)?;
+        state.serialize_field("cloud", &self.cloud)?;
         state.end()
     }
 }
 
 impl<'de, T> Deserialize<'de> for ORSet<T>
 where
-    T: Deserialize<'de> + Eq + std::hash::Hash,
+    T: Deserialize<'de> + Clone + Eq + std::hash::Hash,
 {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -250,7 +710,7 @@ where
 
         impl<'de, T> Visitor<'de> for ORSetVisitor<T>
         where
-            T: Deserialize<'de> + Eq + std::hash::Hash,
+            T: Deserialize<'de> + Clone + Eq + std::hash::Hash,
         {
             type Value = ORSet<T>;
 
@@ -262,10 +722,32 @@ where
             where
                 V: MapAccess<'de>,
             {
-                let mut added = None;
-                let mut removed = None;
+                let mut entries: Option<HashMap<T, HashSet<Dot>>> = None;
+                let mut context = None;
+                let mut cloud = None;
+                // 旧版本格式：元素 -> 唯一 ID 集合，以及已删除的 ID
+                let mut added: Option<HashMap<T, HashSet<String>>> = None;
+                let mut removed: Option<HashSet<String>> = None;
                 while let Some(key) = map.next_key::<String>()? {
                     match key.as_str() {
+                        "entries" => {
+                            if entries.is_some() {
+                                return Err(de::Error::duplicate_field("entries"));
+                            }
+                            entries = Some(map.next_value()?);
+                        }
+                        "context" => {
+                            if context.is_some() {
+                                return Err(de::Error::duplicate_field("context"));
+                            }
+                            context = Some(map.next_value()?);
+                        }
+                        "cloud" => {
+                            if cloud.is_some() {
+                                return Err(de::Error::duplicate_field("cloud"));
+                            }
+                            cloud = Some(map.next_value()?);
+                        }
                         "added" => {
                             if added.is_some() {
                                 return Err(de::Error::duplicate_field("added"));
@@ -283,15 +765,24 @@ where
                         }
                     }
                 }
-                let added = added.ok_or_else(|| de::Error::missing_field("added"))?;
-                let removed = removed.ok_or_else(|| de::Error::missing_field("removed"))?;
-                Ok(ORSet { added, removed })
+
+                if let Some(added) = added {
+                    let removed = removed.ok_or_else(|| de::Error::missing_field("removed"))?;
+                    return Ok(ORSet::from_legacy(added, removed));
+                }
+                let entries = entries.ok_or_else(|| de::Error::missing_field("entries"))?;
+                let context = context.ok_or_else(|| de::Error::missing_field("context"))?;
+                Ok(ORSet {
+                    entries,
+                    context,
+                    cloud: cloud.unwrap_or_default(),
+                })
             }
         }
 
         deserializer.deserialize_struct(
             "ORSet",
-            &["added", "removed"],
+            &["entries", "context", "cloud"],
             ORSetVisitor {
                 marker: std::marker::PhantomData,
             },
@@ -302,153 +793,1227 @@ where
 impl<T: Clone + Eq + std::hash::Hash> ORSet<T> {
     pub fn new() -> Self {
         Self {
-            added: HashMap::new(),
-            removed: HashSet::new(),
+            entries: HashMap::new(),
+            context: VectorClock::new(),
+            cloud: HashSet::new(),
         }
     }
 
-    pub fn add(&mut self, value: T, unique_id: String) {
-        self.added.entry(value).or_default().insert(unique_id);
-    }
-
-    pub fn remove(&mut self, value: &T) {
-        if let Some(ids) = self.added.get(value) {
-            for id in ids {
-                self.removed.insert(id.clone());
+    /// 由旧版本的唯一 ID 表示转换：每个 ID 成为计数为 0 的点，已删除的 ID 仅保留在上下文中
+    fn from_legacy(added: HashMap<T, HashSet<String>>, removed: HashSet<String>) -> Self {
+        let mut set = Self::new();
+        for (value, ids) in added {
+            let live: HashSet<Dot> = ids
+                .iter()
+                .filter(|id| !removed.contains(*id))
+                .map(|id| Dot::new(id, 0))
+                .collect();
+            set.cloud.extend(ids.iter().map(|id| Dot::new(id, 0)));
+            if !live.is_empty() {
+                set.entries.insert(value, live);
             }
         }
+        set.cloud.extend(removed.iter().map(|id| Dot::new(id, 0)));
+        set
     }
 
-    #[allow(dead_code)]
-    pub fn contains(&self, value: &T) -> bool {
-        if let Some(ids) = self.added.get(value) {
-            ids.iter().any(|id| !self.removed.contains(id))
-        } else {
-            false
-        }
+    /// 点是否已被因果上下文覆盖（已观察到）
+    pub fn covers(&self, dot: &Dot) -> bool {
+        (dot.counter > 0 && dot.counter <= self.context.get(&dot.node_id))
+            || self.cloud.contains(dot)
     }
 
-    pub fn elements(&self) -> Vec<T> {
-        self.added
+    /// 节点在此集合上的下一个点
+    pub fn next_dot(&self, node_id: &str) -> Dot {
+        let counter = self
+            .cloud
             .iter()
-            .filter_map(|(value, ids)| {
-                if ids.iter().any(|id| !self.removed.contains(id)) {
-                    Some(value.clone())
-                } else {
-                    None
-                }
-            })
-            .collect()
+            .filter(|dot| dot.node_id == node_id)
+            .map(|dot| dot.counter)
+            .fold(self.context.get(node_id), u64::max);
+        Dot::new(node_id, counter + 1)
     }
 
-    pub fn merge(&mut self, other: &ORSet<T>) {
-        for (value, ids) in &other.added {
-            self.added
-                .entry(value.clone())
-                .or_default()
-                .extend(ids.clone());
+    /// 以节点的下一个点添加元素，返回该点
+    pub fn add(&mut self, value: T, node_id: &str) -> Dot {
+        let dot = self.next_dot(node_id);
+        self.add_dot(value, dot.clone());
+        dot
+    }
+
+    /// 以指定的点添加元素（重放操作时使用），已观察到的点（含已移除的）被忽略
+    pub fn add_dot(&mut self, value: T, dot: Dot) {
+        if self.covers(&dot) {
+            return;
         }
-        self.removed.extend(other.removed.clone());
+        self.observe(dot.clone());
+        self.entries.entry(value).or_default().insert(dot);
     }
-}
 
-impl<T: Clone + Eq + std::hash::Hash> Default for ORSet<T> {
-    fn default() -> Self {
-        Self::new()
+    /// 移除元素当前所有存活的点，返回被移除的点（已排序）
+    pub fn remove(&mut self, value: &T) -> Vec<Dot> {
+        let mut dots: Vec<Dot> = self
+            .entries
+            .remove(value)
+            .map(|dots| dots.into_iter().collect())
+            .unwrap_or_default();
+        dots.sort();
+        dots
     }
-}
 
-/// CRDT Map - 支持多种 CRDT 类型的映射
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum CRDTValue {
-    GCounter(GCounter),
-    PNCounter(PNCounter),
-    LWWRegister(LWWRegister<String>),
-    ORSet(ORSet<String>),
-}
+    /// 移除指定的点（重放远端移除时使用）
+    ///
+    /// 尚未观察到的点同样记入上下文，之后才到达的对应添加不再生效。
+    pub fn remove_dots(&mut self, value: &T, dots: &[Dot]) {
+        if let Some(live) = self.entries.get_mut(value) {
+            for dot in dots {
+                live.remove(dot);
+            }
+            if live.is_empty() {
+                self.entries.remove(value);
+            }
+        }
+        for dot in dots {
+            self.observe(dot.clone());
+        }
+    }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CRDTMap {
-    pub entries: HashMap<String, CRDTValue>,
-    pub vector_clock: VectorClock,
-}
+    /// 元素当前存活的点（已排序）
+    pub fn dots(&self, value: &T) -> Vec<Dot> {
+        let mut dots: Vec<Dot> = self
+            .entries
+            .get(value)
+            .map(|dots| dots.iter().cloned().collect())
+            .unwrap_or_default();
+        dots.sort();
+        dots
+    }
 
-impl CRDTMap {
-    pub fn new() -> Self {
-        Self {
-            entries: HashMap::new(),
-            vector_clock: VectorClock::new(),
+    /// 将点记入因果上下文
+    fn observe(&mut self, dot: Dot) {
+        if self.covers(&dot) {
+            return;
+        }
+        if dot.counter > 0 && dot.counter == self.context.get(&dot.node_id) + 1 {
+            self.context.increment(&dot.node_id);
+        } else {
+            self.cloud.insert(dot);
         }
+        self.compact();
     }
 
     #[allow(dead_code)]
-    pub fn get(&self, key: &str) -> Option<&CRDTValue> {
-        self.entries.get(key)
+    pub fn contains(&self, value: &T) -> bool {
+        self.entries.contains_key(value)
     }
 
-    #[allow(dead_code)]
-    pub fn set(&mut self, key: String, value: CRDTValue) {
-        self.entries.insert(key, value);
+    pub fn elements(&self) -> Vec<T> {
+        self.entries.keys().cloned().collect()
     }
 
-    pub fn merge(&mut self, other: &CRDTMap) {
-        for (key, other_value) in &other.entries {
-            match (self.entries.get_mut(key), other_value) {
-                (Some(CRDTValue::GCounter(a)), CRDTValue::GCounter(b)) => a.merge(b),
-                (Some(CRDTValue::PNCounter(a)), CRDTValue::PNCounter(b)) => a.merge(b),
-                (Some(CRDTValue::LWWRegister(a)), CRDTValue::LWWRegister(b)) => a.merge(b),
-                (Some(CRDTValue::ORSet(a)), CRDTValue::ORSet(b)) => a.merge(b),
-                (None, _) => {
-                    self.entries.insert(key.clone(), other_value.clone());
-                }
-                _ => {
-                    // 类型不匹配，保持不变或采用其他策略
-                }
+    /// 合并：双方都存活的点保留；只在一方存活的点，若另一方的上下文已覆盖则视为已被移除
+    ///
+    /// 合并后将连续的点并入向量时钟，被覆盖的点不再单独记录。
+    pub fn merge(&mut self, other: &ORSet<T>) {
+        let empty = HashSet::new();
+        let values: HashSet<&T> = self.entries.keys().chain(other.entries.keys()).collect();
+        let mut entries = HashMap::new();
+        for value in values {
+            let ours = self.entries.get(value).unwrap_or(&empty);
+            let theirs = other.entries.get(value).unwrap_or(&empty);
+            let dots: HashSet<Dot> = ours
+                .iter()
+                .filter(|dot| theirs.contains(*dot) || !other.covers(dot))
+                .chain(theirs.iter().filter(|dot| !self.covers(dot)))
+                .cloned()
+                .collect();
+            if !dots.is_empty() {
+                entries.insert(value.clone(), dots);
             }
         }
-        self.vector_clock.merge(&other.vector_clock);
+        self.entries = entries;
+        self.context.merge(&other.context);
+        self.cloud.extend(other.cloud.iter().cloned());
+        self.compact();
     }
 
-    pub fn state_hash(&self) -> String {
-        let mut hasher = Sha256::new();
-        let mut sorted: Vec<_> = self.entries.iter().collect();
-        sorted.sort_by(|a, b| a.0.cmp(b.0));
-        for (key, value) in sorted {
-            hasher.update(key.as_bytes());
-            match value {
-                CRDTValue::GCounter(c) => hasher.update(c.state_hash().as_bytes()),
-                CRDTValue::PNCounter(c) => hasher.update(c.state_hash().as_bytes()),
-                CRDTValue::LWWRegister(r) => {
-                    if let Some(v) = r.get() {
-                        hasher.update(v.as_bytes());
-                    }
-                    hasher.update(r.timestamp.to_le_bytes());
-                }
-                CRDTValue::ORSet(s) => {
-                    let mut elements = s.elements();
-                    elements.sort();
-                    for elem in elements {
-                        hasher.update(elem.as_bytes());
-                    }
-                }
+    /// 对端（此集合的因果上下文为 `clock`）缺失的增量
+    ///
+    /// 包含对端未观察到的存活点，上下文则由本地已观察到的点中除对端已有的存活点之外的全部点组成：
+    /// 合并增量时对端其余存活的点不受影响，本地已移除的点在对端也被移除。
+    pub fn delta_since(&self, clock: &VectorClock) -> ORSet<T> {
+        let peer_covers = |dot: &Dot| dot.counter > 0 && dot.counter <= clock.get(&dot.node_id);
+        let live: HashSet<&Dot> = self.entries.values().flatten().collect();
+
+        let mut delta = ORSet::new();
+        for (value, dots) in &self.entries {
+            let missing: HashSet<Dot> = dots
+                .iter()
+                .filter(|dot| !peer_covers(dot))
+                .cloned()
+                .collect();
+            if !missing.is_empty() {
+                delta.entries.insert(value.clone(), missing);
             }
         }
-        hex::encode(hasher.finalize())
+        let observed = self
+            .context
+            .clocks
+            .iter()
+            .flat_map(|(node, &counter)| (1..=counter).map(move |counter| Dot::new(node, counter)))
+            .chain(self.cloud.iter().cloned());
+        delta.cloud = observed
+            .filter(|dot| !(live.contains(dot) && peer_covers(dot)))
+            .collect();
+        delta
+    }
+
+    /// 需要逐个记录的已移除点数量（乱序到达或来自旧版本、尚未并入向量时钟的点）
+    pub fn tombstone_count(&self) -> usize {
+        let live: HashSet<&Dot> = self.entries.values().flatten().collect();
+        self.cloud.iter().filter(|dot| !live.contains(dot)).count()
+    }
+
+    /// 将与向量时钟连续的点并入 `context`，并丢弃已被覆盖的点，返回点集中减少的点数
+    ///
+    /// `elements()` 不变；合并与添加时会自动调用。
+    pub fn compact(&mut self) -> usize {
+        let before = self.cloud.len();
+        loop {
+            let next: Vec<NodeId> = self
+                .cloud
+                .iter()
+                .filter(|dot| dot.counter > 0 && dot.counter == self.context.get(&dot.node_id) + 1)
+                .map(|dot| dot.node_id.clone())
+                .collect();
+            if next.is_empty() {
+                break;
+            }
+            for node in next {
+                self.context.increment(&node);
+            }
+        }
+        let context = &self.context;
+        self.cloud
+            .retain(|dot| dot.counter == 0 || dot.counter > context.get(&dot.node_id));
+        before - self.cloud.len()
     }
 }
 
-impl Default for CRDTMap {
+impl<T: Clone + Eq + std::hash::Hash> Default for ORSet<T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// G-Set - 只增集合
+///
+/// 元素一经加入不可移除，合并即取并集；无需像 OR-Set 那样追踪标识符与墓碑，合并开销更低。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GSet<T: Eq + std::hash::Hash> {
+    pub elements: HashSet<T>,
+}
 
-    #[test]
-    fn test_vector_clock_increment_and_merge() {
+impl<T: Clone + Eq + std::hash::Hash> GSet<T> {
+    pub fn new() -> Self {
+        Self {
+            elements: HashSet::new(),
+        }
+    }
+
+    pub fn add(&mut self, value: T) {
+        self.elements.insert(value);
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.elements.contains(value)
+    }
+
+    pub fn elements(&self) -> Vec<T> {
+        self.elements.iter().cloned().collect()
+    }
+
+    pub fn merge(&mut self, other: &GSet<T>) {
+        self.elements.extend(other.elements.iter().cloned());
+    }
+}
+
+impl<T: Clone + Ord + std::hash::Hash + AsRef<[u8]>> GSet<T> {
+    pub fn state_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        let mut sorted: Vec<_> = self.elements.iter().collect();
+        sorted.sort();
+        for element in sorted {
+            hasher.update(element.as_ref());
+            // 分隔符避免 ["ab"] 与 ["a", "b"] 哈希相同
+            hasher.update([0u8]);
+        }
+        hex::encode(hasher.finalize())
+    }
+}
+
+impl<T: Clone + Eq + std::hash::Hash> Default for GSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Max Register - 只保留观察到的最大值，并发写入与时间戳无关
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaxRegister<T> {
+    pub value: Option<T>,
+}
+
+impl<T: Ord + Clone> MaxRegister<T> {
+    pub fn new() -> Self {
+        Self { value: None }
+    }
+
+    /// 仅当新值更大时更新
+    pub fn set(&mut self, value: T) {
+        if self.value.as_ref().is_none_or(|current| value > *current) {
+            self.value = Some(value);
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+
+    pub fn merge(&mut self, other: &MaxRegister<T>) {
+        if let Some(value) = &other.value {
+            self.set(value.clone());
+        }
+    }
+}
+
+impl<T: Ord + Clone> Default for MaxRegister<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Min Register - 只保留观察到的最小值，并发写入与时间戳无关
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MinRegister<T> {
+    pub value: Option<T>,
+}
+
+impl<T: Ord + Clone> MinRegister<T> {
+    pub fn new() -> Self {
+        Self { value: None }
+    }
+
+    /// 仅当新值更小时更新
+    pub fn set(&mut self, value: T) {
+        if self.value.as_ref().is_none_or(|current| value < *current) {
+            self.value = Some(value);
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+
+    pub fn merge(&mut self, other: &MinRegister<T>) {
+        if let Some(value) = &other.value {
+            self.set(value.clone());
+        }
+    }
+}
+
+impl<T: Ord + Clone> Default for MinRegister<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// MV Register - 多值寄存器，保留所有并发写入的值
+///
+/// 每个值携带写入时的向量时钟；新写入覆盖其因果历史中的值，
+/// 合并后只保留未被其他值的时钟支配的条目。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MVRegister<T> {
+    pub entries: Vec<(T, VectorClock)>,
+}
+
+impl<T: Clone + PartialEq> MVRegister<T> {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// 以写入时的向量时钟设置值，覆盖所有因果上更早的值
+    pub fn set(&mut self, value: T, clock: VectorClock) {
+        self.entries
+            .retain(|(_, existing)| !existing.happens_before(&clock) && *existing != clock);
+        self.entries.push((value, clock));
+    }
+
+    /// 当前所有并发值
+    pub fn values(&self) -> Vec<T> {
+        self.entries
+            .iter()
+            .map(|(value, _)| value.clone())
+            .collect()
+    }
+
+    pub fn merge(&mut self, other: &MVRegister<T>) {
+        for entry in &other.entries {
+            if !self.entries.contains(entry) {
+                self.entries.push(entry.clone());
+            }
+        }
+        let all = self.entries.clone();
+        self.entries
+            .retain(|(_, clock)| !all.iter().any(|(_, other)| clock.happens_before(other)));
+    }
+}
+
+impl<T: Clone + PartialEq> Default for MVRegister<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// RGA 元素的位置标识：(Lamport 计数, 节点)，全局唯一且全序
+///
+/// 文本形式为 `计数@节点`（如 `3@node1`）。
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct RgaId {
+    pub counter: u64,
+    pub node_id: NodeId,
+}
+
+impl std::fmt::Display for RgaId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.counter, self.node_id)
+    }
+}
+
+impl std::str::FromStr for RgaId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (counter, node_id) = s
+            .split_once('@')
+            .filter(|(_, node_id)| !node_id.is_empty())
+            .ok_or_else(|| format!("Invalid RGA position {:?}: expected counter@node", s))?;
+        let counter = counter
+            .parse()
+            .map_err(|_| format!("Invalid RGA position {:?}: counter is not an integer", s))?;
+        Ok(Self {
+            counter,
+            node_id: node_id.to_string(),
+        })
+    }
+}
+
+/// RGA 中的单个元素（删除后保留为墓碑）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RgaNode<T> {
+    pub id: RgaId,
+    /// 插入时的前驱，None 表示插入到开头
+    pub after: Option<RgaId>,
+    pub value: T,
+    pub deleted: bool,
+}
+
+/// RGA - 可复制增长数组，用于协同编辑有序列表与文本
+///
+/// 元素按插入时的前驱定位，删除只标记墓碑，使并发插入仍能找到前驱。
+/// 同一前驱之后的并发插入按标识降序排列，因此各副本以任意顺序合并后得到相同序列。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RGA<T> {
+    /// 按文档顺序排列的元素（含墓碑）
+    pub nodes: Vec<RgaNode<T>>,
+}
+
+impl<T: Clone> RGA<T> {
+    pub fn new() -> Self {
+        Self { nodes: Vec::new() }
+    }
+
+    fn position(&self, id: &RgaId) -> Option<usize> {
+        self.nodes.iter().position(|node| node.id == *id)
+    }
+
+    /// 是否包含该位置标识（含墓碑）
+    pub fn contains(&self, id: &RgaId) -> bool {
+        self.position(id).is_some()
+    }
+
+    /// 本节点下一个插入的位置标识：计数大于已观察到的所有元素
+    pub fn next_id(&self, node_id: &str) -> RgaId {
+        let counter = self.nodes.iter().map(|node| node.id.counter).max();
+        RgaId {
+            counter: counter.unwrap_or(0) + 1,
+            node_id: node_id.to_string(),
+        }
+    }
+
+    /// 在 `after` 之后插入（None 表示开头），返回新元素的标识
+    pub fn insert(
+        &mut self,
+        after: Option<&RgaId>,
+        value: T,
+        node_id: &str,
+    ) -> Result<RgaId, String> {
+        if let Some(after) = after
+            && self.position(after).is_none()
+        {
+            return Err(format!("Unknown RGA position: {}", after));
+        }
+        let id = self.next_id(node_id);
+        self.integrate(id.clone(), after.cloned(), value);
+        Ok(id)
+    }
+
+    /// 按已有标识插入元素（重放操作与合并时使用），已存在时忽略；前驱不存在时返回 false
+    pub fn integrate(&mut self, id: RgaId, after: Option<RgaId>, value: T) -> bool {
+        if self.position(&id).is_some() {
+            return true;
+        }
+        let mut index = match &after {
+            None => 0,
+            Some(after) => match self.position(after) {
+                Some(index) => index + 1,
+                None => return false,
+            },
+        };
+        // 跳过同一前驱之后标识更大的并发插入（及其后继，它们的计数必然更大）
+        while index < self.nodes.len() && self.nodes[index].id > id {
+            index += 1;
+        }
+        self.nodes.insert(
+            index,
+            RgaNode {
+                id,
+                after,
+                value,
+                deleted: false,
+            },
+        );
+        true
+    }
+
+    /// 删除元素（保留墓碑），元素不存在时返回 false
+    pub fn delete(&mut self, id: &RgaId) -> bool {
+        match self.nodes.iter_mut().find(|node| node.id == *id) {
+            Some(node) => {
+                node.deleted = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 未删除元素的值（按文档顺序）
+    pub fn to_vec(&self) -> Vec<T> {
+        self.nodes
+            .iter()
+            .filter(|node| !node.deleted)
+            .map(|node| node.value.clone())
+            .collect()
+    }
+
+    /// 未删除元素的标识与值（按文档顺序）
+    pub fn elements(&self) -> Vec<(RgaId, T)> {
+        self.nodes
+            .iter()
+            .filter(|node| !node.deleted)
+            .map(|node| (node.id.clone(), node.value.clone()))
+            .collect()
+    }
+
+    pub fn merge(&mut self, other: &RGA<T>) {
+        // 对端按文档顺序排列，前驱总在后继之前，可逐个整合
+        for node in &other.nodes {
+            self.integrate(node.id.clone(), node.after.clone(), node.value.clone());
+            if node.deleted {
+                self.delete(&node.id);
+            }
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> RGA<T> {
+    /// 状态哈希（包含墓碑，各副本收敛后元素顺序一致）
+    pub fn state_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for node in &self.nodes {
+            hasher.update(node.id.counter.to_le_bytes());
+            hasher.update((node.id.node_id.len() as u64).to_le_bytes());
+            hasher.update(node.id.node_id.as_bytes());
+            hasher.update([node.deleted as u8]);
+            let value = node.value.as_ref();
+            hasher.update((value.len() as u64).to_le_bytes());
+            hasher.update(value);
+        }
+        hex::encode(hasher.finalize())
+    }
+}
+
+impl<T: Clone> Default for RGA<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// CRDT Map - 支持多种 CRDT 类型的映射
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CRDTValue {
+    GCounter(GCounter),
+    PNCounter(PNCounter),
+    BoundedCounter(BoundedCounter),
+    LWWRegister(LWWRegister<String>),
+    LWWMap(LWWMap),
+    ORSet(ORSet<String>),
+    GSet(GSet<String>),
+    MaxRegister(MaxRegister<i64>),
+    MinRegister(MinRegister<i64>),
+    MVRegister(MVRegister<String>),
+    RGA(RGA<String>),
+}
+
+/// 所有 CRDT 类型名称（与 `CRDTValue::type_name` 一致）
+pub const CRDT_TYPES: &[&str] = &[
+    "g-counter",
+    "pn-counter",
+    "bounded-counter",
+    "lww-register",
+    "lww-map",
+    "or-set",
+    "g-set",
+    "max-register",
+    "min-register",
+    "mv-register",
+    "rga",
+];
+
+impl CRDTValue {
+    /// CRDT 类型名称
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            CRDTValue::GCounter(_) => "g-counter",
+            CRDTValue::PNCounter(_) => "pn-counter",
+            CRDTValue::BoundedCounter(_) => "bounded-counter",
+            CRDTValue::LWWRegister(_) => "lww-register",
+            CRDTValue::LWWMap(_) => "lww-map",
+            CRDTValue::ORSet(_) => "or-set",
+            CRDTValue::GSet(_) => "g-set",
+            CRDTValue::MaxRegister(_) => "max-register",
+            CRDTValue::MinRegister(_) => "min-register",
+            CRDTValue::MVRegister(_) => "mv-register",
+            CRDTValue::RGA(_) => "rga",
+        }
+    }
+
+    /// 无损转换为目标类型，不支持的转换返回 None
+    ///
+    /// 目前仅支持 GCounter → PNCounter（原值计入正向计数）。
+    pub fn converted(&self, to_type: &str) -> Option<CRDTValue> {
+        match (self, to_type) {
+            (CRDTValue::GCounter(c), "pn-counter") => Some(CRDTValue::PNCounter(PNCounter {
+                positive: c.clone(),
+                negative: GCounter::new(),
+            })),
+            _ => None,
+        }
+    }
+
+    /// 将值的状态写入哈希（与 `CRDTMap::state_hash` 中单个键的贡献一致）
+    fn hash_into(&self, hasher: &mut Sha256) {
+        match self {
+            CRDTValue::GCounter(c) => hasher.update(c.state_hash().as_bytes()),
+            CRDTValue::PNCounter(c) => hasher.update(c.state_hash().as_bytes()),
+            CRDTValue::BoundedCounter(c) => hasher.update(c.state_hash().as_bytes()),
+            CRDTValue::LWWRegister(r) => {
+                if let Some(v) = r.get() {
+                    hasher.update(v.as_bytes());
+                }
+                hasher.update(r.timestamp.to_le_bytes());
+            }
+            CRDTValue::LWWMap(m) => hasher.update(m.state_hash().as_bytes()),
+            CRDTValue::ORSet(s) => {
+                let mut elements = s.elements();
+                elements.sort();
+                for elem in elements {
+                    hasher.update(elem.as_bytes());
+                }
+            }
+            CRDTValue::GSet(s) => hasher.update(s.state_hash().as_bytes()),
+            CRDTValue::MaxRegister(r) => {
+                if let Some(v) = r.get() {
+                    hasher.update(v.to_le_bytes());
+                }
+            }
+            CRDTValue::MinRegister(r) => {
+                if let Some(v) = r.get() {
+                    hasher.update(v.to_le_bytes());
+                }
+            }
+            CRDTValue::MVRegister(r) => {
+                let mut values = r.values();
+                values.sort();
+                for value in values {
+                    hasher.update(value.as_bytes());
+                }
+            }
+            CRDTValue::RGA(r) => hasher.update(r.state_hash().as_bytes()),
+        }
+    }
+
+    /// 单个值的状态哈希
+    pub fn state_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        self.hash_into(&mut hasher);
+        hex::encode(hasher.finalize())
+    }
+
+    /// 投影为可序列化的类型化视图（集合元素与多值寄存器的值已排序）
+    pub fn view(&self) -> ValueView {
+        let sorted = |mut items: Vec<String>| {
+            items.sort();
+            items
+        };
+        match self {
+            CRDTValue::GCounter(c) => ValueView::GCounter { value: c.value() },
+            CRDTValue::PNCounter(c) => ValueView::PNCounter { value: c.value() },
+            CRDTValue::BoundedCounter(c) => ValueView::BoundedCounter { value: c.value() },
+            CRDTValue::LWWRegister(r) => ValueView::LWWRegister {
+                value: r.get().cloned(),
+            },
+            CRDTValue::LWWMap(m) => ValueView::LWWMap { fields: m.fields() },
+            CRDTValue::ORSet(s) => ValueView::ORSet {
+                elements: sorted(s.elements()),
+            },
+            CRDTValue::GSet(s) => ValueView::GSet {
+                elements: sorted(s.elements()),
+            },
+            CRDTValue::MaxRegister(r) => ValueView::MaxRegister {
+                value: r.get().copied(),
+            },
+            CRDTValue::MinRegister(r) => ValueView::MinRegister {
+                value: r.get().copied(),
+            },
+            CRDTValue::MVRegister(r) => ValueView::MVRegister {
+                values: sorted(r.values()),
+            },
+            CRDTValue::RGA(r) => ValueView::RGA {
+                elements: r
+                    .elements()
+                    .into_iter()
+                    .map(|(id, value)| RgaElement {
+                        id: id.to_string(),
+                        value,
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    /// 物化后的值（计数器与数值寄存器为数值，寄存器为字符串或 null，集合与多值寄存器为排序后的数组，
+    /// LWW-Map 为未删除字段组成的对象，RGA 为按顺序排列的数组）
+    pub fn materialized(&self) -> serde_json::Value {
+        match self {
+            CRDTValue::GCounter(c) => c.value().into(),
+            CRDTValue::PNCounter(c) => c.value().into(),
+            CRDTValue::BoundedCounter(c) => c.value().into(),
+            CRDTValue::LWWRegister(r) => r.get().cloned().into(),
+            CRDTValue::LWWMap(m) => serde_json::to_value(m.fields()).unwrap_or_default(),
+            CRDTValue::ORSet(s) => {
+                let mut elements = s.elements();
+                elements.sort();
+                elements.into()
+            }
+            CRDTValue::GSet(s) => {
+                let mut elements = s.elements();
+                elements.sort();
+                elements.into()
+            }
+            CRDTValue::MaxRegister(r) => r.get().copied().into(),
+            CRDTValue::MinRegister(r) => r.get().copied().into(),
+            CRDTValue::MVRegister(r) => {
+                let mut values = r.values();
+                values.sort();
+                values.into()
+            }
+            CRDTValue::RGA(r) => r.to_vec().into(),
+        }
+    }
+}
+
+/// 单个键的类型化视图，`type` 字段为 CRDT 类型名称
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum ValueView {
+    #[serde(rename = "g-counter")]
+    GCounter { value: u64 },
+    #[serde(rename = "pn-counter")]
+    PNCounter { value: i64 },
+    #[serde(rename = "bounded-counter")]
+    BoundedCounter { value: i64 },
+    #[serde(rename = "lww-register")]
+    LWWRegister { value: Option<String> },
+    #[serde(rename = "lww-map")]
+    LWWMap { fields: BTreeMap<String, String> },
+    #[serde(rename = "or-set")]
+    ORSet { elements: Vec<String> },
+    #[serde(rename = "g-set")]
+    GSet { elements: Vec<String> },
+    #[serde(rename = "max-register")]
+    MaxRegister { value: Option<i64> },
+    #[serde(rename = "min-register")]
+    MinRegister { value: Option<i64> },
+    #[serde(rename = "mv-register")]
+    MVRegister { values: Vec<String> },
+    #[serde(rename = "rga")]
+    RGA { elements: Vec<RgaElement> },
+}
+
+/// RGA 视图中的元素，`id` 可作为后续插入的前驱或删除的目标
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RgaElement {
+    pub id: String,
+    pub value: String,
+}
+
+/// 单个键物化值的变化
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValueChange {
+    pub from: serde_json::Value,
+    pub to: serde_json::Value,
+}
+
+/// 两个 CRDTMap 之间按键比较的差异
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MapDiff {
+    pub added: BTreeMap<String, serde_json::Value>,
+    pub removed: BTreeMap<String, serde_json::Value>,
+    pub changed: BTreeMap<String, ValueChange>,
+}
+
+impl MapDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// 键列表的展示排序方式（不影响 state_hash）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyCollation {
+    /// 按 UTF-8 字节序
+    #[default]
+    Byte,
+    /// 忽略大小写，大小写不同的相同键再按字节序
+    CaseInsensitive,
+}
+
+/// 键列表的过滤条件
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyFilter {
+    /// 只列出以此开头的键
+    pub prefix: Option<String>,
+    /// 只列出此类型的键（`CRDT_TYPES` 之一）
+    pub crdt_type: Option<String>,
+}
+
+impl KeyFilter {
+    pub fn matches(&self, key: &str, value: &CRDTValue) -> bool {
+        self.prefix
+            .as_deref()
+            .is_none_or(|prefix| key.starts_with(prefix))
+            && self
+                .crdt_type
+                .as_deref()
+                .is_none_or(|crdt_type| value.type_name() == crdt_type)
+    }
+}
+
+/// 键及其 CRDT 类型
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeyInfo {
+    pub key: String,
+    #[serde(rename = "type")]
+    pub crdt_type: &'static str,
+}
+
+/// 合并时同一键两侧 CRDT 类型不一致（本地值保持不变）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeConflict {
+    pub key: String,
+    pub local_type: String,
+    pub remote_type: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CRDTMap {
+    pub entries: HashMap<String, CRDTValue>,
+    pub vector_clock: VectorClock,
+}
+
+impl CRDTMap {
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            vector_clock: VectorClock::new(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get(&self, key: &str) -> Option<&CRDTValue> {
+        self.entries.get(key)
+    }
+
+    #[allow(dead_code)]
+    pub fn set(&mut self, key: String, value: CRDTValue) {
+        self.entries.insert(key, value);
+    }
+
+    pub fn merge(&mut self, other: &CRDTMap) {
+        self.merge_checked(other);
+    }
+
+    /// 合并并返回类型不一致的键，这些键保留本地值
+    pub fn merge_checked(&mut self, other: &CRDTMap) -> Vec<MergeConflict> {
+        let mut conflicts = Vec::new();
+        for (key, other_value) in &other.entries {
+            match (self.entries.get_mut(key), other_value) {
+                (Some(CRDTValue::GCounter(a)), CRDTValue::GCounter(b)) => a.merge(b),
+                (Some(CRDTValue::PNCounter(a)), CRDTValue::PNCounter(b)) => a.merge(b),
+                (Some(CRDTValue::BoundedCounter(a)), CRDTValue::BoundedCounter(b)) => a.merge(b),
+                (Some(CRDTValue::LWWRegister(a)), CRDTValue::LWWRegister(b)) => a.merge(b),
+                (Some(CRDTValue::LWWMap(a)), CRDTValue::LWWMap(b)) => a.merge(b),
+                (Some(CRDTValue::ORSet(a)), CRDTValue::ORSet(b)) => a.merge(b),
+                (Some(CRDTValue::GSet(a)), CRDTValue::GSet(b)) => a.merge(b),
+                (Some(CRDTValue::MaxRegister(a)), CRDTValue::MaxRegister(b)) => a.merge(b),
+                (Some(CRDTValue::MinRegister(a)), CRDTValue::MinRegister(b)) => a.merge(b),
+                (Some(CRDTValue::MVRegister(a)), CRDTValue::MVRegister(b)) => a.merge(b),
+                (Some(CRDTValue::RGA(a)), CRDTValue::RGA(b)) => a.merge(b),
+                // GCounter 可无损嵌入 PNCounter 的正向计数，已转换与未转换的副本据此收敛
+                (Some(CRDTValue::PNCounter(a)), CRDTValue::GCounter(b)) => a.positive.merge(b),
+                (Some(local @ CRDTValue::GCounter(_)), CRDTValue::PNCounter(b)) => {
+                    if let Some(CRDTValue::PNCounter(mut promoted)) = local.converted("pn-counter")
+                    {
+                        promoted.merge(b);
+                        *local = CRDTValue::PNCounter(promoted);
+                    }
+                }
+                (None, _) => {
+                    self.entries.insert(key.clone(), other_value.clone());
+                }
+                (Some(local), _) => conflicts.push(MergeConflict {
+                    key: key.clone(),
+                    local_type: local.type_name().to_string(),
+                    remote_type: other_value.type_name().to_string(),
+                }),
+            }
+        }
+        self.vector_clock.merge(&other.vector_clock);
+        conflicts.sort_by(|a, b| a.key.cmp(&b.key));
+        conflicts
+    }
+
+    /// 移除节点的时钟分量，并将其计数器贡献并入遗忘基线
+    pub fn forget_node(&mut self, node_id: &str) {
+        for value in self.entries.values_mut() {
+            match value {
+                CRDTValue::GCounter(c) => c.forget_node(node_id),
+                CRDTValue::PNCounter(c) => c.forget_node(node_id),
+                CRDTValue::BoundedCounter(c) => c.counts.forget_node(node_id),
+                _ => {}
+            }
+        }
+        self.vector_clock.remove(node_id);
+    }
+
+    /// 对所有计数器应用单节点上限，返回被截断的 (键, 节点)
+    pub fn clamp_counters(&mut self, cap: u64) -> Vec<(String, NodeId)> {
+        let mut clamped = Vec::new();
+        for (key, value) in self.entries.iter_mut() {
+            let nodes = match value {
+                CRDTValue::GCounter(c) => c.clamp_to(cap),
+                CRDTValue::PNCounter(c) => {
+                    let mut nodes = c.positive.clamp_to(cap);
+                    nodes.extend(c.negative.clamp_to(cap));
+                    nodes
+                }
+                CRDTValue::BoundedCounter(c) => {
+                    let mut nodes = c.counts.positive.clamp_to(cap);
+                    nodes.extend(c.counts.negative.clamp_to(cap));
+                    nodes
+                }
+                _ => continue,
+            };
+            clamped.extend(nodes.into_iter().map(|node| (key.clone(), node)));
+        }
+        clamped
+    }
+
+    /// 丢弃节点的时钟分量与计数器贡献（用于过滤来自已遗忘节点的传入数据）
+    pub fn discard_node(&mut self, node_id: &str) {
+        for value in self.entries.values_mut() {
+            match value {
+                CRDTValue::GCounter(c) => c.discard_node(node_id),
+                CRDTValue::PNCounter(c) => {
+                    c.positive.discard_node(node_id);
+                    c.negative.discard_node(node_id);
+                }
+                CRDTValue::BoundedCounter(c) => {
+                    c.counts.positive.discard_node(node_id);
+                    c.counts.negative.discard_node(node_id);
+                }
+                _ => {}
+            }
+        }
+        self.vector_clock.remove(node_id);
+    }
+
+    /// 物化为逻辑文档：键到物化值的映射，不含 CRDT 元数据
+    pub fn document(&self) -> BTreeMap<String, serde_json::Value> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.clone(), value.materialized()))
+            .collect()
+    }
+
+    /// 计算从当前状态到 `other` 的键级差异（比较物化值）
+    pub fn diff(&self, other: &CRDTMap) -> MapDiff {
+        let mut diff = MapDiff::default();
+
+        for (key, value) in &self.entries {
+            match other.entries.get(key) {
+                None => {
+                    diff.removed.insert(key.clone(), value.materialized());
+                }
+                Some(other_value) => {
+                    let (from, to) = (value.materialized(), other_value.materialized());
+                    if from != to {
+                        diff.changed.insert(key.clone(), ValueChange { from, to });
+                    }
+                }
+            }
+        }
+        for (key, value) in &other.entries {
+            if !self.entries.contains_key(key) {
+                diff.added.insert(key.clone(), value.materialized());
+            }
+        }
+
+        diff
+    }
+
+    /// 按指定排序方式列出满足过滤条件的键及其类型（仅用于展示）
+    pub fn key_listing(&self, collation: KeyCollation, filter: &KeyFilter) -> Vec<KeyInfo> {
+        let mut keys: Vec<KeyInfo> = self
+            .entries
+            .iter()
+            .filter(|(key, value)| filter.matches(key, value))
+            .map(|(key, value)| KeyInfo {
+                key: key.clone(),
+                crdt_type: value.type_name(),
+            })
+            .collect();
+        match collation {
+            KeyCollation::Byte => keys.sort_by(|a, b| a.key.cmp(&b.key)),
+            KeyCollation::CaseInsensitive => keys.sort_by(|a, b| {
+                a.key
+                    .to_lowercase()
+                    .cmp(&b.key.to_lowercase())
+                    .then_with(|| a.key.cmp(&b.key))
+            }),
+        }
+        keys
+    }
+
+    /// 构建按键哈希分桶的 Merkle 树
+    pub fn merkle_tree(&self) -> MerkleTree {
+        MerkleTree::build(self)
+    }
+
+    /// Merkle 根哈希（与 `state_hash` 相互独立，空 Map 为 `""`）
+    pub fn merkle_root(&self) -> String {
+        self.merkle_tree().root().to_string()
+    }
+
+    /// 状态哈希
+    ///
+    /// 键始终按 UTF-8 字节序参与哈希，与区域设置及展示排序无关，保证各节点结果一致。
+    pub fn state_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        let mut sorted: Vec<_> = self.entries.iter().collect();
+        sorted.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+        for (key, value) in sorted {
+            hasher.update(key.as_bytes());
+            value.hash_into(&mut hasher);
+        }
+        hex::encode(hasher.finalize())
+    }
+}
+
+impl Default for CRDTMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Merkle 树深度：叶子由键的 SHA-256 前 `MERKLE_DEPTH` 个十六进制位确定，每层 16 个分支
+pub const MERKLE_DEPTH: usize = 4;
+
+/// 按键哈希前缀分桶的 Merkle 树
+///
+/// 键按哈希而非排序位置分桶，增删键只影响所在叶子到根的路径，因此两个键集合不同的节点
+/// 也能逐层对齐比较：从根开始只展开哈希不一致的子树，最多 `MERKLE_DEPTH + 1` 轮即可定位差异键。
+/// 节点以十六进制前缀作为路径（根为 `""`），空子树不出现在树中，其哈希视为 `""`。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MerkleTree {
+    leaves: BTreeMap<String, BTreeMap<String, String>>, // 叶子路径 → 键 → 值哈希
+    nodes: BTreeMap<String, String>,                    // 非空节点路径 → 哈希
+}
+
+/// 按对端提供的节点哈希比较后的本地差异
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MerkleDiff {
+    /// 哈希与对端不一致的节点路径
+    pub mismatched: Vec<String>,
+    /// 不一致的内部节点在本地的非空子节点哈希（下一轮比较的输入）
+    pub children: BTreeMap<String, String>,
+    /// 不一致的叶子中本地的键及其值哈希
+    pub keys: BTreeMap<String, String>,
+}
+
+/// 键所在的叶子路径
+fn merkle_leaf_path(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))[..MERKLE_DEPTH].to_string()
+}
+
+impl MerkleTree {
+    pub fn build(map: &CRDTMap) -> Self {
+        let mut leaves: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        for (key, value) in &map.entries {
+            leaves
+                .entry(merkle_leaf_path(key))
+                .or_default()
+                .insert(key.clone(), value.state_hash());
+        }
+
+        // 叶子哈希覆盖按字节序排列的 (键, 值哈希)
+        let mut nodes = BTreeMap::new();
+        for (path, entries) in &leaves {
+            let mut hasher = Sha256::new();
+            for (key, value_hash) in entries {
+                hasher.update((key.len() as u64).to_le_bytes());
+                hasher.update(key.as_bytes());
+                hasher.update(value_hash.as_bytes());
+            }
+            nodes.insert(path.clone(), hex::encode(hasher.finalize()));
+        }
+
+        // 内部节点哈希覆盖按分支排列的非空子节点
+        for depth in (0..MERKLE_DEPTH).rev() {
+            let mut parents: BTreeMap<String, Sha256> = BTreeMap::new();
+            for (path, hash) in nodes.iter().filter(|(path, _)| path.len() == depth + 1) {
+                let hasher = parents.entry(path[..depth].to_string()).or_default();
+                hasher.update(path[depth..].as_bytes());
+                hasher.update(hash.as_bytes());
+            }
+            for (path, hasher) in parents {
+                nodes.insert(path, hex::encode(hasher.finalize()));
+            }
+        }
+
+        Self { leaves, nodes }
+    }
+
+    /// 根哈希，空树为 `""`
+    pub fn root(&self) -> &str {
+        self.node_hash("")
+    }
+
+    /// 节点哈希，空子树为 `""`
+    pub fn node_hash(&self, path: &str) -> &str {
+        self.nodes.get(path).map_or("", String::as_str)
+    }
+
+    /// 内部节点的非空子节点哈希
+    pub fn children(&self, path: &str) -> BTreeMap<String, String> {
+        self.nodes
+            .range(path.to_string()..)
+            .take_while(|(child, _)| child.starts_with(path))
+            .filter(|(child, _)| child.len() == path.len() + 1)
+            .map(|(child, hash)| (child.clone(), hash.clone()))
+            .collect()
+    }
+
+    /// 与对端提供的节点哈希逐一比较；路径必须是长度不超过 `MERKLE_DEPTH` 的小写十六进制前缀
+    pub fn compare(&self, remote: &BTreeMap<String, String>) -> Result<MerkleDiff, String> {
+        let mut diff = MerkleDiff::default();
+        for (path, hash) in remote {
+            if path.len() > MERKLE_DEPTH
+                || !path
+                    .bytes()
+                    .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+            {
+                return Err(format!("Invalid Merkle node path: {:?}", path));
+            }
+            if self.node_hash(path) == hash {
+                continue;
+            }
+            diff.mismatched.push(path.clone());
+            if path.len() == MERKLE_DEPTH {
+                if let Some(entries) = self.leaves.get(path) {
+                    diff.keys.extend(entries.clone());
+                }
+            } else {
+                diff.children.extend(self.children(path));
+            }
+        }
+        Ok(diff)
+    }
+
+    /// 本地与 `other` 之间值不同或只存在于一方的键（按字节序），只展开哈希不一致的子树
+    pub fn diff_keys(&self, other: &MerkleTree) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut pending = vec![String::new()];
+        while let Some(path) = pending.pop() {
+            if self.node_hash(&path) == other.node_hash(&path) {
+                continue;
+            }
+            if path.len() == MERKLE_DEPTH {
+                let empty = BTreeMap::new();
+                let local = self.leaves.get(&path).unwrap_or(&empty);
+                let remote = other.leaves.get(&path).unwrap_or(&empty);
+                keys.extend(
+                    local
+                        .keys()
+                        .chain(remote.keys())
+                        .filter(|key| local.get(*key) != remote.get(*key))
+                        .cloned(),
+                );
+            } else {
+                let children: HashSet<String> = self
+                    .children(&path)
+                    .into_keys()
+                    .chain(other.children(&path).into_keys())
+                    .collect();
+                pending.extend(children);
+            }
+        }
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vector_clock_increment_and_merge() {
         let mut vc1 = VectorClock::new();
         let mut vc2 = VectorClock::new();
 
@@ -526,6 +2091,41 @@ mod tests {
         assert_eq!(c1.state_hash(), c2.state_hash());
     }
 
+    #[test]
+    fn test_gcounter_compact_to_baseline() {
+        let mut detailed = GCounter::new();
+        for i in 0..50 {
+            detailed.increment(&format!("node{}", i), i + 1);
+        }
+        let total = detailed.value();
+
+        let mut compacted = detailed.clone();
+        assert_eq!(compacted.compact_to_baseline(), total);
+        assert_eq!(compacted.value(), total);
+        assert!(compacted.counts.is_empty());
+
+        // 与仍持有明细的副本合并：基线作为下限，不会重复计数
+        let mut merged = compacted.clone();
+        merged.merge(&detailed);
+        assert_eq!(merged.value(), total);
+        let mut merged_back = detailed.clone();
+        merged_back.merge(&compacted);
+        assert_eq!(merged_back.value(), total);
+        assert_eq!(merged.state_hash(), merged_back.state_hash());
+
+        // 明细副本观察到基线后，两侧继续递增，合并后得到正确的总值
+        detailed = merged_back;
+        compacted.increment("node0", 2);
+        detailed.increment("node1", 3);
+        let mut left = compacted.clone();
+        left.merge(&detailed);
+        let mut right = detailed.clone();
+        right.merge(&compacted);
+        assert_eq!(left.value(), total + 5);
+        assert_eq!(right.value(), total + 5);
+        assert_eq!(left.state_hash(), right.state_hash());
+    }
+
     #[test]
     fn test_pncounter_increment_decrement() {
         let mut counter = PNCounter::new();
@@ -551,6 +2151,65 @@ mod tests {
         assert_eq!(c1.value(), 12); // 10 + 5 - 3
     }
 
+    #[test]
+    fn test_bounded_counter_rejects_local_decrement_below_bound() {
+        let mut counter = BoundedCounter::new(0);
+        counter.increment("node1", 3);
+
+        assert!(counter.try_decrement("node1", 2).is_ok());
+        assert_eq!(counter.value(), 1);
+        assert!(counter.try_decrement("node1", 2).is_err());
+        assert_eq!(counter.value(), 1);
+        assert!(counter.try_decrement("node2", 1).is_ok());
+        assert_eq!(counter.value(), 0);
+        assert!(counter.try_decrement("node1", 1).is_err());
+    }
+
+    #[test]
+    fn test_bounded_counter_concurrent_decrements_converge_at_bound() {
+        let mut base = BoundedCounter::new(0);
+        base.increment("node1", 2);
+        let mut a = base.clone();
+        let mut b = base.clone();
+
+        // 两个节点各自的本地视图都允许递减，合并后实际越过下界
+        a.try_decrement("node1", 2).unwrap();
+        b.try_decrement("node2", 1).unwrap();
+
+        let mut ab = a.clone();
+        ab.merge(&b);
+        let mut ba = b.clone();
+        ba.merge(&a);
+        assert_eq!(ab, ba);
+        assert_eq!(ab.state_hash(), ba.state_hash());
+        assert_eq!(ab.raw_value(), -1);
+        assert_eq!(ab.value(), 0);
+
+        // 越界后任何递减都被拒绝，补充库存后重新可用
+        assert!(ab.try_decrement("node1", 1).is_err());
+        ab.increment("node2", 2);
+        assert_eq!(ab.value(), 1);
+        assert!(ab.try_decrement("node1", 1).is_ok());
+        assert_eq!(ab.value(), 0);
+    }
+
+    #[test]
+    fn test_bounded_counter_merge_keeps_stricter_bound() {
+        let mut a = BoundedCounter::new(-5);
+        let mut b = BoundedCounter::new(0);
+        a.try_decrement("node1", 3).unwrap();
+        b.increment("node2", 1);
+
+        let mut ab = a.clone();
+        ab.merge(&b);
+        let mut ba = b;
+        ba.merge(&a);
+        assert_eq!(ab, ba);
+        assert_eq!(ab.lower_bound, 0);
+        assert_eq!(ab.raw_value(), -2);
+        assert_eq!(ab.value(), 0);
+    }
+
     #[test]
     fn test_lww_register_set_and_get() {
         let mut reg = LWWRegister::new();
@@ -562,6 +2221,67 @@ mod tests {
         assert_eq!(reg.get(), Some(&"value2".to_string()));
     }
 
+    #[test]
+    fn test_lww_map_concurrent_writes_converge() {
+        let mut a = LWWMap::new();
+        let mut b = LWWMap::new();
+
+        // 同一字段并发写入：时间戳较大者胜出，相同时节点 ID 较大者胜出
+        a.set("color".to_string(), "red".to_string(), 100, "node1");
+        b.set("color".to_string(), "blue".to_string(), 200, "node2");
+        a.set("size".to_string(), "S".to_string(), 300, "node1");
+        b.set("size".to_string(), "M".to_string(), 300, "node2");
+        // 不同字段互不影响
+        a.set("owner".to_string(), "alice".to_string(), 100, "node1");
+        b.set("shape".to_string(), "round".to_string(), 100, "node2");
+
+        let mut ab = a.clone();
+        ab.merge(&b);
+        let mut ba = b.clone();
+        ba.merge(&a);
+        assert_eq!(ab, ba);
+        assert_eq!(ab.state_hash(), ba.state_hash());
+        assert_eq!(ab.get("color"), Some(&"blue".to_string()));
+        assert_eq!(ab.get("size"), Some(&"M".to_string()));
+        assert_eq!(ab.fields().len(), 4);
+
+        // 合并幂等
+        let before = ab.clone();
+        ab.merge(&ba);
+        assert_eq!(ab, before);
+    }
+
+    #[test]
+    fn test_lww_map_remove_is_timestamped_tombstone() {
+        let mut a = LWWMap::new();
+        a.set("color".to_string(), "red".to_string(), 100, "node1");
+
+        let mut b = a.clone();
+        b.remove("color".to_string(), 200, "node2");
+        assert_eq!(b.get("color"), None);
+        assert!(b.fields().is_empty());
+
+        // 早于删除的并发写入被忽略
+        a.set("color".to_string(), "green".to_string(), 150, "node1");
+        let mut merged = a.clone();
+        merged.merge(&b);
+        assert_eq!(merged.get("color"), None);
+
+        // 晚于删除的写入使字段重新出现
+        a.set("color".to_string(), "blue".to_string(), 300, "node1");
+        merged.merge(&a);
+        b.merge(&a);
+        assert_eq!(merged.get("color"), Some(&"blue".to_string()));
+        assert_eq!(merged, b);
+
+        // 墓碑与空字符串值的哈希不同
+        let mut empty = LWWMap::new();
+        empty.set("f".to_string(), String::new(), 1, "n");
+        let mut removed = LWWMap::new();
+        removed.remove("f".to_string(), 1, "n");
+        assert_ne!(empty.state_hash(), removed.state_hash());
+    }
+
     #[test]
     fn test_lww_register_merge_with_timestamp() {
         let mut r1 = LWWRegister::new();
@@ -591,8 +2311,8 @@ mod tests {
     fn test_orset_add_and_elements() {
         let mut set = ORSet::new();
 
-        set.add("item1".to_string(), "id1".to_string());
-        set.add("item2".to_string(), "id2".to_string());
+        set.add("item1".to_string(), "node1");
+        set.add("item2".to_string(), "node1");
 
         let elements = set.elements();
         assert_eq!(elements.len(), 2);
@@ -604,8 +2324,8 @@ mod tests {
     fn test_orset_add_and_remove() {
         let mut set = ORSet::new();
 
-        set.add("item1".to_string(), "id1".to_string());
-        set.add("item2".to_string(), "id2".to_string());
+        set.add("item1".to_string(), "node1");
+        set.add("item2".to_string(), "node1");
         set.remove(&"item1".to_string());
 
         let elements = set.elements();
@@ -618,7 +2338,7 @@ mod tests {
     fn test_orset_contains() {
         let mut set = ORSet::new();
 
-        set.add("item1".to_string(), "id1".to_string());
+        set.add("item1".to_string(), "node1");
         assert!(set.contains(&"item1".to_string()));
 
         set.remove(&"item1".to_string());
@@ -630,9 +2350,9 @@ mod tests {
         let mut s1 = ORSet::new();
         let mut s2 = ORSet::new();
 
-        s1.add("item1".to_string(), "id1".to_string());
-        s2.add("item2".to_string(), "id2".to_string());
-        s2.add("item1".to_string(), "id3".to_string());
+        s1.add("item1".to_string(), "node1");
+        s2.add("item2".to_string(), "node2");
+        s2.add("item1".to_string(), "node2");
 
         s1.merge(&s2);
 
@@ -646,9 +2366,9 @@ mod tests {
     fn test_orset_add_remove_add_semantic() {
         let mut set = ORSet::new();
 
-        set.add("item1".to_string(), "id1".to_string());
+        set.add("item1".to_string(), "node1");
         set.remove(&"item1".to_string());
-        set.add("item1".to_string(), "id2".to_string());
+        set.add("item1".to_string(), "node1");
 
         assert!(set.contains(&"item1".to_string()));
         let elements = set.elements();
@@ -693,6 +2413,87 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_crdt_value_conversion() {
+        let mut counter = GCounter::new();
+        counter.increment("node1", 5);
+        let value = CRDTValue::GCounter(counter);
+
+        match value.converted("pn-counter") {
+            Some(CRDTValue::PNCounter(c)) => assert_eq!(c.value(), 5),
+            _ => panic!("Expected PNCounter"),
+        }
+        assert!(value.converted("or-set").is_none());
+        assert!(
+            CRDTValue::ORSet(ORSet::new())
+                .converted("pn-counter")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_crdt_map_merge_gcounter_with_pncounter() {
+        let mut g = GCounter::new();
+        g.increment("node1", 5);
+        g.increment("node2", 2);
+
+        let mut p = PNCounter::new();
+        p.increment("node1", 3);
+        p.decrement("node1", 1);
+
+        let mut m1 = CRDTMap::new();
+        m1.set("counter".to_string(), CRDTValue::GCounter(g));
+        let mut m2 = CRDTMap::new();
+        m2.set("counter".to_string(), CRDTValue::PNCounter(p));
+
+        let mut a = m1.clone();
+        a.merge(&m2);
+        let mut b = m2.clone();
+        b.merge(&m1);
+
+        assert_eq!(a.state_hash(), b.state_hash());
+        match a.get("counter") {
+            Some(CRDTValue::PNCounter(c)) => assert_eq!(c.value(), 6), // max(5,3) + 2 - 1
+            _ => panic!("Expected PNCounter"),
+        }
+    }
+
+    #[test]
+    fn test_crdt_map_merge_checked_reports_type_mismatch() {
+        let mut local_counter = GCounter::new();
+        local_counter.increment("node1", 4);
+        let mut remote_counter = GCounter::new();
+        remote_counter.increment("node2", 1);
+        let mut remote_set = ORSet::new();
+        remote_set.add("a".to_string(), "node1");
+
+        let mut m1 = CRDTMap::new();
+        m1.set("shared".to_string(), CRDTValue::GCounter(local_counter));
+        m1.set("counter".to_string(), CRDTValue::GCounter(GCounter::new()));
+        let mut m2 = CRDTMap::new();
+        m2.set("shared".to_string(), CRDTValue::ORSet(remote_set));
+        m2.set("counter".to_string(), CRDTValue::GCounter(remote_counter));
+
+        let conflicts = m1.merge_checked(&m2);
+        assert_eq!(
+            conflicts,
+            vec![MergeConflict {
+                key: "shared".to_string(),
+                local_type: "g-counter".to_string(),
+                remote_type: "or-set".to_string(),
+            }]
+        );
+        // 类型不一致的键保留本地值，其余键正常合并
+        match m1.get("shared") {
+            Some(CRDTValue::GCounter(c)) => assert_eq!(c.value(), 4),
+            other => panic!("Expected GCounter, got {:?}", other),
+        }
+        match m1.get("counter") {
+            Some(CRDTValue::GCounter(c)) => assert_eq!(c.value(), 1),
+            other => panic!("Expected GCounter, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_crdt_map_state_hash_consistency() {
         let mut m1 = CRDTMap::new();
@@ -718,4 +2519,785 @@ mod tests {
         assert!(map.get("test").is_some());
         assert!(map.get("nonexistent").is_none());
     }
+
+    #[test]
+    fn test_crdt_map_forget_node() {
+        let mut map = CRDTMap::new();
+        let mut g = GCounter::new();
+        g.increment("node1", 5);
+        g.increment("node2", 3);
+        let mut pn = PNCounter::new();
+        pn.increment("node2", 10);
+        pn.decrement("node2", 4);
+        pn.increment("node1", 1);
+        map.set("g".to_string(), CRDTValue::GCounter(g));
+        map.set("pn".to_string(), CRDTValue::PNCounter(pn));
+        map.vector_clock.increment("node1");
+        map.vector_clock.increment("node2");
+
+        map.forget_node("node2");
+
+        match map.get("g") {
+            Some(CRDTValue::GCounter(c)) => {
+                assert_eq!(c.value(), 8);
+                assert!(!c.counts.contains_key("node2"));
+            }
+            _ => panic!("expected GCounter"),
+        }
+        match map.get("pn") {
+            Some(CRDTValue::PNCounter(c)) => assert_eq!(c.value(), 7),
+            _ => panic!("expected PNCounter"),
+        }
+        assert_eq!(map.vector_clock.get("node2"), 0);
+        assert!(!map.vector_clock.clocks.contains_key("node2"));
+        assert_eq!(map.vector_clock.get("node1"), 1);
+    }
+
+    #[test]
+    fn test_crdt_map_diff() {
+        let mut before = CRDTMap::new();
+        let mut counter = GCounter::new();
+        counter.increment("node1", 2);
+        before.set("counter".to_string(), CRDTValue::GCounter(counter.clone()));
+        let mut name = LWWRegister::new();
+        name.set("alice".to_string(), 1, "node1");
+        before.set("name".to_string(), CRDTValue::LWWRegister(name.clone()));
+        before.set("old".to_string(), CRDTValue::GCounter(GCounter::new()));
+
+        let mut after = before.clone();
+        after.entries.remove("old");
+        counter.increment("node1", 3);
+        after.set("counter".to_string(), CRDTValue::GCounter(counter));
+        let mut tags = ORSet::new();
+        tags.add("b".to_string(), "node1");
+        tags.add("a".to_string(), "node1");
+        after.set("tags".to_string(), CRDTValue::ORSet(tags));
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.removed.get("old"), Some(&serde_json::json!(0)));
+        assert_eq!(diff.added.get("tags"), Some(&serde_json::json!(["a", "b"])));
+        assert_eq!(
+            diff.changed.get("counter"),
+            Some(&ValueChange {
+                from: serde_json::json!(2),
+                to: serde_json::json!(5),
+            })
+        );
+        assert!(!diff.changed.contains_key("name"));
+        assert!(before.diff(&before).is_empty());
+    }
+
+    #[test]
+    fn test_pncounter_compact() {
+        let mut uncompacted = PNCounter::new();
+        uncompacted.increment("node1", 10);
+        uncompacted.decrement("node1", 4);
+        uncompacted.increment("node2", 1);
+        uncompacted.decrement("node2", 3);
+        uncompacted.decrement("node3", 2);
+
+        let mut compacted = uncompacted.clone();
+        assert_eq!(compacted.compact(|node| node != "node3"), 2);
+        assert_eq!(compacted.value(), uncompacted.value());
+        assert_eq!(compacted.positive.counts.get("node1"), Some(&6));
+        assert!(!compacted.negative.counts.contains_key("node1"));
+        assert!(!compacted.positive.counts.contains_key("node2"));
+        assert_eq!(compacted.negative.counts.get("node2"), Some(&2));
+        assert_eq!(compacted.negative.counts.get("node3"), Some(&2));
+
+        // 与未压缩副本合并仍然收敛
+        let mut a = uncompacted.clone();
+        let mut b = compacted.clone();
+        a.merge(&compacted);
+        b.merge(&uncompacted);
+        assert_eq!(a, b);
+        assert_eq!(a.value(), uncompacted.value());
+    }
+
+    #[test]
+    fn test_gcounter_merge_capped() {
+        let mut local = GCounter::new();
+        local.increment("node1", 5);
+
+        let mut normal = GCounter::new();
+        normal.increment("node2", 10);
+        assert!(local.merge_capped(&normal, Some(100)).is_empty());
+        assert_eq!(local.value(), 15);
+
+        let mut inflated = GCounter::new();
+        inflated.increment("node2", 1_000_000);
+        inflated.increment(FORGOTTEN_BASELINE, 500);
+        assert_eq!(
+            local.merge_capped(&inflated, Some(100)),
+            vec!["node2".to_string()]
+        );
+        assert_eq!(local.counts.get("node2"), Some(&100));
+        assert_eq!(local.counts.get(FORGOTTEN_BASELINE), Some(&500));
+
+        // 相同上限下截断与合并顺序无关
+        let mut other = GCounter::new();
+        other.merge_capped(&inflated, Some(100));
+        other.merge_capped(&normal, Some(100));
+        let mut expected = local.clone();
+        expected.counts.remove("node1");
+        assert_eq!(other, expected);
+
+        // 未设置上限时与普通合并一致
+        let mut uncapped = GCounter::new();
+        assert!(uncapped.merge_capped(&inflated, None).is_empty());
+        assert_eq!(uncapped.value(), 1_000_500);
+    }
+
+    #[test]
+    fn test_crdt_map_document() {
+        let mut map = CRDTMap::new();
+        let mut counter = PNCounter::new();
+        counter.increment("node1", 9);
+        counter.decrement("node2", 2);
+        map.set("counter".to_string(), CRDTValue::PNCounter(counter));
+        let mut name = LWWRegister::new();
+        name.set("x".to_string(), 1, "node1");
+        map.set("name".to_string(), CRDTValue::LWWRegister(name));
+        let mut tags = ORSet::new();
+        tags.add("b".to_string(), "node1");
+        tags.add("a".to_string(), "node1");
+        map.set("tags".to_string(), CRDTValue::ORSet(tags));
+
+        assert_eq!(
+            serde_json::to_value(map.document()).unwrap(),
+            serde_json::json!({ "counter": 7, "name": "x", "tags": ["a", "b"] })
+        );
+    }
+
+    #[test]
+    fn test_json_nesting_depth() {
+        assert_eq!(json_nesting_depth(b"1"), 0);
+        assert_eq!(json_nesting_depth(br#"{"a":[1,{"b":2}]}"#), 3);
+        // 字符串内的括号与转义引号不计入
+        assert_eq!(json_nesting_depth(br#"{"a":"[[{\"}}"}"#), 1);
+    }
+
+    #[test]
+    fn test_check_crdt_depth_rejects_deep_structures() {
+        let mut state_map = CRDTMap::new();
+        let mut pn = PNCounter::new();
+        pn.increment("node1", 1);
+        state_map.set("pn".to_string(), CRDTValue::PNCounter(pn));
+        let request =
+            serde_json::json!({ "from_node": "node1", "state": { "crdt_map": state_map } });
+        let bytes = serde_json::to_vec(&request).unwrap();
+        assert!(check_crdt_depth(&bytes, 1).is_ok());
+
+        // 深度远超限制的数据在解析前即被拒绝，不会递归
+        let n = 1_000_000;
+        let mut bomb = vec![b'['; n];
+        bomb.extend(vec![b']'; n]);
+        let err = check_crdt_depth(&bomb, DEFAULT_MAX_CRDT_DEPTH).unwrap_err();
+        assert!(err.contains("exceeds limit"));
+    }
+
+    #[test]
+    fn test_value_view_projection() {
+        let view = |value: CRDTValue| serde_json::to_value(value.view()).unwrap();
+
+        let mut counter = PNCounter::new();
+        counter.increment("node1", 10);
+        counter.decrement("node2", 3);
+        assert_eq!(
+            view(CRDTValue::PNCounter(counter)),
+            serde_json::json!({"type": "pn-counter", "value": 7})
+        );
+
+        let mut counter = GCounter::new();
+        counter.increment("node1", 2);
+        assert_eq!(
+            view(CRDTValue::GCounter(counter)),
+            serde_json::json!({"type": "g-counter", "value": 2})
+        );
+
+        let mut counter = BoundedCounter::new(0);
+        counter.increment("node1", 5);
+        assert_eq!(
+            view(CRDTValue::BoundedCounter(counter)),
+            serde_json::json!({"type": "bounded-counter", "value": 5})
+        );
+
+        let mut register = LWWRegister::new();
+        assert_eq!(
+            view(CRDTValue::LWWRegister(register.clone())),
+            serde_json::json!({"type": "lww-register", "value": null})
+        );
+        register.set("foo".to_string(), 1, "node1");
+        assert_eq!(
+            view(CRDTValue::LWWRegister(register)),
+            serde_json::json!({"type": "lww-register", "value": "foo"})
+        );
+
+        let mut map = LWWMap::new();
+        map.set("name".to_string(), "alice".to_string(), 1, "node1");
+        map.set("city".to_string(), "paris".to_string(), 1, "node1");
+        map.remove("city".to_string(), 2, "node1");
+        assert_eq!(
+            view(CRDTValue::LWWMap(map)),
+            serde_json::json!({"type": "lww-map", "fields": {"name": "alice"}})
+        );
+
+        let mut set = ORSet::new();
+        set.add("b".to_string(), "node1");
+        set.add("a".to_string(), "node1");
+        assert_eq!(
+            view(CRDTValue::ORSet(set)),
+            serde_json::json!({"type": "or-set", "elements": ["a", "b"]})
+        );
+
+        let mut set = GSet::new();
+        set.add("y".to_string());
+        set.add("x".to_string());
+        assert_eq!(
+            view(CRDTValue::GSet(set)),
+            serde_json::json!({"type": "g-set", "elements": ["x", "y"]})
+        );
+
+        let mut max = MaxRegister::new();
+        max.set(3);
+        max.set(1);
+        assert_eq!(
+            view(CRDTValue::MaxRegister(max)),
+            serde_json::json!({"type": "max-register", "value": 3})
+        );
+
+        let mut min = MinRegister::new();
+        min.set(3);
+        min.set(1);
+        assert_eq!(
+            view(CRDTValue::MinRegister(min)),
+            serde_json::json!({"type": "min-register", "value": 1})
+        );
+
+        let mut clock1 = VectorClock::new();
+        clock1.increment("node1");
+        let mut clock2 = VectorClock::new();
+        clock2.increment("node2");
+        let mut mv = MVRegister::new();
+        mv.set("v2".to_string(), clock2);
+        mv.set("v1".to_string(), clock1);
+        assert_eq!(
+            view(CRDTValue::MVRegister(mv)),
+            serde_json::json!({"type": "mv-register", "values": ["v1", "v2"]})
+        );
+    }
+
+    #[test]
+    fn test_key_listing_collation() {
+        let mut map = CRDTMap::new();
+        for key in ["b", "A", "a", "B", "ä"] {
+            map.set(key.to_string(), CRDTValue::GCounter(GCounter::new()));
+        }
+        let hash = map.state_hash();
+
+        let keys = |collation| -> Vec<String> {
+            map.key_listing(collation, &KeyFilter::default())
+                .into_iter()
+                .map(|info| info.key)
+                .collect()
+        };
+        assert_eq!(keys(KeyCollation::Byte), vec!["A", "B", "a", "b", "ä"]);
+        assert_eq!(
+            keys(KeyCollation::CaseInsensitive),
+            vec!["A", "a", "B", "b", "ä"]
+        );
+        assert_eq!(
+            map.key_listing(KeyCollation::Byte, &KeyFilter::default())[0].crdt_type,
+            "g-counter"
+        );
+
+        // 展示排序不影响 state_hash，且哈希与插入顺序无关
+        assert_eq!(map.state_hash(), hash);
+        let mut reordered = CRDTMap::new();
+        for key in ["ä", "B", "a", "A", "b"] {
+            reordered.set(key.to_string(), CRDTValue::GCounter(GCounter::new()));
+        }
+        assert_eq!(reordered.state_hash(), hash);
+    }
+
+    fn counter_map(keys: impl IntoIterator<Item = usize>) -> CRDTMap {
+        let mut map = CRDTMap::new();
+        for i in keys {
+            let mut counter = GCounter::new();
+            counter.increment("node1", i as u64 + 1);
+            map.set(format!("key{}", i), CRDTValue::GCounter(counter));
+        }
+        map
+    }
+
+    #[test]
+    fn test_merkle_root_identical_maps() {
+        let a = counter_map(0..500);
+        let b = counter_map((0..500).rev());
+        assert_eq!(a.merkle_root(), b.merkle_root());
+        assert!(a.merkle_tree().diff_keys(&b.merkle_tree()).is_empty());
+        assert_eq!(CRDTMap::new().merkle_root(), "");
+
+        // 任一值改变都会改变根哈希
+        let mut c = counter_map(0..500);
+        if let Some(CRDTValue::GCounter(counter)) = c.entries.get_mut("key42") {
+            counter.increment("node2", 1);
+        }
+        assert_ne!(a.merkle_root(), c.merkle_root());
+    }
+
+    #[test]
+    fn test_merkle_diff_finds_single_changed_key() {
+        let a = counter_map(0..500);
+        let mut b = counter_map(0..500);
+        if let Some(CRDTValue::GCounter(counter)) = b.entries.get_mut("key42") {
+            counter.increment("node2", 1);
+        }
+        assert_eq!(a.merkle_tree().diff_keys(&b.merkle_tree()), vec!["key42"]);
+
+        // 只存在于一方的键同样被找出
+        let c = counter_map(0..501);
+        assert_eq!(c.merkle_tree().diff_keys(&a.merkle_tree()), vec!["key500"]);
+        assert_eq!(a.merkle_tree().diff_keys(&c.merkle_tree()), vec!["key500"]);
+    }
+
+    #[test]
+    fn test_merkle_compare_descends_to_changed_key() {
+        let local = counter_map(0..500).merkle_tree();
+        let mut changed = counter_map(0..500);
+        changed.set(
+            "key7".to_string(),
+            CRDTValue::LWWRegister(LWWRegister::new()),
+        );
+        let remote = changed.merkle_tree();
+
+        // 模拟远程比较：每轮发送本端节点哈希，对端返回不一致节点的子节点或叶子中的键
+        let mut nodes = BTreeMap::from([(String::new(), remote.root().to_string())]);
+        let mut rounds = 0;
+        let keys = loop {
+            rounds += 1;
+            let diff = local.compare(&nodes).unwrap();
+            if !diff.keys.is_empty() {
+                break diff.keys;
+            }
+            assert_eq!(diff.mismatched.len(), 1);
+            // 本端只继续发送与对端子节点哈希不同的节点（含只存在于一方的子节点）
+            nodes = diff
+                .mismatched
+                .iter()
+                .flat_map(|path| remote.children(path).into_keys())
+                .chain(diff.children.keys().cloned())
+                .filter(|path| {
+                    remote.node_hash(path) != diff.children.get(path).map_or("", String::as_str)
+                })
+                .map(|path| {
+                    let hash = remote.node_hash(&path).to_string();
+                    (path, hash)
+                })
+                .collect();
+        };
+        assert_eq!(rounds, MERKLE_DEPTH + 1);
+        assert!(keys.contains_key("key7"));
+        assert_ne!(
+            keys["key7"],
+            changed.entries["key7"].state_hash(),
+            "leaf reports the local value hash"
+        );
+
+        assert!(
+            local
+                .compare(&BTreeMap::from([("xyz".to_string(), String::new())]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_key_listing_filters_by_prefix_and_type() {
+        let mut map = CRDTMap::new();
+        map.set("cart:1".to_string(), CRDTValue::ORSet(ORSet::new()));
+        map.set("cart:2".to_string(), CRDTValue::ORSet(ORSet::new()));
+        map.set(
+            "cart:count".to_string(),
+            CRDTValue::GCounter(GCounter::new()),
+        );
+        map.set("visits".to_string(), CRDTValue::PNCounter(PNCounter::new()));
+        map.set(
+            "title".to_string(),
+            CRDTValue::LWWRegister(LWWRegister::new()),
+        );
+
+        let keys = |prefix: Option<&str>, crdt_type: Option<&str>| -> Vec<(String, &str)> {
+            let filter = KeyFilter {
+                prefix: prefix.map(str::to_string),
+                crdt_type: crdt_type.map(str::to_string),
+            };
+            map.key_listing(KeyCollation::Byte, &filter)
+                .into_iter()
+                .map(|info| (info.key, info.crdt_type))
+                .collect()
+        };
+
+        assert_eq!(keys(None, None).len(), 5);
+        assert_eq!(
+            keys(Some("cart:"), None),
+            vec![
+                ("cart:1".to_string(), "or-set"),
+                ("cart:2".to_string(), "or-set"),
+                ("cart:count".to_string(), "g-counter"),
+            ]
+        );
+        assert_eq!(
+            keys(None, Some("pn-counter")),
+            vec![("visits".to_string(), "pn-counter")]
+        );
+        assert_eq!(
+            keys(Some("cart:"), Some("g-counter")),
+            vec![("cart:count".to_string(), "g-counter")]
+        );
+        assert!(keys(Some("orders"), None).is_empty());
+        assert!(keys(None, Some("g-set")).is_empty());
+
+        // 类型名称列表覆盖所有 CRDT 类型
+        assert!(CRDT_TYPES.contains(&CRDTValue::LWWMap(LWWMap::new()).type_name()));
+        assert!(CRDT_TYPES.contains(&CRDTValue::MVRegister(MVRegister::new()).type_name()));
+    }
+
+    #[test]
+    fn test_max_min_register_merge_ignores_order() {
+        let mut a = MaxRegister::new();
+        let mut b = MaxRegister::new();
+        a.set(7);
+        b.set(3);
+        b.set(9);
+        b.set(4);
+        assert_eq!(b.get(), Some(&9));
+
+        let mut ab = a.clone();
+        ab.merge(&b);
+        let mut ba = b.clone();
+        ba.merge(&a);
+        assert_eq!(ab, ba);
+        assert_eq!(ab.get(), Some(&9));
+
+        let mut low = MinRegister::new();
+        let mut other = MinRegister::new();
+        low.set(7);
+        other.set(3);
+        low.merge(&other);
+        low.merge(&other);
+        assert_eq!(low.get(), Some(&3));
+    }
+
+    #[test]
+    fn test_max_min_register_merge_commutative_and_idempotent() {
+        let orders = [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ];
+        let values = [5, -3, 12];
+
+        let maxes: Vec<MaxRegister<i64>> = values
+            .iter()
+            .map(|&v| {
+                let mut r = MaxRegister::new();
+                r.set(v);
+                r
+            })
+            .collect();
+        let mins: Vec<MinRegister<i64>> = values
+            .iter()
+            .map(|&v| {
+                let mut r = MinRegister::new();
+                r.set(v);
+                r
+            })
+            .collect();
+
+        for order in orders {
+            let mut max = MaxRegister::new();
+            let mut min = MinRegister::new();
+            for i in order {
+                max.merge(&maxes[i]);
+                min.merge(&mins[i]);
+                // 重复合并不改变结果
+                max.merge(&maxes[i]);
+                min.merge(&mins[i]);
+            }
+            assert_eq!(max.get(), Some(&12));
+            assert_eq!(min.get(), Some(&-3));
+            assert_eq!(
+                CRDTValue::MaxRegister(max).state_hash(),
+                CRDTValue::MaxRegister(maxes[2].clone()).state_hash()
+            );
+            assert_eq!(
+                CRDTValue::MinRegister(min).state_hash(),
+                CRDTValue::MinRegister(mins[1].clone()).state_hash()
+            );
+        }
+
+        // set 只朝单调方向更新
+        let mut max = maxes[2].clone();
+        max.set(1);
+        assert_eq!(max.get(), Some(&12));
+        let mut min = mins[1].clone();
+        min.set(100);
+        assert_eq!(min.get(), Some(&-3));
+    }
+
+    #[test]
+    fn test_mv_register_keeps_concurrent_values() {
+        let mut vc1 = VectorClock::new();
+        vc1.increment("node1");
+        let mut vc2 = VectorClock::new();
+        vc2.increment("node2");
+
+        let mut r1 = MVRegister::new();
+        r1.set("a".to_string(), vc1.clone());
+        let mut r2 = MVRegister::new();
+        r2.set("b".to_string(), vc2.clone());
+
+        r1.merge(&r2);
+        let mut values = r1.values();
+        values.sort();
+        assert_eq!(values, vec!["a".to_string(), "b".to_string()]);
+
+        // 观察到两个值之后的写入覆盖二者
+        let mut vc3 = vc1.clone();
+        vc3.merge(&vc2);
+        vc3.increment("node1");
+        r1.set("c".to_string(), vc3);
+        assert_eq!(r1.values(), vec!["c".to_string()]);
+
+        r2.merge(&r1);
+        assert_eq!(r2.values(), vec!["c".to_string()]);
+    }
+
+    #[test]
+    fn test_rga_concurrent_inserts_converge() {
+        let mut base = RGA::new();
+        let a = base.insert(None, "a".to_string(), "node1").unwrap();
+        let b = base.insert(Some(&a), "b".to_string(), "node1").unwrap();
+
+        // 三个副本在同一位置（a 之后）并发插入，node3 同时删除 b
+        let mut r1 = base.clone();
+        r1.insert(Some(&a), "x".to_string(), "node1").unwrap();
+        let mut r2 = base.clone();
+        let y = r2.insert(Some(&a), "y".to_string(), "node2").unwrap();
+        r2.insert(Some(&y), "y2".to_string(), "node2").unwrap();
+        let mut r3 = base.clone();
+        r3.insert(Some(&a), "z".to_string(), "node3").unwrap();
+        assert!(r3.delete(&b));
+
+        let merged = |order: [&RGA<String>; 3]| {
+            let mut first = order[0].clone();
+            for other in &order[1..] {
+                first.merge(other);
+            }
+            first
+        };
+        let expected = merged([&r1, &r2, &r3]);
+        // 同一前驱后的并发插入按标识降序，y2 紧随其前驱 y
+        assert_eq!(expected.to_vec(), vec!["a", "z", "y", "y2", "x"]);
+        for order in [
+            [&r1, &r3, &r2],
+            [&r2, &r1, &r3],
+            [&r2, &r3, &r1],
+            [&r3, &r1, &r2],
+            [&r3, &r2, &r1],
+        ] {
+            let replica = merged(order);
+            assert_eq!(replica.to_vec(), expected.to_vec());
+            assert_eq!(replica.state_hash(), expected.state_hash());
+        }
+
+        // 重复合并是幂等的
+        let mut again = expected.clone();
+        again.merge(&r2);
+        assert_eq!(again, expected);
+
+        assert!(
+            base.insert(Some(&"9@node9".parse().unwrap()), "q".to_string(), "node1")
+                .is_err()
+        );
+        assert_eq!(y.to_string().parse::<RgaId>(), Ok(y));
+        assert!("node1".parse::<RgaId>().is_err());
+    }
+
+    #[test]
+    fn test_orset_concurrent_updates_converge() {
+        let mut base = ORSet::new();
+        base.add("a".to_string(), "node1");
+        base.add("b".to_string(), "node1");
+
+        // node1 移除 a，node2 并发地再次添加 a（添加优先），node3 移除 b 并添加 c
+        let mut r1 = base.clone();
+        r1.remove(&"a".to_string());
+        let mut r2 = base.clone();
+        r2.add("a".to_string(), "node2");
+        let mut r3 = base.clone();
+        r3.remove(&"b".to_string());
+        r3.add("c".to_string(), "node3");
+
+        let merged = |order: [&ORSet<String>; 3]| {
+            let mut first = order[0].clone();
+            for other in &order[1..] {
+                first.merge(other);
+            }
+            let mut elements = first.elements();
+            elements.sort();
+            (elements, first)
+        };
+        let (expected, replica) = merged([&r1, &r2, &r3]);
+        assert_eq!(expected, vec!["a", "c"]);
+        // 移除无需墓碑：所有点都并入向量时钟
+        assert!(replica.cloud.is_empty());
+        assert_eq!(replica.context.get("node1"), 2);
+        for order in [[&r3, &r2, &r1], [&r2, &r1, &r3], [&r3, &r1, &r2]] {
+            let (elements, other) = merged(order);
+            assert_eq!(elements, expected);
+            assert_eq!(other, replica);
+        }
+    }
+
+    #[test]
+    fn test_orset_covered_tombstones_are_collected() {
+        let mut source = ORSet::new();
+        let first = source.add("a".to_string(), "node1");
+        let second = source.add("b".to_string(), "node1");
+        let removed = source.remove(&"b".to_string());
+        assert_eq!(removed, vec![second.clone()]);
+
+        // 移除先于添加到达：点暂记在点集中，之后到达的添加不再生效
+        let mut replica = ORSet::new();
+        replica.remove_dots(&"b".to_string(), &removed);
+        assert_eq!(replica.tombstone_count(), 1);
+        replica.add_dot("b".to_string(), second);
+        assert!(!replica.contains(&"b".to_string()));
+
+        // 缺失的点到达后上下文连续，已覆盖的墓碑被回收
+        replica.add_dot("a".to_string(), first);
+        assert_eq!(replica.tombstone_count(), 0);
+        assert!(replica.cloud.is_empty());
+        assert_eq!(replica.context.get("node1"), 2);
+        assert_eq!(replica, source);
+
+        // 合并同样回收被对端上下文覆盖的点
+        let mut partial = ORSet::new();
+        partial.remove_dots(&"b".to_string(), &removed);
+        partial.merge(&source);
+        assert!(partial.cloud.is_empty());
+        assert_eq!(partial.elements(), vec!["a".to_string()]);
+        assert_eq!(partial.next_dot("node1"), Dot::new("node1", 3));
+    }
+
+    #[test]
+    fn test_orset_delta_since_sends_missing_dots() {
+        let mut local = ORSet::new();
+        local.add("a".to_string(), "node1");
+        local.add("b".to_string(), "node1");
+        let mut peer = local.clone();
+
+        local.add("c".to_string(), "node1");
+        local.remove(&"a".to_string());
+        peer.add("d".to_string(), "node2");
+
+        let delta = local.delta_since(&peer.context);
+        // 对端已有的存活点 b 不在增量中，新添加的 c 在
+        assert_eq!(delta.elements(), vec!["c".to_string()]);
+
+        let mut incremental = peer.clone();
+        incremental.merge(&delta);
+        let mut full = peer.clone();
+        full.merge(&local);
+        let mut elements = incremental.elements();
+        elements.sort();
+        assert_eq!(elements, vec!["b", "c", "d"]);
+        assert_eq!(incremental, full);
+    }
+
+    #[test]
+    fn test_orset_reads_legacy_unique_ids() {
+        let legacy = serde_json::json!({
+            "added": {"a": ["id1", "id2"], "b": ["id3"]},
+            "removed": ["id1", "id3"],
+        });
+        let mut set: ORSet<String> = serde_json::from_value(legacy).unwrap();
+        assert_eq!(set.elements(), vec!["a".to_string()]);
+        assert_eq!(set.dots(&"a".to_string()), vec![Dot::from("id2")]);
+
+        // 旧 ID 的移除在合并中仍然生效
+        let mut other = set.clone();
+        other.remove(&"a".to_string());
+        set.merge(&other);
+        assert!(set.elements().is_empty());
+
+        let round_trip: ORSet<String> =
+            serde_json::from_value(serde_json::to_value(&set).unwrap()).unwrap();
+        assert_eq!(round_trip, set);
+        assert_eq!(Dot::from("3@node1"), Dot::new("node1", 3));
+        assert_eq!(Dot::from("3@node1").to_string(), "3@node1");
+    }
+
+    #[test]
+    fn test_gset_merge_converges() {
+        let gset = |elements: &[&str]| {
+            let mut set = GSet::new();
+            for e in elements {
+                set.add(e.to_string());
+            }
+            set
+        };
+
+        // 不相交与有交集的元素分别按两种顺序合并
+        for (left, right) in [
+            (gset(&["a", "b"]), gset(&["c", "d"])),
+            (gset(&["a", "b", "c"]), gset(&["b", "c", "d"])),
+        ] {
+            let mut lr = left.clone();
+            lr.merge(&right);
+            let mut rl = right.clone();
+            rl.merge(&left);
+
+            assert_eq!(lr, rl);
+            assert_eq!(lr.state_hash(), rl.state_hash());
+            let mut elements = lr.elements();
+            elements.sort();
+            assert_eq!(elements, vec!["a", "b", "c", "d"]);
+
+            // 合并幂等
+            lr.merge(&right);
+            assert_eq!(lr, rl);
+        }
+
+        let set = gset(&["ab"]);
+        assert!(set.contains(&"ab".to_string()));
+        assert_ne!(set.state_hash(), gset(&["a", "b"]).state_hash());
+    }
+
+    #[test]
+    fn test_crdt_map_merges_gsets() {
+        let mut map1 = CRDTMap::new();
+        let mut map2 = CRDTMap::new();
+        let mut tags1 = GSet::new();
+        tags1.add("x".to_string());
+        let mut tags2 = GSet::new();
+        tags2.add("y".to_string());
+        map1.set("tags".to_string(), CRDTValue::GSet(tags1));
+        map2.set("tags".to_string(), CRDTValue::GSet(tags2));
+
+        let mut merged12 = map1.clone();
+        merged12.merge(&map2);
+        let mut merged21 = map2.clone();
+        merged21.merge(&map1);
+
+        assert_eq!(merged12.state_hash(), merged21.state_hash());
+        assert_eq!(
+            merged12.get("tags").unwrap().materialized(),
+            serde_json::json!(["x", "y"])
+        );
+    }
 }
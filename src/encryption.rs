@@ -0,0 +1,171 @@
+use anyhow::{Result, anyhow};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::collections::BTreeMap;
+
+/// 密文前缀
+const CIPHERTEXT_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+/// 敏感字段加密器
+///
+/// 匹配配置模式的键，其 LWW 值在进入 CRDT 前使用集群共享密钥加密。
+/// 密文对合并透明（按时间戳正常比较），仅在授权读取时解密。
+pub struct FieldEncryptor {
+    cipher: ChaCha20Poly1305,
+    patterns: Vec<String>,
+}
+
+impl std::fmt::Debug for FieldEncryptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FieldEncryptor")
+            .field("patterns", &self.patterns)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FieldEncryptor {
+    /// 使用 32 字节密钥创建加密器
+    pub fn new(key: &[u8; 32], patterns: Vec<String>) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+            patterns,
+        }
+    }
+
+    /// 从十六进制编码的密钥创建加密器
+    pub fn from_hex_key(key_hex: &str, patterns: Vec<String>) -> Result<Self> {
+        let bytes = hex::decode(key_hex).map_err(|e| anyhow!("Invalid encryption key: {}", e))?;
+        let key: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("Encryption key must be 32 bytes"))?;
+        Ok(Self::new(&key, patterns))
+    }
+
+    /// 键是否需要加密（模式以 `*` 结尾时按前缀匹配，否则精确匹配）
+    pub fn matches(&self, key: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => key.starts_with(prefix),
+                None => key == pattern,
+            })
+    }
+
+    /// 值是否为本模块生成的密文
+    pub fn is_ciphertext(value: &str) -> bool {
+        value.starts_with(CIPHERTEXT_PREFIX)
+    }
+
+    /// 加密明文，每次使用随机 nonce
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        rand::Rng::fill(&mut rand::thread_rng(), &mut nonce_bytes);
+
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce_bytes), plaintext.as_bytes())
+            .map_err(|e| anyhow!("Failed to encrypt value: {}", e))?;
+
+        let mut payload = nonce_bytes.to_vec();
+        payload.extend(ciphertext);
+        Ok(format!("{}{}", CIPHERTEXT_PREFIX, BASE64.encode(payload)))
+    }
+
+    /// 解密密文
+    pub fn decrypt(&self, value: &str) -> Result<String> {
+        let encoded = value
+            .strip_prefix(CIPHERTEXT_PREFIX)
+            .ok_or_else(|| anyhow!("Value is not encrypted"))?;
+        let payload = BASE64
+            .decode(encoded)
+            .map_err(|e| anyhow!("Invalid ciphertext encoding: {}", e))?;
+        if payload.len() < NONCE_LEN {
+            return Err(anyhow!("Ciphertext too short"));
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to decrypt value"))?;
+        String::from_utf8(plaintext).map_err(|e| anyhow!("Decrypted value is not UTF-8: {}", e))
+    }
+
+    /// 解密物化文档中匹配模式的字符串值（含多值寄存器的字符串数组），无法解密的值保持原样
+    pub fn decrypt_document(&self, document: &mut BTreeMap<String, serde_json::Value>) {
+        for (key, value) in document.iter_mut() {
+            self.decrypt_value(key, value);
+        }
+    }
+
+    /// 解密单个键的物化值（字符串或字符串数组），键不匹配模式或无法解密时保持原样
+    pub fn decrypt_value(&self, key: &str, value: &mut serde_json::Value) {
+        if !self.matches(key) {
+            return;
+        }
+        let strings = match value {
+            serde_json::Value::String(s) => vec![s],
+            serde_json::Value::Array(items) => items
+                .iter_mut()
+                .filter_map(|item| match item {
+                    serde_json::Value::String(s) => Some(s),
+                    _ => None,
+                })
+                .collect(),
+            _ => return,
+        };
+        for s in strings {
+            if !Self::is_ciphertext(s) {
+                continue;
+            }
+            match self.decrypt(s) {
+                Ok(plaintext) => *s = plaintext,
+                Err(e) => tracing::warn!("Failed to decrypt key {}: {}", key, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encryptor(key: u8) -> FieldEncryptor {
+        FieldEncryptor::new(
+            &[key; 32],
+            vec!["secret/*".to_string(), "token".to_string()],
+        )
+    }
+
+    #[test]
+    fn test_pattern_matching() {
+        let enc = encryptor(1);
+        assert!(enc.matches("secret/api"));
+        assert!(enc.matches("token"));
+        assert!(!enc.matches("tokens"));
+        assert!(!enc.matches("public/secret/api"));
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let enc = encryptor(1);
+        let ciphertext = enc.encrypt("hunter2").unwrap();
+        assert!(FieldEncryptor::is_ciphertext(&ciphertext));
+        assert!(!ciphertext.contains("hunter2"));
+        assert_ne!(ciphertext, enc.encrypt("hunter2").unwrap());
+        assert_eq!(enc.decrypt(&ciphertext).unwrap(), "hunter2");
+
+        // 不同密钥无法解密
+        assert!(encryptor(2).decrypt(&ciphertext).is_err());
+        assert!(enc.decrypt("plain").is_err());
+    }
+
+    #[test]
+    fn test_from_hex_key() {
+        assert!(FieldEncryptor::from_hex_key(&"ab".repeat(32), vec![]).is_ok());
+        assert!(FieldEncryptor::from_hex_key("abcd", vec![]).is_err());
+        assert!(FieldEncryptor::from_hex_key("zz", vec![]).is_err());
+    }
+}
@@ -1,6 +1,16 @@
 use crate::api::AppState;
-use crate::sync::ChangeRequest;
-use tonic::{Request, Response, Status};
+use crate::auth::{JwtManager, KeyScope, Role};
+use crate::sync::{ChangeRequest, SetRace, change_op_spec};
+use std::collections::HashMap;
+use std::pin::Pin;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::{Stream, StreamExt};
+use tonic::metadata::MetadataMap;
+use tonic::service::Interceptor;
+use tonic::service::interceptor::InterceptedService;
+use tonic::{Code, Request, Response, Status};
+use tonic_types::{ErrorDetails, StatusExt};
 
 // 引入生成的 protobuf 代码
 pub mod crdt {
@@ -10,6 +20,168 @@ pub mod crdt {
 use crdt::crdt_service_server::{CrdtService, CrdtServiceServer};
 use crdt::*;
 
+/// 错误详情中的领域标识
+const ERROR_DOMAIN: &str = "silent-crdt";
+
+/// 构造携带结构化详情（`ErrorInfo` + `BadRequest`）的 INVALID_ARGUMENT 状态
+///
+/// `ErrorInfo.reason` 为错误码，`metadata` 中的 `field` / `key` 指出出错的字段与键，
+/// 与 HTTP 接口 JSON 错误体中的 `error_code` 对应。
+fn invalid_argument(code: &str, message: String, field: String, key: Option<&str>) -> Status {
+    let mut metadata = HashMap::from([("field".to_string(), field.clone())]);
+    if let Some(key) = key {
+        metadata.insert("key".to_string(), key.to_string());
+    }
+
+    let mut details = ErrorDetails::new();
+    details
+        .set_error_info(code, ERROR_DOMAIN, metadata)
+        .add_bad_request_violation(field, message.clone());
+    Status::with_error_details(Code::InvalidArgument, message, details)
+}
+
+/// 在应用前检查单个变更的操作类型与必填字段
+fn validate_change(index: usize, change: &Change) -> Result<(), Status> {
+    let field = |name: &str| format!("changes[{}].{}", index, name);
+    let key = Some(change.key.as_str());
+
+    let Some(spec) = change_op_spec(&change.op) else {
+        return Err(invalid_argument(
+            "unknown_op",
+            format!("Unknown operation: {}", change.op),
+            field("op"),
+            key,
+        ));
+    };
+    // proto 中的 Change 仅包含 key / value / delta / field / position
+    if let Some(missing) = spec
+        .required
+        .iter()
+        .find(|f| !matches!(**f, "key" | "value" | "field" | "position"))
+    {
+        return Err(invalid_argument(
+            "unsupported_op",
+            format!(
+                "Operation {} requires field {} which is not available over gRPC",
+                change.op, missing
+            ),
+            field("op"),
+            key,
+        ));
+    }
+    if spec.required.contains(&"value") && change.value.is_none() {
+        return Err(invalid_argument(
+            "missing_value",
+            format!("Missing value for {} operation", change.op),
+            field("value"),
+            key,
+        ));
+    }
+    if spec.required.contains(&"field") && change.field.as_deref().is_none_or(str::is_empty) {
+        return Err(invalid_argument(
+            "missing_field",
+            format!("Missing field for {} operation", change.op),
+            field("field"),
+            key,
+        ));
+    }
+    if let Some(delta) = change.delta
+        && delta < 0
+    {
+        return Err(invalid_argument(
+            "invalid_delta",
+            format!("Delta must be non-negative, got {}", delta),
+            field("delta"),
+            key,
+        ));
+    }
+    Ok(())
+}
+
+/// 转换为 protobuf 操作日志条目
+fn op_log_entry(entry: &crate::sync::OpLogEntry) -> OpLogEntry {
+    OpLogEntry {
+        id: entry.id.clone(),
+        timestamp: entry.ts,
+        node_id: entry.op.node_id().unwrap_or_default().to_string(),
+        operation: entry.op.to_string(),
+        causal_context: entry
+            .causal
+            .clocks
+            .iter()
+            .map(|(k, v)| (k.clone(), *v as i64))
+            .collect(),
+        key: entry.op.key().to_string(),
+    }
+}
+
+/// 操作是否命中订阅的键（或键前缀）
+fn watch_matches(entry: &crate::sync::OpLogEntry, key: &str, prefix: bool) -> bool {
+    if prefix {
+        entry.op.keys().iter().any(|k| k.starts_with(key))
+    } else {
+        entry.op.touches(key)
+    }
+}
+
+/// 已认证的调用方（由 `AuthInterceptor` 写入请求扩展）
+#[derive(Debug, Clone)]
+struct Caller {
+    role: Role,
+    scope: KeyScope,
+}
+
+/// 根据 `authorization` 元数据认证调用方，与 HTTP 的 `AuthMiddleware` 规则一致
+///
+/// 未启用权限控制时视为 Admin；缺少元数据时回退到默认角色（若已配置）。
+fn authenticate(state: &AppState, metadata: &MetadataMap) -> Result<Caller, Status> {
+    if !state.auth_enabled {
+        return Ok(Caller {
+            role: Role::Admin,
+            scope: KeyScope::default(),
+        });
+    }
+
+    let Some(header) = metadata.get("authorization") else {
+        return state
+            .default_role
+            .clone()
+            .map(|role| Caller {
+                role,
+                scope: KeyScope::default(),
+            })
+            .ok_or_else(|| Status::unauthenticated("Missing authorization metadata"));
+    };
+    let invalid = |e: String| Status::unauthenticated(format!("Invalid token: {}", e));
+    let header = header.to_str().map_err(|e| invalid(e.to_string()))?;
+    let token = JwtManager::extract_token(header).map_err(|e| invalid(e.to_string()))?;
+    let claims = state
+        .jwt_manager
+        .verify_token(token)
+        .map_err(|e| invalid(e.to_string()))?;
+    if state.revocations.is_revoked(&claims.jti) {
+        return Err(Status::unauthenticated("Token has been revoked"));
+    }
+    Ok(Caller {
+        scope: claims.key_scope(),
+        role: claims.role,
+    })
+}
+
+/// gRPC 认证拦截器：校验 JWT 并记录调用方角色，各 RPC 再检查所需角色
+#[derive(Clone)]
+pub struct AuthInterceptor {
+    app_state: AppState,
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let caller = authenticate(&self.app_state, request.metadata())?;
+        request.extensions_mut().insert(caller);
+        Ok(request)
+    }
+}
+
 /// gRPC 服务实现
 pub struct CrdtServiceImpl {
     app_state: AppState,
@@ -23,14 +195,53 @@ impl CrdtServiceImpl {
     pub fn into_server(self) -> CrdtServiceServer<Self> {
         CrdtServiceServer::new(self)
     }
+
+    /// 挂载认证拦截器的服务
+    pub fn into_authenticated_server(
+        self,
+    ) -> InterceptedService<CrdtServiceServer<Self>, AuthInterceptor> {
+        let interceptor = AuthInterceptor {
+            app_state: self.app_state.clone(),
+        };
+        CrdtServiceServer::with_interceptor(self, interceptor)
+    }
+
+    /// 检查调用方是否具备所需角色，返回调用方
+    ///
+    /// 请求未经过拦截器（如在进程内直接调用）时按其元数据认证。
+    fn authorize<T>(&self, request: &Request<T>, required: Role) -> Result<Caller, Status> {
+        let caller = match request.extensions().get::<Caller>() {
+            Some(caller) => caller.clone(),
+            None => authenticate(&self.app_state, request.metadata())?,
+        };
+        if !caller.role.has_permission(&required) {
+            return Err(Status::permission_denied("Insufficient permissions"));
+        }
+        Ok(caller)
+    }
 }
 
 #[tonic::async_trait]
 impl CrdtService for CrdtServiceImpl {
+    type WatchKeyStream = Pin<Box<dyn Stream<Item = Result<WatchEvent, Status>> + Send + 'static>>;
+    type StreamOpLogStream =
+        Pin<Box<dyn Stream<Item = Result<WatchEvent, Status>> + Send + 'static>>;
+
     /// 同步数据变更
     async fn sync(&self, request: Request<SyncRequest>) -> Result<Response<SyncResponse>, Status> {
+        let caller = self.authorize(&request, Role::Writer)?;
         let req = request.into_inner();
 
+        for (index, change) in req.changes.iter().enumerate() {
+            validate_change(index, change)?;
+        }
+        if let Some(change) = req.changes.iter().find(|c| !caller.scope.allows(&c.key)) {
+            return Err(Status::permission_denied(format!(
+                "Key not permitted by token: {}",
+                change.key
+            )));
+        }
+
         // 转换 gRPC 请求到内部格式
         let changes: Vec<crate::sync::Change> = req
             .changes
@@ -40,16 +251,28 @@ impl CrdtService for CrdtServiceImpl {
                 key: c.key,
                 value: c.value,
                 delta: c.delta.map(|d| d as u64),
+                field: c.field,
+                position: c.position,
+                ..Default::default()
             })
             .collect();
 
         let change_request = ChangeRequest { changes };
+        if let Err(errors) = change_request.validate() {
+            let error = &errors[0];
+            return Err(invalid_argument(
+                "invalid_field",
+                error.to_string(),
+                error.field.clone(),
+                None,
+            ));
+        }
 
         // 应用变更
         let mut sync_state = self.app_state.sync_state.write().await;
         sync_state
             .apply_changes(change_request)
-            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+            .map_err(|e| invalid_argument(e.code(), e.to_string(), "changes".to_string(), None))?;
 
         // 保存状态
         self.app_state
@@ -72,11 +295,37 @@ impl CrdtService for CrdtServiceImpl {
         &self,
         request: Request<MergeRequest>,
     ) -> Result<Response<MergeResponse>, Status> {
+        // 合并整份状态无法按键限制，限定键范围的 token 只能通过 Sync 写入
+        if self
+            .authorize(&request, Role::Writer)?
+            .scope
+            .is_restricted()
+        {
+            return Err(Status::permission_denied(
+                "Key-scoped tokens cannot merge full state",
+            ));
+        }
         let req = request.into_inner();
 
-        // 解析状态数据
+        // 解析状态数据（先检查嵌套深度）
+        crate::crdt::check_crdt_depth(&req.state_data, self.app_state.max_crdt_depth)
+            .map_err(|e| invalid_argument("too_deep", e, "state_data".to_string(), None))?;
         let incoming_state: crate::sync::SyncState = serde_json::from_slice(&req.state_data)
-            .map_err(|e| Status::invalid_argument(format!("Invalid state data: {}", e)))?;
+            .map_err(|e| {
+                invalid_argument(
+                    "invalid_state",
+                    format!("Invalid state data: {}", e),
+                    "state_data".to_string(),
+                    None,
+                )
+            })?;
+
+        crate::api::verify_incoming_signatures(
+            &incoming_state,
+            &self.app_state.signature_manager,
+            self.app_state.require_signatures,
+        )
+        .map_err(|e| invalid_argument("invalid_signature", e, "state_data".to_string(), None))?;
 
         // 合并状态
         let mut sync_state = self.app_state.sync_state.write().await;
@@ -101,8 +350,9 @@ impl CrdtService for CrdtServiceImpl {
     /// 获取当前状态
     async fn get_state(
         &self,
-        _request: Request<GetStateRequest>,
+        request: Request<GetStateRequest>,
     ) -> Result<Response<GetStateResponse>, Status> {
+        self.authorize(&request, Role::Reader)?;
         let sync_state = self.app_state.sync_state.read().await;
 
         let state_data = serde_json::to_vec(&*sync_state)
@@ -117,8 +367,9 @@ impl CrdtService for CrdtServiceImpl {
     /// 获取状态哈希
     async fn get_state_hash(
         &self,
-        _request: Request<GetStateHashRequest>,
+        request: Request<GetStateHashRequest>,
     ) -> Result<Response<GetStateHashResponse>, Status> {
+        self.authorize(&request, Role::Reader)?;
         let sync_state = self.app_state.sync_state.read().await;
         let state_hash = sync_state.state_hash();
 
@@ -128,32 +379,12 @@ impl CrdtService for CrdtServiceImpl {
     /// 获取操作日志
     async fn get_op_log(
         &self,
-        _request: Request<GetOpLogRequest>,
+        request: Request<GetOpLogRequest>,
     ) -> Result<Response<GetOpLogResponse>, Status> {
+        self.authorize(&request, Role::Reader)?;
         let sync_state = self.app_state.sync_state.read().await;
 
-        let entries: Vec<OpLogEntry> = sync_state
-            .op_log
-            .ops
-            .iter()
-            .map(|entry| {
-                let operation = format!("{:?}", entry.op);
-                let causal_context = entry
-                    .causal
-                    .clocks
-                    .iter()
-                    .map(|(k, v)| (k.clone(), *v as i64))
-                    .collect();
-
-                OpLogEntry {
-                    id: entry.id.clone(),
-                    timestamp: entry.ts,
-                    node_id: "".to_string(), // OpLogEntry 不包含 node_id，从 operation 中提取
-                    operation,
-                    causal_context,
-                }
-            })
-            .collect();
+        let entries: Vec<OpLogEntry> = sync_state.op_log.ops.iter().map(op_log_entry).collect();
 
         Ok(Response::new(GetOpLogResponse { entries }))
     }
@@ -161,8 +392,10 @@ impl CrdtService for CrdtServiceImpl {
     /// 获取操作历史
     async fn get_history(
         &self,
-        _request: Request<GetHistoryRequest>,
+        request: Request<GetHistoryRequest>,
     ) -> Result<Response<GetHistoryResponse>, Status> {
+        self.authorize(&request, Role::Reader)?;
+        let iso = request.into_inner().ts_format == "iso";
         let sync_state = self.app_state.sync_state.read().await;
 
         let entries: Vec<HistoryEntry> = sync_state
@@ -170,66 +403,6 @@ impl CrdtService for CrdtServiceImpl {
             .ops
             .iter()
             .map(|entry| {
-                let (op_type, key, details, op_node_id) = match &entry.op {
-                    crate::sync::Operation::GCounterIncrement {
-                        key,
-                        node_id,
-                        delta,
-                    } => (
-                        "GCounter.Increment",
-                        key.clone(),
-                        format!("增加 {}", delta),
-                        node_id.clone(),
-                    ),
-                    crate::sync::Operation::PNCounterIncrement {
-                        key,
-                        node_id,
-                        delta,
-                    } => (
-                        "PNCounter.Increment",
-                        key.clone(),
-                        format!("增加 {}", delta),
-                        node_id.clone(),
-                    ),
-                    crate::sync::Operation::PNCounterDecrement {
-                        key,
-                        node_id,
-                        delta,
-                    } => (
-                        "PNCounter.Decrement",
-                        key.clone(),
-                        format!("减少 {}", delta),
-                        node_id.clone(),
-                    ),
-                    crate::sync::Operation::LwwRegisterSet {
-                        key,
-                        value,
-                        timestamp,
-                        node_id,
-                    } => (
-                        "LWWRegister.Set",
-                        key.clone(),
-                        format!("节点 {} 设置为 '{}' (ts: {})", node_id, value, timestamp),
-                        node_id.clone(),
-                    ),
-                    crate::sync::Operation::OrSetAdd {
-                        key,
-                        value,
-                        unique_id,
-                    } => (
-                        "ORSet.Add",
-                        key.clone(),
-                        format!("添加元素 '{}' (id: {})", value, &unique_id[..8]),
-                        "".to_string(),
-                    ),
-                    crate::sync::Operation::OrSetRemove { key, value } => (
-                        "ORSet.Remove",
-                        key.clone(),
-                        format!("移除元素 '{}'", value),
-                        "".to_string(),
-                    ),
-                };
-
                 let causal_context = entry
                     .causal
                     .clocks
@@ -240,10 +413,11 @@ impl CrdtService for CrdtServiceImpl {
                 HistoryEntry {
                     id: entry.id.clone(),
                     timestamp: entry.ts,
-                    operation_type: op_type.to_string(),
-                    key,
-                    details,
-                    node_id: op_node_id,
+                    timestamp_iso: iso.then(|| entry.ts_iso()),
+                    operation_type: entry.op.op_type().to_string(),
+                    key: entry.op.key().to_string(),
+                    details: entry.op.details(),
+                    node_id: entry.op.node_id().unwrap_or_default().to_string(),
                     causal_context,
                 }
             })
@@ -255,8 +429,9 @@ impl CrdtService for CrdtServiceImpl {
     /// 获取冲突信息
     async fn get_conflicts(
         &self,
-        _request: Request<GetConflictsRequest>,
+        request: Request<GetConflictsRequest>,
     ) -> Result<Response<GetConflictsResponse>, Status> {
+        self.authorize(&request, Role::Reader)?;
         let sync_state = self.app_state.sync_state.read().await;
 
         let mut conflicts: Vec<Conflict> = Vec::new();
@@ -339,9 +514,117 @@ impl CrdtService for CrdtServiceImpl {
             }
         }
 
+        // OR-Set 上对同一元素的并发添加与移除（添加胜出）
+        for race in sync_state.set_add_remove_races() {
+            conflicts.push(Conflict {
+                key: race.key.to_string(),
+                conflict_type: SetRace::CONFLICT_TYPE.to_string(),
+                operations: race
+                    .entries()
+                    .map(|entry| ConflictOperation {
+                        id: entry.id.clone(),
+                        timestamp: entry.ts,
+                        node_id: entry.origin_node().unwrap_or_default().to_string(),
+                        details: entry.op.details(),
+                    })
+                    .collect(),
+                resolution: race.resolution(),
+            });
+        }
+
         Ok(Response::new(GetConflictsResponse { conflicts }))
     }
 
+    /// 订阅键上新应用的操作，客户端取消时订阅随流一起释放
+    ///
+    /// 消费过慢导致广播缓冲溢出时，被丢弃的事件数通过 `dropped` 告知客户端，
+    /// 客户端应重新拉取状态后继续消费。
+    async fn watch_key(
+        &self,
+        request: Request<WatchKeyRequest>,
+    ) -> Result<Response<Self::WatchKeyStream>, Status> {
+        self.authorize(&request, Role::Reader)?;
+        let WatchKeyRequest { key, prefix } = request.into_inner();
+        if key.is_empty() && !prefix {
+            return Err(invalid_argument(
+                "missing_key",
+                "Missing key for WatchKey".to_string(),
+                "key".to_string(),
+                None,
+            ));
+        }
+
+        let events = BroadcastStream::new(self.app_state.op_events.subscribe());
+        let stream = events.filter_map(move |event| match event {
+            Ok(entry) if watch_matches(&entry, &key, prefix) => Some(Ok(WatchEvent {
+                entry: Some(op_log_entry(&entry)),
+                dropped: 0,
+            })),
+            Ok(_) => None,
+            Err(BroadcastStreamRecvError::Lagged(dropped)) => {
+                tracing::warn!("WatchKey subscriber lagged, dropped {} events", dropped);
+                Some(Ok(WatchEvent {
+                    entry: None,
+                    dropped,
+                }))
+            }
+        });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    /// 跟踪操作日志：先重放 `after_id` 之后的已有条目，再推送新应用的操作
+    ///
+    /// 操作只在持有 `sync_state` 写锁时应用并广播，因此在读锁内订阅并截取日志，
+    /// 重放部分与实时部分之间既不会遗漏也不会重复。
+    async fn stream_op_log(
+        &self,
+        request: Request<StreamOpLogRequest>,
+    ) -> Result<Response<Self::StreamOpLogStream>, Status> {
+        self.authorize(&request, Role::Reader)?;
+        let after_id = request.into_inner().after_id.filter(|id| !id.is_empty());
+
+        let sync_state = self.app_state.sync_state.read().await;
+        let events = self.app_state.op_events.subscribe();
+        let replay: Vec<WatchEvent> = match after_id {
+            None => Vec::new(),
+            Some(after_id) => {
+                let ops = &sync_state.op_log.ops;
+                let position = ops
+                    .iter()
+                    .position(|entry| entry.id == after_id)
+                    .ok_or_else(|| {
+                        Status::not_found(format!("Op not found in oplog: {}", after_id))
+                    })?;
+                ops[position + 1..]
+                    .iter()
+                    .map(|entry| WatchEvent {
+                        entry: Some(op_log_entry(entry)),
+                        dropped: 0,
+                    })
+                    .collect()
+            }
+        };
+        drop(sync_state);
+
+        let live = BroadcastStream::new(events).map(|event| match event {
+            Ok(entry) => Ok(WatchEvent {
+                entry: Some(op_log_entry(&entry)),
+                dropped: 0,
+            }),
+            Err(BroadcastStreamRecvError::Lagged(dropped)) => {
+                tracing::warn!("StreamOpLog subscriber lagged, dropped {} events", dropped);
+                Ok(WatchEvent {
+                    entry: None,
+                    dropped,
+                })
+            }
+        });
+        let stream = tokio_stream::iter(replay.into_iter().map(Ok)).chain(live);
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
     /// 健康检查
     async fn health_check(
         &self,
@@ -356,3 +639,311 @@ impl CrdtService for CrdtServiceImpl {
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SledStorage;
+    use std::sync::Arc;
+
+    fn service() -> (CrdtServiceImpl, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(SledStorage::new(dir.path().to_str().unwrap()).unwrap());
+        let app_state =
+            AppState::new("node1".to_string(), storage, "secret".to_string(), false).unwrap();
+        (CrdtServiceImpl::new(app_state), dir)
+    }
+
+    fn sync_request(changes: Vec<Change>) -> Request<SyncRequest> {
+        Request::new(SyncRequest { changes })
+    }
+
+    #[tokio::test]
+    async fn test_watch_key_streams_matching_ops() {
+        let (service, _dir) = service();
+        let mut stream = service
+            .watch_key(Request::new(WatchKeyRequest {
+                key: "counter1".to_string(),
+                prefix: false,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let increment = |key: &str| Change {
+            op: "increment".to_string(),
+            key: key.to_string(),
+            value: None,
+            delta: Some(2),
+            field: None,
+            position: None,
+        };
+        service
+            .sync(sync_request(vec![
+                increment("counter2"),
+                increment("counter1"),
+                increment("counter2"),
+            ]))
+            .await
+            .unwrap();
+
+        let event = stream.next().await.unwrap().unwrap();
+        assert_eq!(event.dropped, 0);
+        let entry = event.entry.unwrap();
+        assert_eq!(entry.key, "counter1");
+        assert_eq!(entry.node_id, "node1");
+
+        // counter2 的操作不会出现在流中
+        let next = tokio::time::timeout(std::time::Duration::from_millis(100), stream.next()).await;
+        assert!(next.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stream_op_log_replays_then_follows() {
+        let (service, _dir) = service();
+        let increment = |key: &str| Change {
+            op: "increment".to_string(),
+            key: key.to_string(),
+            value: None,
+            delta: Some(1),
+            field: None,
+            position: None,
+        };
+        service
+            .sync(sync_request(vec![
+                increment("a"),
+                increment("b"),
+                increment("c"),
+            ]))
+            .await
+            .unwrap();
+        let (first_id, mut expected) = {
+            let sync_state = service.app_state.sync_state.read().await;
+            let ops = &sync_state.op_log.ops;
+            let rest: Vec<String> = ops[1..].iter().map(|e| e.op.key().to_string()).collect();
+            (ops[0].id.clone(), rest)
+        };
+        expected.push("d".to_string());
+
+        let mut stream = service
+            .stream_op_log(Request::new(StreamOpLogRequest {
+                after_id: Some(first_id),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        service
+            .sync(sync_request(vec![increment("d")]))
+            .await
+            .unwrap();
+
+        // 先重放 after_id 之后的条目，再接上实时操作，顺序一致且不重复
+        let mut keys = Vec::new();
+        for _ in 0..3 {
+            let event = stream.next().await.unwrap().unwrap();
+            keys.push(event.entry.unwrap().key);
+        }
+        assert_eq!(keys, expected);
+        let next = tokio::time::timeout(std::time::Duration::from_millis(100), stream.next()).await;
+        assert!(next.is_err());
+
+        let status = service
+            .stream_op_log(Request::new(StreamOpLogRequest {
+                after_id: Some("missing".to_string()),
+            }))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(status.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_get_conflicts_reports_set_add_remove_race() {
+        let (service, _dir) = service();
+        let tag = |op: &str| crate::sync::ChangeRequest {
+            changes: vec![crate::sync::Change {
+                op: op.to_string(),
+                key: "tags".to_string(),
+                value: Some("x".to_string()),
+                ..Default::default()
+            }],
+        };
+        {
+            // node1 添加后，node2 观察到并移除，同时 node1 再次添加
+            let mut sync_state = service.app_state.sync_state.write().await;
+            sync_state.apply_changes(tag("add")).unwrap();
+            let mut remote = crate::sync::SyncState::new("node2".to_string());
+            remote.merge(&sync_state);
+            remote.apply_changes(tag("remove")).unwrap();
+            sync_state.apply_changes(tag("add")).unwrap();
+            sync_state.merge(&remote);
+        }
+
+        let conflicts = service
+            .get_conflicts(Request::new(GetConflictsRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .conflicts;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].key, "tags");
+        assert_eq!(conflicts[0].conflict_type, SetRace::CONFLICT_TYPE);
+        assert_eq!(conflicts[0].operations.len(), 2);
+        assert!(conflicts[0].resolution.contains("add-wins"));
+    }
+
+    #[tokio::test]
+    async fn test_sync_requires_writer_token_when_auth_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(SledStorage::new(dir.path().to_str().unwrap()).unwrap());
+        let app_state =
+            AppState::new("node1".to_string(), storage, "secret".to_string(), true).unwrap();
+        let mut interceptor = AuthInterceptor {
+            app_state: app_state.clone(),
+        };
+        let service = CrdtServiceImpl::new(app_state.clone());
+        let increment = || Change {
+            op: "increment".to_string(),
+            key: "counter".to_string(),
+            value: None,
+            delta: Some(1),
+            field: None,
+            position: None,
+        };
+        let with_token = |role: Role| {
+            let token = app_state
+                .jwt_manager
+                .generate_token("client".to_string(), role, 3600)
+                .unwrap();
+            let mut request = sync_request(vec![increment()]);
+            request.metadata_mut().insert(
+                "authorization",
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+            request
+        };
+
+        // 缺少 token：拦截器与服务都拒绝
+        let status = interceptor.call(Request::new(())).unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        let status = service
+            .sync(sync_request(vec![increment()]))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+
+        // Reader 可以读取，但不能写入
+        let status = service.sync(with_token(Role::Reader)).await.unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+        let mut read = Request::new(GetStateHashRequest {});
+        *read.metadata_mut() = with_token(Role::Reader).metadata().clone();
+        assert!(service.get_state_hash(read).await.is_ok());
+
+        assert!(service.sync(with_token(Role::Writer)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_sync_error_details() {
+        let (service, _dir) = service();
+
+        let status = service
+            .sync(sync_request(vec![
+                Change {
+                    op: "increment".to_string(),
+                    key: "counter".to_string(),
+                    value: None,
+                    delta: Some(1),
+                    field: None,
+                    position: None,
+                },
+                Change {
+                    op: "set".to_string(),
+                    key: "name".to_string(),
+                    value: None,
+                    delta: None,
+                    field: None,
+                    position: None,
+                },
+            ]))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let details = status.get_error_details();
+        let info = details.error_info().unwrap();
+        assert_eq!(info.reason, "missing_value");
+        assert_eq!(info.domain, ERROR_DOMAIN);
+        assert_eq!(info.metadata["field"], "changes[1].value");
+        assert_eq!(info.metadata["key"], "name");
+        let violations = &details.bad_request().unwrap().field_violations;
+        assert_eq!(violations[0].field, "changes[1].value");
+
+        // 校验失败时不应用任何变更
+        assert!(
+            service
+                .app_state
+                .sync_state
+                .read()
+                .await
+                .op_log
+                .ops
+                .is_empty()
+        );
+
+        let status = service
+            .sync(sync_request(vec![Change {
+                op: "rename".to_string(),
+                key: "name".to_string(),
+                value: None,
+                delta: None,
+                field: None,
+                position: None,
+            }]))
+            .await
+            .unwrap_err();
+        let info = status.get_error_details().error_info().cloned().unwrap();
+        assert_eq!(info.reason, "unknown_op");
+        assert_eq!(info.metadata["field"], "changes[0].op");
+    }
+
+    #[tokio::test]
+    async fn test_merge_holds_out_of_order_delta_until_predecessor_arrives() {
+        let (service, _dir) = service();
+        let mut peer = crate::sync::SyncState::new("node2".to_string());
+        let set_name = |value: &str| ChangeRequest {
+            changes: vec![crate::sync::Change {
+                op: "set".to_string(),
+                key: "name".to_string(),
+                value: Some(value.to_string()),
+                ..Default::default()
+            }],
+        };
+        peer.apply_changes(set_name("alice")).unwrap();
+        let first = peer.delta_since(&crate::crdt::VectorClock::new());
+        let base = peer.crdt_map.vector_clock.clone();
+        peer.apply_changes(set_name("bob")).unwrap();
+        let second = peer.delta_since(&base);
+
+        let merge = |delta: &crate::sync::SyncState| {
+            Request::new(MergeRequest {
+                from_node: "node2".to_string(),
+                state_data: serde_json::to_vec(delta).unwrap(),
+            })
+        };
+
+        // 第二个增量先到达：其操作暂缓，值不变
+        service.merge(merge(&second)).await.unwrap();
+        {
+            let sync_state = service.app_state.sync_state.read().await;
+            assert_eq!(sync_state.pending_ops(), 1);
+            assert!(sync_state.crdt_map.get("name").is_none());
+        }
+
+        // 前驱到达后两个操作按因果顺序应用
+        let response = service.merge(merge(&first)).await.unwrap().into_inner();
+        assert_eq!(response.state_hash, peer.state_hash());
+        let sync_state = service.app_state.sync_state.read().await;
+        assert_eq!(sync_state.pending_ops(), 0);
+        assert_eq!(sync_state.crdt_map.document()["name"], "bob");
+    }
+}
@@ -0,0 +1,143 @@
+use crate::crdt::NodeId;
+use crate::sync::OpLogEntry;
+use serde::Serialize;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::Arc;
+
+/// 单次合并的统计信息
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MergeStats {
+    /// 对端节点 ID
+    pub from_node: NodeId,
+    /// 本次新并入的操作数
+    pub new_ops: usize,
+    /// 本次新增的并发写入冲突数
+    pub conflicts: u64,
+}
+
+/// 操作应用后的回调（包括本地写入与合并并入的操作）
+pub type OpHook = Arc<dyn Fn(&OpLogEntry) + Send + Sync>;
+/// 合并完成后的回调
+pub type MergeHook = Arc<dyn Fn(&MergeStats) + Send + Sync>;
+
+/// 进程内事件回调注册表
+///
+/// 供以库方式嵌入的调用方在不经过 HTTP 的情况下感知状态变化。
+/// 回调在持有状态锁时同步执行，应保持轻量；单个回调 panic 会被捕获并记录，
+/// 不影响其余回调与状态本身。
+#[derive(Clone, Default)]
+pub struct EventHooks {
+    on_operation: Vec<OpHook>,
+    on_merge: Vec<MergeHook>,
+}
+
+impl std::fmt::Debug for EventHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventHooks")
+            .field("on_operation", &self.on_operation.len())
+            .field("on_merge", &self.on_merge.len())
+            .finish()
+    }
+}
+
+impl EventHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 注册操作回调
+    pub fn on_operation(&mut self, hook: impl Fn(&OpLogEntry) + Send + Sync + 'static) {
+        self.on_operation.push(Arc::new(hook));
+    }
+
+    /// 注册合并回调
+    pub fn on_merge(&mut self, hook: impl Fn(&MergeStats) + Send + Sync + 'static) {
+        self.on_merge.push(Arc::new(hook));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.on_operation.is_empty() && self.on_merge.is_empty()
+    }
+
+    /// 依次通知所有操作回调
+    pub fn emit_operation(&self, entry: &OpLogEntry) {
+        for hook in &self.on_operation {
+            if catch_unwind(AssertUnwindSafe(|| hook(entry))).is_err() {
+                tracing::warn!("Operation hook panicked on op {}", entry.id);
+            }
+        }
+    }
+
+    /// 依次通知所有合并回调
+    pub fn emit_merge(&self, stats: &MergeStats) {
+        for hook in &self.on_merge {
+            if catch_unwind(AssertUnwindSafe(|| hook(stats))).is_err() {
+                tracing::warn!("Merge hook panicked on merge from {}", stats.from_node);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::{Change, ChangeRequest, SyncState};
+    use std::sync::Mutex;
+
+    fn set(key: &str, value: &str) -> Change {
+        Change {
+            op: "set".to_string(),
+            key: key.to_string(),
+            value: Some(value.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_operation_hook_observes_each_op_in_order() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut state = SyncState::new("node1".to_string());
+        // 先注册的回调 panic 不影响后续回调
+        state.hooks.on_operation(|_| panic!("boom"));
+        let recorder = seen.clone();
+        state
+            .hooks
+            .on_operation(move |entry| recorder.lock().unwrap().push(entry.id.clone()));
+
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![set("a", "1"), set("b", "2"), set("a", "3")],
+            })
+            .unwrap();
+
+        let expected: Vec<String> = state.op_log.ops.iter().map(|e| e.id.clone()).collect();
+        assert_eq!(expected.len(), 3);
+        assert_eq!(*seen.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_merge_hook_reports_stats() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut state1 = SyncState::new("node1".to_string());
+        let recorder = seen.clone();
+        state1
+            .hooks
+            .on_merge(move |stats| recorder.lock().unwrap().push(stats.clone()));
+
+        let mut state2 = SyncState::new("node2".to_string());
+        state2
+            .apply_changes(ChangeRequest {
+                changes: vec![set("a", "1"), set("b", "2")],
+            })
+            .unwrap();
+
+        state1.merge(&state2);
+        state1.merge(&state2);
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[0].from_node, "node2");
+        assert_eq!(seen[0].new_ops, 2);
+        assert_eq!(seen[1].new_ops, 0);
+    }
+}
@@ -1,8 +1,20 @@
 // 导出模块供集成测试使用
 pub mod api;
 pub mod auth;
+pub mod batch;
+pub mod compression;
 pub mod crdt;
+pub mod encryption;
 pub mod grpc_service;
+pub mod hooks;
+pub mod metrics;
+pub mod peers;
+pub mod policy;
+pub mod schema;
+pub mod selftest;
 pub mod signature;
+pub mod snapshot;
 pub mod storage;
 pub mod sync;
+pub mod telemetry;
+pub mod tls;
@@ -1,8 +1,14 @@
 use anyhow::Result;
+use auth::{JwtManager, Role};
 use clap::Parser;
 use silent::prelude::*;
-use silent_crdt::{api, grpc_service, storage};
-use storage::Storage;
+use silent_crdt::encryption::FieldEncryptor;
+use silent_crdt::telemetry::OtelExporter;
+use silent_crdt::tls::{TlsFiles, read_pem};
+use silent_crdt::{api, auth, batch, grpc_service, peers, snapshot, storage};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use storage::{FlushPolicy, StorageConfig, StorageKind, StorageMode};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[derive(Parser, Debug)]
@@ -13,6 +19,10 @@ struct Args {
     #[arg(long, default_value = "8080")]
     port: u16,
 
+    /// HTTP 与 gRPC 服务的监听地址（如 0.0.0.0 对外开放）
+    #[arg(long, default_value = "127.0.0.1")]
+    bind_address: IpAddr,
+
     /// 节点 ID
     #[arg(long)]
     node_id: Option<String>,
@@ -21,14 +31,54 @@ struct Args {
     #[arg(long, default_value = "./data")]
     data_path: String,
 
+    /// 存储后端（sled / memory），memory 不落盘，重启后数据丢失
+    #[arg(long, default_value = "sled")]
+    storage: StorageKind,
+
+    /// sled 页缓存容量（MiB）
+    #[arg(long, default_value_t = storage::DEFAULT_SLED_CACHE_MB)]
+    sled_cache_mb: u64,
+
+    /// sled 后台刷盘间隔（毫秒），0 表示仅在显式 flush 时落盘
+    #[arg(long, default_value_t = storage::DEFAULT_SLED_FLUSH_MS)]
+    sled_flush_ms: u64,
+
+    /// 合并落盘间隔（毫秒）：指定后保存不再逐次 fsync，由后台任务按间隔统一落盘
+    #[arg(long)]
+    flush_interval_ms: Option<u64>,
+
+    /// sled 存储模式（low-space / high-throughput）
+    #[arg(long, default_value = "low-space")]
+    sled_mode: StorageMode,
+
+    /// 以 zstd 压缩保存同步状态（未压缩的旧数据仍可读取）
+    #[arg(long, default_value = "false")]
+    compress_storage: bool,
+
     /// JWT 密钥
     #[arg(long, default_value = "silent-crdt-secret-key-change-in-production")]
     jwt_secret: String,
 
+    /// RS256 JWT 公钥（PEM）文件路径，指定后替代 --jwt-secret
+    #[arg(long, conflicts_with = "jwt_fallback_secret")]
+    jwt_public_key: Option<std::path::PathBuf>,
+
+    /// RS256 JWT 私钥（PEM）文件路径，省略时本节点只验证不签发 token
+    #[arg(long, requires = "jwt_public_key")]
+    jwt_private_key: Option<std::path::PathBuf>,
+
+    /// 仅用于验证的 JWT 备用密钥，可重复指定（轮换密钥期间填入旧密钥）
+    #[arg(long)]
+    jwt_fallback_secret: Vec<String>,
+
     /// 是否启用权限控制
     #[arg(long, default_value = "false")]
     auth_enabled: bool,
 
+    /// 启用权限控制时，未携带 token 的请求所使用的角色（如 reader）
+    #[arg(long)]
+    default_role: Option<Role>,
+
     /// gRPC 服务端口
     #[arg(long, default_value = "50051")]
     grpc_port: u16,
@@ -36,10 +86,152 @@ struct Args {
     /// 是否启用 gRPC 服务
     #[arg(long, default_value = "false")]
     grpc_enabled: bool,
+
+    /// 请求体大小上限（字节），超出时返回 413
+    #[arg(long, default_value_t = silent_crdt::api::DEFAULT_MAX_BODY_BYTES)]
+    max_body_bytes: usize,
+
+    /// 快照最长保留时间（秒），设置后后台定期删除过期快照
+    #[arg(long)]
+    snapshot_max_age: Option<u64>,
+
+    /// 默认文档每应用 N 个操作自动保存一次快照
+    #[arg(long)]
+    snapshot_every_ops: Option<u64>,
+
+    /// 每隔 N 秒自动保存一次快照（状态未变化时跳过）
+    #[arg(long)]
+    snapshot_interval: Option<u64>,
+
+    /// 自动快照启用时保留的最新快照数量
+    #[arg(long, default_value_t = snapshot::DEFAULT_SNAPSHOT_KEEP)]
+    snapshot_keep: usize,
+
+    /// 传入数据允许的 CRDT 最大嵌套深度，超出时在解析前拒绝
+    #[arg(long, default_value_t = silent_crdt::crdt::DEFAULT_MAX_CRDT_DEPTH)]
+    max_crdt_depth: usize,
+
+    /// 计数器单节点贡献上限（集群内所有节点需配置相同的值），超出的传入计数将被截断
+    #[arg(long)]
+    counter_cap: Option<u64>,
+
+    /// 不同键的数量上限（集群内所有节点需配置相同的值），超出时拒绝创建新键，合并时按字节序隔离多余的键
+    #[arg(long)]
+    max_keys: Option<usize>,
+
+    /// 单个集合的元素数量上限（集群内所有节点需配置相同的值），超出时拒绝添加新元素，合并时按字节序隔离多余的元素
+    #[arg(long)]
+    max_set_elements: Option<usize>,
+
+    /// 慢操作日志阈值（毫秒）：apply_changes、merge、state_hash 耗时超过该值时记录警告
+    #[arg(long)]
+    slow_op_threshold_ms: Option<u64>,
+
+    /// 以明文 HTTP/2 直连对等节点（要求所有对等节点支持 h2c）
+    #[arg(long, default_value = "false")]
+    peer_http2: bool,
+
+    /// 对等节点客户端每个节点保留的最大空闲连接数
+    #[arg(long, default_value_t = 8)]
+    peer_pool_max_idle: usize,
+
+    /// 以 zstd 压缩推送给对等节点的状态（要求所有对等节点已升级到支持压缩的版本）
+    #[arg(long, default_value = "false")]
+    compress_peer_sync: bool,
+
+    /// 反熵同步的对等节点列表（host:port，逗号分隔），后台定期将本地状态推送到状态不一致的节点
+    #[arg(long, value_delimiter = ',')]
+    peers: Vec<String>,
+
+    /// 反熵同步间隔（秒）
+    #[arg(long, default_value_t = peers::DEFAULT_SYNC_INTERVAL_SECS)]
+    sync_interval: u64,
+
+    /// 对 GCounter 键执行 decrement 时自动转换为 PNCounter（否则报错）
+    #[arg(long, default_value = "false")]
+    auto_promote_counters: bool,
+
+    /// 敏感字段加密密钥（64 位十六进制，集群内共享）
+    #[arg(long)]
+    encryption_key: Option<String>,
+
+    /// 需要加密的键模式，可重复指定（如 "secret/*"）
+    #[arg(long)]
+    encrypted_keys: Vec<String>,
+
+    /// 快照完整性巡检间隔（秒），设置后后台定期校验并隔离损坏的快照
+    #[arg(long)]
+    scrub_interval: Option<u64>,
+
+    /// 写入合并窗口（毫秒），大于 0 时 /sync 变更将批量应用并持久化
+    #[arg(long, default_value = "0")]
+    apply_batch_ms: u64,
+
+    /// 签名密钥文件（base64 编码的 32 字节私钥），不存在时生成；未指定时使用存储中的密钥
+    #[arg(long)]
+    key_path: Option<std::path::PathBuf>,
+
+    /// 合并时要求所有传入操作都带有有效签名（默认接受未签名的旧操作）
+    #[arg(long, default_value = "false")]
+    require_signatures: bool,
+
+    /// 幂等键（`Idempotency-Key` 请求头）的保留时间（秒）
+    #[arg(long, default_value_t = silent_crdt::api::DEFAULT_IDEMPOTENCY_TTL_SECS)]
+    idempotency_ttl: u64,
+
+    /// 最多保留的幂等键数量，超出时淘汰最久未使用的键（0 表示不记录）
+    #[arg(long, default_value_t = silent_crdt::api::DEFAULT_IDEMPOTENCY_CAPACITY)]
+    idempotency_capacity: usize,
+
+    /// TLS 证书（PEM），与 `--tls-key` 同时指定时 HTTP 与 gRPC 服务均只接受 TLS 连接，并以 HTTPS 访问对等节点
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<std::path::PathBuf>,
+
+    /// TLS 私钥（PEM）
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<std::path::PathBuf>,
+
+    /// 额外信任的对等节点证书 CA（PEM），对等节点使用自签名证书时指定
+    #[arg(long)]
+    peer_ca: Option<std::path::PathBuf>,
+
+    /// 允许跨域访问的来源，可重复指定（如 http://localhost:5173），`*` 表示任意来源
+    #[arg(long)]
+    cors_origin: Vec<String>,
+
+    /// 每个调用方（JWT `sub`，无有效 token 时为客户端 IP）每秒允许的请求数，超出时返回 429
+    #[arg(long)]
+    rate_limit_rps: Option<f64>,
+
+    /// 限流突发容量：调用方空闲后最多可连续发送的请求数
+    #[arg(long, default_value_t = silent_crdt::api::DEFAULT_RATE_LIMIT_BURST)]
+    rate_limit_burst: u32,
+
+    /// OpenTelemetry 收集器地址（OTLP gRPC，如 http://localhost:4317），设置后导出追踪与指标
+    #[arg(long)]
+    otel_endpoint: Option<String>,
+}
+
+/// 是否在非回环地址上以无认证方式对外开放
+fn is_exposed_without_auth(bind_address: IpAddr, auth_enabled: bool) -> bool {
+    !bind_address.is_loopback() && !auth_enabled
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // 解析命令行参数
+    let args = Args::parse();
+
+    // 生成或使用提供的节点 ID
+    let node_id = args.node_id.clone().unwrap_or_else(scru128::new_string);
+
+    // 初始化 OpenTelemetry 导出（可选）
+    let otel = args
+        .otel_endpoint
+        .as_deref()
+        .map(|endpoint| OtelExporter::init(endpoint, &node_id))
+        .transpose()?;
+
     // 初始化日志
     tracing_subscriber::registry()
         .with(
@@ -47,61 +239,242 @@ async fn main() -> Result<()> {
                 .unwrap_or_else(|_| "silent_crdt=info,silent=info".into()),
         )
         .with(tracing_subscriber::fmt::layer())
+        .with(otel.as_ref().map(|otel| otel.layer()))
         .init();
 
-    // 解析命令行参数
-    let args = Args::parse();
-
-    // 生成或使用提供的节点 ID
-    let node_id = args.node_id.unwrap_or_else(|| {
-        let id = scru128::new_string();
-        tracing::info!("Generated node ID: {}", id);
-        id
-    });
+    if args.node_id.is_none() {
+        tracing::info!("Generated node ID: {}", node_id);
+    }
+    if let Some(endpoint) = &args.otel_endpoint {
+        tracing::info!("OpenTelemetry export enabled: {}", endpoint);
+    }
 
     tracing::info!("Starting Silent CRDT node: {}", node_id);
     tracing::info!("Data path: {}", args.data_path);
 
     // 初始化存储
-    let storage = Storage::new(&args.data_path)?;
+    let storage_config = StorageConfig {
+        cache_mb: args.sled_cache_mb,
+        flush_every_ms: args.sled_flush_ms,
+        mode: args.sled_mode,
+        flush_policy: match args.flush_interval_ms {
+            Some(ms) => FlushPolicy::Periodic(std::time::Duration::from_millis(ms)),
+            None => FlushPolicy::Immediate,
+        },
+        compress_state: args.compress_storage,
+    };
+    let storage = storage::open_storage(args.storage, &args.data_path, &storage_config)?;
     tracing::info!("Storage initialized");
+    if let FlushPolicy::Periodic(interval) = storage_config.flush_policy {
+        storage::spawn_periodic_flush_task(storage.clone(), interval);
+        tracing::info!("Periodic flush enabled: {}ms", interval.as_millis());
+    }
+
+    // 敏感字段加密
+    let encryptor = match &args.encryption_key {
+        Some(key) => Some(FieldEncryptor::from_hex_key(
+            key,
+            args.encrypted_keys.clone(),
+        )?),
+        None if !args.encrypted_keys.is_empty() => {
+            anyhow::bail!("--encrypted-keys requires --encryption-key")
+        }
+        None => None,
+    };
+
+    let tls = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => Some(TlsFiles::new(cert.clone(), key.clone())),
+        _ => None,
+    };
+
+    let jwt_manager = match &args.jwt_public_key {
+        Some(public_key) => {
+            let public_pem = std::fs::read(public_key)?;
+            let private_pem = args
+                .jwt_private_key
+                .as_ref()
+                .map(std::fs::read)
+                .transpose()?;
+            Some(JwtManager::from_rsa_pem(
+                private_pem.as_deref(),
+                &public_pem,
+            )?)
+        }
+        None => None,
+    };
 
     // 创建应用状态
-    let app_state = api::AppState::new(
+    let mut app_state = api::AppState::new(
         node_id.clone(),
         storage,
         args.jwt_secret.clone(),
         args.auth_enabled,
-    )?;
+    )?
+    .with_max_body_bytes(args.max_body_bytes)
+    .with_max_crdt_depth(args.max_crdt_depth)
+    .with_jwt_fallback_secrets(&args.jwt_fallback_secret)
+    .with_default_role(args.default_role.clone())
+    .with_counter_cap(args.counter_cap)
+    .with_max_keys(args.max_keys)
+    .with_max_set_elements(args.max_set_elements)
+    .with_auto_promote_counters(args.auto_promote_counters)
+    .with_field_encryption(encryptor)
+    .with_slow_op_threshold(args.slow_op_threshold_ms)
+    .with_require_signatures(args.require_signatures)
+    .with_rate_limit(args.rate_limit_rps, args.rate_limit_burst)
+    .with_cors_origins(args.cors_origin.clone())
+    .with_idempotency(args.idempotency_ttl, args.idempotency_capacity)
+    .with_peer_client_config(&api::PeerClientConfig {
+        http2_prior_knowledge: args.peer_http2,
+        pool_max_idle_per_host: args.peer_pool_max_idle,
+        compress: args.compress_peer_sync,
+        https: tls.is_some(),
+        ca_cert_pem: args.peer_ca.as_deref().map(read_pem).transpose()?,
+        ..Default::default()
+    })?;
+    if let Some(jwt_manager) = jwt_manager {
+        tracing::info!(
+            "JWT algorithm: {:?} (issuing {})",
+            jwt_manager.algorithm(),
+            if jwt_manager.can_issue() {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+        app_state = app_state.with_jwt_manager(jwt_manager);
+    }
+    if let Some(key_path) = &args.key_path {
+        app_state = app_state.with_key_file(key_path)?;
+        tracing::info!("Signing key file: {}", key_path.display());
+    }
     tracing::info!("Application state created");
     tracing::info!("Auth enabled: {}", args.auth_enabled);
+    if !args.jwt_fallback_secret.is_empty() {
+        tracing::info!("JWT fallback secrets: {}", args.jwt_fallback_secret.len());
+    }
+    if let Some(role) = &args.default_role {
+        tracing::info!("Default role for unauthenticated requests: {:?}", role);
+    }
+
+    // 启用写入合并窗口
+    if args.apply_batch_ms > 0 {
+        app_state = app_state.with_apply_batcher();
+        batch::spawn_flush_task(
+            app_state.clone(),
+            std::time::Duration::from_millis(args.apply_batch_ms),
+        );
+        tracing::info!("Apply batching enabled: {}ms window", args.apply_batch_ms);
+    }
+
+    // 启用自动快照
+    if args.snapshot_every_ops.is_some() || args.snapshot_interval.is_some() {
+        if args.snapshot_keep == 0 {
+            anyhow::bail!("--snapshot-keep must be at least 1");
+        }
+        app_state = app_state.with_snapshot_policy(Some(snapshot::SnapshotPolicy::new(
+            args.snapshot_every_ops,
+            args.snapshot_keep,
+        )));
+        if let Some(interval) = args.snapshot_interval {
+            snapshot::spawn_auto_snapshot_task(
+                app_state.clone(),
+                std::time::Duration::from_secs(interval),
+            );
+        }
+        tracing::info!(
+            "Automatic snapshots: every {:?} ops, every {:?}s, keeping {}",
+            args.snapshot_every_ops,
+            args.snapshot_interval,
+            args.snapshot_keep
+        );
+    }
+
+    // 启用按时间的快照清理
+    if let Some(max_age) = args.snapshot_max_age {
+        storage::spawn_snapshot_pruning_task(
+            app_state.storage.clone(),
+            node_id.clone(),
+            std::time::Duration::from_secs(max_age),
+        );
+        tracing::info!("Snapshot retention: {}s", max_age);
+    }
+
+    // 启用快照完整性巡检
+    if let Some(interval) = args.scrub_interval {
+        storage::spawn_snapshot_scrub_task(
+            app_state.storage.clone(),
+            node_id.clone(),
+            std::time::Duration::from_secs(interval),
+        );
+        tracing::info!("Snapshot scrub interval: {}s", interval);
+    }
+
+    // 启用反熵同步
+    if !args.peers.is_empty() {
+        if args.sync_interval == 0 {
+            anyhow::bail!("--sync-interval must be at least 1 second");
+        }
+        tracing::info!(
+            "Anti-entropy with {} peers every {}s",
+            args.peers.len(),
+            args.sync_interval
+        );
+        peers::spawn_anti_entropy_task(
+            app_state.clone(),
+            Arc::new(peers::PeerManager::new(args.peers.clone())),
+            std::time::Duration::from_secs(args.sync_interval),
+        );
+    }
+
+    if args.auth_enabled {
+        auth::spawn_revocation_purge_task(
+            app_state.revocations.clone(),
+            std::time::Duration::from_secs(auth::REVOCATION_PURGE_INTERVAL_SECS),
+        );
+    }
 
     // 构建路由
     let routes = api::build_routes(app_state.clone());
 
+    if is_exposed_without_auth(args.bind_address, args.auth_enabled) {
+        tracing::warn!(
+            "⚠️  Listening on non-loopback address {} with auth disabled: \
+             any host that can reach this node can read and modify its state. \
+             Use --auth-enabled for multi-host deployments.",
+            args.bind_address
+        );
+    }
+
     // 启动 HTTP 服务器
-    let http_addr: std::net::SocketAddr = format!("127.0.0.1:{}", args.port)
-        .parse()
-        .expect("Invalid HTTP address");
-    tracing::info!("Starting HTTP server on http://{}", http_addr);
+    let http_addr = SocketAddr::new(args.bind_address, args.port);
+    let mut http_server = Server::new().bind(http_addr);
+    if let Some(tls) = &tls {
+        http_server = http_server.with_rustls(tls.rustls_config()?);
+        tracing::info!("Starting HTTP server on https://{}", http_addr);
+    } else {
+        tracing::info!("Starting HTTP server on http://{}", http_addr);
+    }
 
     // 如果启用 gRPC，同时启动 gRPC 服务器
-    if args.grpc_enabled {
-        let grpc_addr: std::net::SocketAddr = format!("127.0.0.1:{}", args.grpc_port)
-            .parse()
-            .expect("Invalid gRPC address");
+    let result = if args.grpc_enabled {
+        let grpc_addr = SocketAddr::new(args.bind_address, args.grpc_port);
         tracing::info!("Starting gRPC server on {}", grpc_addr);
 
         let grpc_service = grpc_service::CrdtServiceImpl::new(app_state.clone());
-        let grpc_server = grpc_service.into_server();
+        let grpc_server = grpc_service.into_authenticated_server();
+        let mut grpc_builder = tonic::transport::Server::builder();
+        if let Some(tls) = &tls {
+            grpc_builder = grpc_builder.tls_config(tls.grpc_config()?)?;
+        }
 
         // 并行运行 HTTP 和 gRPC 服务器
         tokio::select! {
-            _ = Server::new().bind(http_addr).serve(routes) => {
+            _ = http_server.serve(routes) => {
                 tracing::info!("HTTP server stopped");
                 Ok(())
             }
-            result = tonic::transport::Server::builder()
+            result = grpc_builder
                 .add_service(grpc_server)
                 .serve(grpc_addr) => {
                 tracing::info!("gRPC server stopped");
@@ -109,7 +482,66 @@ async fn main() -> Result<()> {
             }
         }
     } else {
-        Server::new().bind(http_addr).serve(routes).await;
+        http_server.serve(routes).await;
         Ok(())
+    };
+
+    // 退出前持久化尚未应用的批量变更
+    if let Err(e) = app_state.flush_apply_batch().await {
+        tracing::error!("Failed to flush pending batched changes: {}", e);
+    }
+    if let Err(e) = app_state.storage.flush_pending() {
+        tracing::error!("Failed to flush storage: {}", e);
+    }
+
+    if let Some(otel) = otel {
+        otel.shutdown();
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_address_parsing() {
+        let args = Args::try_parse_from(["silent-crdt"]).unwrap();
+        assert_eq!(args.bind_address, IpAddr::from([127, 0, 0, 1]));
+
+        let args = Args::try_parse_from(["silent-crdt", "--bind-address", "0.0.0.0"]).unwrap();
+        assert_eq!(args.bind_address, IpAddr::from([0, 0, 0, 0]));
+
+        let args = Args::try_parse_from(["silent-crdt", "--bind-address", "::1"]).unwrap();
+        assert!(args.bind_address.is_loopback());
+
+        assert!(Args::try_parse_from(["silent-crdt", "--bind-address", "not-an-ip"]).is_err());
+    }
+
+    #[test]
+    fn test_tls_flags_require_each_other() {
+        assert!(Args::try_parse_from(["silent-crdt", "--tls-cert", "cert.pem"]).is_err());
+        assert!(Args::try_parse_from(["silent-crdt", "--tls-key", "key.pem"]).is_err());
+        let args = Args::try_parse_from([
+            "silent-crdt",
+            "--tls-cert",
+            "cert.pem",
+            "--tls-key",
+            "key.pem",
+        ])
+        .unwrap();
+        assert!(args.tls_cert.is_some() && args.tls_key.is_some());
+    }
+
+    #[test]
+    fn test_exposed_without_auth_warning_condition() {
+        let loopback = IpAddr::from([127, 0, 0, 1]);
+        let any = IpAddr::from([0, 0, 0, 0]);
+
+        assert!(!is_exposed_without_auth(loopback, false));
+        assert!(!is_exposed_without_auth(loopback, true));
+        assert!(is_exposed_without_auth(any, false));
+        assert!(!is_exposed_without_auth(any, true));
     }
 }
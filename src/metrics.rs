@@ -0,0 +1,137 @@
+use crate::sync::{CHANGE_OPS, SyncState};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 节点运行指标（原子计数，处理器递增时无需持有 `sync_state` 锁）
+#[derive(Debug)]
+pub struct Metrics {
+    ops_applied: Vec<AtomicU64>, // 与 CHANGE_OPS 一一对应
+    merges_received: AtomicU64,
+    sync_peer_attempts: AtomicU64,
+    sync_peer_failures: AtomicU64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            ops_applied: CHANGE_OPS.iter().map(|_| AtomicU64::new(0)).collect(),
+            merges_received: AtomicU64::new(0),
+            sync_peer_attempts: AtomicU64::new(0),
+            sync_peer_failures: AtomicU64::new(0),
+        }
+    }
+}
+
+/// 单个文档在抓取时的规模
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentSize {
+    pub doc_id: String,
+    pub keys: usize,
+    pub oplog_len: usize,
+    pub state_bytes: usize,
+}
+
+impl DocumentSize {
+    pub fn of(doc_id: &str, sync_state: &SyncState) -> Self {
+        Self {
+            doc_id: doc_id.to_string(),
+            keys: sync_state.crdt_map.entries.len(),
+            oplog_len: sync_state.op_log.ops.len(),
+            state_bytes: serde_json::to_vec(sync_state).map_or(0, |bytes| bytes.len()),
+        }
+    }
+}
+
+impl Metrics {
+    /// 记录已应用的变更（按 `Change.op` 计数，未知类型不计）
+    pub fn record_ops<'a>(&self, ops: impl IntoIterator<Item = &'a str>) {
+        for op in ops {
+            if let Some(index) = CHANGE_OPS.iter().position(|spec| spec.op == op) {
+                self.ops_applied[index].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn record_merge(&self) {
+        self.merges_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录一次 sync-peer 尝试及其结果
+    pub fn record_sync_peer(&self, success: bool) {
+        self.sync_peer_attempts.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            self.sync_peer_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 以 Prometheus 文本格式输出计数器与各文档的规模
+    pub fn render(&self, documents: &[DocumentSize]) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "crdt_ops_applied_total",
+            "counter",
+            "已应用的操作数（按操作类型）",
+        );
+        for (spec, count) in CHANGE_OPS.iter().zip(&self.ops_applied) {
+            let _ = writeln!(
+                out,
+                "crdt_ops_applied_total{{op=\"{}\"}} {}",
+                spec.op,
+                count.load(Ordering::Relaxed)
+            );
+        }
+
+        let counters = [
+            (
+                "crdt_merges_received_total",
+                "收到的合并请求数",
+                &self.merges_received,
+            ),
+            (
+                "crdt_sync_peer_attempts_total",
+                "sync-peer 尝试次数",
+                &self.sync_peer_attempts,
+            ),
+            (
+                "crdt_sync_peer_failures_total",
+                "sync-peer 失败次数",
+                &self.sync_peer_failures,
+            ),
+        ];
+        for (name, help, value) in counters {
+            header(&mut out, name, "counter", help);
+            let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+        }
+
+        let gauges: [(&str, &str, fn(&DocumentSize) -> usize); 3] = [
+            ("crdt_keys", "文档中的键数量", |d| d.keys),
+            ("crdt_oplog_length", "文档操作日志长度", |d| {
+                d.oplog_len
+            }),
+            ("crdt_state_bytes", "文档序列化后的字节数", |d| {
+                d.state_bytes
+            }),
+        ];
+        for (name, help, value) in gauges {
+            header(&mut out, name, "gauge", help);
+            for document in documents {
+                let _ = writeln!(
+                    out,
+                    "{}{{document=\"{}\"}} {}",
+                    name,
+                    document.doc_id,
+                    value(document)
+                );
+            }
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
@@ -0,0 +1,259 @@
+use crate::api::{AppState, post_to_peer};
+use crate::sync::SyncRequest;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// 默认反熵同步间隔（秒）
+pub const DEFAULT_SYNC_INTERVAL_SECS: u64 = 30;
+
+/// 单个对等节点的反熵同步记录
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PeerSyncStatus {
+    /// 最近一次成功对账的时间（毫秒时间戳，哈希一致而跳过传输也计入）
+    pub last_sync: Option<i64>,
+    /// 最近一次失败的原因，成功后清除
+    pub last_error: Option<String>,
+}
+
+/// 一次对账的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconcileOutcome {
+    /// 状态哈希一致，未传输状态
+    InSync,
+    /// 已将本地状态推送到对端的 `/merge`
+    Pushed,
+}
+
+#[derive(Debug, Deserialize)]
+struct StateHashResponse {
+    hash: String,
+}
+
+/// 反熵对等节点列表及各节点最近的同步时间
+#[derive(Debug)]
+pub struct PeerManager {
+    peers: Vec<String>, // host:port
+    status: Mutex<HashMap<String, PeerSyncStatus>>,
+}
+
+impl PeerManager {
+    pub fn new(peers: Vec<String>) -> Self {
+        Self {
+            peers,
+            status: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn peers(&self) -> &[String] {
+        &self.peers
+    }
+
+    /// 对等节点的同步记录（尚未尝试过时为默认值）
+    pub fn status(&self, peer: &str) -> PeerSyncStatus {
+        self.status
+            .lock()
+            .unwrap()
+            .get(peer)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// 与单个对等节点对账：先比较 `/state-hash`，不一致时将本地默认文档推送到对端的 `/merge`
+    pub async fn reconcile(&self, state: &AppState, peer: &str) -> Result<ReconcileOutcome> {
+        let remote: StateHashResponse = state
+            .peer_client
+            .get(state.peer_url(peer, "/state-hash"))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Failed to fetch state hash from {}", peer))?
+            .json()
+            .await
+            .with_context(|| format!("Invalid state hash response from {}", peer))?;
+
+        let sync_request = {
+            let sync_state = state.sync_state.read().await;
+            if state.timed_state_hash(&sync_state) == remote.hash {
+                return Ok(ReconcileOutcome::InSync);
+            }
+            SyncRequest {
+                from_node: state.node_id.clone(),
+                state: sync_state.clone(),
+            }
+        };
+
+        post_to_peer(state, &state.peer_url(peer, "/merge"), &sync_request)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to push state to {}: {}", peer, e))?;
+        Ok(ReconcileOutcome::Pushed)
+    }
+
+    /// 依次与所有对等节点对账，单个节点失败只记录日志，不影响其余节点
+    ///
+    /// 每轮先刷新对等节点的公钥，使其轮换后的新公钥能及时用于验证传入的合并。
+    pub async fn run_round(&self, state: &AppState) {
+        for peer in &self.peers {
+            if let Err(e) = state.refresh_peer_keys(peer).await {
+                tracing::warn!("Failed to refresh public key of {}: {:#}", peer, e);
+            }
+            let result = self.reconcile(state, peer).await;
+            let mut status = self.status.lock().unwrap();
+            let entry = status.entry(peer.clone()).or_default();
+            match result {
+                Ok(outcome) => {
+                    tracing::debug!("Anti-entropy with {}: {:?}", peer, outcome);
+                    if outcome == ReconcileOutcome::Pushed {
+                        state.metrics.record_sync_peer(true);
+                    }
+                    entry.last_sync = Some(chrono::Utc::now().timestamp_millis());
+                    entry.last_error = None;
+                }
+                Err(e) => {
+                    tracing::warn!("Anti-entropy with {} failed: {:#}", peer, e);
+                    state.metrics.record_sync_peer(false);
+                    entry.last_error = Some(format!("{:#}", e));
+                }
+            }
+        }
+    }
+}
+
+/// 启动后台反熵任务，按固定间隔与所有对等节点对账
+pub fn spawn_anti_entropy_task(
+    app_state: AppState,
+    peers: Arc<PeerManager>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            peers.run_round(&app_state).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::sync::{Change, ChangeRequest};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// 模拟对等节点：`/state-hash` 返回共享的哈希，`/merge` 计数并将哈希更新为请求方的状态
+    async fn mock_peer(hash: Arc<Mutex<String>>, merges: Arc<AtomicUsize>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let hash = hash.clone();
+                let merges = merges.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = vec![0u8; 4096];
+                    let header_end = loop {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                        if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                            break end + 4;
+                        }
+                    };
+                    let head = String::from_utf8_lossy(&request[..header_end]).to_lowercase();
+                    let content_length: usize = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length:"))
+                        .map_or(0, |value| value.trim().parse().unwrap());
+                    while request.len() < header_end + content_length {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+
+                    let body = if head.starts_with("post /merge") {
+                        merges.fetch_add(1, Ordering::SeqCst);
+                        let sync_request: SyncRequest =
+                            serde_json::from_slice(&request[header_end..]).unwrap();
+                        let state_hash = sync_request.state.state_hash();
+                        *hash.lock().unwrap() = state_hash.clone();
+                        serde_json::json!({
+                            "success": true,
+                            "state_hash": state_hash,
+                            "message": "merged",
+                        })
+                    } else {
+                        serde_json::json!({ "hash": *hash.lock().unwrap() })
+                    }
+                    .to_string();
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        addr.to_string()
+    }
+
+    #[tokio::test]
+    async fn test_anti_entropy_pushes_only_when_hashes_differ() -> Result<()> {
+        let state = AppState::new(
+            "node1".to_string(),
+            Arc::new(MemoryStorage::new()),
+            "secret".to_string(),
+            false,
+        )?;
+        let local_hash = state.sync_state.read().await.state_hash();
+
+        let hash = Arc::new(Mutex::new(local_hash));
+        let merges = Arc::new(AtomicUsize::new(0));
+        let peer = mock_peer(hash.clone(), merges.clone()).await;
+
+        // 不可达的对等节点：端口已释放
+        let unreachable = {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+            listener.local_addr()?.to_string()
+        };
+        let manager = PeerManager::new(vec![unreachable.clone(), peer.clone()]);
+
+        // 哈希一致时跳过传输，不可达节点不影响其他节点
+        manager.run_round(&state).await;
+        assert_eq!(merges.load(Ordering::SeqCst), 0);
+        assert!(manager.status(&peer).last_sync.is_some());
+        assert!(manager.status(&unreachable).last_sync.is_none());
+        assert!(manager.status(&unreachable).last_error.is_some());
+
+        // 本地写入后哈希不一致，推送一次；对端合并后再次对账不再传输
+        state
+            .sync_state
+            .write()
+            .await
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "increment".to_string(),
+                    key: "visits".to_string(),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        manager.run_round(&state).await;
+        assert_eq!(merges.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            *hash.lock().unwrap(),
+            state.sync_state.read().await.state_hash()
+        );
+        manager.run_round(&state).await;
+        assert_eq!(merges.load(Ordering::SeqCst), 1);
+        assert!(manager.status(&peer).last_error.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use crate::crdt::LWWRegister;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// 键的并发写入冲突解决策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// 最后写入胜出（默认）
+    #[default]
+    Lww,
+    /// 保留数值最大的写入
+    Max,
+    /// 保留数值最小的写入
+    Min,
+    /// 保留所有并发写入
+    KeepAll,
+}
+
+impl ConflictPolicy {
+    /// 承载该策略的 CRDT 类型
+    pub fn crdt_type(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Lww => "lww-register",
+            ConflictPolicy::Max => "max-register",
+            ConflictPolicy::Min => "min-register",
+            ConflictPolicy::KeepAll => "mv-register",
+        }
+    }
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConflictPolicy::Lww => "lww",
+            ConflictPolicy::Max => "max",
+            ConflictPolicy::Min => "min",
+            ConflictPolicy::KeepAll => "keep-all",
+        };
+        f.write_str(name)
+    }
+}
+
+/// 按键设置的冲突解决策略（随状态同步）
+///
+/// 每个键的策略本身是一个 LWW 寄存器，并发修改策略时各节点按时间戳收敛到同一结果。
+/// 策略只决定之后 `set` 写入使用的 CRDT 类型，应在写入该键之前设置。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyRegistry {
+    pub policies: HashMap<String, LWWRegister<ConflictPolicy>>,
+}
+
+impl PolicyRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    /// 设置键的策略
+    pub fn set(&mut self, key: String, policy: ConflictPolicy, timestamp: i64, node_id: &str) {
+        self.policies
+            .entry(key)
+            .or_default()
+            .set(policy, timestamp, node_id);
+    }
+
+    /// 键的策略，未设置时为 LWW
+    pub fn get(&self, key: &str) -> ConflictPolicy {
+        self.policies
+            .get(key)
+            .and_then(|r| r.get())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn merge(&mut self, other: &PolicyRegistry) {
+        for (key, register) in &other.policies {
+            self.policies
+                .entry(key.clone())
+                .or_default()
+                .merge(register);
+        }
+    }
+
+    /// 按键排序的策略列表
+    pub fn listing(&self) -> BTreeMap<String, ConflictPolicy> {
+        self.policies
+            .keys()
+            .map(|key| (key.clone(), self.get(key)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_deserialize() {
+        let policy: ConflictPolicy = serde_json::from_str(r#""keep-all""#).unwrap();
+        assert_eq!(policy, ConflictPolicy::KeepAll);
+        assert!(serde_json::from_str::<ConflictPolicy>(r#""median""#).is_err());
+    }
+
+    #[test]
+    fn test_registry_merge_converges() {
+        let mut a = PolicyRegistry::new();
+        let mut b = PolicyRegistry::new();
+        a.set("bid".to_string(), ConflictPolicy::Max, 100, "node1");
+        b.set("bid".to_string(), ConflictPolicy::Min, 200, "node2");
+        b.set("tags".to_string(), ConflictPolicy::KeepAll, 50, "node2");
+
+        let mut ab = a.clone();
+        ab.merge(&b);
+        let mut ba = b.clone();
+        ba.merge(&a);
+
+        assert_eq!(ab.listing(), ba.listing());
+        assert_eq!(ab.get("bid"), ConflictPolicy::Min);
+        assert_eq!(ab.get("tags"), ConflictPolicy::KeepAll);
+        assert_eq!(ab.get("other"), ConflictPolicy::Lww);
+    }
+}
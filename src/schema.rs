@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 值类型约束
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    String,
+    Integer,
+    Number,
+}
+
+/// 单个键的取值约束
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KeySchema {
+    /// 值类型
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub value_type: Option<ValueType>,
+    /// 允许的取值集合
+    #[serde(default, rename = "enum", skip_serializing_if = "Option::is_none")]
+    pub allowed: Option<Vec<String>>,
+    /// 数值下界（含）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    /// 数值上界（含）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+}
+
+impl KeySchema {
+    /// 校验值是否满足约束
+    pub fn validate(&self, value: &str) -> Result<(), String> {
+        match self.value_type {
+            Some(ValueType::Integer) if value.parse::<i64>().is_err() => {
+                return Err(format!("'{}' is not an integer", value));
+            }
+            Some(ValueType::Number) if value.parse::<f64>().is_err() => {
+                return Err(format!("'{}' is not a number", value));
+            }
+            _ => {}
+        }
+
+        if let Some(allowed) = &self.allowed
+            && !allowed.iter().any(|v| v == value)
+        {
+            return Err(format!("'{}' is not one of {:?}", value, allowed));
+        }
+
+        if self.min.is_some() || self.max.is_some() {
+            let number: f64 = value
+                .parse()
+                .map_err(|_| format!("'{}' is not a number", value))?;
+            if let Some(min) = self.min
+                && number < min
+            {
+                return Err(format!("{} is less than minimum {}", number, min));
+            }
+            if let Some(max) = self.max
+                && number > max
+            {
+                return Err(format!("{} is greater than maximum {}", number, max));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// 按键注册的取值约束集合
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchemaRegistry {
+    pub schemas: HashMap<String, KeySchema>,
+}
+
+impl SchemaRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.schemas.is_empty()
+    }
+
+    /// 注册（或替换）键的约束
+    pub fn register(&mut self, key: String, schema: KeySchema) {
+        self.schemas.insert(key, schema);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&KeySchema> {
+        self.schemas.get(key)
+    }
+
+    /// 校验键的取值，未注册约束的键总是通过
+    pub fn validate(&self, key: &str, value: &str) -> Result<(), String> {
+        match self.schemas.get(key) {
+            Some(schema) => schema
+                .validate(value)
+                .map_err(|e| format!("Value for key '{}' violates schema: {}", key, e)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enum_schema() {
+        let schema = KeySchema {
+            allowed: Some(vec!["open".to_string(), "closed".to_string()]),
+            ..Default::default()
+        };
+
+        assert!(schema.validate("open").is_ok());
+        assert!(schema.validate("pending").is_err());
+    }
+
+    #[test]
+    fn test_type_and_range_schema() {
+        let schema = KeySchema {
+            value_type: Some(ValueType::Integer),
+            min: Some(0.0),
+            max: Some(10.0),
+            ..Default::default()
+        };
+
+        assert!(schema.validate("5").is_ok());
+        assert!(schema.validate("5.5").is_err());
+        assert!(schema.validate("abc").is_err());
+        assert!(schema.validate("-1").is_err());
+        assert!(schema.validate("11").is_err());
+    }
+
+    #[test]
+    fn test_registry_ignores_unregistered_keys() {
+        let mut registry = SchemaRegistry::new();
+        registry.register(
+            "score".to_string(),
+            KeySchema {
+                value_type: Some(ValueType::Number),
+                ..Default::default()
+            },
+        );
+
+        assert!(registry.validate("score", "1.5").is_ok());
+        assert!(registry.validate("score", "high").is_err());
+        assert!(registry.validate("other", "anything").is_ok());
+    }
+
+    #[test]
+    fn test_schema_deserialize() {
+        let schema: KeySchema =
+            serde_json::from_str(r#"{"type":"string","enum":["a","b"]}"#).unwrap();
+        assert_eq!(schema.value_type, Some(ValueType::String));
+        assert_eq!(schema.allowed, Some(vec!["a".to_string(), "b".to_string()]));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::hooks::EventHooks;
+use crate::sync::{Change, ChangeRequest, SyncState};
+use rand::Rng;
+use serde::Serialize;
+
+/// 自检默认轮数
+pub const DEFAULT_SELF_TEST_ROUNDS: usize = 5;
+
+/// 每轮每个虚拟副本生成的随机操作数
+const OPS_PER_REPLICA: usize = 8;
+
+/// 自检使用的键（与真实键隔离，只存在于内存中的副本上）
+const COUNTER_KEY: &str = "__self_test:counter";
+const SET_KEY: &str = "__self_test:set";
+const REGISTER_KEY: &str = "__self_test:register";
+
+/// 收敛自检结果
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub rounds: usize,
+    /// 两个副本上成功应用的随机操作总数
+    pub ops_applied: usize,
+    /// 被拒绝的随机操作数（如触及键数量上限）
+    pub ops_rejected: usize,
+    /// 未收敛的轮次诊断信息
+    pub failures: Vec<SelfTestFailure>,
+}
+
+/// 单轮未收敛的诊断信息
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestFailure {
+    pub round: usize,
+    pub check: &'static str,
+    pub left_hash: String,
+    pub right_hash: String,
+}
+
+/// 对当前状态运行收敛自检（只操作内存中的副本，不修改也不持久化原状态）
+pub fn run_self_test(state: &SyncState, rounds: usize) -> SelfTestReport {
+    run_self_test_with(state, rounds, |local, other| local.merge(other))
+}
+
+/// 以指定的合并函数运行收敛自检
+///
+/// 每轮从当前状态派生两个虚拟副本，各自应用随机操作后按两种顺序合并，
+/// 检查结果的 `state_hash` 一致（交换律），并检查重复合并不改变结果（幂等性）。
+pub fn run_self_test_with(
+    state: &SyncState,
+    rounds: usize,
+    merge: impl Fn(&mut SyncState, &SyncState),
+) -> SelfTestReport {
+    let mut rng = rand::thread_rng();
+    let mut report = SelfTestReport {
+        passed: true,
+        rounds,
+        ops_applied: 0,
+        ops_rejected: 0,
+        failures: Vec::new(),
+    };
+
+    for round in 0..rounds {
+        let mut left = replica(state, "self-test-a");
+        let mut right = replica(state, "self-test-b");
+        for replica in [&mut left, &mut right] {
+            for i in 0..OPS_PER_REPLICA {
+                // 每个副本先写同一个寄存器，保证每轮都存在并发冲突
+                let change = if i == 0 {
+                    set_change(rng.gen_range(0..1000))
+                } else {
+                    random_change(&mut rng)
+                };
+                match replica.apply_changes(ChangeRequest {
+                    changes: vec![change],
+                }) {
+                    Ok(()) => report.ops_applied += 1,
+                    Err(_) => report.ops_rejected += 1,
+                }
+            }
+        }
+
+        let mut left_right = left.clone();
+        merge(&mut left_right, &right);
+        let mut right_left = right.clone();
+        merge(&mut right_left, &left);
+        let mut fail = |check, left_hash: String, right_hash: String| {
+            if left_hash != right_hash {
+                report.failures.push(SelfTestFailure {
+                    round,
+                    check,
+                    left_hash,
+                    right_hash,
+                });
+            }
+        };
+        fail(
+            "commutativity",
+            left_right.state_hash(),
+            right_left.state_hash(),
+        );
+
+        let mut twice = left_right.clone();
+        merge(&mut twice, &right);
+        fail("idempotence", left_right.state_hash(), twice.state_hash());
+    }
+
+    report.passed = report.failures.is_empty();
+    report
+}
+
+/// 从当前状态派生的内存副本（清除进程内回调，避免自检操作触发外部事件）
+fn replica(state: &SyncState, node_id: &str) -> SyncState {
+    let mut replica = state.clone();
+    replica.node_id = node_id.to_string();
+    replica.op_log.node_id = node_id.to_string();
+    replica.hooks = EventHooks::new();
+    replica
+}
+
+fn set_change(value: u32) -> Change {
+    Change {
+        op: "set".to_string(),
+        key: REGISTER_KEY.to_string(),
+        value: Some(value.to_string()),
+        ..Default::default()
+    }
+}
+
+fn random_change(rng: &mut impl Rng) -> Change {
+    let element = format!("e{}", rng.gen_range(0..4));
+    match rng.gen_range(0..5) {
+        0 => Change {
+            op: "increment".to_string(),
+            key: COUNTER_KEY.to_string(),
+            delta: Some(rng.gen_range(1..10)),
+            ..Default::default()
+        },
+        1 => Change {
+            op: "decrement".to_string(),
+            key: COUNTER_KEY.to_string(),
+            delta: Some(rng.gen_range(1..10)),
+            ..Default::default()
+        },
+        2 => Change {
+            op: "add".to_string(),
+            key: SET_KEY.to_string(),
+            value: Some(element),
+            ..Default::default()
+        },
+        3 => Change {
+            op: "remove".to_string(),
+            key: SET_KEY.to_string(),
+            value: Some(element),
+            ..Default::default()
+        },
+        _ => set_change(rng.gen_range(0..1000)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seeded_state() -> SyncState {
+        let mut state = SyncState::new("node1".to_string());
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "add".to_string(),
+                    key: "tags".to_string(),
+                    value: Some("a".to_string()),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        state
+    }
+
+    #[test]
+    fn test_self_test_passes_and_leaves_state_untouched() {
+        let state = seeded_state();
+        let hash = state.state_hash();
+        let ops = state.op_log.ops.len();
+
+        let report = run_self_test(&state, DEFAULT_SELF_TEST_ROUNDS);
+        assert!(report.passed, "{:?}", report.failures);
+        assert!(report.ops_applied > 0);
+        assert_eq!(state.state_hash(), hash);
+        assert_eq!(state.op_log.ops.len(), ops);
+    }
+
+    #[test]
+    fn test_self_test_catches_broken_merge() {
+        // 直接覆盖条目的“合并”不满足交换律
+        let report = run_self_test_with(&seeded_state(), 3, |local, other| {
+            for (key, value) in &other.crdt_map.entries {
+                local.crdt_map.entries.insert(key.clone(), value.clone());
+            }
+        });
+        assert!(!report.passed);
+        assert!(report.failures.iter().any(|f| f.check == "commutativity"));
+    }
+}
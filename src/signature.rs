@@ -3,6 +3,8 @@ use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{PoisonError, RwLock};
 
 /// 密钥对
 #[derive(Clone)]
@@ -40,6 +42,17 @@ impl KeyPair {
         })
     }
 
+    /// 从 base64 编码的 32 字节私钥创建签名密钥
+    pub fn from_base64(encoded: &str) -> Result<Self> {
+        let bytes = BASE64
+            .decode(encoded.trim())
+            .map_err(|e| anyhow!("Invalid base64 secret key: {}", e))?;
+        let bytes: [u8; 32] = bytes.try_into().map_err(|bytes: Vec<u8>| {
+            anyhow!("Secret key must be 32 bytes, got {}", bytes.len())
+        })?;
+        Self::from_bytes(&bytes)
+    }
+
     /// 导出公钥字节
     pub fn public_key_bytes(&self) -> [u8; 32] {
         self.verifying_key.to_bytes()
@@ -109,6 +122,16 @@ impl SignedOperation {
 
     /// 验证签名
     pub fn verify(&self) -> Result<()> {
+        let (verifying_key, signature, hash) = self.decode()?;
+
+        // 验证签名
+        verifying_key
+            .verify(&hash, &signature)
+            .map_err(|e| anyhow!("Signature verification failed: {}", e))
+    }
+
+    /// 解码公钥、签名并计算待验证的消息哈希
+    fn decode(&self) -> Result<(VerifyingKey, Signature, Vec<u8>)> {
         // 解码公钥
         let public_key_bytes = BASE64
             .decode(&self.public_key)
@@ -145,10 +168,7 @@ impl SignedOperation {
         // 对消息进行哈希
         let hash = Self::hash_message(&message);
 
-        // 验证签名
-        verifying_key
-            .verify(&hash, &signature)
-            .map_err(|e| anyhow!("Signature verification failed: {}", e))
+        Ok((verifying_key, signature, hash))
     }
 
     /// 构造待签名的消息
@@ -174,24 +194,123 @@ impl SignedOperation {
     }
 }
 
+/// 达到该数量时使用批量验证
+pub const BATCH_VERIFY_THRESHOLD: usize = 8;
+
+/// 验证一批签名操作，返回验证失败的 (下标, 原因)，全部有效时为空
+///
+/// 数量达到阈值时先做批量验证；批量验证失败则逐个验证以定位无效签名。
+pub fn verify_batch(ops: &[SignedOperation]) -> Vec<(usize, String)> {
+    let mut invalid = Vec::new();
+    let mut decoded = Vec::with_capacity(ops.len());
+    for (i, op) in ops.iter().enumerate() {
+        match op.decode() {
+            Ok(parts) => decoded.push((i, parts)),
+            Err(e) => invalid.push((i, e.to_string())),
+        }
+    }
+
+    if decoded.len() >= BATCH_VERIFY_THRESHOLD {
+        let messages: Vec<&[u8]> = decoded.iter().map(|(_, (_, _, h))| h.as_slice()).collect();
+        let signatures: Vec<Signature> = decoded.iter().map(|(_, (_, s, _))| *s).collect();
+        let keys: Vec<VerifyingKey> = decoded.iter().map(|(_, (k, _, _))| *k).collect();
+        if ed25519_dalek::verify_batch(&messages, &signatures, &keys).is_ok() {
+            return invalid;
+        }
+        tracing::debug!("Batch signature verification failed, falling back to individual checks");
+    }
+
+    for (i, (key, signature, hash)) in &decoded {
+        if let Err(e) = key.verify(hash, signature) {
+            invalid.push((*i, format!("Signature verification failed: {}", e)));
+        }
+    }
+    invalid.sort_by_key(|(i, _)| *i);
+    invalid
+}
+
+/// 轮换后旧公钥的默认验证宽限期（毫秒）
+pub const DEFAULT_KEY_GRACE_PERIOD_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// 已轮换下线、仍在宽限期内受信任的公钥
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetiringKey {
+    pub public_key: String, // Base64 编码的公钥
+    pub retired_at: i64,    // 轮换时间（毫秒）
+    pub expires_at: i64,    // 宽限期截止时间（毫秒）
+}
+
+/// 节点公布的公钥（`GET /auth/public-key` 的响应体）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicKeys {
+    pub node_id: String,
+    pub public_key: String, // Base64 编码的当前公钥
+    #[serde(default)]
+    pub retiring_keys: Vec<RetiringKey>,
+}
+
+impl PublicKeys {
+    /// 公钥是否为当前公钥或在 `now_ms` 时仍处于宽限期内的旧公钥
+    fn contains_at(&self, public_key: &str, now_ms: i64) -> bool {
+        self.public_key == public_key
+            || self
+                .retiring_keys
+                .iter()
+                .any(|k| k.public_key == public_key && k.expires_at > now_ms)
+    }
+}
+
 /// 签名管理器
+///
+/// 密钥可在运行期间轮换：旧公钥进入 retiring 集合，在宽限期内签名仍被视为本节点签发。
+/// 同时保存从对等节点 `/auth/public-key` 获取的公钥，用于按发起节点验证传入操作的签名。
 #[allow(dead_code)]
 pub struct SignatureManager {
-    keypair: KeyPair,
+    keypair: RwLock<KeyPair>,
+    retiring: RwLock<Vec<RetiringKey>>,
+    peer_keys: RwLock<HashMap<String, PublicKeys>>,
+    grace_period_ms: i64,
     node_id: String,
 }
 
+impl std::fmt::Debug for SignatureManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SignatureManager")
+            .field("node_id", &self.node_id)
+            .field("public_key", &self.public_key_base64())
+            .finish_non_exhaustive()
+    }
+}
+
 #[allow(dead_code)]
 impl SignatureManager {
     /// 创建新的签名管理器
     pub fn new(node_id: String) -> Self {
-        let keypair = KeyPair::generate();
-        Self { keypair, node_id }
+        Self::from_keypair(node_id, KeyPair::generate())
     }
 
     /// 从现有密钥创建签名管理器
     pub fn from_keypair(node_id: String, keypair: KeyPair) -> Self {
-        Self { keypair, node_id }
+        Self {
+            keypair: RwLock::new(keypair),
+            retiring: RwLock::new(Vec::new()),
+            peer_keys: RwLock::new(HashMap::new()),
+            grace_period_ms: DEFAULT_KEY_GRACE_PERIOD_MS,
+            node_id,
+        }
+    }
+
+    /// 设置轮换后旧公钥的验证宽限期
+    pub fn with_grace_period_ms(mut self, grace_period_ms: i64) -> Self {
+        self.grace_period_ms = grace_period_ms;
+        self
+    }
+
+    fn current(&self) -> KeyPair {
+        self.keypair
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
     }
 
     /// 签名操作
@@ -210,18 +329,190 @@ impl SignatureManager {
             operation_type,
             operation_data,
             causal_context,
-            &self.keypair,
+            &self.current(),
         )
     }
 
     /// 获取公钥（Base64 编码）
     pub fn public_key_base64(&self) -> String {
-        BASE64.encode(self.keypair.public_key_bytes())
+        BASE64.encode(self.current().public_key_bytes())
     }
 
     /// 获取私钥（Base64 编码）
     pub fn secret_key_base64(&self) -> String {
-        BASE64.encode(self.keypair.secret_key_bytes())
+        BASE64.encode(self.current().secret_key_bytes())
+    }
+
+    /// 恢复持久化的 retiring 集合（已过期的旧公钥在验证时自动忽略）
+    pub fn with_retiring_keys(self, retiring: Vec<RetiringKey>) -> Self {
+        *self
+            .retiring
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = retiring;
+        self
+    }
+
+    /// 轮换密钥：生成新密钥对，旧公钥进入宽限期，返回新密钥对
+    ///
+    /// 新密钥对与新的 retiring 集合先交给 `persist` 持久化，成功后才替换内存中的密钥；
+    /// 持久化失败时内存中的密钥保持不变。轮换期间持有密钥写锁，并发轮换依次进行。
+    pub fn rotate_with(
+        &self,
+        persist: impl FnOnce(&KeyPair, &[RetiringKey]) -> Result<()>,
+    ) -> Result<KeyPair> {
+        self.rotate_with_at(chrono::Utc::now().timestamp_millis(), persist)
+    }
+
+    /// 在指定时间轮换密钥（同时清理已过期的旧公钥）
+    pub fn rotate_with_at(
+        &self,
+        now_ms: i64,
+        persist: impl FnOnce(&KeyPair, &[RetiringKey]) -> Result<()>,
+    ) -> Result<KeyPair> {
+        let mut keypair = self.keypair.write().unwrap_or_else(PoisonError::into_inner);
+        let mut retiring = self
+            .retiring
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let new_keypair = KeyPair::generate();
+        let mut next: Vec<RetiringKey> = retiring
+            .iter()
+            .filter(|k| k.expires_at > now_ms)
+            .cloned()
+            .collect();
+        next.push(RetiringKey {
+            public_key: BASE64.encode(keypair.public_key_bytes()),
+            retired_at: now_ms,
+            expires_at: now_ms.saturating_add(self.grace_period_ms),
+        });
+        persist(&new_keypair, &next)?;
+
+        *keypair = new_keypair.clone();
+        *retiring = next;
+        Ok(new_keypair)
+    }
+
+    /// 在指定时间轮换密钥，不做持久化
+    pub fn rotate_at(&self, now_ms: i64) -> KeyPair {
+        self.rotate_with_at(now_ms, |_, _| Ok(()))
+            .expect("rotation without persistence cannot fail")
+    }
+
+    /// 仍在宽限期内的旧公钥
+    pub fn retiring_keys(&self) -> Vec<RetiringKey> {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        self.retiring
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|k| k.expires_at > now_ms)
+            .cloned()
+            .collect()
+    }
+
+    /// 本节点当前公钥及宽限期内的旧公钥
+    pub fn public_keys(&self) -> PublicKeys {
+        PublicKeys {
+            node_id: self.node_id.clone(),
+            public_key: self.public_key_base64(),
+            retiring_keys: self.retiring_keys(),
+        }
+    }
+
+    /// 信任对等节点公布的公钥，替换该节点此前的记录（不接受本节点自身的记录）
+    pub fn trust_peer_keys(&self, keys: PublicKeys) -> Result<()> {
+        if keys.node_id == self.node_id {
+            return Err(anyhow!(
+                "Refusing to replace own public key of node {}",
+                self.node_id
+            ));
+        }
+        self.peer_keys
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(keys.node_id.clone(), keys);
+        Ok(())
+    }
+
+    /// 已信任的对等节点公钥
+    pub fn peer_keys(&self, node_id: &str) -> Option<PublicKeys> {
+        self.peer_keys
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(node_id)
+            .cloned()
+    }
+
+    /// 检查公钥是否为 `node_id` 的当前公钥或宽限期内的旧公钥，不受信任时返回原因
+    fn check_trusted_key_at(&self, node_id: &str, public_key: &str, now_ms: i64) -> Result<()> {
+        let trusted = if node_id == self.node_id {
+            public_key == self.public_key_base64()
+                || self
+                    .retiring
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .iter()
+                    .any(|k| k.public_key == public_key && k.expires_at > now_ms)
+        } else {
+            match self
+                .peer_keys
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(node_id)
+            {
+                Some(keys) => keys.contains_at(public_key, now_ms),
+                None => return Err(anyhow!("No trusted public key for node {:?}", node_id)),
+            }
+        };
+        if trusted {
+            Ok(())
+        } else {
+            Err(anyhow!("Public key is not trusted for node {:?}", node_id))
+        }
+    }
+
+    /// 公钥是否为 `node_id` 的当前公钥或宽限期内的旧公钥
+    pub fn is_trusted_key_at(&self, node_id: &str, public_key: &str, now_ms: i64) -> bool {
+        self.check_trusted_key_at(node_id, public_key, now_ms)
+            .is_ok()
+    }
+
+    /// 验证签名有效且由发起节点受信任的公钥签发
+    pub fn verify_trusted_at(&self, op: &SignedOperation, now_ms: i64) -> Result<()> {
+        self.check_trusted_key_at(&op.node_id, &op.public_key, now_ms)?;
+        op.verify()
+    }
+
+    /// 以当前时间验证签名
+    pub fn verify_trusted(&self, op: &SignedOperation) -> Result<()> {
+        self.verify_trusted_at(op, chrono::Utc::now().timestamp_millis())
+    }
+
+    /// 以当前时间批量验证签名，返回验证失败的 (下标, 原因)，全部有效时为空
+    ///
+    /// 公钥不受信任的操作直接判为失败，其余操作交给 [`verify_batch`]。
+    pub fn verify_trusted_batch(&self, ops: &[SignedOperation]) -> Vec<(usize, String)> {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let mut invalid = Vec::new();
+        let mut trusted = Vec::with_capacity(ops.len());
+        let mut indices = Vec::with_capacity(ops.len());
+        for (i, op) in ops.iter().enumerate() {
+            match self.check_trusted_key_at(&op.node_id, &op.public_key, now_ms) {
+                Ok(()) => {
+                    trusted.push(op.clone());
+                    indices.push(i);
+                }
+                Err(e) => invalid.push((i, e.to_string())),
+            }
+        }
+        invalid.extend(
+            verify_batch(&trusted)
+                .into_iter()
+                .map(|(j, reason)| (indices[j], reason)),
+        );
+        invalid.sort_by_key(|(i, _)| *i);
+        invalid
     }
 }
 
@@ -278,6 +569,17 @@ mod tests {
         assert!(signed_op.verify().is_err());
     }
 
+    #[test]
+    fn test_keypair_from_base64() {
+        let keypair = KeyPair::generate();
+        let encoded = BASE64.encode(keypair.secret_key_bytes());
+
+        let decoded = KeyPair::from_base64(&format!("{}\n", encoded)).unwrap();
+        assert_eq!(decoded.public_key_bytes(), keypair.public_key_bytes());
+        assert!(KeyPair::from_base64("not base64!").is_err());
+        assert!(KeyPair::from_base64(&BASE64.encode([0u8; 16])).is_err());
+    }
+
     #[test]
     fn test_keypair_from_bytes() {
         let keypair1 = KeyPair::generate();
@@ -287,4 +589,152 @@ mod tests {
 
         assert_eq!(keypair1.public_key_bytes(), keypair2.public_key_bytes());
     }
+
+    fn signed_ops(manager: &SignatureManager, n: usize) -> Vec<SignedOperation> {
+        (0..n)
+            .map(|i| {
+                manager
+                    .sign_operation(
+                        format!("op{}", i),
+                        1234567890 + i as i64,
+                        "GCounter.Increment".to_string(),
+                        format!("counter+{}", i),
+                        "{}".to_string(),
+                    )
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_verify_batch_all_valid() {
+        let manager = SignatureManager::new("node1".to_string());
+        assert!(verify_batch(&signed_ops(&manager, 3)).is_empty());
+        assert!(verify_batch(&signed_ops(&manager, BATCH_VERIFY_THRESHOLD * 2)).is_empty());
+        assert!(verify_batch(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_verify_batch_isolates_bad_signature() {
+        let manager = SignatureManager::new("node1".to_string());
+        let other = SignatureManager::new("node2".to_string());
+        let mut ops = signed_ops(&manager, BATCH_VERIFY_THRESHOLD * 2);
+        ops[5].operation_data = "counter+999".to_string();
+        ops.extend(signed_ops(&other, 2));
+        ops[17].signature = "not-base64!".to_string();
+
+        let invalid = verify_batch(&ops);
+        let indices: Vec<usize> = invalid.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, vec![5, 17]);
+        assert!(invalid[0].1.contains("verification failed"));
+
+        // 低于阈值时逐个验证，结果一致
+        let invalid = verify_batch(&ops[4..7]);
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].0, 1);
+    }
+
+    fn sign(manager: &SignatureManager, id: &str) -> SignedOperation {
+        manager
+            .sign_operation(
+                id.to_string(),
+                1234567890,
+                "LWWRegister.Set".to_string(),
+                "key=value".to_string(),
+                "{}".to_string(),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rotate_keeps_old_key_within_grace_period() {
+        let manager = SignatureManager::new("node1".to_string()).with_grace_period_ms(1000);
+        let old_key = manager.public_key_base64();
+        let before = sign(&manager, "op1");
+
+        let new_keypair = manager.rotate_at(10_000);
+        let new_key = manager.public_key_base64();
+        assert_ne!(old_key, new_key);
+        assert_eq!(new_key, BASE64.encode(new_keypair.public_key_bytes()));
+
+        // 轮换后的签名使用新公钥
+        let after = sign(&manager, "op2");
+        assert_eq!(after.public_key, new_key);
+        assert!(manager.verify_trusted_at(&after, 10_500).is_ok());
+
+        // 旧签名在宽限期内仍受信任，过期后不再受信任
+        assert_eq!(before.public_key, old_key);
+        assert!(manager.verify_trusted_at(&before, 10_500).is_ok());
+        assert!(manager.verify_trusted_at(&before, 11_000).is_err());
+
+        // 未登记的其他节点的密钥不受信任
+        let other = SignatureManager::new("node2".to_string());
+        assert!(
+            manager
+                .verify_trusted_at(&sign(&other, "op3"), 10_500)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_rotate_keeps_key_when_persist_fails() {
+        let manager = SignatureManager::new("node1".to_string()).with_grace_period_ms(1000);
+        let old_key = manager.public_key_base64();
+
+        let result = manager.rotate_with_at(10_000, |_, _| Err(anyhow!("disk full")));
+        assert!(result.is_err());
+        assert_eq!(manager.public_key_base64(), old_key);
+        assert!(manager.retiring_keys().is_empty());
+
+        // 持久化的内容与轮换后内存中的一致，并可由 with_retiring_keys 恢复
+        let mut persisted = None;
+        let new_keypair = manager
+            .rotate_with_at(10_000, |keypair, retiring| {
+                persisted = Some((keypair.public_key_bytes(), retiring.to_vec()));
+                Ok(())
+            })
+            .unwrap();
+        let (public_key, retiring) = persisted.unwrap();
+        assert_eq!(public_key, new_keypair.public_key_bytes());
+        assert_eq!(retiring.len(), 1);
+        assert_eq!(retiring[0].public_key, old_key);
+
+        let restored = SignatureManager::from_keypair("node1".to_string(), new_keypair)
+            .with_retiring_keys(retiring);
+        assert!(restored.is_trusted_key_at("node1", &old_key, 10_500));
+        assert!(!restored.is_trusted_key_at("node1", &old_key, 11_000));
+    }
+
+    #[test]
+    fn test_peer_keys_trusted_per_origin() {
+        let manager = SignatureManager::new("node1".to_string());
+        let node2 = SignatureManager::new("node2".to_string());
+        let node3 = SignatureManager::new("node3".to_string());
+        let op = sign(&node2, "op1");
+
+        // 未登记的节点：拒绝
+        let err = manager.verify_trusted(&op).unwrap_err();
+        assert!(err.to_string().contains("No trusted public key"));
+
+        // 登记 node2 的公钥后接受
+        manager.trust_peer_keys(node2.public_keys()).unwrap();
+        assert!(manager.verify_trusted(&op).is_ok());
+
+        // 以另一把密钥冒充 node2：公钥与登记的不符
+        let impostor = SignatureManager::new("node2".to_string());
+        let forged = sign(&impostor, "op2");
+        let err = manager.verify_trusted(&forged).unwrap_err();
+        assert!(err.to_string().contains("not trusted"));
+
+        // 不能替换本节点自身的公钥
+        let mut own = node2.public_keys();
+        own.node_id = "node1".to_string();
+        assert!(manager.trust_peer_keys(own).is_err());
+
+        // 批量验证逐条给出原因
+        let ops = vec![op, forged, sign(&node3, "op3"), sign(&manager, "op4")];
+        let invalid = manager.verify_trusted_batch(&ops);
+        let indices: Vec<usize> = invalid.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, vec![1, 2]);
+    }
 }
@@ -0,0 +1,149 @@
+use crate::api::AppState;
+use crate::storage::{SnapshotMeta, StorageBackend};
+use crate::sync::SyncState;
+use anyhow::Result;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// 自动快照的标签
+pub const AUTO_SNAPSHOT_LABEL: &str = "auto";
+
+/// 默认保留的快照数量
+pub const DEFAULT_SNAPSHOT_KEEP: usize = 10;
+
+/// 自动快照策略：默认文档每应用 N 个操作或每隔一段时间保存一次快照，只保留最新的 K 个
+///
+/// 快照版本号在已有最大版本上递增；保留数量至少为 1，因此版本号始终单调递增。
+#[derive(Debug)]
+pub struct SnapshotPolicy {
+    every_ops: Option<u64>,
+    keep: usize,
+    ops_since: AtomicU64,             // 上次快照后应用的操作数
+    last_hash: Mutex<Option<String>>, // 上次快照的状态哈希（定时快照据此跳过未变化的状态）
+}
+
+impl SnapshotPolicy {
+    pub fn new(every_ops: Option<u64>, keep: usize) -> Self {
+        Self {
+            every_ops: every_ops.filter(|n| *n > 0),
+            keep: keep.max(1),
+            ops_since: AtomicU64::new(0),
+            last_hash: Mutex::new(None),
+        }
+    }
+
+    /// 记录新应用的操作数，累计达到阈值时返回 true
+    pub fn record_ops(&self, count: u64) -> bool {
+        let total = self.ops_since.fetch_add(count, Ordering::AcqRel) + count;
+        self.every_ops.is_some_and(|every| total >= every)
+    }
+
+    /// 状态哈希与上次快照相同时无需再保存
+    pub fn is_unchanged(&self, sync_state: &SyncState) -> bool {
+        self.last_hash.lock().unwrap().as_deref() == Some(sync_state.state_hash().as_str())
+    }
+
+    /// 保存快照并清理超出保留数量的旧快照
+    pub fn snapshot(
+        &self,
+        storage: &dyn StorageBackend,
+        node_id: &str,
+        sync_state: &SyncState,
+    ) -> Result<SnapshotMeta> {
+        self.ops_since.store(0, Ordering::Release);
+        let meta = storage.save_labeled_snapshot(node_id, sync_state, AUTO_SNAPSHOT_LABEL)?;
+        *self.last_hash.lock().unwrap() = Some(sync_state.state_hash());
+        storage.cleanup_old_snapshots(node_id, self.keep)?;
+        Ok(meta)
+    }
+}
+
+/// 启动后台任务，按间隔为默认文档保存快照（状态未变化时跳过）
+pub fn spawn_auto_snapshot_task(
+    app_state: AppState,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let Some(policy) = app_state.snapshot_policy.clone() else {
+            return;
+        };
+        let mut ticker = tokio::time::interval(interval.max(Duration::from_secs(1)));
+        // 第一次 tick 立即返回，跳过以免启动时重复保存
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let sync_state = app_state.sync_state.read().await;
+            if policy.is_unchanged(&sync_state) {
+                continue;
+            }
+            match policy.snapshot(app_state.storage.as_ref(), &app_state.node_id, &sync_state) {
+                Ok(meta) => tracing::info!("Saved periodic snapshot version {}", meta.version),
+                Err(e) => tracing::warn!("Failed to save periodic snapshot: {}", e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::sync::{Change, ChangeRequest};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_snapshots_every_n_ops_and_prunes_to_keep() {
+        let storage = Arc::new(MemoryStorage::new());
+        let state = AppState::new(
+            "node1".to_string(),
+            storage.clone(),
+            "secret".to_string(),
+            false,
+        )
+        .unwrap()
+        .with_snapshot_policy(Some(SnapshotPolicy::new(Some(3), 2)));
+
+        for i in 0..10 {
+            let mut sync_state = state.sync_state.write().await;
+            sync_state
+                .apply_changes(ChangeRequest {
+                    changes: vec![Change {
+                        op: "increment".to_string(),
+                        key: format!("counter{}", i),
+                        ..Default::default()
+                    }],
+                })
+                .unwrap();
+            state.record_applied_ops("default", &sync_state, 1);
+            if i == 2 {
+                // 达到 3 个操作后出现第一个快照
+                assert_eq!(storage.list_snapshots("node1").unwrap(), vec![1]);
+            }
+        }
+
+        // 共保存了 3 个快照（第 3、6、9 个操作后），只保留最新的 2 个
+        assert_eq!(storage.list_snapshots("node1").unwrap(), vec![2, 3]);
+        let latest = storage.load_snapshot("node1", 3).unwrap().unwrap();
+        assert_eq!(latest.crdt_map.entries.len(), 9);
+        let meta = storage.snapshot_meta("node1", 3).unwrap().unwrap();
+        assert_eq!(meta.label.as_deref(), Some(AUTO_SNAPSHOT_LABEL));
+
+        // 命名文档不触发快照
+        let sync_state = state.sync_state.read().await;
+        state.record_applied_ops("other", &sync_state, 100);
+        assert_eq!(storage.list_snapshots("node1").unwrap(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_periodic_snapshot_skips_unchanged_state() {
+        let storage = MemoryStorage::new();
+        let policy = SnapshotPolicy::new(None, 5);
+        let sync_state = SyncState::new("node1".to_string());
+
+        assert!(!policy.record_ops(1000));
+        assert!(!policy.is_unchanged(&sync_state));
+        policy.snapshot(&storage, "node1", &sync_state).unwrap();
+        assert!(policy.is_unchanged(&sync_state));
+    }
+}
@@ -1,78 +1,523 @@
-use crate::sync::SyncState;
+use crate::compression;
+use crate::schema::SchemaRegistry;
+use crate::signature::{KeyPair, RetiringKey, SignatureManager};
+use crate::sync::{OpLogEntry, SyncState};
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sled::Db;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// 快照元数据
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotMeta {
+    pub version: u64,
+    pub created_at: i64, // 创建时间（毫秒时间戳）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>, // 快照标签（如 "pre-reset"）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>, // 快照数据的 SHA-256 校验和
+}
 
-/// 存储管理器
-pub struct Storage {
-    db: Db,
+impl SnapshotMeta {
+    pub fn new(version: u64) -> Self {
+        Self {
+            version,
+            created_at: chrono::Local::now()
+                .naive_local()
+                .and_utc()
+                .timestamp_millis(),
+            label: None,
+            checksum: None,
+        }
+    }
 }
 
-impl Storage {
-    /// 创建或打开存储
-    pub fn new(path: &str) -> Result<Self> {
-        let db =
-            sled::open(path).with_context(|| format!("Failed to open database at {}", path))?;
-        Ok(Self { db })
+/// 快照完整性巡检结果
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ScrubReport {
+    pub checked: usize,
+    pub corrupt: Vec<u64>, // 已隔离的损坏快照版本
+}
+
+/// 归档导入进度
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportProgress {
+    pub archive_id: String,
+    pub next_offset: u64, // 下一条待处理记录的行号（从 0 开始）
+    pub applied: u64,     // 已应用的记录数
+    pub skipped: u64,     // 已存在或来自已遗忘节点而跳过的记录数
+    pub completed: bool,
+}
+
+/// 导入时每处理多少条记录保存一次检查点
+pub const IMPORT_CHECKPOINT_INTERVAL: u64 = 100;
+
+/// 节点默认文档的 ID（未使用 `/doc/<doc_id>` 路由的请求均作用于该文档）
+pub const DEFAULT_DOCUMENT: &str = "default";
+
+/// 文档状态的存储键：命名文档为 `state:{node_id}:{doc_id}`，默认文档沿用 `state:{node_id}`
+fn state_key(node_id: &str, doc_id: &str) -> String {
+    if doc_id == DEFAULT_DOCUMENT {
+        format!("state:{}", node_id)
+    } else {
+        format!("state:{}:{}", node_id, doc_id)
+    }
+}
+
+/// 计算快照数据的校验和
+fn snapshot_checksum(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// sled 默认缓存容量（MiB）
+pub const DEFAULT_SLED_CACHE_MB: u64 = 1024;
+/// sled 默认后台刷盘间隔（毫秒）
+pub const DEFAULT_SLED_FLUSH_MS: u64 = 500;
+/// 后台刷盘间隔上限（毫秒）
+const MAX_SLED_FLUSH_MS: u64 = 60 * 60 * 1000;
+
+/// sled 存储模式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StorageMode {
+    /// 优先节省磁盘空间（sled 默认）
+    #[default]
+    LowSpace,
+    /// 优先写入吞吐
+    HighThroughput,
+}
+
+impl std::str::FromStr for StorageMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "low-space" => Ok(StorageMode::LowSpace),
+            "high-throughput" => Ok(StorageMode::HighThroughput),
+            other => Err(format!("Unknown storage mode: {}", other)),
+        }
+    }
+}
+
+/// 单次写入的持久化级别
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Durability {
+    /// 写入存储但不等待落盘，由 sled 后台刷盘
+    Async,
+    /// 落盘后再响应（默认）
+    #[default]
+    Flush,
+    /// 仅保存在内存中，重启后丢失
+    Memory,
+}
+
+impl std::str::FromStr for Durability {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "async" => Ok(Durability::Async),
+            "flush" => Ok(Durability::Flush),
+            "memory" => Ok(Durability::Memory),
+            other => Err(format!("Unknown durability level: {}", other)),
+        }
+    }
+}
+
+impl From<StorageMode> for sled::Mode {
+    fn from(mode: StorageMode) -> Self {
+        match mode {
+            StorageMode::LowSpace => sled::Mode::LowSpace,
+            StorageMode::HighThroughput => sled::Mode::HighThroughput,
+        }
+    }
+}
+
+/// 写入后的落盘策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// 每次保存后立即 fsync（默认）
+    #[default]
+    Immediate,
+    /// 保存时只标记待落盘，由后台任务按间隔合并为一次 fsync
+    Periodic(Duration),
+}
+
+/// sled 调优配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageConfig {
+    /// 页缓存容量（MiB）
+    pub cache_mb: u64,
+    /// 后台刷盘间隔（毫秒），0 表示仅在显式 flush 时落盘
+    pub flush_every_ms: u64,
+    pub mode: StorageMode,
+    pub flush_policy: FlushPolicy,
+    /// 是否以 zstd 压缩保存同步状态（读取时自动识别压缩与未压缩的值）
+    pub compress_state: bool,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            cache_mb: DEFAULT_SLED_CACHE_MB,
+            flush_every_ms: DEFAULT_SLED_FLUSH_MS,
+            mode: StorageMode::default(),
+            flush_policy: FlushPolicy::default(),
+            compress_state: false,
+        }
+    }
+}
+
+impl StorageConfig {
+    /// 校验配置取值
+    pub fn validate(&self) -> Result<()> {
+        if self.cache_mb == 0 {
+            anyhow::bail!("sled cache capacity must be at least 1 MiB");
+        }
+        if self.cache_mb.checked_mul(1024 * 1024).is_none() {
+            anyhow::bail!("sled cache capacity of {} MiB is too large", self.cache_mb);
+        }
+        if self.flush_every_ms > MAX_SLED_FLUSH_MS {
+            anyhow::bail!(
+                "sled flush interval must not exceed {} ms, got {}",
+                MAX_SLED_FLUSH_MS,
+                self.flush_every_ms
+            );
+        }
+        if let FlushPolicy::Periodic(interval) = self.flush_policy
+            && (interval.is_zero() || interval.as_millis() > MAX_SLED_FLUSH_MS as u128)
+        {
+            anyhow::bail!(
+                "periodic flush interval must be between 1 and {} ms, got {}",
+                MAX_SLED_FLUSH_MS,
+                interval.as_millis()
+            );
+        }
+        Ok(())
+    }
+
+    fn sled_config(&self, path: &str) -> sled::Config {
+        sled::Config::new()
+            .path(path)
+            .cache_capacity(self.cache_mb * 1024 * 1024)
+            .flush_every_ms((self.flush_every_ms > 0).then_some(self.flush_every_ms))
+            .mode(self.mode.into())
+    }
+}
+
+/// 存储后端类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StorageKind {
+    /// sled 持久化存储（默认）
+    #[default]
+    Sled,
+    /// 纯内存存储，进程退出后数据丢失（用于测试与临时部署）
+    Memory,
+}
+
+impl std::str::FromStr for StorageKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sled" => Ok(StorageKind::Sled),
+            "memory" => Ok(StorageKind::Memory),
+            other => Err(format!("Unknown storage backend: {}", other)),
+        }
+    }
+}
+
+/// 按类型打开存储后端（内存后端忽略路径与 sled 配置）
+pub fn open_storage(
+    kind: StorageKind,
+    path: &str,
+    config: &StorageConfig,
+) -> Result<Arc<dyn StorageBackend>> {
+    Ok(match kind {
+        StorageKind::Sled => Arc::new(SledStorage::with_config(path, config)?),
+        StorageKind::Memory => {
+            tracing::info!("Using in-memory storage, data will not survive restart");
+            Arc::new(MemoryStorage::new())
+        }
+    })
+}
+
+/// 存储后端
+///
+/// 实现方只需提供按字符串键读写字节的基本操作，状态、快照、导入进度等的编码与组织
+/// 由默认方法统一实现，因此各后端的键布局与行为完全一致。
+pub trait StorageBackend: Send + Sync {
+    /// 读取键对应的值
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// 写入键值（覆盖已有值）
+    fn insert(&self, key: &str, value: Vec<u8>) -> Result<()>;
+
+    /// 删除键（不存在时忽略）
+    fn remove(&self, key: &str) -> Result<()>;
+
+    /// 列出以 `prefix` 开头的所有键
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>>;
+
+    /// 写入多个键值，支持原子批量写入的后端一次性提交
+    fn insert_batch(&self, entries: Vec<(String, Vec<u8>)>) -> Result<()> {
+        for (key, value) in entries {
+            self.insert(&key, value)?;
+        }
+        Ok(())
+    }
+
+    /// 将已写入的数据落盘（按后端的落盘策略，可能延后到后台任务执行）
+    fn flush(&self) -> Result<()>;
+
+    /// 立即落盘所有尚未持久化的写入（后台刷盘任务与退出前调用）
+    fn flush_pending(&self) -> Result<()> {
+        self.flush()
+    }
+
+    /// 删除所有键
+    fn clear(&self) -> Result<()>;
+
+    /// 巡检累计发现的损坏快照数计数器
+    fn corrupt_snapshot_counter(&self) -> &AtomicU64;
+
+    /// 保存同步状态时是否压缩
+    fn compress_state(&self) -> bool {
+        false
     }
 
     /// 保存同步状态
-    pub fn save_state(&self, node_id: &str, state: &SyncState) -> Result<()> {
-        let key = format!("state:{}", node_id);
-        let value = serde_json::to_vec(state).context("Failed to serialize sync state")?;
+    fn save_state(&self, node_id: &str, state: &SyncState) -> Result<()> {
+        self.save_state_with(node_id, state, Durability::Flush)
+    }
 
-        self.db
-            .insert(key.as_bytes(), value)
+    /// 按指定持久化级别保存同步状态（临时键始终不会被写入）
+    fn save_state_with(
+        &self,
+        node_id: &str,
+        state: &SyncState,
+        durability: Durability,
+    ) -> Result<()> {
+        self.save_document_state(node_id, DEFAULT_DOCUMENT, state, durability)
+    }
+
+    /// 按指定持久化级别保存某个文档的同步状态
+    fn save_document_state(
+        &self,
+        node_id: &str,
+        doc_id: &str,
+        state: &SyncState,
+        durability: Durability,
+    ) -> Result<()> {
+        if durability == Durability::Memory {
+            return Ok(());
+        }
+
+        let key = state_key(node_id, doc_id);
+        let mut value = serde_json::to_vec(state.persistable().as_ref())
+            .context("Failed to serialize sync state")?;
+        if self.compress_state() {
+            value = compression::encode_value(&value)?;
+        }
+
+        self.insert(&key, value)
             .context("Failed to insert state into database")?;
 
-        self.db.flush().context("Failed to flush database")?;
+        if durability == Durability::Flush {
+            self.flush().context("Failed to flush database")?;
+        }
 
-        tracing::info!("Saved state for node: {}", node_id);
+        tracing::info!("Saved state for node: {} document: {}", node_id, doc_id);
         Ok(())
     }
 
     /// 加载同步状态
-    pub fn load_state(&self, node_id: &str) -> Result<Option<SyncState>> {
-        let key = format!("state:{}", node_id);
+    fn load_state(&self, node_id: &str) -> Result<Option<SyncState>> {
+        self.load_document_state(node_id, DEFAULT_DOCUMENT)
+    }
+
+    /// 加载某个文档的同步状态
+    fn load_document_state(&self, node_id: &str, doc_id: &str) -> Result<Option<SyncState>> {
+        let key = state_key(node_id, doc_id);
 
         if let Some(value) = self
-            .db
-            .get(key.as_bytes())
+            .get(&key)
             .context("Failed to get state from database")?
         {
+            let value = compression::decode_value(&value)?;
             let state =
                 serde_json::from_slice(&value).context("Failed to deserialize sync state")?;
-            tracing::info!("Loaded state for node: {}", node_id);
+            tracing::info!("Loaded state for node: {} document: {}", node_id, doc_id);
             Ok(Some(state))
         } else {
-            tracing::info!("No saved state found for node: {}", node_id);
+            tracing::info!(
+                "No saved state found for node: {} document: {}",
+                node_id,
+                doc_id
+            );
             Ok(None)
         }
     }
 
-    /// 保存快照（用于版本记录）
-    #[allow(dead_code)]
-    pub fn save_snapshot(&self, node_id: &str, version: u64, state: &SyncState) -> Result<()> {
-        let key = format!("snapshot:{}:{}", node_id, version);
-        let value = serde_json::to_vec(state).context("Failed to serialize snapshot")?;
+    /// 列出节点已持久化的命名文档（不含默认文档），按文档 ID 排序
+    fn list_documents(&self, node_id: &str) -> Result<Vec<String>> {
+        let prefix = format!("state:{}:", node_id);
+        let mut documents: Vec<String> = self
+            .keys_with_prefix(&prefix)
+            .context("Failed to scan database")?
+            .into_iter()
+            .filter_map(|key| key.strip_prefix(&prefix).map(str::to_string))
+            .collect();
+        documents.sort();
+        Ok(documents)
+    }
 
-        self.db
-            .insert(key.as_bytes(), value)
-            .context("Failed to insert snapshot into database")?;
+    /// 保存键取值约束
+    fn save_schemas(&self, node_id: &str, schemas: &SchemaRegistry) -> Result<()> {
+        let key = format!("schema:{}", node_id);
+        let value = serde_json::to_vec(schemas).context("Failed to serialize schemas")?;
+
+        self.insert(&key, value)
+            .context("Failed to insert schemas into database")?;
 
-        self.db.flush().context("Failed to flush database")?;
+        self.flush().context("Failed to flush database")?;
 
-        tracing::info!("Saved snapshot for node: {} version: {}", node_id, version);
+        tracing::info!(
+            "Saved {} schemas for node: {}",
+            schemas.schemas.len(),
+            node_id
+        );
         Ok(())
     }
 
+    /// 加载键取值约束（不存在时返回空集合）
+    fn load_schemas(&self, node_id: &str) -> Result<SchemaRegistry> {
+        let key = format!("schema:{}", node_id);
+
+        match self
+            .get(&key)
+            .context("Failed to get schemas from database")?
+        {
+            Some(value) => serde_json::from_slice(&value).context("Failed to deserialize schemas"),
+            None => Ok(SchemaRegistry::new()),
+        }
+    }
+
+    /// 保存节点签名密钥（32 字节私钥）
+    fn save_keypair(&self, node_id: &str, keypair: &KeyPair) -> Result<()> {
+        let key = format!("keypair:{}", node_id);
+
+        self.insert(&key, keypair.secret_key_bytes().to_vec())
+            .context("Failed to insert keypair into database")?;
+
+        self.flush().context("Failed to flush database")?;
+
+        tracing::info!("Saved signing keypair for node: {}", node_id);
+        Ok(())
+    }
+
+    /// 加载节点签名密钥（不存在时返回 None）
+    fn load_keypair(&self, node_id: &str) -> Result<Option<KeyPair>> {
+        let key = format!("keypair:{}", node_id);
+
+        let Some(value) = self
+            .get(&key)
+            .context("Failed to get keypair from database")?
+        else {
+            return Ok(None);
+        };
+        let bytes: [u8; 32] = value
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Stored keypair must be 32 bytes, got {}", value.len()))?;
+        KeyPair::from_bytes(&bytes).map(Some)
+    }
+
+    /// 原子地保存轮换后的签名密钥及宽限期内的旧公钥
+    fn save_rotated_keypair(
+        &self,
+        node_id: &str,
+        keypair: &KeyPair,
+        retiring: &[RetiringKey],
+    ) -> Result<()> {
+        let retiring = serde_json::to_vec(retiring).context("Failed to serialize retiring keys")?;
+
+        self.insert_batch(vec![
+            (
+                format!("keypair:{}", node_id),
+                keypair.secret_key_bytes().to_vec(),
+            ),
+            (format!("retiring-keys:{}", node_id), retiring),
+        ])
+        .context("Failed to insert rotated keypair into database")?;
+
+        self.flush().context("Failed to flush database")?;
+
+        tracing::info!("Saved rotated signing keypair for node: {}", node_id);
+        Ok(())
+    }
+
+    /// 加载轮换下线的旧公钥（不存在时返回空集合）
+    fn load_retiring_keys(&self, node_id: &str) -> Result<Vec<RetiringKey>> {
+        let key = format!("retiring-keys:{}", node_id);
+
+        match self
+            .get(&key)
+            .context("Failed to get retiring keys from database")?
+        {
+            Some(value) => {
+                serde_json::from_slice(&value).context("Failed to deserialize retiring keys")
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// 保存快照（用于版本记录）
+    fn save_snapshot(&self, node_id: &str, version: u64, state: &SyncState) -> Result<()> {
+        save_snapshot_with_meta(self, node_id, state, &SnapshotMeta::new(version))?;
+        Ok(())
+    }
+
+    /// 以下一个可用版本号保存带标签的快照，返回其元数据
+    fn save_labeled_snapshot(
+        &self,
+        node_id: &str,
+        state: &SyncState,
+        label: &str,
+    ) -> Result<SnapshotMeta> {
+        let version = self.list_snapshots(node_id)?.last().map_or(1, |v| v + 1);
+        let meta = SnapshotMeta {
+            label: Some(label.to_string()),
+            ..SnapshotMeta::new(version)
+        };
+        save_snapshot_with_meta(self, node_id, state, &meta)
+    }
+
+    /// 获取快照元数据（旧版本快照可能没有元数据）
+    fn snapshot_meta(&self, node_id: &str, version: u64) -> Result<Option<SnapshotMeta>> {
+        let key = format!("snapshot-meta:{}:{}", node_id, version);
+
+        match self
+            .get(&key)
+            .context("Failed to get snapshot meta from database")?
+        {
+            Some(value) => Ok(Some(
+                serde_json::from_slice(&value).context("Failed to deserialize snapshot meta")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
     /// 加载快照
-    #[allow(dead_code)]
-    pub fn load_snapshot(&self, node_id: &str, version: u64) -> Result<Option<SyncState>> {
+    fn load_snapshot(&self, node_id: &str, version: u64) -> Result<Option<SyncState>> {
         let key = format!("snapshot:{}:{}", node_id, version);
 
         if let Some(value) = self
-            .db
-            .get(key.as_bytes())
+            .get(&key)
             .context("Failed to get snapshot from database")?
         {
             let state = serde_json::from_slice(&value).context("Failed to deserialize snapshot")?;
@@ -89,16 +534,15 @@ impl Storage {
     }
 
     /// 列出节点的所有快照版本
-    #[allow(dead_code)]
-    pub fn list_snapshots(&self, node_id: &str) -> Result<Vec<u64>> {
+    fn list_snapshots(&self, node_id: &str) -> Result<Vec<u64>> {
         let prefix = format!("snapshot:{}:", node_id);
         let mut versions = Vec::new();
 
-        for item in self.db.scan_prefix(prefix.as_bytes()) {
-            let (key, _) = item.context("Failed to scan database")?;
-            let key_str = String::from_utf8_lossy(&key);
-
-            if let Some(version_str) = key_str.split(':').nth(2)
+        for key in self
+            .keys_with_prefix(&prefix)
+            .context("Failed to scan database")?
+        {
+            if let Some(version_str) = key.split(':').nth(2)
                 && let Ok(version) = version_str.parse::<u64>()
             {
                 versions.push(version);
@@ -110,8 +554,7 @@ impl Storage {
     }
 
     /// 删除旧快照（保留最新的 N 个）
-    #[allow(dead_code)]
-    pub fn cleanup_old_snapshots(&self, node_id: &str, keep: usize) -> Result<()> {
+    fn cleanup_old_snapshots(&self, node_id: &str, keep: usize) -> Result<()> {
         let mut versions = self.list_snapshots(node_id)?;
 
         if versions.len() <= keep {
@@ -122,21 +565,45 @@ impl Storage {
         let to_delete = &versions[..versions.len() - keep];
 
         for version in to_delete {
-            let key = format!("snapshot:{}:{}", node_id, version);
-            self.db
-                .remove(key.as_bytes())
-                .context("Failed to remove old snapshot")?;
+            remove_snapshot(self, node_id, *version)?;
             tracing::info!("Deleted old snapshot: node={} version={}", node_id, version);
         }
 
-        self.db.flush().context("Failed to flush database")?;
+        self.flush().context("Failed to flush database")?;
 
         Ok(())
     }
 
+    /// 删除创建时间早于 `cutoff_ms` 的快照，返回删除数量
+    ///
+    /// 没有元数据的旧版本快照无法判断创建时间，会被保留。
+    fn cleanup_snapshots_older_than(&self, node_id: &str, cutoff_ms: i64) -> Result<usize> {
+        let mut deleted = 0;
+
+        for version in self.list_snapshots(node_id)? {
+            let Some(meta) = self.snapshot_meta(node_id, version)? else {
+                continue;
+            };
+            if meta.created_at < cutoff_ms {
+                remove_snapshot(self, node_id, version)?;
+                tracing::info!(
+                    "Deleted expired snapshot: node={} version={}",
+                    node_id,
+                    version
+                );
+                deleted += 1;
+            }
+        }
+
+        if deleted > 0 {
+            self.flush().context("Failed to flush database")?;
+        }
+
+        Ok(deleted)
+    }
+
     /// 导出操作日志到文件
-    #[allow(dead_code)]
-    pub fn export_oplog(&self, node_id: &str, output_path: &str) -> Result<()> {
+    fn export_oplog(&self, node_id: &str, output_path: &str) -> Result<()> {
         if let Some(state) = self.load_state(node_id)? {
             let oplog_json = state
                 .export_oplog()
@@ -152,26 +619,558 @@ impl Storage {
         Ok(())
     }
 
+    /// 校验所有快照的完整性，损坏的快照被隔离（改名为 `snapshot-corrupt:` 前缀）
+    ///
+    /// 有校验和的快照比对 SHA-256，旧版本快照仅检查能否反序列化。
+    fn scrub_snapshots(&self, node_id: &str) -> Result<ScrubReport> {
+        let mut report = ScrubReport::default();
+
+        for version in self.list_snapshots(node_id)? {
+            let key = format!("snapshot:{}:{}", node_id, version);
+            let Some(value) = self
+                .get(&key)
+                .context("Failed to get snapshot from database")?
+            else {
+                continue;
+            };
+            report.checked += 1;
+
+            let problem = match self.snapshot_meta(node_id, version) {
+                Ok(Some(SnapshotMeta {
+                    checksum: Some(expected),
+                    ..
+                })) => {
+                    let actual = snapshot_checksum(&value);
+                    (actual != expected).then(|| {
+                        format!("checksum mismatch (expected {}, got {})", expected, actual)
+                    })
+                }
+                Ok(_) => serde_json::from_slice::<SyncState>(&value)
+                    .err()
+                    .map(|e| format!("unreadable snapshot: {}", e)),
+                Err(e) => Some(format!("unreadable snapshot meta: {}", e)),
+            };
+
+            if let Some(problem) = problem {
+                tracing::error!(
+                    "Corrupt snapshot detected: node={} version={}: {}",
+                    node_id,
+                    version,
+                    problem
+                );
+                quarantine_snapshot(self, node_id, version, value)?;
+                self.corrupt_snapshot_counter()
+                    .fetch_add(1, Ordering::Relaxed);
+                report.corrupt.push(version);
+            }
+        }
+
+        if !report.corrupt.is_empty() {
+            self.flush().context("Failed to flush database")?;
+        }
+        Ok(report)
+    }
+
+    /// 巡检累计发现的损坏快照数
+    fn corrupt_snapshot_count(&self) -> u64 {
+        self.corrupt_snapshot_counter().load(Ordering::Relaxed)
+    }
+
+    /// 导出完整状态到文件（用于离线同步）
+    fn export_state(&self, node_id: &str, output_path: &str) -> Result<()> {
+        let state = self
+            .load_state(node_id)?
+            .with_context(|| format!("No state found for node: {}", node_id))?;
+        let json = serde_json::to_string(&state).context("Failed to serialize state")?;
+
+        std::fs::write(output_path, json)
+            .with_context(|| format!("Failed to write state to {}", output_path))?;
+
+        tracing::info!("Exported state to: {}", output_path);
+        Ok(())
+    }
+
+    /// 加载归档导入进度
+    fn load_import_progress(
+        &self,
+        node_id: &str,
+        archive_id: &str,
+    ) -> Result<Option<ImportProgress>> {
+        let key = format!("import-progress:{}:{}", node_id, archive_id);
+
+        match self
+            .get(&key)
+            .context("Failed to get import progress from database")?
+        {
+            Some(value) => Ok(Some(
+                serde_json::from_slice(&value).context("Failed to deserialize import progress")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// 保存归档导入进度
+    fn save_import_progress(&self, node_id: &str, progress: &ImportProgress) -> Result<()> {
+        let key = format!("import-progress:{}:{}", node_id, progress.archive_id);
+        let value = serde_json::to_vec(progress).context("Failed to serialize import progress")?;
+
+        self.insert(&key, value)
+            .context("Failed to insert import progress into database")?;
+        self.flush().context("Failed to flush database")?;
+        Ok(())
+    }
+
+    /// 逐条导入 JSON Lines 归档（每行一个操作日志条目），可中断后以相同归档 ID 续传
+    ///
+    /// 每处理 `IMPORT_CHECKPOINT_INTERVAL` 条记录先保存状态再保存进度；续传时跳过检查点之前的记录，
+    /// 并按操作 ID 去重，因此即使检查点之间中断也不会重复应用。
+    /// 每条记录应用前按 `trust` 验证签名，验证失败时与损坏的记录一样中断导入。
+    fn import_archive(
+        &self,
+        node_id: &str,
+        state: &mut SyncState,
+        archive_id: &str,
+        path: &str,
+        trust: &SignatureManager,
+        require_signatures: bool,
+    ) -> Result<ImportProgress> {
+        use std::io::BufRead;
+
+        let mut progress = self
+            .load_import_progress(node_id, archive_id)?
+            .unwrap_or_else(|| ImportProgress {
+                archive_id: archive_id.to_string(),
+                ..Default::default()
+            });
+        if progress.completed {
+            return Ok(progress);
+        }
+
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open archive {}", path))?;
+        let mut seen: std::collections::HashSet<String> =
+            state.op_log.ops.iter().map(|e| e.id.clone()).collect();
+
+        let checkpoint = |state: &mut SyncState, progress: &ImportProgress| -> Result<()> {
+            state.op_log.sort();
+            self.save_state(node_id, state)?;
+            self.save_import_progress(node_id, progress)
+        };
+
+        for (offset, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let offset = offset as u64;
+            if offset < progress.next_offset {
+                continue;
+            }
+
+            let record = line
+                .with_context(|| format!("Failed to read archive {} at record {}", path, offset))
+                .and_then(|line| {
+                    serde_json::from_str::<OpLogEntry>(&line)
+                        .with_context(|| format!("Invalid record {} in archive {}", offset, path))
+                })
+                .and_then(|entry| {
+                    entry
+                        .verify_signature(trust, require_signatures)
+                        .map_err(|reason| {
+                            anyhow::anyhow!(
+                                "Rejected record {} in archive {}: {}",
+                                offset,
+                                path,
+                                reason
+                            )
+                        })?;
+                    Ok(entry)
+                });
+            let entry = match record {
+                Ok(entry) => entry,
+                Err(e) => {
+                    checkpoint(state, &progress)?;
+                    return Err(e);
+                }
+            };
+
+            if seen.insert(entry.id.clone()) && state.apply_remote_entry(entry) {
+                progress.applied += 1;
+            } else {
+                progress.skipped += 1;
+            }
+            progress.next_offset = offset + 1;
+
+            if progress.next_offset % IMPORT_CHECKPOINT_INTERVAL == 0 {
+                checkpoint(state, &progress)?;
+            }
+        }
+
+        progress.completed = true;
+        checkpoint(state, &progress)?;
+        tracing::info!(
+            "Imported archive {}: {} applied, {} skipped",
+            archive_id,
+            progress.applied,
+            progress.skipped
+        );
+        Ok(progress)
+    }
+
     /// 清空所有数据
-    #[allow(dead_code)]
-    pub fn clear_all(&self) -> Result<()> {
-        self.db.clear().context("Failed to clear database")?;
-        self.db.flush().context("Failed to flush database")?;
+    fn clear_all(&self) -> Result<()> {
+        self.clear().context("Failed to clear database")?;
+        self.flush().context("Failed to flush database")?;
         tracing::info!("Cleared all data from storage");
         Ok(())
     }
 }
 
+/// 保存快照及其元数据
+fn save_snapshot_with_meta<S: StorageBackend + ?Sized>(
+    storage: &S,
+    node_id: &str,
+    state: &SyncState,
+    meta: &SnapshotMeta,
+) -> Result<SnapshotMeta> {
+    let key = format!("snapshot:{}:{}", node_id, meta.version);
+    let value = serde_json::to_vec(state).context("Failed to serialize snapshot")?;
+    let meta = SnapshotMeta {
+        checksum: Some(snapshot_checksum(&value)),
+        ..meta.clone()
+    };
+    let meta_key = format!("snapshot-meta:{}:{}", node_id, meta.version);
+    let meta_value = serde_json::to_vec(&meta).context("Failed to serialize snapshot meta")?;
+
+    storage
+        .insert_batch(vec![(key, value), (meta_key, meta_value)])
+        .context("Failed to insert snapshot into database")?;
+
+    storage.flush().context("Failed to flush database")?;
+
+    tracing::info!(
+        "Saved snapshot for node: {} version: {}",
+        node_id,
+        meta.version
+    );
+    Ok(meta)
+}
+
+/// 删除单个快照及其元数据
+fn remove_snapshot<S: StorageBackend + ?Sized>(
+    storage: &S,
+    node_id: &str,
+    version: u64,
+) -> Result<()> {
+    let key = format!("snapshot:{}:{}", node_id, version);
+    let meta_key = format!("snapshot-meta:{}:{}", node_id, version);
+    storage.remove(&key).context("Failed to remove snapshot")?;
+    storage
+        .remove(&meta_key)
+        .context("Failed to remove snapshot meta")?;
+    Ok(())
+}
+
+/// 将损坏的快照移出正常快照列表，保留原始数据以便排查
+fn quarantine_snapshot<S: StorageBackend + ?Sized>(
+    storage: &S,
+    node_id: &str,
+    version: u64,
+    value: Vec<u8>,
+) -> Result<()> {
+    let corrupt_key = format!("snapshot-corrupt:{}:{}", node_id, version);
+    storage
+        .insert(&corrupt_key, value)
+        .context("Failed to quarantine snapshot")?;
+    remove_snapshot(storage, node_id, version)
+}
+
+/// 从文件读取导出的状态
+pub fn read_state_file(path: &str) -> Result<SyncState> {
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read state file {}", path))?;
+    serde_json::from_str(&data).with_context(|| format!("Invalid state file {}", path))
+}
+
+/// sled 存储
+pub struct SledStorage {
+    db: Db,
+    flush_policy: FlushPolicy,
+    compress_state: bool,
+    dirty: AtomicBool,            // Periodic 策略下是否有尚未落盘的写入
+    corrupt_snapshots: AtomicU64, // 巡检累计发现的损坏快照数
+}
+
+impl SledStorage {
+    /// 创建或打开存储
+    pub fn new(path: &str) -> Result<Self> {
+        Self::with_config(path, &StorageConfig::default())
+    }
+
+    /// 按指定 sled 配置创建或打开存储
+    pub fn with_config(path: &str, config: &StorageConfig) -> Result<Self> {
+        config.validate()?;
+        let db = config
+            .sled_config(path)
+            .open()
+            .with_context(|| format!("Failed to open database at {}", path))?;
+        tracing::info!(
+            "Opened sled database at {} (cache: {} MiB, flush every: {} ms, mode: {:?}, flush policy: {:?}, compress state: {})",
+            path,
+            config.cache_mb,
+            config.flush_every_ms,
+            config.mode,
+            config.flush_policy,
+            config.compress_state
+        );
+        Ok(Self {
+            db,
+            flush_policy: config.flush_policy,
+            compress_state: config.compress_state,
+            dirty: AtomicBool::new(false),
+            corrupt_snapshots: AtomicU64::new(0),
+        })
+    }
+}
+
+impl Drop for SledStorage {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush_pending() {
+            tracing::error!("Failed to flush pending writes on close: {}", e);
+        }
+    }
+}
+
+impl StorageBackend for SledStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.db.get(key.as_bytes())?.map(|value| value.to_vec()))
+    }
+
+    fn insert(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.db.insert(key.as_bytes(), value)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.db.remove(key.as_bytes())?;
+        Ok(())
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        self.db
+            .scan_prefix(prefix.as_bytes())
+            .keys()
+            .map(|key| Ok(String::from_utf8_lossy(&key?).into_owned()))
+            .collect()
+    }
+
+    fn insert_batch(&self, entries: Vec<(String, Vec<u8>)>) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for (key, value) in entries {
+            batch.insert(key.as_bytes(), value);
+        }
+        self.db.apply_batch(batch)?;
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        match self.flush_policy {
+            FlushPolicy::Immediate => {
+                self.db.flush()?;
+            }
+            FlushPolicy::Periodic(_) => self.dirty.store(true, Ordering::Release),
+        }
+        Ok(())
+    }
+
+    fn flush_pending(&self) -> Result<()> {
+        if self.dirty.swap(false, Ordering::AcqRel)
+            && let Err(e) = self.db.flush()
+        {
+            self.dirty.store(true, Ordering::Release);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.db.clear()?;
+        Ok(())
+    }
+
+    fn corrupt_snapshot_counter(&self) -> &AtomicU64 {
+        &self.corrupt_snapshots
+    }
+
+    fn compress_state(&self) -> bool {
+        self.compress_state
+    }
+}
+
+/// 内存存储（用于测试与无需持久化的临时部署）
+#[derive(Default)]
+pub struct MemoryStorage {
+    data: Mutex<HashMap<String, Vec<u8>>>,
+    corrupt_snapshots: AtomicU64,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.data.lock().unwrap().get(key).cloned())
+    }
+
+    fn insert(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.data.lock().unwrap().insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.data.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut keys: Vec<String> = self
+            .data
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.data.lock().unwrap().clear();
+        Ok(())
+    }
+
+    fn corrupt_snapshot_counter(&self) -> &AtomicU64 {
+        &self.corrupt_snapshots
+    }
+}
+
+/// 启动后台任务，按间隔将 Periodic 策略下积累的写入合并落盘
+pub fn spawn_periodic_flush_task(
+    storage: Arc<dyn StorageBackend>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = storage.flush_pending() {
+                tracing::warn!("Periodic flush failed: {}", e);
+            }
+        }
+    })
+}
+
+/// 启动后台任务，定期删除超过保留期限的快照
+pub fn spawn_snapshot_pruning_task(
+    storage: Arc<dyn StorageBackend>,
+    node_id: String,
+    max_age: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker =
+            tokio::time::interval(max_age.clamp(Duration::from_secs(1), Duration::from_secs(60)));
+        loop {
+            ticker.tick().await;
+            let cutoff = chrono::Local::now()
+                .naive_local()
+                .and_utc()
+                .timestamp_millis()
+                - max_age.as_millis() as i64;
+            if let Err(e) = storage.cleanup_snapshots_older_than(&node_id, cutoff) {
+                tracing::warn!("Failed to prune expired snapshots: {}", e);
+            }
+        }
+    })
+}
+
+/// 启动后台任务，定期校验快照完整性
+pub fn spawn_snapshot_scrub_task(
+    storage: Arc<dyn StorageBackend>,
+    node_id: String,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval.max(Duration::from_secs(1)));
+        loop {
+            ticker.tick().await;
+            match storage.scrub_snapshots(&node_id) {
+                Ok(report) if !report.corrupt.is_empty() => tracing::warn!(
+                    "Snapshot scrub quarantined {} of {} snapshots: {:?}",
+                    report.corrupt.len(),
+                    report.checked,
+                    report.corrupt
+                ),
+                Ok(report) => tracing::debug!("Snapshot scrub checked {}", report.checked),
+                Err(e) => tracing::warn!("Failed to scrub snapshots: {}", e),
+            }
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::sync::SyncState;
+    use crate::sync::{Change, SyncState};
+
+    /// 对 sled 与内存两种后端分别运行同一组测试
+    macro_rules! backend_tests {
+        ($($name:ident),* $(,)?) => {
+            mod sled_backend {
+                $(
+                    #[test]
+                    fn $name() -> anyhow::Result<()> {
+                        let dir = tempfile::tempdir()?;
+                        let storage = super::SledStorage::new(dir.path().to_str().unwrap())?;
+                        super::$name(&storage)
+                    }
+                )*
+            }
 
-    #[test]
-    fn test_storage_basic() -> Result<()> {
-        let temp_dir = tempfile::tempdir()?;
-        let storage = Storage::new(temp_dir.path().to_str().unwrap())?;
+            mod memory_backend {
+                $(
+                    #[test]
+                    fn $name() -> anyhow::Result<()> {
+                        super::$name(&super::MemoryStorage::new())
+                    }
+                )*
+            }
+        };
+    }
 
+    backend_tests!(
+        test_storage_basic,
+        test_snapshot_management,
+        test_cleanup_snapshots_older_than,
+        test_load_snapshot,
+        test_state_persistence,
+        test_document_states_are_isolated,
+        test_keypair_persistence,
+        test_clear_all,
+        test_load_nonexistent_state,
+        test_schema_persistence,
+        test_list_snapshots_empty,
+        test_merge_from_exported_file,
+        test_snapshot_diff,
+        test_labeled_snapshot_restores_pre_reset_state,
+        test_scrub_detects_corrupt_snapshot,
+        test_import_archive_resumes_without_duplicates,
+    );
+
+    fn test_storage_basic(storage: &dyn StorageBackend) -> Result<()> {
         let node_id = "test-node";
         let state = SyncState::new(node_id.to_string());
 
@@ -186,10 +1185,215 @@ mod tests {
     }
 
     #[test]
-    fn test_snapshot_management() -> Result<()> {
+    fn test_memory_writes_do_not_survive_restart() -> Result<()> {
         let temp_dir = tempfile::tempdir()?;
-        let storage = Storage::new(temp_dir.path().to_str().unwrap())?;
+        let path = temp_dir.path().to_str().unwrap();
+        let node_id = "test-node";
+        let change = |op: &str, key: &str, value: Option<&str>| crate::sync::ChangeRequest {
+            changes: vec![Change {
+                op: op.to_string(),
+                key: key.to_string(),
+                value: value.map(str::to_string),
+                ..Default::default()
+            }],
+        };
+
+        let mut state = SyncState::new(node_id.to_string());
+        {
+            let storage = SledStorage::new(path)?;
+            state
+                .apply_changes_in_memory(change("increment", "telemetry", None))
+                .unwrap();
+            storage.save_state_with(node_id, &state, Durability::Memory)?;
+
+            // 后续持久化写入会保存整个状态，但临时键被排除
+            state
+                .apply_changes(change("set", "config", Some("on")))
+                .unwrap();
+            storage.save_state_with(node_id, &state, Durability::Flush)?;
+
+            // 已持久化的键不能以 memory 级别写入
+            assert!(
+                state
+                    .apply_changes_in_memory(change("set", "config", Some("off")))
+                    .is_err()
+            );
+        }
+
+        // 模拟重启
+        let storage = SledStorage::new(path)?;
+        let loaded = storage.load_state(node_id)?.unwrap();
+        assert!(loaded.crdt_map.get("config").is_some());
+        assert!(loaded.crdt_map.get("telemetry").is_none());
+        assert!(
+            loaded
+                .op_log
+                .ops
+                .iter()
+                .all(|entry| entry.op.key() != "telemetry")
+        );
+        assert!(state.crdt_map.get("telemetry").is_some());
+
+        assert_eq!("ASYNC".parse::<Durability>(), Ok(Durability::Async));
+        assert!("disk".parse::<Durability>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_storage_with_config() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let config = StorageConfig {
+            cache_mb: 8,
+            flush_every_ms: 0,
+            mode: StorageMode::HighThroughput,
+            ..Default::default()
+        };
+        let storage = SledStorage::with_config(temp_dir.path().to_str().unwrap(), &config)?;
 
+        let node_id = "test-node";
+        let mut state = SyncState::new(node_id.to_string());
+        state
+            .apply_changes(crate::sync::ChangeRequest {
+                changes: vec![Change {
+                    op: "increment".to_string(),
+                    key: "counter".to_string(),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        storage.save_state(node_id, &state)?;
+        storage.save_snapshot(node_id, 1, &state)?;
+
+        let loaded = storage.load_state(node_id)?.unwrap();
+        assert_eq!(loaded.state_hash(), state.state_hash());
+
+        // 非法配置被拒绝
+        let invalid = StorageConfig {
+            cache_mb: 0,
+            ..Default::default()
+        };
+        assert!(
+            SledStorage::with_config(temp_dir.path().join("other").to_str().unwrap(), &invalid)
+                .is_err()
+        );
+        assert!(
+            StorageConfig {
+                flush_every_ms: MAX_SLED_FLUSH_MS + 1,
+                ..Default::default()
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(
+            StorageConfig {
+                flush_policy: FlushPolicy::Periodic(Duration::ZERO),
+                ..Default::default()
+            }
+            .validate()
+            .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_state_round_trip_and_mixed_reads() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().to_str().unwrap();
+        let compressed = StorageConfig {
+            compress_state: true,
+            ..Default::default()
+        };
+        let node_id = "test-node";
+        let mut state = SyncState::new(node_id.to_string());
+        state
+            .apply_changes(crate::sync::ChangeRequest {
+                changes: (0..50)
+                    .map(|i| Change {
+                        op: "add".to_string(),
+                        key: "tags".to_string(),
+                        value: Some(format!("tag-{}", i)),
+                        ..Default::default()
+                    })
+                    .collect(),
+            })
+            .unwrap();
+
+        // 压缩前写入的未压缩值
+        {
+            let storage = SledStorage::new(path)?;
+            storage.save_state(node_id, &state)?;
+        }
+
+        let uncompressed_len;
+        {
+            let storage = SledStorage::with_config(path, &compressed)?;
+            let raw = storage.get(&state_key(node_id, DEFAULT_DOCUMENT))?.unwrap();
+            assert!(!raw.starts_with(compression::COMPRESSED_MAGIC));
+            uncompressed_len = raw.len();
+            let loaded = storage.load_state(node_id)?.unwrap();
+            assert_eq!(loaded.state_hash(), state.state_hash());
+
+            storage.save_state(node_id, &state)?;
+            let raw = storage.get(&state_key(node_id, DEFAULT_DOCUMENT))?.unwrap();
+            assert!(raw.starts_with(compression::COMPRESSED_MAGIC));
+            assert!(raw.len() < uncompressed_len);
+            let loaded = storage.load_state(node_id)?.unwrap();
+            assert_eq!(loaded.state_hash(), state.state_hash());
+        }
+
+        // 关闭压缩后仍可读取已压缩的值
+        let storage = SledStorage::new(path)?;
+        let loaded = storage.load_state(node_id)?.unwrap();
+        assert_eq!(loaded.state_hash(), state.state_hash());
+        Ok(())
+    }
+
+    #[test]
+    fn test_periodic_flush_keeps_acknowledged_writes_after_shutdown() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().to_str().unwrap();
+        let config = StorageConfig {
+            flush_every_ms: 0,
+            flush_policy: FlushPolicy::Periodic(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        let node_id = "test-node";
+        let mut state = SyncState::new(node_id.to_string());
+
+        {
+            let storage = SledStorage::with_config(path, &config)?;
+            for i in 0..20 {
+                state
+                    .apply_changes(crate::sync::ChangeRequest {
+                        changes: vec![Change {
+                            op: "set".to_string(),
+                            key: format!("key{}", i),
+                            value: Some(i.to_string()),
+                            ..Default::default()
+                        }],
+                    })
+                    .unwrap();
+                storage.save_state(node_id, &state)?;
+            }
+            storage.save_snapshot(node_id, 1, &state)?;
+            assert!(storage.dirty.load(Ordering::Acquire));
+
+            storage.flush_pending()?;
+            assert!(!storage.dirty.load(Ordering::Acquire));
+            // 关闭时仍会落盘此后确认的写入
+            storage.save_schemas(node_id, &SchemaRegistry::new())?;
+        }
+
+        let storage = SledStorage::with_config(path, &config)?;
+        let loaded = storage.load_state(node_id)?.unwrap();
+        assert_eq!(loaded.state_hash(), state.state_hash());
+        assert_eq!(loaded.crdt_map.entries.len(), 20);
+        assert!(storage.load_snapshot(node_id, 1)?.is_some());
+        Ok(())
+    }
+
+    fn test_snapshot_management(storage: &dyn StorageBackend) -> Result<()> {
         let node_id = "test-node";
         let state = SyncState::new(node_id.to_string());
 
@@ -211,11 +1415,36 @@ mod tests {
         Ok(())
     }
 
-    #[test]
-    fn test_load_snapshot() -> Result<()> {
-        let temp_dir = tempfile::tempdir()?;
-        let storage = Storage::new(temp_dir.path().to_str().unwrap())?;
+    fn test_cleanup_snapshots_older_than(storage: &dyn StorageBackend) -> Result<()> {
+        let node_id = "test-node";
+        let state = SyncState::new(node_id.to_string());
+
+        for (version, created_at) in [(1, 1_000), (2, 2_000), (3, 3_000)] {
+            save_snapshot_with_meta(
+                storage,
+                node_id,
+                &state,
+                &SnapshotMeta {
+                    version,
+                    created_at,
+                    label: None,
+                    checksum: None,
+                },
+            )?;
+        }
+        // 新保存的快照记录当前时间
+        storage.save_snapshot(node_id, 4, &state)?;
+        assert!(storage.snapshot_meta(node_id, 4)?.unwrap().created_at > 3_000);
+
+        let deleted = storage.cleanup_snapshots_older_than(node_id, 2_500)?;
+        assert_eq!(deleted, 2);
+        assert_eq!(storage.list_snapshots(node_id)?, vec![3, 4]);
+        assert!(storage.snapshot_meta(node_id, 1)?.is_none());
 
+        Ok(())
+    }
+
+    fn test_load_snapshot(storage: &dyn StorageBackend) -> Result<()> {
         let node_id = "test-node";
         let mut state = SyncState::new(node_id.to_string());
 
@@ -228,6 +1457,7 @@ mod tests {
                     key: "counter".to_string(),
                     value: None,
                     delta: Some(5),
+                    ..Default::default()
                 }],
             })
             .map_err(|e| anyhow::anyhow!(e))?;
@@ -246,11 +1476,7 @@ mod tests {
         Ok(())
     }
 
-    #[test]
-    fn test_state_persistence() -> Result<()> {
-        let temp_dir = tempfile::tempdir()?;
-        let storage = Storage::new(temp_dir.path().to_str().unwrap())?;
-
+    fn test_state_persistence(storage: &dyn StorageBackend) -> Result<()> {
         let node_id = "test-node";
         let mut state = SyncState::new(node_id.to_string());
 
@@ -263,6 +1489,7 @@ mod tests {
                     key: "counter".to_string(),
                     value: None,
                     delta: Some(10),
+                    ..Default::default()
                 }],
             })
             .map_err(|e| anyhow::anyhow!(e))?;
@@ -281,11 +1508,68 @@ mod tests {
         Ok(())
     }
 
-    #[test]
-    fn test_clear_all() -> Result<()> {
-        let temp_dir = tempfile::tempdir()?;
-        let storage = Storage::new(temp_dir.path().to_str().unwrap())?;
+    fn test_keypair_persistence(storage: &dyn StorageBackend) -> Result<()> {
+        assert!(storage.load_keypair("test-node")?.is_none());
+
+        let keypair = KeyPair::generate();
+        storage.save_keypair("test-node", &keypair)?;
+        let loaded = storage.load_keypair("test-node")?.expect("keypair saved");
+        assert_eq!(loaded.public_key_bytes(), keypair.public_key_bytes());
+        assert!(storage.load_keypair("other-node")?.is_none());
+
+        // 轮换后新密钥与旧公钥一并保存
+        assert!(storage.load_retiring_keys("test-node")?.is_empty());
+        let rotated = KeyPair::generate();
+        let retiring = vec![RetiringKey {
+            public_key: "old-key".to_string(),
+            retired_at: 1,
+            expires_at: 2,
+        }];
+        storage.save_rotated_keypair("test-node", &rotated, &retiring)?;
+        let loaded = storage.load_keypair("test-node")?.expect("keypair saved");
+        assert_eq!(loaded.public_key_bytes(), rotated.public_key_bytes());
+        assert_eq!(storage.load_retiring_keys("test-node")?, retiring);
+        assert!(storage.load_retiring_keys("other-node")?.is_empty());
+        Ok(())
+    }
+
+    fn test_document_states_are_isolated(storage: &dyn StorageBackend) -> Result<()> {
+        let node_id = "test-node";
+        let mut notes = SyncState::new(node_id.to_string());
+        notes
+            .apply_changes(crate::sync::ChangeRequest {
+                changes: vec![Change {
+                    op: "set".to_string(),
+                    key: "title".to_string(),
+                    value: Some("notes".to_string()),
+                    ..Default::default()
+                }],
+            })
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        storage.save_state(node_id, &SyncState::new(node_id.to_string()))?;
+        storage.save_document_state(node_id, "notes", &notes, Durability::Flush)?;
+        storage.save_document_state(node_id, "board", &notes, Durability::Memory)?;
+
+        // 命名文档与默认文档分别存储，memory 级别的文档不会出现在列表中
+        assert_eq!(storage.list_documents(node_id)?, vec!["notes".to_string()]);
+        assert_eq!(
+            storage
+                .load_document_state(node_id, "notes")?
+                .map(|s| s.state_hash()),
+            Some(notes.state_hash())
+        );
+        assert_ne!(
+            storage.load_state(node_id)?.map(|s| s.state_hash()),
+            Some(notes.state_hash())
+        );
+        assert!(storage.load_document_state(node_id, "board")?.is_none());
+        assert!(storage.list_documents("other-node")?.is_empty());
+
+        Ok(())
+    }
 
+    fn test_clear_all(storage: &dyn StorageBackend) -> Result<()> {
         // 保存多个节点的状态
         for i in 1..=3 {
             let node_id = format!("node-{}", i);
@@ -305,11 +1589,7 @@ mod tests {
         Ok(())
     }
 
-    #[test]
-    fn test_load_nonexistent_state() -> Result<()> {
-        let temp_dir = tempfile::tempdir()?;
-        let storage = Storage::new(temp_dir.path().to_str().unwrap())?;
-
+    fn test_load_nonexistent_state(storage: &dyn StorageBackend) -> Result<()> {
         // 尝试加载不存在的状态
         let result = storage.load_state("nonexistent-node")?;
         assert!(result.is_none());
@@ -317,11 +1597,26 @@ mod tests {
         Ok(())
     }
 
-    #[test]
-    fn test_list_snapshots_empty() -> Result<()> {
-        let temp_dir = tempfile::tempdir()?;
-        let storage = Storage::new(temp_dir.path().to_str().unwrap())?;
+    fn test_schema_persistence(storage: &dyn StorageBackend) -> Result<()> {
+        let node_id = "test-node";
+        assert!(storage.load_schemas(node_id)?.is_empty());
 
+        let mut schemas = SchemaRegistry::new();
+        schemas.register(
+            "status".to_string(),
+            crate::schema::KeySchema {
+                allowed: Some(vec!["open".to_string()]),
+                ..Default::default()
+            },
+        );
+        storage.save_schemas(node_id, &schemas)?;
+
+        assert_eq!(storage.load_schemas(node_id)?, schemas);
+
+        Ok(())
+    }
+
+    fn test_list_snapshots_empty(storage: &dyn StorageBackend) -> Result<()> {
         let node_id = "test-node";
 
         // 列出空快照列表
@@ -330,4 +1625,261 @@ mod tests {
 
         Ok(())
     }
+
+    fn test_merge_from_exported_file(storage_a: &dyn StorageBackend) -> Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        let mut state_a = SyncState::new("node_a".to_string());
+        state_a
+            .apply_changes(crate::sync::ChangeRequest {
+                changes: vec![
+                    Change {
+                        op: "increment".to_string(),
+                        key: "counter".to_string(),
+                        delta: Some(3),
+                        ..Default::default()
+                    },
+                    Change {
+                        op: "add".to_string(),
+                        key: "tags".to_string(),
+                        value: Some("rust".to_string()),
+                        ..Default::default()
+                    },
+                ],
+            })
+            .unwrap();
+        storage_a.save_state("node_a", &state_a)?;
+
+        let export_path = dir.path().join("node_a.json");
+        let export_path = export_path.to_str().unwrap();
+        storage_a.export_state("node_a", export_path)?;
+
+        let mut state_b = SyncState::new("node_b".to_string());
+        state_b
+            .apply_changes(crate::sync::ChangeRequest {
+                changes: vec![Change {
+                    op: "increment".to_string(),
+                    key: "counter".to_string(),
+                    delta: Some(2),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        let mut via_network = state_b.clone();
+
+        state_b.merge(&read_state_file(export_path)?);
+        via_network.merge(&state_a);
+
+        assert_eq!(state_b.state_hash(), via_network.state_hash());
+        Ok(())
+    }
+
+    fn test_snapshot_diff(storage: &dyn StorageBackend) -> Result<()> {
+        let mut state = SyncState::new("node1".to_string());
+        state
+            .apply_changes(crate::sync::ChangeRequest {
+                changes: vec![Change {
+                    op: "set".to_string(),
+                    key: "name".to_string(),
+                    value: Some("alice".to_string()),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        storage.save_snapshot("node1", 1, &state)?;
+
+        state
+            .apply_changes(crate::sync::ChangeRequest {
+                changes: vec![
+                    Change {
+                        op: "set".to_string(),
+                        key: "name".to_string(),
+                        value: Some("bob".to_string()),
+                        ..Default::default()
+                    },
+                    Change {
+                        op: "increment".to_string(),
+                        key: "visits".to_string(),
+                        delta: Some(2),
+                        ..Default::default()
+                    },
+                ],
+            })
+            .unwrap();
+        storage.save_snapshot("node1", 2, &state)?;
+
+        let from = storage.load_snapshot("node1", 1)?.unwrap();
+        let to = storage.load_snapshot("node1", 2)?.unwrap();
+        let diff = from.crdt_map.diff(&to.crdt_map);
+
+        assert_eq!(diff.added.get("visits"), Some(&serde_json::json!(2)));
+        assert!(diff.removed.is_empty());
+        let change = &diff.changed["name"];
+        assert_eq!(change.from, serde_json::json!("alice"));
+        assert_eq!(change.to, serde_json::json!("bob"));
+
+        assert!(storage.load_snapshot("node1", 3)?.is_none());
+        Ok(())
+    }
+
+    fn test_labeled_snapshot_restores_pre_reset_state(storage: &dyn StorageBackend) -> Result<()> {
+        let mut state = SyncState::new("node1".to_string());
+        state
+            .apply_changes(crate::sync::ChangeRequest {
+                changes: vec![Change {
+                    op: "increment".to_string(),
+                    key: "counter".to_string(),
+                    delta: Some(7),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        storage.save_snapshot("node1", 3, &state)?;
+        let before_hash = state.state_hash();
+
+        let meta = storage.save_labeled_snapshot("node1", &state, "pre-reset")?;
+        assert_eq!(meta.version, 4);
+        assert_eq!(meta.label.as_deref(), Some("pre-reset"));
+        assert_eq!(storage.snapshot_meta("node1", 4)?, Some(meta.clone()));
+
+        state.reset();
+        assert!(state.crdt_map.entries.is_empty());
+        assert_ne!(state.state_hash(), before_hash);
+
+        let snapshot = storage.load_snapshot("node1", meta.version)?.unwrap();
+        state.replace_with(snapshot);
+        assert_eq!(state.state_hash(), before_hash);
+        Ok(())
+    }
+
+    fn test_scrub_detects_corrupt_snapshot(storage: &dyn StorageBackend) -> Result<()> {
+        let state = SyncState::new("node1".to_string());
+
+        for version in 1..=3 {
+            storage.save_snapshot("node1", version, &state)?;
+        }
+        assert!(
+            storage
+                .snapshot_meta("node1", 1)?
+                .unwrap()
+                .checksum
+                .is_some()
+        );
+
+        let report = storage.scrub_snapshots("node1")?;
+        assert_eq!(report.checked, 3);
+        assert!(report.corrupt.is_empty());
+
+        // 有校验和的快照被篡改（仍是合法 JSON）
+        let mut tampered = state.clone();
+        tampered.node_id = "tampered".to_string();
+        storage.insert("snapshot:node1:2", serde_json::to_vec(&tampered)?)?;
+        // 无元数据的旧快照写入了不完整的数据
+        storage.insert("snapshot:node1:4", b"{\"node_id\":".to_vec())?;
+
+        let report = storage.scrub_snapshots("node1")?;
+        assert_eq!(report.checked, 4);
+        assert_eq!(report.corrupt, vec![2, 4]);
+        assert_eq!(storage.corrupt_snapshot_count(), 2);
+        assert_eq!(storage.list_snapshots("node1")?, vec![1, 3]);
+        assert!(storage.get("snapshot-corrupt:node1:2")?.is_some());
+        Ok(())
+    }
+
+    fn test_import_archive_resumes_without_duplicates(storage: &dyn StorageBackend) -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+
+        let mut source = SyncState::new("source".to_string());
+        for i in 0..(IMPORT_CHECKPOINT_INTERVAL + 50) {
+            source
+                .apply_changes(crate::sync::ChangeRequest {
+                    changes: vec![Change {
+                        op: "increment".to_string(),
+                        key: "counter".to_string(),
+                        ..Default::default()
+                    }],
+                })
+                .unwrap();
+            if i % 10 == 0 {
+                source
+                    .apply_changes(crate::sync::ChangeRequest {
+                        changes: vec![Change {
+                            op: "add".to_string(),
+                            key: "tags".to_string(),
+                            value: Some(format!("tag{}", i)),
+                            ..Default::default()
+                        }],
+                    })
+                    .unwrap();
+            }
+        }
+        let lines: Vec<String> = source
+            .op_log
+            .ops
+            .iter()
+            .map(|entry| serde_json::to_string(entry).unwrap())
+            .collect();
+
+        // 第一次导入在检查点之后遇到损坏的记录而中断
+        let archive = temp_dir.path().join("archive.jsonl");
+        let broken_at = IMPORT_CHECKPOINT_INTERVAL as usize + 20;
+        let mut broken = lines.clone();
+        broken[broken_at] = "{ not json".to_string();
+        std::fs::write(&archive, broken.join("\n"))?;
+
+        let path = archive.to_str().unwrap();
+        let trust = SignatureManager::new("target".to_string());
+        let mut target = SyncState::new("target".to_string());
+        assert!(
+            storage
+                .import_archive("target", &mut target, "a1", path, &trust, false)
+                .is_err()
+        );
+        let progress = storage.load_import_progress("target", "a1")?.unwrap();
+        assert_eq!(progress.next_offset, broken_at as u64);
+        assert!(!progress.completed);
+
+        // 模拟进程重启：从持久化状态恢复后续传
+        let mut target = storage.load_state("target")?.unwrap();
+        std::fs::write(&archive, lines.join("\n"))?;
+        let progress = storage.import_archive("target", &mut target, "a1", path, &trust, false)?;
+        assert!(progress.completed);
+        assert_eq!(progress.applied, lines.len() as u64);
+        assert_eq!(target.crdt_map.document(), source.crdt_map.document());
+        assert_eq!(target.op_log.ops.len(), source.op_log.ops.len());
+
+        // 已完成的归档再次导入不做任何修改
+        let again = storage.import_archive("target", &mut target, "a1", path, &trust, false)?;
+        assert_eq!(again, progress);
+        assert_eq!(target.crdt_map.document(), source.crdt_map.document());
+
+        // 由未登记公钥签名的记录被拒绝，未签名的记录在要求签名时被拒绝
+        let mut signed = SyncState::new("stranger".to_string());
+        signed.op_log.signer = Some(std::sync::Arc::new(SignatureManager::new(
+            "stranger".to_string(),
+        )));
+        signed
+            .apply_changes(crate::sync::ChangeRequest {
+                changes: vec![Change {
+                    op: "increment".to_string(),
+                    key: "counter".to_string(),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        std::fs::write(&archive, serde_json::to_string(&signed.op_log.ops[0])?)?;
+        let err = storage
+            .import_archive("target", &mut target, "a2", path, &trust, false)
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("No trusted public key"));
+        std::fs::write(&archive, &lines[0])?;
+        assert!(
+            storage
+                .import_archive("target", &mut target, "a3", path, &trust, true)
+                .is_err()
+        );
+        assert_eq!(target.crdt_map.document(), source.crdt_map.document());
+
+        Ok(())
+    }
 }
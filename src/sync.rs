@@ -1,7 +1,18 @@
 use crate::crdt::{
-    CRDTMap, CRDTValue, GCounter, LWWRegister, NodeId, ORSet, PNCounter, VectorClock,
+    BoundedCounter, CRDTMap, CRDTValue, Dot, GCounter, GSet, LWWMap, LWWRegister, MVRegister,
+    MaxRegister, MergeConflict, MinRegister, NodeId, ORSet, PNCounter, RGA, RgaId, VectorClock,
 };
+use crate::encryption::FieldEncryptor;
+use crate::hooks::{EventHooks, MergeStats};
+use crate::policy::{ConflictPolicy, PolicyRegistry};
+use crate::schema::SchemaRegistry;
+use crate::signature::{SignatureManager, SignedOperation};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
 
 /// 操作类型
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,695 +33,6179 @@ pub enum Operation {
         node_id: NodeId,
         delta: u64,
     },
+    /// 有下界计数器的增减（携带下界，以便在未见过该键的副本上创建计数器）
+    BoundedCounterIncrement {
+        key: String,
+        node_id: NodeId,
+        delta: u64,
+        lower_bound: i64,
+    },
+    BoundedCounterDecrement {
+        key: String,
+        node_id: NodeId,
+        delta: u64,
+        lower_bound: i64,
+    },
     LwwRegisterSet {
         key: String,
         value: String,
         timestamp: i64,
         node_id: NodeId,
     },
+    /// LWW-Map 单个字段的写入与删除（删除记录为带时间戳的墓碑）
+    LwwMapSet {
+        key: String,
+        field: String,
+        value: String,
+        timestamp: i64,
+        node_id: NodeId,
+    },
+    LwwMapRemove {
+        key: String,
+        field: String,
+        timestamp: i64,
+        node_id: NodeId,
+    },
+    /// OR-Set 添加（序列化字段名沿用旧版的 `unique_id`，旧日志中的唯一 ID 读取为计数为 0 的点）
     OrSetAdd {
         key: String,
         value: String,
-        unique_id: String,
+        #[serde(rename = "unique_id")]
+        dot: Dot,
     },
+    /// OR-Set 移除：携带发起节点观察到的点（旧日志中没有此字段，重放时移除本地当前所有的点）
     OrSetRemove {
         key: String,
         value: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        dots: Option<Vec<Dot>>,
+    },
+    GSetAdd {
+        key: String,
+        value: String,
+    },
+    ConvertType {
+        key: String,
+        from_type: String,
+        to_type: String,
+    },
+    MaxRegisterSet {
+        key: String,
+        value: i64,
+    },
+    MinRegisterSet {
+        key: String,
+        value: i64,
+    },
+    MvRegisterSet {
+        key: String,
+        value: String,
+        node_id: NodeId,
+        clock: VectorClock,
+    },
+    /// 一次增加多个 PNCounter，作为单个日志条目记录
+    MultiIncrement {
+        node_id: NodeId,
+        counters: BTreeMap<String, u64>,
+    },
+    /// RGA 插入（携带新元素的位置标识与前驱，重放结果与发起节点一致）
+    RgaInsert {
+        key: String,
+        id: RgaId,
+        after: Option<RgaId>,
+        value: String,
+    },
+    RgaDelete {
+        key: String,
+        id: RgaId,
     },
 }
 
-/// 操作日志条目
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OpLogEntry {
-    pub id: String,          // 使用 scru128 生成的唯一 ID
-    pub ts: i64,             // 时间戳
-    pub causal: VectorClock, // 因果元数据
-    pub op: Operation,       // 操作内容
-}
-
-/// 操作日志
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OpLog {
-    pub node_id: NodeId,
-    pub ops: Vec<OpLogEntry>,
-}
-
-impl OpLog {
-    pub fn new(node_id: NodeId) -> Self {
-        Self {
-            node_id,
-            ops: Vec::new(),
+impl Operation {
+    /// 操作作用的键（多键操作返回字典序最小的键，完整列表见 `keys`）
+    pub fn key(&self) -> &str {
+        match self {
+            Operation::MultiIncrement { counters, .. } => counters
+                .keys()
+                .next()
+                .map(String::as_str)
+                .unwrap_or_default(),
+            Operation::GCounterIncrement { key, .. }
+            | Operation::PNCounterIncrement { key, .. }
+            | Operation::PNCounterDecrement { key, .. }
+            | Operation::BoundedCounterIncrement { key, .. }
+            | Operation::BoundedCounterDecrement { key, .. }
+            | Operation::LwwRegisterSet { key, .. }
+            | Operation::LwwMapSet { key, .. }
+            | Operation::LwwMapRemove { key, .. }
+            | Operation::OrSetAdd { key, .. }
+            | Operation::OrSetRemove { key, .. }
+            | Operation::GSetAdd { key, .. }
+            | Operation::ConvertType { key, .. }
+            | Operation::MaxRegisterSet { key, .. }
+            | Operation::MinRegisterSet { key, .. }
+            | Operation::MvRegisterSet { key, .. }
+            | Operation::RgaInsert { key, .. }
+            | Operation::RgaDelete { key, .. } => key,
         }
     }
 
-    pub fn add_operation(&mut self, op: Operation, vector_clock: &mut VectorClock) {
-        let id = scru128::new_string();
-        let ts = chrono::Local::now()
-            .naive_local()
-            .and_utc()
-            .timestamp_millis();
+    /// 操作作用的所有键
+    pub fn keys(&self) -> Vec<&str> {
+        match self {
+            Operation::MultiIncrement { counters, .. } => {
+                counters.keys().map(String::as_str).collect()
+            }
+            _ => vec![self.key()],
+        }
+    }
 
-        vector_clock.increment(&self.node_id);
+    /// 是否作用于指定键
+    pub fn touches(&self, key: &str) -> bool {
+        match self {
+            Operation::MultiIncrement { counters, .. } => counters.contains_key(key),
+            _ => self.key() == key,
+        }
+    }
 
-        let entry = OpLogEntry {
-            id,
-            ts,
-            causal: vector_clock.clone(),
-            op,
-        };
+    /// 发起操作的节点（仅携带 node_id 字段的操作）
+    pub fn node_id(&self) -> Option<&str> {
+        match self {
+            Operation::GCounterIncrement { node_id, .. }
+            | Operation::PNCounterIncrement { node_id, .. }
+            | Operation::PNCounterDecrement { node_id, .. }
+            | Operation::BoundedCounterIncrement { node_id, .. }
+            | Operation::BoundedCounterDecrement { node_id, .. }
+            | Operation::LwwRegisterSet { node_id, .. }
+            | Operation::LwwMapSet { node_id, .. }
+            | Operation::LwwMapRemove { node_id, .. }
+            | Operation::MvRegisterSet { node_id, .. }
+            | Operation::MultiIncrement { node_id, .. } => Some(node_id.as_str()),
+            Operation::OrSetAdd { .. }
+            | Operation::OrSetRemove { .. }
+            | Operation::GSetAdd { .. }
+            | Operation::ConvertType { .. }
+            | Operation::MaxRegisterSet { .. }
+            | Operation::MinRegisterSet { .. }
+            | Operation::RgaInsert { .. }
+            | Operation::RgaDelete { .. } => None,
+        }
+    }
 
-        self.ops.push(entry);
+    /// 返回写入型操作携带的 (键, 值)
+    pub fn written_value(&self) -> Option<(&str, &str)> {
+        match self {
+            Operation::LwwRegisterSet { key, value, .. }
+            | Operation::OrSetAdd { key, value, .. }
+            | Operation::GSetAdd { key, value }
+            | Operation::MvRegisterSet { key, value, .. }
+            | Operation::RgaInsert { key, value, .. } => Some((key.as_str(), value.as_str())),
+            _ => None,
+        }
     }
 
-    pub fn merge(&mut self, other: &OpLog) {
-        for op in &other.ops {
-            if !self.ops.iter().any(|e| e.id == op.id) {
-                self.ops.push(op.clone());
-            }
+    /// 操作类型名称（如 `GCounter.Increment`）
+    pub fn op_type(&self) -> &'static str {
+        match self {
+            Operation::GCounterIncrement { .. } => "GCounter.Increment",
+            Operation::PNCounterIncrement { .. } => "PNCounter.Increment",
+            Operation::PNCounterDecrement { .. } => "PNCounter.Decrement",
+            Operation::BoundedCounterIncrement { .. } => "BoundedCounter.Increment",
+            Operation::BoundedCounterDecrement { .. } => "BoundedCounter.Decrement",
+            Operation::LwwRegisterSet { .. } => "LWWRegister.Set",
+            Operation::LwwMapSet { .. } => "LWWMap.Set",
+            Operation::LwwMapRemove { .. } => "LWWMap.Remove",
+            Operation::OrSetAdd { .. } => "ORSet.Add",
+            Operation::OrSetRemove { .. } => "ORSet.Remove",
+            Operation::GSetAdd { .. } => "GSet.Add",
+            Operation::ConvertType { .. } => "Convert",
+            Operation::MaxRegisterSet { .. } => "MaxRegister.Set",
+            Operation::MinRegisterSet { .. } => "MinRegister.Set",
+            Operation::MvRegisterSet { .. } => "MVRegister.Set",
+            Operation::MultiIncrement { .. } => "PNCounter.MultiIncrement",
+            Operation::RgaInsert { .. } => "RGA.Insert",
+            Operation::RgaDelete { .. } => "RGA.Delete",
         }
-        // 按时间戳排序
-        self.ops
-            .sort_by(|a, b| a.ts.cmp(&b.ts).then_with(|| a.id.cmp(&b.id)));
     }
-}
 
-/// 同步状态
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SyncState {
-    pub node_id: NodeId,
-    pub crdt_map: CRDTMap,
-    pub op_log: OpLog,
-}
+    /// 从 `Display` 输出中解析出操作类型
+    pub fn parse_op_type(display: &str) -> Option<&'static str> {
+        let op_type = display.split('(').next()?;
+        OP_TYPES.iter().copied().find(|t| *t == op_type)
+    }
 
-impl SyncState {
-    pub fn new(node_id: NodeId) -> Self {
-        Self {
-            node_id: node_id.clone(),
-            crdt_map: CRDTMap::new(),
-            op_log: OpLog::new(node_id),
+    /// 用于历史记录的操作描述
+    pub fn details(&self) -> String {
+        match self {
+            Operation::GCounterIncrement { node_id, delta, .. }
+            | Operation::PNCounterIncrement { node_id, delta, .. }
+            | Operation::BoundedCounterIncrement { node_id, delta, .. } => {
+                format!("节点 {} 增加 {}", node_id, delta)
+            }
+            Operation::PNCounterDecrement { node_id, delta, .. }
+            | Operation::BoundedCounterDecrement { node_id, delta, .. } => {
+                format!("节点 {} 减少 {}", node_id, delta)
+            }
+            Operation::LwwRegisterSet {
+                value,
+                timestamp,
+                node_id,
+                ..
+            } => format!("节点 {} 设置为 '{}' (ts: {})", node_id, value, timestamp),
+            Operation::LwwMapSet {
+                field,
+                value,
+                timestamp,
+                node_id,
+                ..
+            } => format!(
+                "节点 {} 将字段 {} 设置为 '{}' (ts: {})",
+                node_id, field, value, timestamp
+            ),
+            Operation::LwwMapRemove {
+                field,
+                timestamp,
+                node_id,
+                ..
+            } => format!("节点 {} 删除字段 {} (ts: {})", node_id, field, timestamp),
+            Operation::OrSetAdd { value, dot, .. } => {
+                format!("添加元素 '{}' (id: {})", value, dot)
+            }
+            Operation::OrSetRemove { value, .. } => format!("移除元素 '{}'", value),
+            Operation::GSetAdd { value, .. } => format!("添加元素 '{}'", value),
+            Operation::ConvertType {
+                from_type, to_type, ..
+            } => format!("类型转换 {} -> {}", from_type, to_type),
+            Operation::MaxRegisterSet { value, .. } | Operation::MinRegisterSet { value, .. } => {
+                format!("写入 {}", value)
+            }
+            Operation::MvRegisterSet { value, node_id, .. } => {
+                format!("节点 {} 写入 '{}'", node_id, value)
+            }
+            Operation::MultiIncrement { node_id, counters } => {
+                format!("节点 {} 增加 {} 个计数器", node_id, counters.len())
+            }
+            Operation::RgaInsert {
+                id, after, value, ..
+            } => match after {
+                Some(after) => format!("在 {} 之后插入 '{}' (id: {})", after, value, id),
+                None => format!("在开头插入 '{}' (id: {})", value, id),
+            },
+            Operation::RgaDelete { id, .. } => format!("删除元素 {}", id),
         }
     }
+}
 
-    /// 应用操作到 CRDT Map
-    pub fn apply_operation(&mut self, op: Operation) {
-        self.op_log
-            .add_operation(op.clone(), &mut self.crdt_map.vector_clock);
+/// 所有操作类型名称
+pub const OP_TYPES: &[&str] = &[
+    "GCounter.Increment",
+    "PNCounter.Increment",
+    "PNCounter.Decrement",
+    "BoundedCounter.Increment",
+    "BoundedCounter.Decrement",
+    "LWWRegister.Set",
+    "LWWMap.Set",
+    "LWWMap.Remove",
+    "ORSet.Add",
+    "ORSet.Remove",
+    "GSet.Add",
+    "Convert",
+    "MaxRegister.Set",
+    "MinRegister.Set",
+    "MVRegister.Set",
+    "PNCounter.MultiIncrement",
+    "RGA.Insert",
+    "RGA.Delete",
+];
 
-        match op {
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op_type = self.op_type();
+        match self {
             Operation::GCounterIncrement {
                 key,
                 node_id,
                 delta,
-            } => {
-                let counter = self
-                    .crdt_map
-                    .entries
-                    .entry(key)
-                    .or_insert_with(|| CRDTValue::GCounter(GCounter::new()));
-
-                if let CRDTValue::GCounter(c) = counter {
-                    c.increment(&node_id, delta);
-                }
             }
-            Operation::PNCounterIncrement {
+            | Operation::PNCounterIncrement {
                 key,
                 node_id,
                 delta,
-            } => {
-                let counter = self
-                    .crdt_map
-                    .entries
-                    .entry(key)
-                    .or_insert_with(|| CRDTValue::PNCounter(PNCounter::new()));
-
-                if let CRDTValue::PNCounter(c) = counter {
-                    c.increment(&node_id, delta);
-                }
             }
+            | Operation::BoundedCounterIncrement {
+                key,
+                node_id,
+                delta,
+                ..
+            } => write!(f, "{}({}, {}, +{})", op_type, key, node_id, delta),
             Operation::PNCounterDecrement {
                 key,
                 node_id,
                 delta,
-            } => {
-                let counter = self
-                    .crdt_map
-                    .entries
-                    .entry(key)
-                    .or_insert_with(|| CRDTValue::PNCounter(PNCounter::new()));
-
-                if let CRDTValue::PNCounter(c) = counter {
-                    c.decrement(&node_id, delta);
-                }
             }
+            | Operation::BoundedCounterDecrement {
+                key,
+                node_id,
+                delta,
+                ..
+            } => write!(f, "{}({}, {}, -{})", op_type, key, node_id, delta),
             Operation::LwwRegisterSet {
                 key,
                 value,
                 timestamp,
                 node_id,
-            } => {
-                let register = self
-                    .crdt_map
-                    .entries
-                    .entry(key)
-                    .or_insert_with(|| CRDTValue::LWWRegister(LWWRegister::new()));
-
-                if let CRDTValue::LWWRegister(r) = register {
-                    r.set(value, timestamp, &node_id);
-                }
-            }
-            Operation::OrSetAdd {
+            } => write!(
+                f,
+                "{}({}, {}, {:?}, ts={})",
+                op_type, key, node_id, value, timestamp
+            ),
+            Operation::LwwMapSet {
                 key,
+                field,
                 value,
-                unique_id,
-            } => {
-                let set = self
-                    .crdt_map
-                    .entries
-                    .entry(key)
-                    .or_insert_with(|| CRDTValue::ORSet(ORSet::new()));
-
-                if let CRDTValue::ORSet(s) = set {
-                    s.add(value, unique_id);
-                }
+                timestamp,
+                node_id,
+            } => write!(
+                f,
+                "{}({}.{}, {}, {:?}, ts={})",
+                op_type, key, field, node_id, value, timestamp
+            ),
+            Operation::LwwMapRemove {
+                key,
+                field,
+                timestamp,
+                node_id,
+            } => write!(
+                f,
+                "{}({}.{}, {}, ts={})",
+                op_type, key, field, node_id, timestamp
+            ),
+            Operation::OrSetAdd { key, value, dot } => {
+                write!(f, "{}({}, {:?}, id={})", op_type, key, value, dot)
             }
-            Operation::OrSetRemove { key, value } => {
-                if let Some(CRDTValue::ORSet(s)) = self.crdt_map.entries.get_mut(&key) {
-                    s.remove(&value);
-                }
+            Operation::OrSetRemove { key, value, .. } | Operation::GSetAdd { key, value } => {
+                write!(f, "{}({}, {:?})", op_type, key, value)
+            }
+            Operation::ConvertType {
+                key,
+                from_type,
+                to_type,
+            } => write!(f, "{}({}, {} -> {})", op_type, key, from_type, to_type),
+            Operation::MaxRegisterSet { key, value } | Operation::MinRegisterSet { key, value } => {
+                write!(f, "{}({}, {})", op_type, key, value)
+            }
+            Operation::MvRegisterSet {
+                key,
+                value,
+                node_id,
+                ..
+            } => write!(f, "{}({}, {}, {:?})", op_type, key, node_id, value),
+            Operation::MultiIncrement { node_id, counters } => {
+                let deltas: Vec<String> = counters
+                    .iter()
+                    .map(|(key, delta)| format!("{}+{}", key, delta))
+                    .collect();
+                write!(f, "{}({}, {})", op_type, node_id, deltas.join(", "))
             }
+            Operation::RgaInsert {
+                key,
+                id,
+                after,
+                value,
+            } => match after {
+                Some(after) => write!(
+                    f,
+                    "{}({}, {}, after={}, {:?})",
+                    op_type, key, id, after, value
+                ),
+                None => write!(f, "{}({}, {}, head, {:?})", op_type, key, id, value),
+            },
+            Operation::RgaDelete { key, id } => write!(f, "{}({}, {})", op_type, key, id),
         }
     }
+}
 
-    /// 合并来自另一个节点的状态
-    pub fn merge(&mut self, other: &SyncState) {
-        // 合并操作日志
-        self.op_log.merge(&other.op_log);
+/// 操作日志条目
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpLogEntry {
+    pub id: String,          // 使用 scru128 生成的唯一 ID
+    pub ts: i64,             // 时间戳
+    pub causal: VectorClock, // 因果元数据
+    pub op: Operation,       // 操作内容
+    /// 记录该操作的节点（旧版本日志中可能为空）
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub origin: NodeId,
+    /// 发起节点对该条目的签名（Base64，未签名的条目为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// 签名所用的公钥（Base64）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
 
-        // 合并 CRDT Map
-        self.crdt_map.merge(&other.crdt_map);
+impl OpLogEntry {
+    /// 参与签名的内容：操作类型、操作与因果时钟的 JSON
+    ///
+    /// 先转换为 `serde_json::Value`，对象键按字典序输出，不受 HashMap 迭代顺序影响。
+    fn signing_payload(&self) -> (String, String, String) {
+        let canonical = |value: serde_json::Result<serde_json::Value>| {
+            value.map(|value| value.to_string()).unwrap_or_default()
+        };
+        (
+            self.op.op_type().to_string(),
+            canonical(serde_json::to_value(&self.op)),
+            canonical(serde_json::to_value(&self.causal)),
+        )
     }
 
-    /// 获取状态哈希
-    pub fn state_hash(&self) -> String {
-        self.crdt_map.state_hash()
+    /// 以签名管理器的当前密钥签名该条目
+    pub fn sign(&mut self, signer: &SignatureManager) -> anyhow::Result<()> {
+        let (operation_type, operation_data, causal_context) = self.signing_payload();
+        let signed = signer.sign_operation(
+            self.id.clone(),
+            self.ts,
+            operation_type,
+            operation_data,
+            causal_context,
+        )?;
+        self.signature = Some(signed.signature);
+        self.public_key = Some(signed.public_key);
+        Ok(())
     }
 
-    /// 导出操作日志为 JSON
-    pub fn export_oplog(&self) -> Result<String, serde_json::Error> {
-        serde_json::to_string_pretty(&self.op_log)
+    /// 用于验证签名的签名操作，未签名的条目返回 None
+    pub fn signed_operation(&self) -> Option<SignedOperation> {
+        let (operation_type, operation_data, causal_context) = self.signing_payload();
+        Some(SignedOperation {
+            id: self.id.clone(),
+            timestamp: self.ts,
+            node_id: self.origin.clone(),
+            operation_type,
+            operation_data,
+            causal_context,
+            signature: self.signature.clone()?,
+            public_key: self.public_key.clone()?,
+        })
+    }
+
+    /// 记录该操作的节点：优先使用 `origin`，旧日志回退到操作携带的 node_id
+    pub fn origin_node(&self) -> Option<&str> {
+        if self.origin.is_empty() {
+            self.op.node_id()
+        } else {
+            Some(&self.origin)
+        }
+    }
+
+    /// 验证单个条目的签名（规则同 [`OpLog::verify_signatures`]）
+    pub fn verify_signature(
+        &self,
+        trust: &SignatureManager,
+        require_signatures: bool,
+    ) -> Result<(), String> {
+        match self.signed_operation() {
+            Some(op) => trust.verify_trusted(&op).map_err(|e| e.to_string()),
+            None if require_signatures => Err("Missing signature".to_string()),
+            None => Ok(()),
+        }
+    }
+
+    /// 以 ISO-8601 / RFC 3339（UTC）格式返回时间戳
+    pub fn ts_iso(&self) -> String {
+        format_ts_iso(self.ts)
     }
 }
 
-/// 同步请求
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SyncRequest {
-    pub from_node: NodeId,
-    pub state: SyncState,
+/// 将毫秒时间戳格式化为 RFC 3339 UTC 字符串（毫秒精度）
+pub fn format_ts_iso(ts: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(ts)
+        .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+        .unwrap_or_default()
 }
 
-/// 同步响应
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SyncResponse {
-    pub success: bool,
-    pub state_hash: String,
-    pub message: String,
+/// 操作日志过滤条件
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OpLogFilter {
+    /// 目标键
+    pub key: Option<String>,
+    /// 发起节点（匹配条目的 `origin_node`，对不携带 node_id 的操作类型同样有效）
+    pub node: Option<String>,
+    /// 起始时间戳（含，毫秒）
+    pub since_ts: Option<i64>,
+    /// 截止时间戳（含，毫秒）
+    pub until_ts: Option<i64>,
+    /// 操作类型（如 `GCounter.Increment`）
+    pub op_type: Option<String>,
 }
 
-/// 变更请求（用于 HTTP API）
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ChangeRequest {
-    pub changes: Vec<Change>,
+impl OpLogFilter {
+    pub fn matches(&self, entry: &OpLogEntry) -> bool {
+        self.key.as_deref().is_none_or(|key| entry.op.touches(key))
+            && self
+                .op_type
+                .as_deref()
+                .is_none_or(|op_type| entry.op.op_type() == op_type)
+            && self
+                .node
+                .as_deref()
+                .is_none_or(|node| entry.origin_node() == Some(node))
+            && self.since_ts.is_none_or(|since| entry.ts >= since)
+            && self.until_ts.is_none_or(|until| entry.ts <= until)
+    }
 }
 
-/// 单个变更
+/// 操作日志默认分页大小
+pub const DEFAULT_OPLOG_PAGE_LIMIT: usize = 100;
+
+/// 操作日志分页大小上限
+pub const MAX_OPLOG_PAGE_LIMIT: usize = 1000;
+
+/// 操作日志分页参数
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OpLogPage {
+    /// 每页条数（默认 `DEFAULT_OPLOG_PAGE_LIMIT`，超过 `MAX_OPLOG_PAGE_LIMIT` 时截断）
+    pub limit: Option<usize>,
+    /// 游标：只返回日志中位于该条目之后的条目
+    pub after_id: Option<String>,
+}
+
+/// 某个节点在因果前沿上的最新操作
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FrontierEntry {
+    pub id: String,
+    /// 该节点的操作序号（其向量时钟分量）
+    pub seq: u64,
+}
+
+/// 操作日志
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Change {
-    pub op: String, // "add", "remove", "increment", "decrement", "set"
-    pub key: String,
-    pub value: Option<String>,
-    pub delta: Option<u64>,
+pub struct OpLog {
+    pub node_id: NodeId,
+    pub ops: Vec<OpLogEntry>,
+    /// 为新记录的操作签名（本地配置，不随状态同步）
+    #[serde(skip)]
+    pub signer: Option<Arc<SignatureManager>>,
+    /// 压缩前沿：因果上被其覆盖的操作已折叠进 CRDT Map 并从日志中截断
+    #[serde(default, skip_serializing_if = "VectorClock::is_empty")]
+    pub compacted: VectorClock,
 }
 
-impl SyncState {
-    /// 从变更请求应用操作
-    pub fn apply_changes(&mut self, request: ChangeRequest) -> Result<(), String> {
-        for change in request.changes {
-            match change.op.as_str() {
-                "add" => {
-                    let value = change.value.ok_or("Missing value for add operation")?;
-                    let unique_id = scru128::new_string();
-                    let op = Operation::OrSetAdd {
-                        key: change.key,
-                        value,
-                        unique_id,
-                    };
-                    self.apply_operation(op);
-                }
-                "remove" => {
-                    let value = change.value.ok_or("Missing value for remove operation")?;
-                    let op = Operation::OrSetRemove {
-                        key: change.key,
-                        value,
-                    };
-                    self.apply_operation(op);
-                }
-                "increment" => {
-                    let delta = change.delta.unwrap_or(1);
-                    let op = Operation::PNCounterIncrement {
-                        key: change.key,
-                        node_id: self.node_id.clone(),
-                        delta,
-                    };
-                    self.apply_operation(op);
-                }
-                "decrement" => {
-                    let delta = change.delta.unwrap_or(1);
-                    let op = Operation::PNCounterDecrement {
-                        key: change.key,
-                        node_id: self.node_id.clone(),
-                        delta,
-                    };
-                    self.apply_operation(op);
+impl OpLog {
+    pub fn new(node_id: NodeId) -> Self {
+        Self {
+            node_id,
+            ops: Vec::new(),
+            signer: None,
+            compacted: VectorClock::new(),
+        }
+    }
+
+    pub fn add_operation(&mut self, op: Operation, vector_clock: &mut VectorClock) {
+        self.add_operation_at(op, vector_clock, SystemClock.now_millis());
+    }
+
+    /// 以指定时间戳记录操作
+    pub fn add_operation_at(&mut self, op: Operation, vector_clock: &mut VectorClock, ts: i64) {
+        let id = scru128::new_string();
+
+        vector_clock.increment(&self.node_id);
+
+        let mut entry = OpLogEntry {
+            id,
+            ts,
+            causal: vector_clock.clone(),
+            op,
+            origin: self.node_id.clone(),
+            signature: None,
+            public_key: None,
+        };
+        if let Some(signer) = &self.signer
+            && let Err(e) = entry.sign(signer)
+        {
+            tracing::warn!("Failed to sign op {}: {}", entry.id, e);
+        }
+
+        self.ops.push(entry);
+    }
+
+    /// 验证各条目的签名，返回验证失败的 (操作 ID, 原因)，全部通过时为空
+    ///
+    /// 签名必须由 `origin` 节点受信任的公钥签发（见 [`SignatureManager::verify_trusted_batch`]），
+    /// 公钥未知或与登记的不符都视为失败。未签名的条目仅在 `require_signatures` 为真时视为失败。
+    pub fn verify_signatures(
+        &self,
+        trust: &SignatureManager,
+        require_signatures: bool,
+    ) -> Vec<(String, String)> {
+        let mut invalid = Vec::new();
+        let mut signed = Vec::new();
+        let mut signed_ids = Vec::new();
+        for entry in &self.ops {
+            match entry.signed_operation() {
+                Some(op) => {
+                    signed.push(op);
+                    signed_ids.push(entry.id.as_str());
                 }
-                "set" => {
-                    let value = change.value.ok_or("Missing value for set operation")?;
-                    let timestamp = chrono::Local::now()
-                        .naive_local()
-                        .and_utc()
-                        .timestamp_millis();
-                    let op = Operation::LwwRegisterSet {
-                        key: change.key,
-                        value,
-                        timestamp,
-                        node_id: self.node_id.clone(),
-                    };
-                    self.apply_operation(op);
+                None if require_signatures => {
+                    invalid.push((entry.id.clone(), "Missing signature".to_string()))
+                }
+                None => {}
+            }
+        }
+        invalid.extend(
+            trust
+                .verify_trusted_batch(&signed)
+                .into_iter()
+                .map(|(index, reason)| (signed_ids[index].to_string(), reason)),
+        );
+        invalid
+    }
+
+    /// 返回仅包含匹配条目的操作日志副本
+    pub fn filtered(&self, filter: &OpLogFilter) -> OpLog {
+        OpLog {
+            node_id: self.node_id.clone(),
+            signer: None,
+            compacted: self.compacted.clone(),
+            ops: self
+                .ops
+                .iter()
+                .filter(|entry| filter.matches(entry))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// 按日志顺序返回游标之后匹配过滤条件的一页条目，以及下一页的游标（已是最后一页时为 `None`）
+    ///
+    /// 游标不在日志中（如已被压缩）时返回错误。
+    pub fn page(
+        &self,
+        filter: &OpLogFilter,
+        page: &OpLogPage,
+    ) -> Result<(OpLog, Option<String>), String> {
+        let start = match &page.after_id {
+            Some(after_id) => {
+                self.ops
+                    .iter()
+                    .position(|entry| &entry.id == after_id)
+                    .ok_or_else(|| format!("Unknown after_id: {}", after_id))?
+                    + 1
+            }
+            None => 0,
+        };
+        let limit = page
+            .limit
+            .unwrap_or(DEFAULT_OPLOG_PAGE_LIMIT)
+            .clamp(1, MAX_OPLOG_PAGE_LIMIT);
+
+        let mut matching = self.ops[start..]
+            .iter()
+            .filter(|entry| filter.matches(entry));
+        let ops: Vec<OpLogEntry> = matching.by_ref().take(limit).cloned().collect();
+        let next_after_id = match ops.last() {
+            Some(last) if matching.next().is_some() => Some(last.id.clone()),
+            _ => None,
+        };
+        Ok((
+            OpLog {
+                node_id: self.node_id.clone(),
+                signer: None,
+                compacted: self.compacted.clone(),
+                ops,
+            },
+            next_after_id,
+        ))
+    }
+
+    pub fn merge(&mut self, other: &OpLog) {
+        let missing: Vec<OpLogEntry> = self.missing_ops(other).into_iter().cloned().collect();
+        self.ops.extend(missing);
+        self.sort();
+    }
+
+    /// 对方日志中本地尚未包含的操作（按操作 ID 去重，保持对方日志顺序）
+    ///
+    /// 被本地压缩前沿覆盖的操作已折叠进 CRDT Map，不视为缺失。
+    pub fn missing_ops<'a>(&self, other: &'a OpLog) -> Vec<&'a OpLogEntry> {
+        let known: HashSet<&str> = self.ops.iter().map(|e| e.id.as_str()).collect();
+        let mut seen = HashSet::new();
+        other
+            .ops
+            .iter()
+            .filter(|op| !known.contains(op.id.as_str()) && seen.insert(op.id.as_str()))
+            .filter(|op| self.compacted.is_empty() || !op.causal.covered_by(&self.compacted))
+            .collect()
+    }
+
+    /// 因果前沿：每个节点本地已知的最新操作（按节点 ID 排序）
+    ///
+    /// 序号取该操作向量时钟中发起节点的分量，对端比较前沿即可按节点请求缺失的后缀。
+    /// 无法确定发起节点的旧日志条目不计入前沿。
+    pub fn frontier(&self) -> BTreeMap<NodeId, FrontierEntry> {
+        let mut frontier: BTreeMap<NodeId, FrontierEntry> = BTreeMap::new();
+        for entry in &self.ops {
+            let Some(node) = entry.origin_node() else {
+                continue;
+            };
+            let seq = entry.causal.get(node);
+            if frontier.get(node).is_none_or(|head| seq > head.seq) {
+                frontier.insert(
+                    node.to_string(),
+                    FrontierEntry {
+                        id: entry.id.clone(),
+                        seq,
+                    },
+                );
+            }
+        }
+        frontier
+    }
+
+    /// 按时间戳（相同时按 ID）排序
+    pub fn sort(&mut self) {
+        self.ops
+            .sort_by(|a, b| a.ts.cmp(&b.ts).then_with(|| a.id.cmp(&b.id)));
+    }
+}
+
+/// 合并时因未通过校验而被隔离的传入操作
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedOp {
+    pub reason: String,
+    pub entry: OpLogEntry,
+}
+
+/// 最近一次合并时观察到的对等节点向量时钟
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerClock {
+    pub clock: VectorClock,
+    pub last_sync: i64, // 最近一次成功合并的时间（毫秒时间戳）
+}
+
+/// 对等节点相对本地的复制状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReplicationStatus {
+    Ahead,
+    Behind,
+    Concurrent,
+    InSync,
+}
+
+/// 单个对等节点的复制情况
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerReplication {
+    pub peer: NodeId,
+    pub status: ReplicationStatus,
+    pub last_sync: i64,
+    pub last_sync_iso: String,
+}
+
+/// `/stats` 默认列出的最大键数量
+pub const DEFAULT_STATS_TOP_KEYS: usize = 10;
+
+/// 集合元素数达到上限的该比例（百分比）时视为接近上限
+pub const SET_CAPACITY_WARN_PERCENT: usize = 90;
+
+/// 接近元素上限的集合
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SetCapacity {
+    pub key: String,
+    pub elements: usize,
+    pub cap: usize,
+}
+
+/// 单个键的序列化大小
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeySize {
+    pub key: String,
+    pub bytes: usize,
+}
+
+/// CRDT 组成统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StateStats {
+    /// 键总数
+    pub total_entries: usize,
+    /// 各 CRDT 类型的键数量
+    pub type_counts: BTreeMap<String, usize>,
+    /// 所有集合中的元素总数
+    pub set_elements: usize,
+    /// 所有 OR-Set 中的墓碑总数
+    pub set_tombstones: usize,
+    /// 操作日志条目数
+    pub oplog_len: usize,
+    /// 按序列化大小降序排列的最大键
+    pub largest_keys: Vec<KeySize>,
+    /// 近似内存占用（键值与操作日志的 JSON 序列化字节数之和）
+    pub approx_bytes: usize,
+    /// 元素数接近上限的集合（仅在配置 `max_set_elements` 时统计）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub near_capacity_sets: Vec<SetCapacity>,
+}
+
+/// OR-Set 上对同一元素的并发添加与移除（按 add-wins 规则，添加胜出）
+#[derive(Debug, Clone)]
+pub struct SetRace<'a> {
+    pub key: &'a str,
+    pub value: &'a str,
+    pub adds: Vec<&'a OpLogEntry>,
+    pub removes: Vec<&'a OpLogEntry>,
+}
+
+impl SetRace<'_> {
+    pub const CONFLICT_TYPE: &'static str = "ORSet add/remove race";
+
+    pub fn resolution(&self) -> String {
+        format!(
+            "OR-Set 采用 add-wins 规则：并发移除未观察到的添加保留，元素 '{}' 仍在集合中",
+            self.value
+        )
+    }
+
+    /// 参与竞争的条目（先添加后移除）
+    pub fn entries(&self) -> impl Iterator<Item = &OpLogEntry> {
+        self.adds.iter().chain(&self.removes).copied()
+    }
+}
+
+/// 快照回滚结果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RestoreReport {
+    /// 回滚前状态的哈希
+    pub discarded_state_hash: String,
+    /// 被丢弃的操作日志条目数（保留操作日志时为 0）
+    pub dropped_ops: usize,
+}
+
+/// 状态包的格式版本，结构发生不兼容变化时递增
+pub const STATE_BUNDLE_VERSION: u32 = 1;
+
+/// 状态包的编码格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BundleFormat {
+    #[default]
+    Json,
+    Cbor,
+}
+
+impl BundleFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            BundleFormat::Json => "application/json",
+            BundleFormat::Cbor => "application/cbor",
+        }
+    }
+}
+
+impl std::str::FromStr for BundleFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(BundleFormat::Json),
+            "cbor" => Ok(BundleFormat::Cbor),
+            other => Err(format!(
+                "Unknown bundle format: {} (expected json or cbor)",
+                other
+            )),
+        }
+    }
+}
+
+/// 可移植的完整状态包，用于备份、迁移或初始化新节点
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateBundle {
+    pub format_version: u32,
+    /// 导出节点
+    pub node_id: NodeId,
+    /// 导出时间（毫秒）
+    pub created_at: i64,
+    /// 导出时的状态哈希，导入时用于校验完整性
+    pub state_hash: String,
+    pub state: SyncState,
+}
+
+/// 只读取格式版本，在解析完整状态之前拒绝不兼容的包
+#[derive(Deserialize)]
+struct BundleHeader {
+    format_version: u32,
+}
+
+impl StateBundle {
+    pub fn new(state: &SyncState) -> Self {
+        Self {
+            format_version: STATE_BUNDLE_VERSION,
+            node_id: state.node_id.clone(),
+            created_at: SystemClock.now_millis(),
+            state_hash: state.state_hash(),
+            state: state.clone(),
+        }
+    }
+
+    pub fn encode(&self, format: BundleFormat) -> Result<Vec<u8>, String> {
+        match format {
+            BundleFormat::Json => serde_json::to_vec(self).map_err(|e| e.to_string()),
+            BundleFormat::Cbor => serde_cbor::to_vec(self).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// 解码并校验格式版本与状态哈希
+    pub fn decode(bytes: &[u8], format: BundleFormat) -> Result<Self, String> {
+        fn parse<T: serde::de::DeserializeOwned>(
+            bytes: &[u8],
+            format: BundleFormat,
+        ) -> Result<T, String> {
+            match format {
+                BundleFormat::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+                BundleFormat::Cbor => serde_cbor::from_slice(bytes).map_err(|e| e.to_string()),
+            }
+        }
+
+        let header: BundleHeader =
+            parse(bytes, format).map_err(|e| format!("Invalid state bundle: {}", e))?;
+        if header.format_version != STATE_BUNDLE_VERSION {
+            return Err(format!(
+                "Incompatible state bundle format version {} (supported: {})",
+                header.format_version, STATE_BUNDLE_VERSION
+            ));
+        }
+        let bundle: StateBundle =
+            parse(bytes, format).map_err(|e| format!("Invalid state bundle: {}", e))?;
+        let actual = bundle.state.state_hash();
+        if actual != bundle.state_hash {
+            return Err(format!(
+                "State bundle is corrupted: state hash {} does not match recorded {}",
+                actual, bundle.state_hash
+            ));
+        }
+        Ok(bundle)
+    }
+}
+
+/// 用于复现问题的自包含重放包
+///
+/// 操作日志条目自带 ID、时间戳与元素标识，重放不依赖 ID 生成器或时钟，
+/// 在任意全新节点上都得到相同的结果。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReproBundle {
+    pub node_id: NodeId,
+    /// 导出时间（毫秒）
+    pub created_at: i64,
+    /// 导出时节点的状态哈希，重放结果应与之一致
+    pub expected_state_hash: String,
+    /// 重放起点
+    pub initial_state: SyncState,
+    /// 起点之后按日志顺序排列的操作
+    pub ops: Vec<OpLogEntry>,
+}
+
+impl ReproBundle {
+    /// 在起点状态上按顺序重放操作，返回重放后的状态
+    pub fn replay(&self) -> SyncState {
+        let mut state = self.initial_state.clone();
+        state.node_id = self.node_id.clone();
+        state.op_log.node_id = self.node_id.clone();
+        for entry in &self.ops {
+            state.apply_remote_entry(entry.clone());
+        }
+        state
+    }
+}
+
+/// 操作时间戳来源
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// 当前时间（毫秒时间戳）
+    fn now_millis(&self) -> i64;
+}
+
+/// 系统时钟（默认）
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> i64 {
+        chrono::Local::now()
+            .naive_local()
+            .and_utc()
+            .timestamp_millis()
+    }
+}
+
+/// 手动控制的时钟，用于测试中固定时间戳
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicI64,
+}
+
+impl ManualClock {
+    pub fn new(now: i64) -> Self {
+        Self {
+            now: AtomicI64::new(now),
+        }
+    }
+
+    pub fn set(&self, now: i64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, millis: i64) {
+        self.now.fetch_add(millis, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> i64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+fn default_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+/// 同步状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncState {
+    pub node_id: NodeId,
+    pub crdt_map: CRDTMap,
+    pub op_log: OpLog,
+    /// 各键累计的并发写入冲突次数（仅本地统计，不参与 state_hash）
+    #[serde(default)]
+    pub conflict_stats: HashMap<String, u64>,
+    /// 合并时被隔离的操作（仅本地记录，不参与 state_hash）
+    #[serde(default)]
+    pub quarantine: Vec<QuarantinedOp>,
+    /// 合并时 CRDT 类型不一致而保留本地值的键（仅本地记录，不参与 state_hash）
+    #[serde(default)]
+    pub type_conflicts: Vec<MergeConflict>,
+    /// 已永久离开集群的节点，来自这些节点的操作将被拒绝
+    #[serde(default)]
+    pub forgotten_nodes: HashSet<NodeId>,
+    /// 各对等节点最近一次合并时的向量时钟（仅本地记录，不参与 state_hash）
+    #[serde(default)]
+    pub peer_clocks: HashMap<NodeId, PeerClock>,
+    /// 按键设置的冲突解决策略（随状态同步）
+    #[serde(default)]
+    pub policies: PolicyRegistry,
+    /// 键取值约束（单独持久化，不随状态同步）
+    #[serde(skip)]
+    pub schemas: SchemaRegistry,
+    /// 计数器单节点贡献上限（集群统一配置，不随状态同步）
+    #[serde(skip)]
+    pub counter_cap: Option<u64>,
+    /// 不同键的数量上限（集群统一配置，不随状态同步）
+    #[serde(skip)]
+    pub max_keys: Option<usize>,
+    /// 单个集合的元素数量上限（集群统一配置，不随状态同步）
+    #[serde(skip)]
+    pub max_set_elements: Option<usize>,
+    /// 对 GCounter 键执行 decrement 时是否自动转换为 PNCounter（本地配置，不随状态同步）
+    #[serde(skip)]
+    pub auto_promote_counters: bool,
+    /// 敏感字段加密器（本地配置，不随状态同步）
+    #[serde(skip)]
+    pub encryptor: Option<Arc<FieldEncryptor>>,
+    /// 操作时间戳来源（默认系统时钟，测试中可替换）
+    #[serde(skip, default = "default_clock")]
+    pub clock: Arc<dyn Clock>,
+    /// 进程内事件回调（本地注册，不随状态同步）
+    #[serde(skip)]
+    pub hooks: EventHooks,
+    /// 仅通过 `memory` 级别写入的临时键，持久化时被排除（本地记录，重启后连同数据一起丢失）
+    #[serde(skip)]
+    pub ephemeral_keys: HashSet<String>,
+    /// 因缺少因果前驱而暂缓应用的传入操作（仅本地记录，不参与 state_hash）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pending: Vec<OpLogEntry>,
+    /// `delta_since` 导出增量时使用的基线时钟（全量状态为 None，不参与 state_hash）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta_base: Option<VectorClock>,
+}
+
+/// 集合元素数是否达到上限的 `SET_CAPACITY_WARN_PERCENT`
+fn set_near_capacity(elements: usize, cap: usize) -> bool {
+    elements * 100 >= cap * SET_CAPACITY_WARN_PERCENT
+}
+
+impl SyncState {
+    pub fn new(node_id: NodeId) -> Self {
+        Self {
+            node_id: node_id.clone(),
+            crdt_map: CRDTMap::new(),
+            op_log: OpLog::new(node_id),
+            conflict_stats: HashMap::new(),
+            quarantine: Vec::new(),
+            type_conflicts: Vec::new(),
+            forgotten_nodes: HashSet::new(),
+            peer_clocks: HashMap::new(),
+            policies: PolicyRegistry::new(),
+            schemas: SchemaRegistry::new(),
+            counter_cap: None,
+            max_keys: None,
+            max_set_elements: None,
+            auto_promote_counters: false,
+            encryptor: None,
+            clock: default_clock(),
+            hooks: EventHooks::new(),
+            ephemeral_keys: HashSet::new(),
+            pending: Vec::new(),
+            delta_base: None,
+        }
+    }
+
+    /// 替换操作时间戳来源
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// 应用操作到 CRDT Map
+    pub fn apply_operation(&mut self, op: Operation) {
+        let ts = self.clock.now_millis();
+        self.op_log
+            .add_operation_at(op.clone(), &mut self.crdt_map.vector_clock, ts);
+        apply_to_map(&mut self.crdt_map, op);
+
+        if let Some(entry) = self.op_log.ops.last() {
+            self.hooks.emit_operation(entry);
+        }
+    }
+
+    /// 应用来自其他节点的操作日志条目（用于归档导入），返回是否实际应用
+    ///
+    /// 调用方需保证该条目尚未应用过（计数器操作不是幂等的）；来自已遗忘节点的条目会被跳过。
+    /// 条目追加到日志末尾，批量导入结束后应调用 `OpLog::sort` 恢复时间顺序。
+    pub fn apply_remote_entry(&mut self, entry: OpLogEntry) -> bool {
+        if entry
+            .op
+            .node_id()
+            .is_some_and(|node| self.forgotten_nodes.contains(node))
+        {
+            return false;
+        }
+
+        self.crdt_map.vector_clock.merge(&entry.causal);
+        apply_to_map(&mut self.crdt_map, entry.op.clone());
+        self.op_log.ops.push(entry);
+        true
+    }
+
+    /// 按操作日志顺序重放满足条件的操作，得到对应的 CRDT Map
+    ///
+    /// 重放仅依赖操作日志：遗忘节点并入基线的计数不会出现在结果中。
+    pub fn replay(&self, include: impl Fn(&OpLogEntry) -> bool) -> (CRDTMap, usize) {
+        let mut crdt_map = CRDTMap::new();
+        let mut applied = 0;
+        for entry in self.op_log.ops.iter().filter(|entry| include(entry)) {
+            crdt_map.vector_clock.merge(&entry.causal);
+            apply_to_map(&mut crdt_map, entry.op.clone());
+            applied += 1;
+        }
+        (crdt_map, applied)
+    }
+
+    /// 导出用于复现问题的重放包
+    ///
+    /// `initial` 为重放起点（通常是最早的已保存快照），缺省时从空状态开始；
+    /// 包中只包含起点之后新增的操作日志条目。
+    pub fn repro_bundle(&self, initial: Option<SyncState>) -> ReproBundle {
+        let initial_state = initial.unwrap_or_else(|| SyncState::new(self.node_id.clone()));
+        let known: HashSet<&str> = initial_state
+            .op_log
+            .ops
+            .iter()
+            .map(|entry| entry.id.as_str())
+            .collect();
+        let ops = self
+            .op_log
+            .ops
+            .iter()
+            .filter(|entry| !known.contains(entry.id.as_str()))
+            .cloned()
+            .collect();
+
+        ReproBundle {
+            node_id: self.node_id.clone(),
+            created_at: self.clock.now_millis(),
+            expected_state_hash: self.state_hash(),
+            initial_state,
+            ops,
+        }
+    }
+
+    /// 由操作日志重建 CRDT Map，返回重放的操作数
+    ///
+    /// 用于内存状态可疑时的恢复。向量时钟取重建结果与当前时钟的合并，避免本地序号回退；
+    /// 与 `replay` 相同，未记录在操作日志中的内容（如遗忘节点基线）不会出现在重建结果中。
+    pub fn rebuild_from_oplog(&mut self) -> usize {
+        let (mut crdt_map, applied) = self.replay(|_| true);
+        crdt_map.vector_clock.merge(&self.crdt_map.vector_clock);
+        self.crdt_map = crdt_map;
+        applied
+    }
+
+    /// 重放时间戳不晚于 `ts`（毫秒）的操作，返回当时的状态与应用的操作数
+    ///
+    /// 基于各节点本地时间戳而非因果关系：节点间存在时钟偏差时，结果只是近似的历史视图。
+    pub fn state_as_of(&self, ts: i64) -> (CRDTMap, usize) {
+        self.replay(|entry| entry.ts <= ts)
+    }
+
+    /// 将已有键转换为兼容的 CRDT 类型，并记录转换操作以便副本收敛
+    ///
+    /// 仅支持无损转换（GCounter → PNCounter），其余转换会被拒绝。
+    pub fn convert_key(&mut self, key: &str, to_type: &str) -> Result<(), String> {
+        let value = self
+            .crdt_map
+            .get(key)
+            .ok_or_else(|| format!("Key not found: {}", key))?;
+        let from_type = value.type_name();
+        if value.converted(to_type).is_none() {
+            return Err(format!(
+                "Unsupported conversion for key '{}': {} -> {}",
+                key, from_type, to_type
+            ));
+        }
+
+        self.apply_operation(Operation::ConvertType {
+            key: key.to_string(),
+            from_type: from_type.to_string(),
+            to_type: to_type.to_string(),
+        });
+        Ok(())
+    }
+
+    /// 合并来自另一个节点的状态
+    ///
+    /// 传入的是基于本地尚未包含的基线导出的增量时（增量之间乱序送达），不合并其 CRDT 值与向量时钟，
+    /// 而是将其中的操作暂缓（见 `pending_ops`），待因果前驱到达后按因果顺序逐条应用。
+    pub fn merge(&mut self, other: &SyncState) {
+        // 接受对端已遗忘的节点
+        for node_id in &other.forgotten_nodes {
+            if !self.forgotten_nodes.contains(node_id)
+                && let Err(e) = self.forget_node(node_id)
+            {
+                tracing::warn!("Ignored forgotten node from {}: {}", other.node_id, e);
+            }
+        }
+        let deliverable = self.causally_deliverable(other);
+
+        // 隔离不满足取值约束或来自已遗忘节点的传入数据
+        let sanitized;
+        let other = if self.schemas.is_empty()
+            && self.forgotten_nodes.is_empty()
+            && self.counter_cap.is_none()
+        {
+            other
+        } else {
+            sanitized = self.sanitize_incoming(other);
+            &sanitized
+        };
+
+        if !deliverable {
+            let buffered = self.buffer_ops(&other.op_log);
+            let applied = self.deliver_pending();
+            tracing::info!(
+                "Buffered {} op(s) from {} awaiting causal predecessors, applied {}",
+                buffered,
+                other.node_id,
+                applied
+            );
+            return;
+        }
+
+        // 记录对端时钟，用于复制状态观测
+        if other.node_id != self.node_id {
+            self.peer_clocks.insert(
+                other.node_id.clone(),
+                PeerClock {
+                    clock: other.crdt_map.vector_clock.clone(),
+                    last_sync: self.clock.now_millis(),
+                },
+            );
+        }
+
+        let ops_before = self.op_log.ops.len();
+        let conflicts_before: u64 = self.conflict_stats.values().sum();
+        let known_ids: Option<HashSet<String>> = (!self.hooks.is_empty())
+            .then(|| self.op_log.ops.iter().map(|e| e.id.clone()).collect());
+
+        // 统计并发写入冲突（需在合并操作日志之前进行）
+        self.record_conflicts(&other.op_log);
+
+        // 合并操作日志
+        self.op_log.merge(&other.op_log);
+
+        // 合并 CRDT Map 与冲突解决策略
+        for conflict in self.crdt_map.merge_checked(&other.crdt_map) {
+            tracing::warn!(
+                "Type mismatch merging key {} from {}: local {}, remote {}",
+                conflict.key,
+                other.node_id,
+                conflict.local_type,
+                conflict.remote_type
+            );
+            self.type_conflicts.retain(|c| c.key != conflict.key);
+            self.type_conflicts.push(conflict);
+        }
+        self.policies.merge(&other.policies);
+
+        if let Some(max_keys) = self.max_keys {
+            self.enforce_key_cap(max_keys);
+        }
+        if let Some(max_set_elements) = self.max_set_elements {
+            self.enforce_set_cap(max_set_elements);
+        }
+
+        // 合并后的时钟可能覆盖或满足暂缓操作的前驱
+        self.deliver_pending();
+
+        if let Some(known_ids) = known_ids {
+            for entry in self
+                .op_log
+                .ops
+                .iter()
+                .filter(|entry| !known_ids.contains(&entry.id))
+            {
+                self.hooks.emit_operation(entry);
+            }
+            self.hooks.emit_merge(&MergeStats {
+                from_node: other.node_id.clone(),
+                new_ops: self.op_log.ops.len().saturating_sub(ops_before),
+                conflicts: self.conflict_stats.values().sum::<u64>() - conflicts_before,
+            });
+        }
+    }
+
+    /// 合并来自另一个节点的状态，返回值发生变化的键（按键名排序）
+    ///
+    /// 通过比较合并前后传入键的序列化结果判断变化，仅在需要通知订阅方时使用。
+    pub fn merge_tracking_changes(&mut self, other: &SyncState) -> Vec<String> {
+        let serialized = |map: &CRDTMap, key: &str| {
+            map.get(key)
+                .and_then(|value| serde_json::to_vec(value).ok())
+        };
+        let before: Vec<(String, Option<Vec<u8>>)> = other
+            .crdt_map
+            .entries
+            .keys()
+            .map(|key| (key.clone(), serialized(&self.crdt_map, key)))
+            .collect();
+
+        self.merge(other);
+
+        let mut changed: Vec<String> = before
+            .into_iter()
+            .filter(|(key, value)| serialized(&self.crdt_map, key) != *value)
+            .map(|(key, _)| key)
+            .collect();
+        changed.sort();
+        changed
+    }
+
+    /// 导出对端尚未观察到的增量状态
+    ///
+    /// 只包含因果上不早于 `clock` 的操作日志条目，以及这些操作涉及的键的当前值
+    /// （未记录在操作日志中的键也会包含，因为无法判断对端是否已有）；
+    /// 向量时钟、冲突解决策略与已遗忘节点随增量完整传输。
+    pub fn delta_since(&self, clock: &VectorClock) -> SyncState {
+        self.delta_since_with_sets(clock, &HashMap::new())
+    }
+
+    /// 同 `delta_since`，对 `set_contexts` 中给出对端因果上下文的 OR-Set 只导出对端缺失的点
+    pub fn delta_since_with_sets(
+        &self,
+        clock: &VectorClock,
+        set_contexts: &HashMap<String, VectorClock>,
+    ) -> SyncState {
+        let mut delta = SyncState::new(self.node_id.clone());
+        delta.op_log.ops = self
+            .op_log
+            .ops
+            .iter()
+            .filter(|entry| {
+                !matches!(
+                    entry.causal.partial_cmp_causal(clock),
+                    Some(std::cmp::Ordering::Less | std::cmp::Ordering::Equal)
+                )
+            })
+            .cloned()
+            .collect();
+
+        let logged: HashSet<&str> = self.op_log.ops.iter().flat_map(|e| e.op.keys()).collect();
+        let changed: HashSet<&str> = delta.op_log.ops.iter().flat_map(|e| e.op.keys()).collect();
+        delta.crdt_map.entries = self
+            .crdt_map
+            .entries
+            .iter()
+            .filter(|(key, _)| changed.contains(key.as_str()) || !logged.contains(key.as_str()))
+            .map(|(key, value)| match (value, set_contexts.get(key)) {
+                (CRDTValue::ORSet(set), Some(context)) => {
+                    (key.clone(), CRDTValue::ORSet(set.delta_since(context)))
                 }
-                _ => return Err(format!("Unknown operation: {}", change.op)),
+                _ => (key.clone(), value.clone()),
+            })
+            .collect();
+        delta.crdt_map.vector_clock = self.crdt_map.vector_clock.clone();
+        delta.policies = self.policies.clone();
+        delta.forgotten_nodes = self.forgotten_nodes.clone();
+        delta.delta_base = Some(clock.clone());
+        delta
+    }
+
+    /// 合并 `delta_since` 导出的增量状态
+    ///
+    /// 增量中每个键携带完整的当前值，因此与全量合并使用相同的合并规则（含隔离与上限检查）；
+    /// 本地尚未包含导出增量时所用的基线时钟对应的状态时，增量中的操作暂缓应用（见 `merge`）。
+    pub fn merge_delta(&mut self, delta: &SyncState) {
+        self.merge(delta);
+    }
+
+    /// 逐条合并对端的操作日志并按因果顺序应用，返回本次实际应用的操作数
+    ///
+    /// 条目的因果时钟中存在本地时钟尚未观察到的前驱时暂缓应用（见 `pending_ops`），
+    /// 待前驱到达后再依次应用；已被本地时钟覆盖或已在日志中的条目会被跳过。
+    pub fn merge_ops(&mut self, incoming: &OpLog) -> usize {
+        self.buffer_ops(incoming);
+        self.deliver_pending()
+    }
+
+    /// 将本地尚未包含且未被本地时钟覆盖的传入操作加入暂缓队列，返回新加入的数量
+    fn buffer_ops(&mut self, incoming: &OpLog) -> usize {
+        let mut known: HashSet<String> = self
+            .op_log
+            .ops
+            .iter()
+            .chain(&self.pending)
+            .map(|entry| entry.id.clone())
+            .collect();
+        let before = self.pending.len();
+        for entry in &incoming.ops {
+            if !causally_covered(entry, &self.crdt_map.vector_clock)
+                && known.insert(entry.id.clone())
+            {
+                self.pending.push(entry.clone());
             }
         }
-        Ok(())
+        self.pending.len() - before
+    }
+
+    /// 本地是否已包含传入增量的基线时钟对应的状态（全量状态没有基线，总是可以合并）
+    ///
+    /// 已遗忘节点的分量已从本地时钟移除，不参与比较。
+    fn causally_deliverable(&self, incoming: &SyncState) -> bool {
+        let Some(base) = &incoming.delta_base else {
+            return true;
+        };
+        base.clocks.iter().all(|(node, &seq)| {
+            self.forgotten_nodes.contains(node) || seq <= self.crdt_map.vector_clock.get(node)
+        })
+    }
+
+    /// 暂缓应用、等待因果前驱的操作数
+    pub fn pending_ops(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// 应用所有因果前驱已满足的暂缓操作，返回应用的数量
+    ///
+    /// 已被本地时钟覆盖（效果已包含在状态中）或来自已遗忘节点的暂缓操作直接丢弃。
+    fn deliver_pending(&mut self) -> usize {
+        let mut applied = 0;
+        loop {
+            let clock = &self.crdt_map.vector_clock;
+            let forgotten = &self.forgotten_nodes;
+            self.pending.retain(|entry| {
+                !causally_covered(entry, clock)
+                    && !entry
+                        .origin_node()
+                        .is_some_and(|node| forgotten.contains(node))
+            });
+            let Some(index) = self
+                .pending
+                .iter()
+                .position(|entry| causally_ready(entry, &self.crdt_map.vector_clock))
+            else {
+                break;
+            };
+
+            let entry = self.pending.remove(index);
+            if self.apply_remote_entry(entry) {
+                if let Some(entry) = self.op_log.ops.last() {
+                    self.hooks.emit_operation(entry);
+                }
+                applied += 1;
+            }
+        }
+        if applied > 0 {
+            self.op_log.sort();
+        }
+        applied
+    }
+
+    /// 用另一个节点的状态整体替换本地状态（非合并），返回被丢弃状态的哈希
+    ///
+    /// 本地节点 ID、累计冲突统计与取值约束会被保留，其余内容（含向量时钟）与对方完全一致。
+    pub fn replace_with(&mut self, other: SyncState) -> String {
+        let discarded_hash = self.state_hash();
+        let node_id = self.node_id.clone();
+        let conflict_stats = std::mem::take(&mut self.conflict_stats);
+        let peer_clocks = std::mem::take(&mut self.peer_clocks);
+        let schemas = std::mem::take(&mut self.schemas);
+        let counter_cap = self.counter_cap;
+        let max_keys = self.max_keys;
+        let max_set_elements = self.max_set_elements;
+        let auto_promote_counters = self.auto_promote_counters;
+        let encryptor = self.encryptor.take();
+        let clock = self.clock.clone();
+        let hooks = std::mem::take(&mut self.hooks);
+        let ephemeral_keys = std::mem::take(&mut self.ephemeral_keys);
+        let signer = self.op_log.signer.take();
+
+        *self = other;
+        self.node_id = node_id.clone();
+        self.op_log.node_id = node_id;
+        self.conflict_stats = conflict_stats;
+        self.peer_clocks = peer_clocks;
+        self.schemas = schemas;
+        self.counter_cap = counter_cap;
+        self.max_keys = max_keys;
+        self.max_set_elements = max_set_elements;
+        self.auto_promote_counters = auto_promote_counters;
+        self.encryptor = encryptor;
+        self.clock = clock;
+        self.hooks = hooks;
+        self.ephemeral_keys = ephemeral_keys;
+        self.op_log.signer = signer;
+
+        discarded_hash
+    }
+
+    /// 采用另一个状态的本地配置（取值约束、各类上限、加密器、签名器与时钟），不改变 CRDT 数据
+    ///
+    /// 用于在同一节点上创建新文档，使其与默认文档遵循相同的本地规则；事件回调不会被复制。
+    pub fn inherit_local_config(&mut self, from: &SyncState) {
+        self.schemas = from.schemas.clone();
+        self.counter_cap = from.counter_cap;
+        self.max_keys = from.max_keys;
+        self.max_set_elements = from.max_set_elements;
+        self.auto_promote_counters = from.auto_promote_counters;
+        self.encryptor = from.encryptor.clone();
+        self.clock = from.clock.clone();
+        self.op_log.signer = from.op_log.signer.clone();
+    }
+
+    /// 合并后键数量超出上限时，按字节序保留最小的 `max_keys` 个键
+    ///
+    /// 规则只取决于合并后的键集合，配置相同上限的诚实节点会收敛到同一结果。
+    /// 被淘汰键的操作移入隔离区并记录日志。
+    fn enforce_key_cap(&mut self, max_keys: usize) {
+        if self.crdt_map.entries.len() <= max_keys {
+            return;
+        }
+
+        let mut keys: Vec<String> = self.crdt_map.entries.keys().cloned().collect();
+        keys.sort();
+        let evicted: HashSet<String> = keys.split_off(max_keys).into_iter().collect();
+
+        for key in &evicted {
+            tracing::warn!("Quarantined key {}: key limit {} exceeded", key, max_keys);
+            self.crdt_map.entries.remove(key);
+        }
+
+        let reason = format!("Key limit {} exceeded", max_keys);
+        let (dropped, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.op_log.ops)
+            .into_iter()
+            .partition(|entry| entry.op.keys().iter().any(|key| evicted.contains(*key)));
+        self.op_log.ops = kept;
+        self.quarantine
+            .extend(dropped.into_iter().map(|entry| QuarantinedOp {
+                reason: reason.clone(),
+                entry,
+            }));
+    }
+
+    /// 合并后集合元素数超出上限时，按字节序保留最小的 `max_set_elements` 个元素
+    ///
+    /// 与键数量上限相同，规则只取决于合并后的元素集合，配置相同上限的诚实节点会收敛到同一结果。
+    /// 被淘汰元素的添加操作移入隔离区并记录日志。
+    fn enforce_set_cap(&mut self, max_set_elements: usize) {
+        let mut evicted: HashSet<(String, String)> = HashSet::new();
+        for (key, value) in self.crdt_map.entries.iter_mut() {
+            let CRDTValue::ORSet(set) = value else {
+                continue;
+            };
+            let mut elements = set.elements();
+            if elements.len() <= max_set_elements {
+                continue;
+            }
+            elements.sort();
+            for element in elements.split_off(max_set_elements) {
+                tracing::warn!(
+                    "Quarantined element {:?} of set {}: set element limit {} exceeded",
+                    element,
+                    key,
+                    max_set_elements
+                );
+                // 被淘汰元素的点仍在因果上下文中，与移除等效
+                set.entries.remove(&element);
+                evicted.insert((key.clone(), element));
+            }
+        }
+        if evicted.is_empty() {
+            return;
+        }
+
+        let reason = format!("Set element limit {} exceeded", max_set_elements);
+        let (dropped, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.op_log.ops)
+            .into_iter()
+            .partition(|entry| match &entry.op {
+                Operation::OrSetAdd { key, value, .. } => {
+                    evicted.contains(&(key.clone(), value.clone()))
+                }
+                _ => false,
+            });
+        self.op_log.ops = kept;
+        self.quarantine
+            .extend(dropped.into_iter().map(|entry| QuarantinedOp {
+                reason: reason.clone(),
+                entry,
+            }));
+    }
+
+    /// 遗忘已永久离开集群的节点
+    ///
+    /// 从向量时钟中移除该节点，并将其计数器贡献并入遗忘基线以保持计数值不变。
+    /// 之后来自该节点的操作在合并时都会被丢弃，避免节点重新出现时破坏收敛。
+    pub fn forget_node(&mut self, node_id: &str) -> Result<(), String> {
+        if node_id == self.node_id {
+            return Err("Cannot forget the local node".to_string());
+        }
+
+        self.crdt_map.forget_node(node_id);
+        self.forgotten_nodes.insert(node_id.to_string());
+        self.peer_clocks.remove(node_id);
+        for peer in self.peer_clocks.values_mut() {
+            peer.clock.remove(node_id);
+        }
+        Ok(())
+    }
+
+    /// 本地与所有已知对等节点都已观察到的向量时钟（逐分量取最小值）
+    pub fn stable_clock(&self) -> VectorClock {
+        self.peer_clocks
+            .values()
+            .fold(self.crdt_map.vector_clock.clone(), |stable, peer| {
+                stable.meet(&peer.clock)
+            })
+    }
+
+    /// 将因果上被 `up_to_clock` 覆盖的操作折叠进 CRDT Map 并从操作日志中截断，返回截断的条目数
+    ///
+    /// CRDT Map 本身就是所有已应用操作的物化结果，折叠不改变状态（`state_hash` 不变）；
+    /// 截断的范围记录为压缩前沿（`op_log.compacted`），随状态一起序列化，
+    /// 之后合并传入的日志时被前沿覆盖的条目不会再加入。`up_to_clock` 先截断到本地时钟，
+    /// 尚未观察到的操作不会被视为已折叠。压缩后 `replay`、`state_as_of` 与 `rebuild_from_oplog`
+    /// 只能覆盖前沿之后的操作，调用方应在压缩后保存快照。
+    ///
+    /// 同时抵消 PNCounter 中因果稳定节点的正负计数（见 `PNCounter::compact`）：节点在本地观察到的
+    /// 全部操作都在前沿内且被所有已知对等节点观察到（`stable_clock`）时才抵消；本节点之后还会继续写入，
+    /// 其计数不抵消。抵消改变计数器的内部表示（`state_hash` 随之变化）但不改变计数值。
+    pub fn compact(&mut self, up_to_clock: &VectorClock) -> usize {
+        let frontier = up_to_clock.meet(&self.crdt_map.vector_clock);
+        let before = self.op_log.ops.len();
+        self.op_log
+            .ops
+            .retain(|entry| !entry.causal.covered_by(&frontier));
+        self.op_log.compacted.merge(&frontier);
+
+        let stable = frontier.meet(&self.stable_clock());
+        let local = self.crdt_map.vector_clock.clone();
+        let is_stable = |node: &str| {
+            node != self.node_id && local.get(node) > 0 && local.get(node) <= stable.get(node)
+        };
+        let mut netted = 0;
+        for value in self.crdt_map.entries.values_mut() {
+            if let CRDTValue::PNCounter(counter) = value {
+                netted += counter.compact(is_stable);
+            }
+        }
+        if netted > 0 {
+            tracing::debug!("Netted {} stable counter contribution(s)", netted);
+        }
+
+        before - self.op_log.ops.len()
+    }
+
+    /// 将计数器的各节点明细压缩为基线，返回压缩后的计数值
+    ///
+    /// 仅在因果稳定时允许：所有已知对等节点的时钟都与本地一致，即各副本都已观察到全部增量。
+    /// 压缩结果通过状态合并传播，基线作为下限，与仍持有明细的副本合并后仍然收敛。
+    pub fn compact_counter(&mut self, key: &str) -> Result<i64, String> {
+        if let Some(peer) = self
+            .replication_status()
+            .into_iter()
+            .find(|peer| peer.status != ReplicationStatus::InSync)
+        {
+            return Err(format!(
+                "Counter {} is not causally stable: peer {} is {:?}",
+                key, peer.peer, peer.status
+            ));
+        }
+
+        match self.crdt_map.entries.get_mut(key) {
+            Some(CRDTValue::GCounter(c)) => Ok(c.compact_to_baseline() as i64),
+            Some(CRDTValue::PNCounter(c)) => Ok(c.compact_to_baseline()),
+            Some(other) => Err(format!(
+                "Key {} is a {}, not a counter",
+                key,
+                other.type_name()
+            )),
+            None => Err(format!("Key not found: {}", key)),
+        }
+    }
+
+    /// 各对等节点相对本地向量时钟的复制状态（按节点 ID 排序）
+    pub fn replication_status(&self) -> Vec<PeerReplication> {
+        let local = &self.crdt_map.vector_clock;
+        let mut peers: Vec<PeerReplication> = self
+            .peer_clocks
+            .iter()
+            .map(|(peer, peer_clock)| PeerReplication {
+                peer: peer.clone(),
+                status: match peer_clock.clock.partial_cmp_causal(local) {
+                    Some(std::cmp::Ordering::Less) => ReplicationStatus::Behind,
+                    Some(std::cmp::Ordering::Greater) => ReplicationStatus::Ahead,
+                    Some(std::cmp::Ordering::Equal) => ReplicationStatus::InSync,
+                    None => ReplicationStatus::Concurrent,
+                },
+                last_sync: peer_clock.last_sync,
+                last_sync_iso: format_ts_iso(peer_clock.last_sync),
+            })
+            .collect();
+        peers.sort_by(|a, b| a.peer.cmp(&b.peer));
+        peers
+    }
+
+    /// 清空 CRDT 数据与操作日志（保留节点 ID 与本地配置），返回被清空状态的哈希
+    pub fn reset(&mut self) -> String {
+        self.replace_with(SyncState::new(self.node_id.clone()))
+    }
+
+    /// 回滚到快照中的状态（保留节点 ID 与本地配置）
+    ///
+    /// `keep_oplog` 为 true 时保留当前操作日志，只回滚物化状态；否则快照之后的操作日志条目被丢弃。
+    pub fn restore_snapshot(&mut self, snapshot: SyncState, keep_oplog: bool) -> RestoreReport {
+        let dropped_ops = if keep_oplog {
+            0
+        } else {
+            let kept: HashSet<&str> = snapshot
+                .op_log
+                .ops
+                .iter()
+                .map(|entry| entry.id.as_str())
+                .collect();
+            self.op_log
+                .ops
+                .iter()
+                .filter(|entry| !kept.contains(entry.id.as_str()))
+                .count()
+        };
+        let op_log = keep_oplog
+            .then(|| std::mem::replace(&mut self.op_log, OpLog::new(self.node_id.clone())));
+
+        let discarded_state_hash = self.replace_with(snapshot);
+        if let Some(op_log) = op_log {
+            self.op_log = op_log;
+        }
+
+        RestoreReport {
+            discarded_state_hash,
+            dropped_ops,
+        }
+    }
+
+    /// 过滤传入状态中违反取值约束的操作与值，违规操作记入隔离区
+    fn sanitize_incoming(&mut self, other: &SyncState) -> SyncState {
+        let mut incoming = other.clone();
+
+        let forgotten = &self.forgotten_nodes;
+        incoming.op_log.ops.retain(|entry| {
+            entry
+                .op
+                .node_id()
+                .is_none_or(|node| !forgotten.contains(node))
+        });
+        for node_id in forgotten {
+            incoming.crdt_map.discard_node(node_id);
+        }
+
+        if let Some(cap) = self.counter_cap {
+            for (key, node_id) in incoming.crdt_map.clamp_counters(cap) {
+                tracing::warn!(
+                    "Clamped counter {} contribution of node {} from {} to cap {}",
+                    key,
+                    node_id,
+                    other.node_id,
+                    cap
+                );
+            }
+        }
+
+        let schemas = &self.schemas;
+        let quarantine = &mut self.quarantine;
+        incoming.op_log.ops.retain(|entry| {
+            let Some((key, value)) = entry.op.written_value() else {
+                return true;
+            };
+            // 密文无法在本地校验
+            if FieldEncryptor::is_ciphertext(value) {
+                return true;
+            }
+            match schemas.validate(key, value) {
+                Ok(()) => true,
+                Err(reason) => {
+                    tracing::warn!(
+                        "Quarantined op {} from {}: {}",
+                        entry.id,
+                        other.node_id,
+                        reason
+                    );
+                    quarantine.push(QuarantinedOp {
+                        reason,
+                        entry: entry.clone(),
+                    });
+                    false
+                }
+            }
+        });
+
+        incoming.crdt_map.entries.retain(|key, value| match value {
+            CRDTValue::LWWRegister(r) => match r.get() {
+                Some(v) => FieldEncryptor::is_ciphertext(v) || schemas.validate(key, v).is_ok(),
+                None => true,
+            },
+            CRDTValue::ORSet(s) => {
+                s.entries.retain(|v, _| schemas.validate(key, v).is_ok());
+                true
+            }
+            CRDTValue::GSet(s) => {
+                s.elements.retain(|v| schemas.validate(key, v).is_ok());
+                true
+            }
+            _ => true,
+        });
+
+        incoming
+    }
+
+    /// 记录传入操作日志中与本地并发的 LWWRegister 写入
+    ///
+    /// 合并前对本地日志建立一次索引：已知条目 ID，以及每个键上因果最新的写入（互不可比的写入集合）。
+    /// 与某个较早写入并发、却早于最新写入的传入写入已被覆盖，不计为冲突。
+    fn record_conflicts(&mut self, incoming: &OpLog) {
+        let mut known: HashSet<&str> = HashSet::new();
+        let mut latest: HashMap<&str, Vec<&VectorClock>> = HashMap::new();
+        for local in &self.op_log.ops {
+            known.insert(&local.id);
+            let Operation::LwwRegisterSet { key, .. } = &local.op else {
+                continue;
+            };
+            let writes = latest.entry(key.as_str()).or_default();
+            if writes
+                .iter()
+                .any(|clock| local.causal == **clock || local.causal.happens_before(clock))
+            {
+                continue;
+            }
+            writes.retain(|clock| !clock.happens_before(&local.causal));
+            writes.push(&local.causal);
+        }
+
+        for entry in &incoming.ops {
+            let Operation::LwwRegisterSet { key, .. } = &entry.op else {
+                continue;
+            };
+            if known.contains(entry.id.as_str()) {
+                continue;
+            }
+            let concurrent = latest.get(key.as_str()).is_some_and(|writes| {
+                writes
+                    .iter()
+                    .any(|clock| clock.is_concurrent(&entry.causal))
+            });
+            if concurrent {
+                *self.conflict_stats.entry(key.clone()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// 获取某个键的累计冲突次数
+    pub fn conflict_count(&self, key: &str) -> u64 {
+        self.conflict_stats.get(key).copied().unwrap_or(0)
+    }
+
+    /// 按冲突次数降序（次数相同按键名）返回累计冲突统计
+    pub fn conflict_stats_sorted(&self) -> Vec<(String, u64)> {
+        let mut stats: Vec<_> = self
+            .conflict_stats
+            .iter()
+            .map(|(k, v)| (k.clone(), *v))
+            .collect();
+        stats.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        stats
+    }
+
+    /// 多值寄存器中尚未解决的并发写入：按键名返回保留多个值的键及写入这些值的日志条目
+    pub fn unresolved_writes(&self) -> Vec<(&str, Vec<&OpLogEntry>)> {
+        let mut unresolved: Vec<_> = self
+            .crdt_map
+            .entries
+            .iter()
+            .filter_map(|(key, value)| match value {
+                CRDTValue::MVRegister(register) if register.entries.len() > 1 => {
+                    let writes = self
+                        .op_log
+                        .ops
+                        .iter()
+                        .filter(|entry| match &entry.op {
+                            Operation::MvRegisterSet {
+                                key: k,
+                                value,
+                                clock,
+                                ..
+                            } => {
+                                k == key
+                                    && register
+                                        .entries
+                                        .iter()
+                                        .any(|(v, c)| v == value && c == clock)
+                            }
+                            _ => false,
+                        })
+                        .collect();
+                    Some((key.as_str(), writes))
+                }
+                _ => None,
+            })
+            .collect();
+        unresolved.sort_by_key(|(key, _)| *key);
+        unresolved
+    }
+
+    /// OR-Set 上对同一元素因果并发的添加与移除，按键名与元素排序
+    ///
+    /// 只返回参与并发的条目：移除已观察到的添加（或观察到移除之后的添加）不算竞争。
+    pub fn set_add_remove_races(&self) -> Vec<SetRace<'_>> {
+        let mut groups: BTreeMap<(&str, &str), (Vec<&OpLogEntry>, Vec<&OpLogEntry>)> =
+            BTreeMap::new();
+        for entry in &self.op_log.ops {
+            match &entry.op {
+                Operation::OrSetAdd { key, value, .. } => {
+                    groups
+                        .entry((key.as_str(), value.as_str()))
+                        .or_default()
+                        .0
+                        .push(entry);
+                }
+                Operation::OrSetRemove { key, value, .. } => {
+                    groups
+                        .entry((key.as_str(), value.as_str()))
+                        .or_default()
+                        .1
+                        .push(entry);
+                }
+                _ => {}
+            }
+        }
+
+        groups
+            .into_iter()
+            .filter_map(|((key, value), (adds, removes))| {
+                let concurrent = |entry: &OpLogEntry, others: &[&OpLogEntry]| {
+                    others
+                        .iter()
+                        .any(|other| entry.causal.is_concurrent(&other.causal))
+                };
+                let racing_adds: Vec<&OpLogEntry> = adds
+                    .iter()
+                    .copied()
+                    .filter(|add| concurrent(add, &removes))
+                    .collect();
+                if racing_adds.is_empty() {
+                    return None;
+                }
+                let racing_removes = removes
+                    .iter()
+                    .copied()
+                    .filter(|remove| concurrent(remove, &adds))
+                    .collect();
+                Some(SetRace {
+                    key,
+                    value,
+                    adds: racing_adds,
+                    removes: racing_removes,
+                })
+            })
+            .collect()
+    }
+
+    /// 统计 CRDT 组成，`top` 为列出的最大键数量
+    pub fn stats(&self, top: usize) -> StateStats {
+        fn serialized_len<T: Serialize>(value: &T) -> usize {
+            serde_json::to_vec(value)
+                .map(|bytes| bytes.len())
+                .unwrap_or(0)
+        }
+
+        let mut stats = StateStats {
+            total_entries: self.crdt_map.entries.len(),
+            oplog_len: self.op_log.ops.len(),
+            ..Default::default()
+        };
+
+        let mut sizes = Vec::with_capacity(self.crdt_map.entries.len());
+        for (key, value) in &self.crdt_map.entries {
+            *stats
+                .type_counts
+                .entry(value.type_name().to_string())
+                .or_insert(0) += 1;
+            if let CRDTValue::ORSet(set) = value {
+                let elements = set.elements().len();
+                stats.set_elements += elements;
+                stats.set_tombstones += set.tombstone_count();
+                if let Some(cap) = self.max_set_elements
+                    && set_near_capacity(elements, cap)
+                {
+                    stats.near_capacity_sets.push(SetCapacity {
+                        key: key.clone(),
+                        elements,
+                        cap,
+                    });
+                }
+            }
+            if let CRDTValue::GSet(set) = value {
+                stats.set_elements += set.elements.len();
+            }
+            let bytes = key.len() + serialized_len(value);
+            stats.approx_bytes += bytes;
+            sizes.push(KeySize {
+                key: key.clone(),
+                bytes,
+            });
+        }
+        stats.approx_bytes += self.op_log.ops.iter().map(serialized_len).sum::<usize>();
+
+        sizes.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.key.cmp(&b.key)));
+        sizes.truncate(top);
+        stats.largest_keys = sizes;
+        stats.near_capacity_sets.sort_by(|a, b| a.key.cmp(&b.key));
+        stats
+    }
+
+    /// 用于持久化的状态：排除临时键的值与操作（向量时钟保持不变，避免重启后复用序号）
+    pub fn persistable(&self) -> Cow<'_, SyncState> {
+        if self.ephemeral_keys.is_empty() {
+            return Cow::Borrowed(self);
+        }
+        let mut state = self.clone();
+        for key in &self.ephemeral_keys {
+            state.crdt_map.entries.remove(key);
+        }
+        state.op_log.ops.retain(|entry| {
+            !entry
+                .op
+                .keys()
+                .iter()
+                .any(|key| self.ephemeral_keys.contains(*key))
+        });
+        Cow::Owned(state)
+    }
+
+    /// 获取状态哈希
+    pub fn state_hash(&self) -> String {
+        self.crdt_map.state_hash()
+    }
+
+    /// 导出操作日志为 JSON
+    pub fn export_oplog(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(&self.op_log)
+    }
+}
+
+/// 条目是否已被时钟覆盖（发起节点的分量不超过时钟中的对应分量）
+fn causally_covered(entry: &OpLogEntry, clock: &VectorClock) -> bool {
+    entry
+        .origin_node()
+        .is_some_and(|node| entry.causal.get(node) <= clock.get(node))
+}
+
+/// 条目的因果前驱是否都已被时钟观察到：发起节点的分量恰好领先一步，其余分量均不超过时钟
+///
+/// 无法确定发起节点的旧日志条目没有可用的因果信息，视为随时可以应用。
+fn causally_ready(entry: &OpLogEntry, clock: &VectorClock) -> bool {
+    let Some(origin) = entry.origin_node() else {
+        return true;
+    };
+    entry.causal.clocks.iter().all(|(node, &seq)| {
+        if node == origin {
+            seq == clock.get(node) + 1
+        } else {
+            seq <= clock.get(node)
+        }
+    })
+}
+
+/// 将单个操作作用到 CRDT Map（不记录日志）
+fn apply_to_map(crdt_map: &mut CRDTMap, op: Operation) {
+    match op {
+        Operation::GCounterIncrement {
+            key,
+            node_id,
+            delta,
+        } => {
+            let counter = crdt_map
+                .entries
+                .entry(key)
+                .or_insert_with(|| CRDTValue::GCounter(GCounter::new()));
+
+            if let CRDTValue::GCounter(c) = counter {
+                c.increment(&node_id, delta);
+            }
+        }
+        Operation::PNCounterIncrement {
+            key,
+            node_id,
+            delta,
+        } => {
+            let counter = crdt_map
+                .entries
+                .entry(key)
+                .or_insert_with(|| CRDTValue::PNCounter(PNCounter::new()));
+
+            if let CRDTValue::PNCounter(c) = counter {
+                c.increment(&node_id, delta);
+            }
+        }
+        Operation::PNCounterDecrement {
+            key,
+            node_id,
+            delta,
+        } => {
+            let counter = crdt_map
+                .entries
+                .entry(key)
+                .or_insert_with(|| CRDTValue::PNCounter(PNCounter::new()));
+
+            if let CRDTValue::PNCounter(c) = counter {
+                c.decrement(&node_id, delta);
+            }
+        }
+        Operation::BoundedCounterIncrement {
+            key,
+            node_id,
+            delta,
+            lower_bound,
+        } => {
+            let counter = crdt_map
+                .entries
+                .entry(key)
+                .or_insert_with(|| CRDTValue::BoundedCounter(BoundedCounter::new(lower_bound)));
+
+            if let CRDTValue::BoundedCounter(c) = counter {
+                c.increment(&node_id, delta);
+            }
+        }
+        Operation::BoundedCounterDecrement {
+            key,
+            node_id,
+            delta,
+            lower_bound,
+        } => {
+            let counter = crdt_map
+                .entries
+                .entry(key)
+                .or_insert_with(|| CRDTValue::BoundedCounter(BoundedCounter::new(lower_bound)));
+
+            // 发起节点已检查过下界，重放时不再拒绝
+            if let CRDTValue::BoundedCounter(c) = counter {
+                c.decrement(&node_id, delta);
+            }
+        }
+        Operation::LwwRegisterSet {
+            key,
+            value,
+            timestamp,
+            node_id,
+        } => {
+            let register = crdt_map
+                .entries
+                .entry(key)
+                .or_insert_with(|| CRDTValue::LWWRegister(LWWRegister::new()));
+
+            if let CRDTValue::LWWRegister(r) = register {
+                r.set(value, timestamp, &node_id);
+            }
+        }
+        Operation::LwwMapSet {
+            key,
+            field,
+            value,
+            timestamp,
+            node_id,
+        } => {
+            let map = crdt_map
+                .entries
+                .entry(key)
+                .or_insert_with(|| CRDTValue::LWWMap(LWWMap::new()));
+
+            if let CRDTValue::LWWMap(m) = map {
+                m.set(field, value, timestamp, &node_id);
+            }
+        }
+        Operation::LwwMapRemove {
+            key,
+            field,
+            timestamp,
+            node_id,
+        } => {
+            let map = crdt_map
+                .entries
+                .entry(key)
+                .or_insert_with(|| CRDTValue::LWWMap(LWWMap::new()));
+
+            if let CRDTValue::LWWMap(m) = map {
+                m.remove(field, timestamp, &node_id);
+            }
+        }
+        Operation::OrSetAdd { key, value, dot } => {
+            let set = crdt_map
+                .entries
+                .entry(key)
+                .or_insert_with(|| CRDTValue::ORSet(ORSet::new()));
+
+            if let CRDTValue::ORSet(s) = set {
+                s.add_dot(value, dot);
+            }
+        }
+        Operation::OrSetRemove { key, value, dots } => {
+            if let Some(CRDTValue::ORSet(s)) = crdt_map.entries.get_mut(&key) {
+                match dots {
+                    Some(dots) => s.remove_dots(&value, &dots),
+                    None => {
+                        s.remove(&value);
+                    }
+                }
+            }
+        }
+        Operation::GSetAdd { key, value } => {
+            let set = crdt_map
+                .entries
+                .entry(key)
+                .or_insert_with(|| CRDTValue::GSet(GSet::new()));
+
+            if let CRDTValue::GSet(s) = set {
+                s.add(value);
+            }
+        }
+        Operation::ConvertType { key, to_type, .. } => {
+            if let Some(value) = crdt_map.entries.get_mut(&key)
+                && let Some(converted) = value.converted(&to_type)
+            {
+                *value = converted;
+            }
+        }
+        Operation::MaxRegisterSet { key, value } => {
+            let register = crdt_map
+                .entries
+                .entry(key)
+                .or_insert_with(|| CRDTValue::MaxRegister(MaxRegister::new()));
+
+            if let CRDTValue::MaxRegister(r) = register {
+                r.set(value);
+            }
+        }
+        Operation::MinRegisterSet { key, value } => {
+            let register = crdt_map
+                .entries
+                .entry(key)
+                .or_insert_with(|| CRDTValue::MinRegister(MinRegister::new()));
+
+            if let CRDTValue::MinRegister(r) = register {
+                r.set(value);
+            }
+        }
+        Operation::MultiIncrement { node_id, counters } => {
+            for (key, delta) in counters {
+                let counter = crdt_map
+                    .entries
+                    .entry(key)
+                    .or_insert_with(|| CRDTValue::PNCounter(PNCounter::new()));
+
+                if let CRDTValue::PNCounter(c) = counter {
+                    c.increment(&node_id, delta);
+                }
+            }
+        }
+        Operation::MvRegisterSet {
+            key, value, clock, ..
+        } => {
+            let register = crdt_map
+                .entries
+                .entry(key)
+                .or_insert_with(|| CRDTValue::MVRegister(MVRegister::new()));
+
+            if let CRDTValue::MVRegister(r) = register {
+                r.set(value, clock);
+            }
+        }
+        Operation::RgaInsert {
+            key,
+            id,
+            after,
+            value,
+        } => {
+            let sequence = crdt_map
+                .entries
+                .entry(key)
+                .or_insert_with(|| CRDTValue::RGA(RGA::new()));
+
+            // 因果交付保证前驱先于插入到达
+            if let CRDTValue::RGA(r) = sequence {
+                r.integrate(id, after, value);
+            }
+        }
+        Operation::RgaDelete { key, id } => {
+            if let Some(CRDTValue::RGA(r)) = crdt_map.entries.get_mut(&key) {
+                r.delete(&id);
+            }
+        }
+    }
+}
+
+/// 同步请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncRequest {
+    pub from_node: NodeId,
+    pub state: SyncState,
+}
+
+impl SyncRequest {
+    /// 在合并前校验请求内容，返回全部字段级错误
+    pub fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        if self.from_node.is_empty() {
+            errors.push(FieldError::new(
+                "from_node".to_string(),
+                "must not be empty",
+            ));
+        }
+        if self.state.node_id.is_empty() {
+            errors.push(FieldError::new(
+                "state.node_id".to_string(),
+                "must not be empty",
+            ));
+        }
+        let mut keys: Vec<&String> = self.state.crdt_map.entries.keys().collect();
+        keys.sort();
+        for key in keys {
+            validate_key(
+                format!("state.crdt_map.entries[{:?}]", key),
+                key,
+                &mut errors,
+            );
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// 同步响应
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncResponse {
+    pub success: bool,
+    pub state_hash: String,
+    pub message: String,
+}
+
+/// 变更请求（用于 HTTP API）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeRequest {
+    pub changes: Vec<Change>,
+}
+
+/// 键的最大长度（字节）
+pub const MAX_KEY_LEN: usize = 1024;
+
+/// 字段级校验错误
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    /// 出错字段路径，如 `changes[0].key`
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: String, message: impl Into<String>) -> Self {
+        Self {
+            field,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// 应用变更失败的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeError {
+    /// 未知的操作类型
+    UnknownOp(String),
+    /// 缺少操作所需的字段
+    MissingValue { op: String, field: &'static str },
+    /// 键已存在且类型与操作不符
+    TypeMismatch {
+        key: String,
+        expected: String,
+        found: String,
+    },
+    /// 操作引用的键不存在
+    InvalidKey(String),
+    /// 其他校验失败（取值约束、数量上限、加密限制等）
+    Rejected(String),
+}
+
+impl ChangeError {
+    fn missing(op: &str, field: &'static str) -> Self {
+        ChangeError::MissingValue {
+            op: op.to_string(),
+            field,
+        }
+    }
+
+    /// 机器可读的错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            ChangeError::UnknownOp(_) => "unknown_op",
+            ChangeError::MissingValue { .. } => "missing_value",
+            ChangeError::TypeMismatch { .. } => "type_mismatch",
+            ChangeError::InvalidKey(_) => "invalid_key",
+            ChangeError::Rejected(_) => "change_rejected",
+        }
+    }
+}
+
+impl fmt::Display for ChangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeError::UnknownOp(op) => write!(f, "Unknown operation: {}", op),
+            ChangeError::MissingValue { op, field } => {
+                write!(f, "Missing {} for {} operation", field, op)
+            }
+            ChangeError::TypeMismatch {
+                key,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Type mismatch for key {}: expected {}, found {}",
+                key, expected, found
+            ),
+            ChangeError::InvalidKey(key) => write!(f, "Key not found: {}", key),
+            ChangeError::Rejected(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for ChangeError {}
+
+impl From<String> for ChangeError {
+    fn from(message: String) -> Self {
+        ChangeError::Rejected(message)
+    }
+}
+
+/// 校验键名：非空且不超过长度上限
+fn validate_key(field: String, key: &str, errors: &mut Vec<FieldError>) {
+    if key.is_empty() {
+        errors.push(FieldError::new(field, "must not be empty"));
+    } else if key.len() > MAX_KEY_LEN {
+        errors.push(FieldError::new(
+            field,
+            format!("must be at most {} bytes", MAX_KEY_LEN),
+        ));
+    }
+}
+
+impl ChangeRequest {
+    /// 在应用前校验请求内容，返回全部字段级错误
+    pub fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let errors: Vec<FieldError> = self
+            .changes
+            .iter()
+            .enumerate()
+            .flat_map(|(index, change)| change.field_errors(index))
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl ChangeRequest {
+    /// 变更涉及的所有键（含 move 的源键与目标键）
+    pub fn touched_keys(&self) -> Vec<&str> {
+        self.changes
+            .iter()
+            .flat_map(|change| {
+                [
+                    Some(change.key.as_str()),
+                    change.from_key.as_deref(),
+                    change.to_key.as_deref(),
+                ]
+                .into_iter()
+                .flatten()
+                .chain(change.counters.keys().map(String::as_str))
+            })
+            .filter(|key| !key.is_empty())
+            .collect()
+    }
+}
+
+impl Change {
+    /// 校验单个变更的字段，`index` 为其在请求中的位置
+    fn field_errors(&self, index: usize) -> Vec<FieldError> {
+        let field = |name: &str| format!("changes[{}].{}", index, name);
+        let mut errors = Vec::new();
+
+        if self.op.is_empty() {
+            errors.push(FieldError::new(field("op"), "must not be empty"));
+            return errors;
+        }
+        let Some(spec) = change_op_spec(&self.op) else {
+            errors.push(FieldError::new(
+                field("op"),
+                format!("unknown operation '{}'", self.op),
+            ));
+            return errors;
+        };
+
+        for name in spec.required {
+            match *name {
+                "key" => validate_key(field("key"), &self.key, &mut errors),
+                "from_key" => validate_key(
+                    field("from_key"),
+                    self.from_key.as_deref().unwrap_or_default(),
+                    &mut errors,
+                ),
+                "to_key" => validate_key(
+                    field("to_key"),
+                    self.to_key.as_deref().unwrap_or_default(),
+                    &mut errors,
+                ),
+                "value" if self.value.is_none() => {
+                    errors.push(FieldError::new(field("value"), "is required"));
+                }
+                "crdt_type" if self.crdt_type.is_none() => {
+                    errors.push(FieldError::new(field("crdt_type"), "is required"));
+                }
+                "field" if self.field.as_deref().is_none_or(str::is_empty) => {
+                    errors.push(FieldError::new(field("field"), "must not be empty"));
+                }
+                "counters" if self.counters.is_empty() => {
+                    errors.push(FieldError::new(field("counters"), "must not be empty"));
+                }
+                "position" if self.position.is_none() => {
+                    errors.push(FieldError::new(field("position"), "is required"));
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(position) = &self.position
+            && let Err(e) = position.parse::<RgaId>()
+        {
+            errors.push(FieldError::new(field("position"), e));
+        }
+
+        let mut counters: Vec<(&String, &u64)> = self.counters.iter().collect();
+        counters.sort();
+        for (key, delta) in counters {
+            let counter_field = field(&format!("counters[{:?}]", key));
+            validate_key(counter_field.clone(), key, &mut errors);
+            if *delta > i64::MAX as u64 {
+                errors.push(FieldError::new(
+                    counter_field,
+                    format!("must be at most {}", i64::MAX),
+                ));
+            }
+        }
+
+        // PNCounter 以 i64 表示计数值
+        if let Some(delta) = self.delta
+            && delta > i64::MAX as u64
+        {
+            errors.push(FieldError::new(
+                field("delta"),
+                format!("must be at most {}", i64::MAX),
+            ));
+        }
+
+        errors
+    }
+}
+
+/// 单个变更的试运行结果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChangeValidation {
+    pub index: usize,
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+/// 单个变更
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Change {
+    pub op: String, // "add", "remove", "increment", "decrement", "set", "ensure", "move"
+    #[serde(default)]
+    pub key: String,
+    pub value: Option<String>,
+    pub delta: Option<u64>,
+    /// CRDT 类型（用于 "ensure"）："g-counter"、"pn-counter"、"bounded-counter"、"lww-register"、"lww-map"、"or-set"、"g-set"、"rga"
+    #[serde(default)]
+    pub crdt_type: Option<String>,
+    /// 源集合与目标集合（用于 "move"）
+    #[serde(default)]
+    pub from_key: Option<String>,
+    #[serde(default)]
+    pub to_key: Option<String>,
+    /// 各计数器的增量（用于 "multi-inc"）
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub counters: HashMap<String, u64>,
+    /// LWW-Map 中的字段（用于 "map-set" / "map-remove"）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// RGA 位置标识 `计数@节点`（"rga-insert" 的前驱，省略时插入到开头；"rga-delete" 的目标）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<String>,
+}
+
+/// 变更操作描述（`apply_changes` 依据此表分发，`/schema/operations` 直接返回）
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ChangeOpSpec {
+    /// `Change.op` 取值
+    pub op: &'static str,
+    /// 目标 CRDT 类型
+    pub crdt_type: &'static str,
+    /// 必填的 `Change` 字段
+    pub required: &'static [&'static str],
+    /// 可选的 `Change` 字段
+    pub optional: &'static [&'static str],
+    pub description: &'static str,
+}
+
+/// 所有支持的变更操作
+pub const CHANGE_OPS: &[ChangeOpSpec] = &[
+    ChangeOpSpec {
+        op: "add",
+        crdt_type: "or-set",
+        required: &["key", "value"],
+        optional: &[],
+        description: "向集合添加元素",
+    },
+    ChangeOpSpec {
+        op: "remove",
+        crdt_type: "or-set",
+        required: &["key", "value"],
+        optional: &[],
+        description: "从集合移除已观察到的元素",
+    },
+    ChangeOpSpec {
+        op: "move",
+        crdt_type: "or-set",
+        required: &["from_key", "to_key", "value"],
+        optional: &[],
+        description: "将元素从一个集合移动到另一个集合",
+    },
+    ChangeOpSpec {
+        op: "g-add",
+        crdt_type: "g-set",
+        required: &["key", "value"],
+        optional: &[],
+        description: "向只增集合添加元素（不可移除）",
+    },
+    ChangeOpSpec {
+        op: "increment",
+        crdt_type: "pn-counter",
+        required: &["key"],
+        optional: &["delta"],
+        description: "计数器增加 delta（默认 1）",
+    },
+    ChangeOpSpec {
+        op: "decrement",
+        crdt_type: "pn-counter",
+        required: &["key"],
+        optional: &["delta"],
+        description: "计数器减少 delta（默认 1）",
+    },
+    ChangeOpSpec {
+        op: "multi-inc",
+        crdt_type: "pn-counter",
+        required: &["counters"],
+        optional: &[],
+        description: "以单个操作增加多个计数器（counters 为键到增量的映射）",
+    },
+    ChangeOpSpec {
+        op: "set",
+        crdt_type: "lww-register",
+        required: &["key", "value"],
+        optional: &[],
+        description: "设置寄存器值（最后写入者胜出）",
+    },
+    ChangeOpSpec {
+        op: "map-set",
+        crdt_type: "lww-map",
+        required: &["key", "field", "value"],
+        optional: &[],
+        description: "设置 LWW-Map 中的字段（各字段独立按最后写入者胜出）",
+    },
+    ChangeOpSpec {
+        op: "map-remove",
+        crdt_type: "lww-map",
+        required: &["key", "field"],
+        optional: &[],
+        description: "删除 LWW-Map 中的字段（带时间戳的墓碑，更晚的写入可使其重新出现）",
+    },
+    ChangeOpSpec {
+        op: "rga-insert",
+        crdt_type: "rga",
+        required: &["key", "value"],
+        optional: &["position"],
+        description: "在 position 指定的元素之后插入（省略时插入到开头）",
+    },
+    ChangeOpSpec {
+        op: "rga-delete",
+        crdt_type: "rga",
+        required: &["key", "position"],
+        optional: &[],
+        description: "删除 position 指定的元素（保留墓碑）",
+    },
+    ChangeOpSpec {
+        op: "ensure",
+        crdt_type: "any",
+        required: &["key", "crdt_type"],
+        optional: &["value", "delta"],
+        description: "仅在键不存在时按类型初始化",
+    },
+];
+
+/// 查找变更操作描述
+pub fn change_op_spec(op: &str) -> Option<&'static ChangeOpSpec> {
+    CHANGE_OPS.iter().find(|spec| spec.op == op)
+}
+
+impl SyncState {
+    /// 从变更请求应用操作
+    ///
+    /// 请求整体生效或整体不生效：任一变更失败时，之前已应用的变更被撤销。
+    /// 涉及的临时键转为普通键，之后随状态一起持久化。
+    pub fn apply_changes(&mut self, request: ChangeRequest) -> Result<(), ChangeError> {
+        let mut promoted = Vec::new();
+        if !self.ephemeral_keys.is_empty() {
+            for key in request.touched_keys() {
+                if self.ephemeral_keys.remove(key) {
+                    promoted.push(key.to_string());
+                }
+            }
+        }
+        let result = self.apply_change_list(request);
+        if result.is_err() {
+            self.ephemeral_keys.extend(promoted);
+        }
+        result
+    }
+
+    /// 以 `memory` 级别应用变更：涉及的键成为临时键，不会被持久化
+    ///
+    /// 只允许写入新键或已有的临时键，避免丢弃已持久化的数据。
+    pub fn apply_changes_in_memory(&mut self, request: ChangeRequest) -> Result<(), ChangeError> {
+        let keys: Vec<String> = request
+            .touched_keys()
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        if let Some(key) = keys.iter().find(|key| {
+            self.crdt_map.entries.contains_key(*key) && !self.ephemeral_keys.contains(*key)
+        }) {
+            return Err(format!(
+                "Key {} is persisted and cannot be written with memory durability",
+                key
+            )
+            .into());
+        }
+
+        self.ephemeral_keys.extend(keys);
+        let result = self.apply_change_list(request);
+        // 失败时不保留未创建成功的键
+        let entries = &self.crdt_map.entries;
+        self.ephemeral_keys.retain(|key| entries.contains_key(key));
+        result
+    }
+
+    /// 原子地应用变更列表
+    ///
+    /// 单条变更在记录操作之前完成全部校验，本身即是原子的。多条变更时先记录涉及键的值、
+    /// 向量时钟与日志长度，任一变更失败时恢复；操作回调推迟到整个请求成功后再触发。
+    fn apply_change_list(&mut self, request: ChangeRequest) -> Result<(), ChangeError> {
+        if request.changes.len() <= 1 {
+            return self.apply_each_change(request);
+        }
+
+        let saved: Vec<(String, Option<CRDTValue>)> = request
+            .touched_keys()
+            .into_iter()
+            .map(|key| (key.to_string(), self.crdt_map.entries.get(key).cloned()))
+            .collect();
+        let clock = self.crdt_map.vector_clock.clone();
+        let ops_before = self.op_log.ops.len();
+        let hooks = std::mem::take(&mut self.hooks);
+        let result = self.apply_each_change(request);
+        self.hooks = hooks;
+
+        match result {
+            Ok(()) => {
+                for entry in &self.op_log.ops[ops_before..] {
+                    self.hooks.emit_operation(entry);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                self.op_log.ops.truncate(ops_before);
+                self.crdt_map.vector_clock = clock;
+                for (key, value) in saved {
+                    match value {
+                        Some(value) => self.crdt_map.entries.insert(key, value),
+                        None => self.crdt_map.entries.remove(&key),
+                    };
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn apply_each_change(&mut self, request: ChangeRequest) -> Result<(), ChangeError> {
+        for change in request.changes {
+            let spec = change_op_spec(&change.op)
+                .ok_or_else(|| ChangeError::UnknownOp(change.op.clone()))?;
+            match spec.op {
+                "add" => {
+                    let value = change.value.ok_or(ChangeError::missing("add", "value"))?;
+                    self.check_key_type(&change.key, spec.crdt_type)?;
+                    self.schemas.validate(&change.key, &value)?;
+                    self.check_new_key(&change.key)?;
+                    self.check_set_capacity(&change.key, &value)?;
+                    let dot = self.next_set_dot(&change.key);
+                    let op = Operation::OrSetAdd {
+                        key: change.key,
+                        value,
+                        dot,
+                    };
+                    self.apply_operation(op);
+                }
+                "remove" => {
+                    let value = change
+                        .value
+                        .ok_or(ChangeError::missing("remove", "value"))?;
+                    self.check_key_type(&change.key, spec.crdt_type)?;
+                    let dots = Some(self.observed_dots(&change.key, &value));
+                    let op = Operation::OrSetRemove {
+                        key: change.key,
+                        value,
+                        dots,
+                    };
+                    self.apply_operation(op);
+                }
+                "g-add" => {
+                    let value = change.value.ok_or(ChangeError::missing("g-add", "value"))?;
+                    self.check_key_type(&change.key, spec.crdt_type)?;
+                    self.schemas.validate(&change.key, &value)?;
+                    self.check_new_key(&change.key)?;
+                    self.apply_operation(Operation::GSetAdd {
+                        key: change.key,
+                        value,
+                    });
+                }
+                "move" => {
+                    let from_key = change
+                        .from_key
+                        .ok_or(ChangeError::missing("move", "from_key"))?;
+                    let to_key = change
+                        .to_key
+                        .ok_or(ChangeError::missing("move", "to_key"))?;
+                    let value = change.value.ok_or(ChangeError::missing("move", "value"))?;
+                    self.move_element(from_key, to_key, value)?;
+                }
+                "increment" => {
+                    let delta = change.delta.unwrap_or(1);
+                    if let Some(CRDTValue::BoundedCounter(counter)) = self.crdt_map.get(&change.key)
+                    {
+                        let lower_bound = counter.lower_bound;
+                        self.check_counter_cap(&change.key, false, delta)?;
+                        self.apply_operation(Operation::BoundedCounterIncrement {
+                            key: change.key,
+                            node_id: self.node_id.clone(),
+                            delta,
+                            lower_bound,
+                        });
+                        continue;
+                    }
+                    self.check_key_type(&change.key, spec.crdt_type)?;
+                    self.check_counter_cap(&change.key, false, delta)?;
+                    self.check_new_key(&change.key)?;
+                    let op = Operation::PNCounterIncrement {
+                        key: change.key,
+                        node_id: self.node_id.clone(),
+                        delta,
+                    };
+                    self.apply_operation(op);
+                }
+                "decrement" => {
+                    let delta = change.delta.unwrap_or(1);
+                    if let Some(CRDTValue::BoundedCounter(counter)) = self.crdt_map.get(&change.key)
+                    {
+                        // 仅依据本地视图检查下界，并发递减仍可能越界（value() 截断为下界）
+                        counter
+                            .check_decrement(delta)
+                            .map_err(|e| format!("{}: {}", change.key, e))?;
+                        let lower_bound = counter.lower_bound;
+                        self.check_counter_cap(&change.key, true, delta)?;
+                        self.apply_operation(Operation::BoundedCounterDecrement {
+                            key: change.key,
+                            node_id: self.node_id.clone(),
+                            delta,
+                            lower_bound,
+                        });
+                        continue;
+                    }
+                    let promote =
+                        matches!(self.crdt_map.get(&change.key), Some(CRDTValue::GCounter(_)));
+                    if promote && !self.auto_promote_counters {
+                        return Err(format!(
+                            "Cannot decrement g-counter {}: convert it to a pn-counter first \
+                             or enable auto-promote-counters",
+                            change.key
+                        )
+                        .into());
+                    }
+                    if !promote {
+                        self.check_key_type(&change.key, spec.crdt_type)?;
+                    }
+                    self.check_counter_cap(&change.key, true, delta)?;
+                    self.check_new_key(&change.key)?;
+                    if promote {
+                        // 记录转换操作以便副本收敛
+                        self.convert_key(&change.key, "pn-counter")?;
+                    }
+                    let op = Operation::PNCounterDecrement {
+                        key: change.key,
+                        node_id: self.node_id.clone(),
+                        delta,
+                    };
+                    self.apply_operation(op);
+                }
+                "multi-inc" => self.multi_increment(change.counters)?,
+                "set" => {
+                    let value = change.value.ok_or(ChangeError::missing("set", "value"))?;
+                    self.set_value(change.key, value)?;
+                }
+                "map-set" => {
+                    let field = change
+                        .field
+                        .ok_or(ChangeError::missing("map-set", "field"))?;
+                    let value = change
+                        .value
+                        .ok_or(ChangeError::missing("map-set", "value"))?;
+                    self.map_write(change.key, field, Some(value))?;
+                }
+                "map-remove" => {
+                    let field = change
+                        .field
+                        .ok_or(ChangeError::missing("map-remove", "field"))?;
+                    self.map_write(change.key, field, None)?;
+                }
+                "rga-insert" => {
+                    let value = change
+                        .value
+                        .ok_or(ChangeError::missing("rga-insert", "value"))?;
+                    let after = change.position.as_deref().map(str::parse).transpose()?;
+                    self.rga_insert(change.key, after, value)?;
+                }
+                "rga-delete" => {
+                    let id = change
+                        .position
+                        .ok_or(ChangeError::missing("rga-delete", "position"))?
+                        .parse()?;
+                    self.rga_delete(change.key, id)?;
+                }
+                "ensure" => {
+                    let crdt_type = change
+                        .crdt_type
+                        .ok_or(ChangeError::missing("ensure", "crdt_type"))?;
+                    self.ensure_key(change.key, &crdt_type, change.value, change.delta)?;
+                }
+                op => unreachable!("operation {} listed in CHANGE_OPS but not handled", op),
+            }
+        }
+        Ok(())
+    }
+
+    /// 以单个操作增加多个 PNCounter：全部校验通过后才记录一条日志，各计数器要么全部增加要么都不变
+    pub fn multi_increment(&mut self, counters: HashMap<String, u64>) -> Result<(), ChangeError> {
+        if counters.is_empty() {
+            return Err(ChangeError::missing("multi-inc", "counters"));
+        }
+        let counters: BTreeMap<String, u64> = counters.into_iter().collect();
+
+        for (key, delta) in &counters {
+            self.check_key_type(key, "pn-counter")?;
+            self.check_counter_cap(key, false, *delta)?;
+        }
+        if let Some(max_keys) = self.max_keys {
+            let new_keys = counters
+                .keys()
+                .filter(|key| !self.crdt_map.entries.contains_key(*key))
+                .count();
+            if new_keys > 0 && self.crdt_map.entries.len() + new_keys > max_keys {
+                return Err(format!(
+                    "Key limit reached ({} keys), cannot create {} new counters",
+                    max_keys, new_keys
+                )
+                .into());
+            }
+        }
+
+        self.apply_operation(Operation::MultiIncrement {
+            node_id: self.node_id.clone(),
+            counters,
+        });
+        Ok(())
+    }
+
+    /// 写入（`value` 为 None 时删除）LWW-Map 的字段
+    ///
+    /// 时间戳至少比字段现有时间戳大 1，保证本节点的后续写入总能覆盖先前的写入，
+    /// 即使系统时钟回拨或同一毫秒内多次写入。
+    fn map_write(
+        &mut self,
+        key: String,
+        field: String,
+        value: Option<String>,
+    ) -> Result<(), ChangeError> {
+        self.check_key_type(&key, "lww-map")?;
+        if self
+            .encryptor
+            .as_ref()
+            .is_some_and(|encryptor| encryptor.matches(&key))
+        {
+            return Err(
+                format!("Key {} is encrypted and cannot be used as an lww-map", key).into(),
+            );
+        }
+        self.check_new_key(&key)?;
+
+        let last_write = match self.crdt_map.get(&key) {
+            Some(CRDTValue::LWWMap(map)) => map.entries.get(&field).map(|(_, ts, _)| *ts),
+            _ => None,
+        };
+        let timestamp = last_write.map_or(self.clock.now_millis(), |ts| {
+            self.clock.now_millis().max(ts.saturating_add(1))
+        });
+        let node_id = self.node_id.clone();
+        let op = match value {
+            Some(value) => Operation::LwwMapSet {
+                key,
+                field,
+                value,
+                timestamp,
+                node_id,
+            },
+            None => Operation::LwwMapRemove {
+                key,
+                field,
+                timestamp,
+                node_id,
+            },
+        };
+        self.apply_operation(op);
+        Ok(())
+    }
+
+    /// 在 RGA 中 `after` 之后插入元素（None 表示开头），前驱必须已存在（可以是墓碑）
+    fn rga_insert(
+        &mut self,
+        key: String,
+        after: Option<RgaId>,
+        value: String,
+    ) -> Result<(), ChangeError> {
+        self.check_key_type(&key, "rga")?;
+        self.schemas.validate(&key, &value)?;
+        self.check_new_key(&key)?;
+
+        let empty = RGA::new();
+        let sequence = match self.crdt_map.get(&key) {
+            Some(CRDTValue::RGA(sequence)) => sequence,
+            _ => &empty,
+        };
+        if let Some(after) = &after
+            && !sequence.contains(after)
+        {
+            return Err(format!("Unknown RGA position {} in {}", after, key).into());
+        }
+        let id = sequence.next_id(&self.node_id);
+        self.apply_operation(Operation::RgaInsert {
+            key,
+            id,
+            after,
+            value,
+        });
+        Ok(())
+    }
+
+    /// 删除 RGA 中的元素，重复删除不报错
+    fn rga_delete(&mut self, key: String, id: RgaId) -> Result<(), ChangeError> {
+        match self.crdt_map.get(&key) {
+            Some(CRDTValue::RGA(sequence)) if !sequence.contains(&id) => {
+                return Err(format!("Unknown RGA position {} in {}", id, key).into());
+            }
+            Some(CRDTValue::RGA(_)) => {}
+            Some(_) => self.check_key_type(&key, "rga")?,
+            None => return Err(ChangeError::InvalidKey(key)),
+        }
+        self.apply_operation(Operation::RgaDelete { key, id });
+        Ok(())
+    }
+
+    /// 按键的冲突解决策略写入值
+    ///
+    /// LWW 键写入 LWWRegister；`max` / `min` 键要求整数值，分别写入 MaxRegister / MinRegister；
+    /// `keep-all` 键写入 MVRegister，保留所有并发写入。
+    fn set_value(&mut self, key: String, value: String) -> Result<(), ChangeError> {
+        let policy = self.policies.get(&key);
+        self.schemas.validate(&key, &value)?;
+        self.check_key_type(&key, policy.crdt_type())?;
+        self.check_new_key(&key)?;
+
+        let op = match policy {
+            ConflictPolicy::Lww => Operation::LwwRegisterSet {
+                value: self.seal_value(&key, value)?,
+                timestamp: self.clock.now_millis(),
+                node_id: self.node_id.clone(),
+                key,
+            },
+            ConflictPolicy::Max | ConflictPolicy::Min => {
+                if self
+                    .encryptor
+                    .as_ref()
+                    .is_some_and(|encryptor| encryptor.matches(&key))
+                {
+                    return Err(format!(
+                        "Key {} is encrypted and cannot use a numeric policy",
+                        key
+                    )
+                    .into());
+                }
+                let value: i64 = value.parse().map_err(|_| {
+                    format!(
+                        "Value '{}' for key {} with {} policy is not an integer",
+                        value, key, policy
+                    )
+                })?;
+                if policy == ConflictPolicy::Max {
+                    Operation::MaxRegisterSet { key, value }
+                } else {
+                    Operation::MinRegisterSet { key, value }
+                }
+            }
+            ConflictPolicy::KeepAll => {
+                // 与 apply_operation 记录的因果元数据一致
+                let mut clock = self.crdt_map.vector_clock.clone();
+                clock.increment(&self.node_id);
+                Operation::MvRegisterSet {
+                    value: self.seal_value(&key, value)?,
+                    node_id: self.node_id.clone(),
+                    clock,
+                    key,
+                }
+            }
+        };
+        self.apply_operation(op);
+        Ok(())
+    }
+
+    /// 设置键的冲突解决策略
+    ///
+    /// 已存在的键只能设置与其当前类型一致的策略（或 LWW 键保持 LWW）。
+    pub fn set_policy(&mut self, key: &str, policy: ConflictPolicy) -> Result<(), String> {
+        if let Some(existing) = self.crdt_map.get(key)
+            && existing.type_name() != policy.crdt_type()
+        {
+            return Err(format!(
+                "Key {} is a {} and cannot use the {} policy",
+                key,
+                existing.type_name(),
+                policy
+            ));
+        }
+        let timestamp = self.clock.now_millis();
+        let node_id = self.node_id.clone();
+        self.policies
+            .set(key.to_string(), policy, timestamp, &node_id);
+        Ok(())
+    }
+
+    /// 将元素从一个集合移动到另一个集合
+    ///
+    /// 本地先完成全部校验，再依次记录 `from_key` 上的移除与 `to_key` 上的添加。
+    /// 合并时 CRDT 无法保证跨键原子性：并发移动可能使元素暂时同时出现在两个集合中
+    /// 或都不出现，但各节点最终会收敛到相同结果。
+    pub fn move_element(
+        &mut self,
+        from_key: String,
+        to_key: String,
+        value: String,
+    ) -> Result<(), ChangeError> {
+        if from_key == to_key {
+            return Err("Move requires distinct from_key and to_key"
+                .to_string()
+                .into());
+        }
+        match self.crdt_map.get(&from_key) {
+            Some(CRDTValue::ORSet(set)) if set.contains(&value) => {}
+            Some(CRDTValue::ORSet(_)) => {
+                return Err(format!("Value '{}' not found in set {}", value, from_key).into());
+            }
+            Some(_) => self.check_key_type(&from_key, "or-set")?,
+            None => return Err(ChangeError::InvalidKey(from_key)),
+        }
+        self.check_key_type(&to_key, "or-set")?;
+        self.schemas.validate(&to_key, &value)?;
+        self.check_new_key(&to_key)?;
+        self.check_set_capacity(&to_key, &value)?;
+
+        let dots = Some(self.observed_dots(&from_key, &value));
+        self.apply_operation(Operation::OrSetRemove {
+            key: from_key,
+            value: value.clone(),
+            dots,
+        });
+        let dot = self.next_set_dot(&to_key);
+        self.apply_operation(Operation::OrSetAdd {
+            key: to_key,
+            value,
+            dot,
+        });
+        Ok(())
+    }
+
+    /// 本节点在集合上的下一个添加点（集合不存在时从 1 开始）
+    fn next_set_dot(&self, key: &str) -> Dot {
+        match self.crdt_map.get(key) {
+            Some(CRDTValue::ORSet(set)) => set.next_dot(&self.node_id),
+            _ => Dot::new(&self.node_id, 1),
+        }
+    }
+
+    /// 集合中元素当前存活的点（移除操作据此只移除本节点已观察到的添加）
+    fn observed_dots(&self, key: &str, value: &str) -> Vec<Dot> {
+        match self.crdt_map.get(key) {
+            Some(CRDTValue::ORSet(set)) => set.dots(&value.to_string()),
+            _ => Vec::new(),
+        }
+    }
+
+    /// 检查已存在的键是否为变更所需的 CRDT 类型，避免类型不符的操作被记录却不生效
+    fn check_key_type(&self, key: &str, expected: &str) -> Result<(), ChangeError> {
+        match self.crdt_map.get(key) {
+            Some(existing) if existing.type_name() != expected => Err(ChangeError::TypeMismatch {
+                key: key.to_string(),
+                expected: expected.to_string(),
+                found: existing.type_name().to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// 试运行变更请求：在状态副本上逐条应用并返回每条变更的校验结果
+    ///
+    /// 执行与 `apply_changes` 相同的校验（字段、类型、取值约束、键数量与计数器上限），
+    /// 后续变更能看到前面有效变更的效果；失败的变更不会中止试运行。
+    /// 本地状态与操作日志不会被修改，也不会触发事件回调。
+    pub fn validate_changes(&self, request: &ChangeRequest) -> Vec<ChangeValidation> {
+        let mut scratch = self.clone();
+        scratch.hooks = EventHooks::new();
+
+        request
+            .changes
+            .iter()
+            .enumerate()
+            .map(|(index, change)| {
+                let mut errors = change.field_errors(index);
+                if errors.is_empty()
+                    && let Err(message) = scratch.apply_changes(ChangeRequest {
+                        changes: vec![change.clone()],
+                    })
+                {
+                    errors.push(FieldError::new(
+                        format!("changes[{}]", index),
+                        message.to_string(),
+                    ));
+                }
+                ChangeValidation {
+                    index,
+                    valid: errors.is_empty(),
+                    errors,
+                }
+            })
+            .collect()
+    }
+
+    /// 检查创建新键是否会超出键数量上限（已存在的键不受限制）
+    fn check_new_key(&self, key: &str) -> Result<(), String> {
+        match self.max_keys {
+            Some(max_keys)
+                if !self.crdt_map.entries.contains_key(key)
+                    && self.crdt_map.entries.len() >= max_keys =>
+            {
+                Err(format!(
+                    "Key limit reached ({} keys), cannot create new key {}",
+                    max_keys, key
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 检查向集合添加新元素是否会超出元素数量上限（已存在的元素不受限制）
+    ///
+    /// 添加后接近上限时记录警告日志。
+    fn check_set_capacity(&self, key: &str, value: &str) -> Result<(), String> {
+        let Some(cap) = self.max_set_elements else {
+            return Ok(());
+        };
+        let elements = match self.crdt_map.get(key) {
+            Some(CRDTValue::ORSet(set)) if set.contains(&value.to_string()) => return Ok(()),
+            Some(CRDTValue::ORSet(set)) => set.elements().len(),
+            _ => 0,
+        };
+        if elements >= cap {
+            return Err(format!(
+                "Set element limit reached ({} elements), cannot add to set {}",
+                cap, key
+            ));
+        }
+        if set_near_capacity(elements + 1, cap) {
+            tracing::warn!(
+                "Set {} is near its element limit: {} of {}",
+                key,
+                elements + 1,
+                cap
+            );
+        }
+        Ok(())
+    }
+
+    /// 对匹配加密模式的键加密寄存器值
+    fn seal_value(&self, key: &str, value: String) -> Result<String, String> {
+        match &self.encryptor {
+            Some(encryptor) if encryptor.matches(key) => {
+                encryptor.encrypt(&value).map_err(|e| e.to_string())
+            }
+            _ => Ok(value),
+        }
+    }
+
+    /// 检查本节点对计数器的贡献在增加 `delta` 后是否超过上限
+    fn check_counter_cap(&self, key: &str, negative: bool, delta: u64) -> Result<(), String> {
+        let Some(cap) = self.counter_cap else {
+            return Ok(());
+        };
+        let counter = match self.crdt_map.get(key) {
+            Some(CRDTValue::GCounter(c)) if !negative => Some(c),
+            Some(CRDTValue::PNCounter(c)) if negative => Some(&c.negative),
+            Some(CRDTValue::PNCounter(c)) => Some(&c.positive),
+            Some(CRDTValue::BoundedCounter(c)) if negative => Some(&c.counts.negative),
+            Some(CRDTValue::BoundedCounter(c)) => Some(&c.counts.positive),
+            _ => None,
+        };
+        let current = counter
+            .and_then(|c| c.counts.get(&self.node_id))
+            .copied()
+            .unwrap_or(0);
+        if current.saturating_add(delta) > cap {
+            return Err(format!(
+                "Counter {} would exceed per-node cap of {}",
+                key, cap
+            ));
+        }
+        Ok(())
+    }
+
+    /// 若键不存在则按类型初始化（可带初始值），已存在时不做任何修改
+    ///
+    /// 判断基于本地状态：两个节点可能并发地对同一键执行 ensure，
+    /// 这种情况下双方的初始化结果会按 CRDT 规则正常合并。
+    /// 返回是否创建了新键。
+    pub fn ensure_key(
+        &mut self,
+        key: String,
+        crdt_type: &str,
+        value: Option<String>,
+        delta: Option<u64>,
+    ) -> Result<bool, String> {
+        if self.crdt_map.get(&key).is_some() {
+            return Ok(false);
+        }
+        if let Some(value) = &value {
+            self.schemas.validate(&key, value)?;
+        }
+
+        if let Some(delta) = delta {
+            self.check_counter_cap(&key, false, delta)?;
+        }
+        self.check_new_key(&key)?;
+
+        let node_id = self.node_id.clone();
+        match (crdt_type, value, delta.filter(|d| *d > 0)) {
+            ("g-counter", _, Some(delta)) => self.apply_operation(Operation::GCounterIncrement {
+                key,
+                node_id,
+                delta,
+            }),
+            ("g-counter", _, None) => self.crdt_map.set(key, CRDTValue::GCounter(GCounter::new())),
+            ("pn-counter", _, Some(delta)) => self.apply_operation(Operation::PNCounterIncrement {
+                key,
+                node_id,
+                delta,
+            }),
+            ("pn-counter", _, None) => self
+                .crdt_map
+                .set(key, CRDTValue::PNCounter(PNCounter::new())),
+            // value 为下界（默认 0），delta 为初始值相对下界的增量
+            ("bounded-counter", value, delta) => {
+                let lower_bound = match value {
+                    Some(value) => value.parse::<i64>().map_err(|_| {
+                        format!(
+                            "Invalid lower bound for bounded-counter {}: expected an integer",
+                            key
+                        )
+                    })?,
+                    None => 0,
+                };
+                match delta {
+                    Some(delta) => self.apply_operation(Operation::BoundedCounterIncrement {
+                        key,
+                        node_id,
+                        delta,
+                        lower_bound,
+                    }),
+                    None => self.crdt_map.set(
+                        key,
+                        CRDTValue::BoundedCounter(BoundedCounter::new(lower_bound)),
+                    ),
+                }
+            }
+            ("lww-register", Some(value), _) => {
+                let value = self.seal_value(&key, value)?;
+                let timestamp = self.clock.now_millis();
+                self.apply_operation(Operation::LwwRegisterSet {
+                    key,
+                    value,
+                    timestamp,
+                    node_id,
+                })
+            }
+            ("lww-register", None, _) => self
+                .crdt_map
+                .set(key, CRDTValue::LWWRegister(LWWRegister::new())),
+            ("lww-map", _, _) => self.crdt_map.set(key, CRDTValue::LWWMap(LWWMap::new())),
+            ("or-set", Some(value), _) => {
+                let dot = self.next_set_dot(&key);
+                self.apply_operation(Operation::OrSetAdd { key, value, dot })
+            }
+            ("or-set", None, _) => self.crdt_map.set(key, CRDTValue::ORSet(ORSet::new())),
+            ("g-set", Some(value), _) => self.apply_operation(Operation::GSetAdd { key, value }),
+            ("g-set", None, _) => self.crdt_map.set(key, CRDTValue::GSet(GSet::new())),
+            ("rga", _, _) => self.crdt_map.set(key, CRDTValue::RGA(RGA::new())),
+            (other, _, _) => return Err(format!("Unknown CRDT type: {}", other)),
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oplog_add_operation() {
+        let mut oplog = OpLog::new("node1".to_string());
+        let mut vc = VectorClock::new();
+
+        let op = Operation::GCounterIncrement {
+            key: "counter1".to_string(),
+            node_id: "node1".to_string(),
+            delta: 5,
+        };
+
+        oplog.add_operation(op, &mut vc);
+
+        assert_eq!(oplog.ops.len(), 1);
+        assert_eq!(vc.get("node1"), 1);
+    }
+
+    #[test]
+    fn test_oplog_merge() {
+        let mut oplog1 = OpLog::new("node1".to_string());
+        let mut oplog2 = OpLog::new("node2".to_string());
+        let mut vc = VectorClock::new();
+
+        let op1 = Operation::GCounterIncrement {
+            key: "counter1".to_string(),
+            node_id: "node1".to_string(),
+            delta: 5,
+        };
+        oplog1.add_operation(op1, &mut vc);
+
+        let op2 = Operation::GCounterIncrement {
+            key: "counter2".to_string(),
+            node_id: "node2".to_string(),
+            delta: 3,
+        };
+        oplog2.add_operation(op2, &mut vc);
+
+        oplog1.merge(&oplog2);
+
+        assert_eq!(oplog1.ops.len(), 2);
+    }
+
+    #[test]
+    fn test_sync_state_apply_gcounter_operation() {
+        let mut state = SyncState::new("node1".to_string());
+
+        let op = Operation::GCounterIncrement {
+            key: "counter1".to_string(),
+            node_id: "node1".to_string(),
+            delta: 5,
+        };
+
+        state.apply_operation(op);
+
+        if let Some(CRDTValue::GCounter(c)) = state.crdt_map.entries.get("counter1") {
+            assert_eq!(c.value(), 5);
+        } else {
+            panic!("Counter not found or wrong type");
+        }
+    }
+
+    #[test]
+    fn test_sync_state_apply_pncounter_operations() {
+        let mut state = SyncState::new("node1".to_string());
+
+        let op1 = Operation::PNCounterIncrement {
+            key: "counter1".to_string(),
+            node_id: "node1".to_string(),
+            delta: 10,
+        };
+        state.apply_operation(op1);
+
+        let op2 = Operation::PNCounterDecrement {
+            key: "counter1".to_string(),
+            node_id: "node1".to_string(),
+            delta: 3,
+        };
+        state.apply_operation(op2);
+
+        if let Some(CRDTValue::PNCounter(c)) = state.crdt_map.entries.get("counter1") {
+            assert_eq!(c.value(), 7);
+        } else {
+            panic!("Counter not found or wrong type");
+        }
+    }
+
+    #[test]
+    fn test_sync_state_apply_lww_register_operation() {
+        let mut state = SyncState::new("node1".to_string());
+
+        let op = Operation::LwwRegisterSet {
+            key: "register1".to_string(),
+            value: "test_value".to_string(),
+            timestamp: 12345,
+            node_id: "node1".to_string(),
+        };
+
+        state.apply_operation(op);
+
+        if let Some(CRDTValue::LWWRegister(r)) = state.crdt_map.entries.get("register1") {
+            assert_eq!(r.get(), Some(&"test_value".to_string()));
+        } else {
+            panic!("Register not found or wrong type");
+        }
+    }
+
+    #[test]
+    fn test_sync_state_apply_orset_operations() {
+        let mut state = SyncState::new("node1".to_string());
+
+        let op1 = Operation::OrSetAdd {
+            key: "set1".to_string(),
+            value: "item1".to_string(),
+            dot: Dot::new("node1", 1),
+        };
+        state.apply_operation(op1);
+
+        let op2 = Operation::OrSetAdd {
+            key: "set1".to_string(),
+            value: "item2".to_string(),
+            dot: Dot::new("node1", 2),
+        };
+        state.apply_operation(op2);
+
+        if let Some(CRDTValue::ORSet(s)) = state.crdt_map.entries.get("set1") {
+            let elements = s.elements();
+            assert_eq!(elements.len(), 2);
+            assert!(elements.contains(&"item1".to_string()));
+            assert!(elements.contains(&"item2".to_string()));
+        } else {
+            panic!("Set not found or wrong type");
+        }
+    }
+
+    #[test]
+    fn test_oplog_filter_by_key_and_node() {
+        let mut state = SyncState::new("node1".to_string());
+        state.apply_operation(Operation::GCounterIncrement {
+            key: "counter1".to_string(),
+            node_id: "node1".to_string(),
+            delta: 1,
+        });
+        let mut other = SyncState::new("node2".to_string());
+        other.apply_operation(Operation::GCounterIncrement {
+            key: "counter2".to_string(),
+            node_id: "node2".to_string(),
+            delta: 1,
+        });
+        other.apply_operation(Operation::LwwRegisterSet {
+            key: "counter1".to_string(),
+            value: "x".to_string(),
+            timestamp: 1,
+            node_id: "node2".to_string(),
+        });
+        // OR-Set 添加不携带 node_id，按日志条目的发起节点匹配
+        other.apply_operation(Operation::OrSetAdd {
+            key: "tags".to_string(),
+            value: "a".to_string(),
+            dot: Dot {
+                node_id: "node2".to_string(),
+                counter: 1,
+            },
+        });
+        assert_eq!(other.op_log.ops[2].op.node_id(), None);
+        state.merge(&other);
+
+        let by_key = state.op_log.filtered(&OpLogFilter {
+            key: Some("counter1".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(by_key.ops.len(), 2);
+        assert!(by_key.ops.iter().all(|e| e.op.key() == "counter1"));
+
+        let by_node = state.op_log.filtered(&OpLogFilter {
+            node: Some("node2".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(by_node.ops.len(), 3);
+        assert!(by_node.ops.iter().all(|e| e.origin_node() == Some("node2")));
+        assert!(
+            by_node
+                .ops
+                .iter()
+                .any(|e| matches!(e.op, Operation::OrSetAdd { .. }))
+        );
+
+        let combined = state.op_log.filtered(&OpLogFilter {
+            key: Some("counter1".to_string()),
+            node: Some("node1".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(combined.ops.len(), 1);
+
+        let first_ts = state.op_log.ops[0].ts;
+        let none = state.op_log.filtered(&OpLogFilter {
+            until_ts: Some(first_ts - 1),
+            ..Default::default()
+        });
+        assert!(none.ops.is_empty());
+
+        let all = state.op_log.filtered(&OpLogFilter {
+            since_ts: Some(first_ts),
+            ..Default::default()
+        });
+        assert_eq!(all.ops.len(), 4);
+    }
+
+    #[test]
+    fn test_oplog_page_follows_cursor() {
+        let mut state = SyncState::new("node1".to_string());
+        for i in 0..25 {
+            state.apply_operation(Operation::GCounterIncrement {
+                key: if i % 5 == 0 { "hot" } else { "cold" }.to_string(),
+                node_id: "node1".to_string(),
+                delta: 1,
+            });
+        }
+        let filter = OpLogFilter::default();
+
+        let mut seen = Vec::new();
+        let mut page = OpLogPage {
+            limit: Some(10),
+            after_id: None,
+        };
+        loop {
+            let (oplog, next_after_id) = state.op_log.page(&filter, &page).unwrap();
+            assert!(oplog.ops.len() <= 10);
+            seen.extend(oplog.ops.into_iter().map(|entry| entry.id));
+            match next_after_id {
+                Some(cursor) => page.after_id = Some(cursor),
+                None => break,
+            }
+        }
+        let all: Vec<String> = state.op_log.ops.iter().map(|e| e.id.clone()).collect();
+        assert_eq!(seen, all);
+
+        // 按键过滤后分页，最后一页恰好取完时不返回游标
+        let hot = OpLogFilter {
+            key: Some("hot".to_string()),
+            ..Default::default()
+        };
+        let (first, cursor) = state
+            .op_log
+            .page(
+                &hot,
+                &OpLogPage {
+                    limit: Some(3),
+                    after_id: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(first.ops.len(), 3);
+        assert!(first.ops.iter().all(|e| e.op.key() == "hot"));
+        let (rest, cursor) = state
+            .op_log
+            .page(
+                &hot,
+                &OpLogPage {
+                    limit: Some(3),
+                    after_id: cursor,
+                },
+            )
+            .unwrap();
+        assert_eq!(rest.ops.len(), 2);
+        assert!(cursor.is_none());
+
+        let by_type = state.op_log.filtered(&OpLogFilter {
+            op_type: Some("LWWRegister.Set".to_string()),
+            ..Default::default()
+        });
+        assert!(by_type.ops.is_empty());
+
+        let unknown = OpLogPage {
+            limit: None,
+            after_id: Some("missing".to_string()),
+        };
+        assert!(state.op_log.page(&filter, &unknown).is_err());
+    }
+
+    #[test]
+    fn test_restore_snapshot_reverts_value() {
+        let set_title = |state: &mut SyncState, title: &str| {
+            state
+                .apply_changes(ChangeRequest {
+                    changes: vec![Change {
+                        op: "set".to_string(),
+                        key: "title".to_string(),
+                        value: Some(title.to_string()),
+                        ..Default::default()
+                    }],
+                })
+                .unwrap();
+        };
+        let title = |state: &SyncState| state.crdt_map.document()["title"].clone();
+
+        let mut state = SyncState::new("node1".to_string());
+        set_title(&mut state, "good");
+        let snapshot = state.clone();
+        set_title(&mut state, "mangled");
+        set_title(&mut state, "worse");
+        let mangled_hash = state.state_hash();
+
+        // 保留操作日志，只回滚物化状态
+        let mut kept = state.clone();
+        let report = kept.restore_snapshot(snapshot.clone(), true);
+        assert_eq!(report.dropped_ops, 0);
+        assert_eq!(report.discarded_state_hash, mangled_hash);
+        assert_eq!(title(&kept), "good");
+        assert_eq!(kept.op_log.ops.len(), 3);
+
+        let report = state.restore_snapshot(snapshot.clone(), false);
+        assert_eq!(report.dropped_ops, 2);
+        assert_eq!(title(&state), "good");
+        assert_eq!(state.state_hash(), snapshot.state_hash());
+        assert_eq!(state.op_log.ops.len(), 1);
+    }
+
+    #[test]
+    fn test_state_bundle_round_trip() {
+        let mut state = SyncState::new("node1".to_string());
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![
+                    Change {
+                        op: "increment".to_string(),
+                        key: "visits".to_string(),
+                        delta: Some(3),
+                        ..Default::default()
+                    },
+                    Change {
+                        op: "add".to_string(),
+                        key: "tags".to_string(),
+                        value: Some("rust".to_string()),
+                        ..Default::default()
+                    },
+                    Change {
+                        op: "set".to_string(),
+                        key: "title".to_string(),
+                        value: Some("hello".to_string()),
+                        ..Default::default()
+                    },
+                ],
+            })
+            .unwrap();
+        let original = state.state_hash();
+
+        for format in [BundleFormat::Json, BundleFormat::Cbor] {
+            let bytes = StateBundle::new(&state).encode(format).unwrap();
+            let mut restored = state.clone();
+            restored.reset();
+            assert_ne!(restored.state_hash(), original);
+
+            let bundle = StateBundle::decode(&bytes, format).unwrap();
+            assert_eq!(bundle.format_version, STATE_BUNDLE_VERSION);
+            restored.replace_with(bundle.state);
+            assert_eq!(restored.state_hash(), original);
+        }
+    }
+
+    #[test]
+    fn test_state_bundle_rejects_incompatible_or_corrupted() {
+        let state = SyncState::new("node1".to_string());
+        let mut bundle = serde_json::to_value(StateBundle::new(&state)).unwrap();
+
+        bundle["format_version"] = serde_json::json!(STATE_BUNDLE_VERSION + 1);
+        let err =
+            StateBundle::decode(bundle.to_string().as_bytes(), BundleFormat::Json).unwrap_err();
+        assert!(err.contains("Incompatible state bundle format version"));
+
+        bundle["format_version"] = serde_json::json!(STATE_BUNDLE_VERSION);
+        bundle["state_hash"] = serde_json::json!("0000");
+        let err =
+            StateBundle::decode(bundle.to_string().as_bytes(), BundleFormat::Json).unwrap_err();
+        assert!(err.contains("corrupted"));
+
+        assert!(StateBundle::decode(b"not a bundle", BundleFormat::Cbor).is_err());
+        assert!("xml".parse::<BundleFormat>().is_err());
+    }
+
+    #[test]
+    fn test_sync_state_merge() {
+        let mut state1 = SyncState::new("node1".to_string());
+        let mut state2 = SyncState::new("node2".to_string());
+
+        let op1 = Operation::GCounterIncrement {
+            key: "counter1".to_string(),
+            node_id: "node1".to_string(),
+            delta: 5,
+        };
+        state1.apply_operation(op1);
+
+        let op2 = Operation::GCounterIncrement {
+            key: "counter1".to_string(),
+            node_id: "node2".to_string(),
+            delta: 3,
+        };
+        state2.apply_operation(op2);
+
+        state1.merge(&state2);
+
+        if let Some(CRDTValue::GCounter(c)) = state1.crdt_map.entries.get("counter1") {
+            assert_eq!(c.value(), 8);
+        } else {
+            panic!("Counter not found or wrong type");
+        }
+    }
+
+    #[test]
+    fn test_merge_tracking_changes_reports_changed_keys() {
+        let mut state1 = SyncState::new("node1".to_string());
+        let mut state2 = SyncState::new("node2".to_string());
+        let increment = |state: &mut SyncState, key: &str| {
+            let node_id = state.node_id.clone();
+            state.apply_operation(Operation::GCounterIncrement {
+                key: key.to_string(),
+                node_id,
+                delta: 1,
+            });
+        };
+        increment(&mut state1, "shared");
+        state2.merge(&state1);
+        increment(&mut state2, "new");
+
+        // 对端的 shared 与本地一致，只有 new 发生变化
+        assert_eq!(state1.merge_tracking_changes(&state2), vec!["new"]);
+        assert!(state1.merge_tracking_changes(&state2).is_empty());
+    }
+
+    #[test]
+    fn test_delta_merge_matches_full_merge() {
+        let change = |op: &str, key: &str, value: Option<&str>, delta: Option<u64>| ChangeRequest {
+            changes: vec![Change {
+                op: op.to_string(),
+                key: key.to_string(),
+                value: value.map(str::to_string),
+                delta,
+                ..Default::default()
+            }],
+        };
+        let mut state1 = SyncState::new("node1".to_string());
+        let mut state2 = SyncState::new("node2".to_string());
+        for i in 0..5 {
+            state1
+                .apply_changes(change("set", &format!("reg{}", i), Some("v"), None))
+                .unwrap();
+        }
+        state1
+            .apply_changes(change("add", "tags", Some("a"), None))
+            .unwrap();
+        state2.merge(&state1);
+
+        // 同步之后两端各自产生新的写入
+        state1
+            .apply_changes(change("increment", "hits", None, Some(3)))
+            .unwrap();
+        state1
+            .apply_changes(change("add", "tags", Some("b"), None))
+            .unwrap();
+        state2
+            .apply_changes(change("remove", "tags", Some("a"), None))
+            .unwrap();
+
+        let delta = state1.delta_since(&state2.crdt_map.vector_clock);
+        assert_eq!(delta.op_log.ops.len(), 2);
+        let mut keys: Vec<_> = delta.crdt_map.entries.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["hits", "tags"]);
+
+        let mut full = state2.clone();
+        full.merge(&state1);
+        let mut incremental = state2.clone();
+        incremental.merge_delta(&delta);
+        assert_eq!(incremental.state_hash(), full.state_hash());
+        assert_eq!(incremental.op_log.ops.len(), full.op_log.ops.len());
+    }
+
+    #[test]
+    fn test_set_delta_sends_only_missing_dots() {
+        let change = |op: &str, value: &str| ChangeRequest {
+            changes: vec![Change {
+                op: op.to_string(),
+                key: "tags".to_string(),
+                value: Some(value.to_string()),
+                ..Default::default()
+            }],
+        };
+        let mut state1 = SyncState::new("node1".to_string());
+        for i in 0..20 {
+            state1
+                .apply_changes(change("add", &format!("t{}", i)))
+                .unwrap();
+        }
+        let mut state2 = SyncState::new("node2".to_string());
+        state2.merge(&state1);
+
+        state1.apply_changes(change("add", "new")).unwrap();
+        state1.apply_changes(change("remove", "t0")).unwrap();
+        state2.apply_changes(change("add", "t0")).unwrap();
+
+        let Some(CRDTValue::ORSet(peer_set)) = state2.crdt_map.get("tags") else {
+            panic!("tags should be an or-set");
+        };
+        let set_contexts = HashMap::from([("tags".to_string(), peer_set.context.clone())]);
+        let delta = state1.delta_since_with_sets(&state2.crdt_map.vector_clock, &set_contexts);
+        let Some(CRDTValue::ORSet(delta_set)) = delta.crdt_map.get("tags") else {
+            panic!("delta should carry the set");
+        };
+        assert_eq!(delta_set.elements(), vec!["new".to_string()]);
+
+        let mut full = state2.clone();
+        full.merge(&state1);
+        let mut incremental = state2.clone();
+        incremental.merge_delta(&delta);
+        assert_eq!(incremental.state_hash(), full.state_hash());
+        // node2 并发的再次添加优先于 node1 的移除
+        let Some(CRDTValue::ORSet(merged)) = incremental.crdt_map.get("tags") else {
+            panic!("tags should be an or-set");
+        };
+        assert!(merged.contains(&"t0".to_string()));
+        assert_eq!(merged.elements().len(), 21);
+    }
+
+    #[test]
+    fn test_merge_buffers_until_causal_predecessors_arrive() {
+        let change = |op: &str, key: &str, value: Option<&str>| ChangeRequest {
+            changes: vec![Change {
+                op: op.to_string(),
+                key: key.to_string(),
+                value: value.map(str::to_string),
+                ..Default::default()
+            }],
+        };
+        let mut state1 = SyncState::new("node1".to_string());
+        state1
+            .apply_changes(change("increment", "hits", None))
+            .unwrap();
+        let after_first = state1.clone();
+        state1
+            .apply_changes(change("set", "name", Some("alice")))
+            .unwrap();
+        let mut state2 = SyncState::new("node2".to_string());
+        state2.merge(&state1);
+        // node2 的写入因果依赖 node1 的两个操作
+        state2
+            .apply_changes(change("add", "tags", Some("a")))
+            .unwrap();
+
+        // 三个增量各只含一个操作，后两个基于接收方未知的基线导出
+        let first = after_first.delta_since(&VectorClock::new());
+        let second = state1.delta_since(&after_first.crdt_map.vector_clock);
+        let third = state2.delta_since(&state1.crdt_map.vector_clock);
+        assert_eq!(second.op_log.ops.len(), 1);
+        assert_eq!(third.op_log.ops.len(), 1);
+
+        // 逆序送达：前驱到达之前不应用任何值
+        let mut reversed = SyncState::new("node3".to_string());
+        reversed.merge(&third);
+        reversed.merge(&second);
+        assert_eq!(reversed.pending_ops(), 2);
+        assert!(reversed.crdt_map.entries.is_empty());
+        assert!(reversed.op_log.ops.is_empty());
+
+        // 重复送达不会重复缓冲
+        reversed.merge(&third);
+        assert_eq!(reversed.pending_ops(), 2);
+
+        // 前驱到达后，暂缓的操作按因果顺序应用
+        reversed.merge(&first);
+        assert_eq!(reversed.pending_ops(), 0);
+        assert_eq!(reversed.op_log.ops.len(), 3);
+        assert_eq!(reversed.state_hash(), state2.state_hash());
+
+        // 已应用的操作再次送达时被跳过（计数器不会重复累加）
+        reversed.merge(&state2);
+        assert_eq!(reversed.state_hash(), state2.state_hash());
+        assert_eq!(reversed.crdt_map.document()["hits"], 1);
+    }
+
+    #[test]
+    fn test_state_merge_releases_covered_pending_ops() {
+        let mut state1 = SyncState::new("node1".to_string());
+        for _ in 0..2 {
+            state1
+                .apply_changes(ChangeRequest {
+                    changes: vec![Change {
+                        op: "increment".to_string(),
+                        key: "hits".to_string(),
+                        ..Default::default()
+                    }],
+                })
+                .unwrap();
+        }
+
+        let mut state2 = SyncState::new("node2".to_string());
+        let first_clock = state1.op_log.ops[0].causal.clone();
+        state2.merge(&state1.delta_since(&first_clock));
+        assert_eq!(state2.pending_ops(), 1);
+
+        // 全量合并已包含该操作的效果：暂缓条目被丢弃而不是再次应用
+        state2.merge(&state1);
+        assert_eq!(state2.pending_ops(), 0);
+        assert_eq!(state2.state_hash(), state1.state_hash());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_sync_state_state_hash() {
+        let mut state = SyncState::new("node1".to_string());
+
+        let op = Operation::GCounterIncrement {
+            key: "counter1".to_string(),
+            node_id: "node1".to_string(),
+            delta: 5,
+        };
+        state.apply_operation(op);
+
+        let hash1 = state.state_hash();
+        let hash2 = state.state_hash();
+
+        assert_eq!(hash1, hash2);
+        assert!(!hash1.is_empty());
+    }
 
     #[test]
-    fn test_oplog_add_operation() {
-        let mut oplog = OpLog::new("node1".to_string());
-        let mut vc = VectorClock::new();
+    fn test_sync_state_export_oplog() {
+        let mut state = SyncState::new("node1".to_string());
 
         let op = Operation::GCounterIncrement {
             key: "counter1".to_string(),
             node_id: "node1".to_string(),
             delta: 5,
         };
+        state.apply_operation(op);
+
+        let result = state.export_oplog();
+        assert!(result.is_ok());
+
+        let json = result.unwrap();
+        assert!(json.contains("counter1"));
+    }
+
+    #[test]
+    fn test_sync_state_apply_changes_increment() {
+        let mut state = SyncState::new("node1".to_string());
+
+        let change = Change {
+            op: "increment".to_string(),
+            key: "counter1".to_string(),
+            value: None,
+            delta: Some(5),
+            ..Default::default()
+        };
+
+        let request = ChangeRequest {
+            changes: vec![change],
+        };
+
+        let result = state.apply_changes(request);
+        assert!(result.is_ok());
+
+        if let Some(CRDTValue::GCounter(c)) = state.crdt_map.entries.get("counter1") {
+            assert_eq!(c.value(), 5);
+        }
+    }
+
+    #[test]
+    fn test_sync_state_apply_changes_decrement() {
+        let mut state = SyncState::new("node1".to_string());
+
+        let changes = vec![
+            Change {
+                op: "increment".to_string(),
+                key: "counter1".to_string(),
+                value: None,
+                delta: Some(10),
+                ..Default::default()
+            },
+            Change {
+                op: "decrement".to_string(),
+                key: "counter1".to_string(),
+                value: None,
+                delta: Some(3),
+                ..Default::default()
+            },
+        ];
+
+        let request = ChangeRequest { changes };
+
+        let result = state.apply_changes(request);
+        assert!(result.is_ok());
+
+        if let Some(CRDTValue::PNCounter(c)) = state.crdt_map.entries.get("counter1") {
+            assert_eq!(c.value(), 7);
+        }
+    }
+
+    #[test]
+    fn test_sync_state_apply_changes_add() {
+        let mut state = SyncState::new("node1".to_string());
+
+        let change = Change {
+            op: "add".to_string(),
+            key: "set1".to_string(),
+            value: Some("item1".to_string()),
+            delta: None,
+            ..Default::default()
+        };
+
+        let request = ChangeRequest {
+            changes: vec![change],
+        };
+
+        let result = state.apply_changes(request);
+        assert!(result.is_ok());
+
+        if let Some(CRDTValue::ORSet(s)) = state.crdt_map.entries.get("set1") {
+            assert!(s.contains(&"item1".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_sync_state_apply_changes_set() {
+        let mut state = SyncState::new("node1".to_string());
+
+        let change = Change {
+            op: "set".to_string(),
+            key: "register1".to_string(),
+            value: Some("test_value".to_string()),
+            delta: None,
+            ..Default::default()
+        };
+
+        let request = ChangeRequest {
+            changes: vec![change],
+        };
+
+        let result = state.apply_changes(request);
+        assert!(result.is_ok());
+
+        if let Some(CRDTValue::LWWRegister(r)) = state.crdt_map.entries.get("register1") {
+            assert_eq!(r.get(), Some(&"test_value".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_sync_state_apply_changes_remove() {
+        let mut state = SyncState::new("node1".to_string());
+
+        let changes = vec![
+            Change {
+                op: "add".to_string(),
+                key: "set1".to_string(),
+                value: Some("item1".to_string()),
+                delta: None,
+                ..Default::default()
+            },
+            Change {
+                op: "remove".to_string(),
+                key: "set1".to_string(),
+                value: Some("item1".to_string()),
+                delta: None,
+                ..Default::default()
+            },
+        ];
+
+        let request = ChangeRequest { changes };
+
+        let result = state.apply_changes(request);
+        assert!(result.is_ok());
+
+        if let Some(CRDTValue::ORSet(s)) = state.crdt_map.entries.get("set1") {
+            assert!(!s.contains(&"item1".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_gset_add_merges_in_any_order() {
+        let g_add = |value: &str| ChangeRequest {
+            changes: vec![Change {
+                op: "g-add".to_string(),
+                key: "seen".to_string(),
+                value: Some(value.to_string()),
+                ..Default::default()
+            }],
+        };
+        let mut state1 = SyncState::new("node1".to_string());
+        let mut state2 = SyncState::new("node2".to_string());
+        state1.apply_changes(g_add("a")).unwrap();
+        state1.apply_changes(g_add("b")).unwrap();
+        state2.apply_changes(g_add("b")).unwrap();
+        state2.apply_changes(g_add("c")).unwrap();
+
+        // 只增集合不支持移除
+        let remove = ChangeRequest {
+            changes: vec![Change {
+                op: "remove".to_string(),
+                key: "seen".to_string(),
+                value: Some("a".to_string()),
+                ..Default::default()
+            }],
+        };
+        assert!(state1.apply_changes(remove).is_err());
+
+        let mut merged12 = state1.clone();
+        merged12.merge(&state2);
+        let mut merged21 = state2.clone();
+        merged21.merge(&state1);
+        assert_eq!(merged12.state_hash(), merged21.state_hash());
+
+        let Some(CRDTValue::GSet(seen)) = merged12.crdt_map.get("seen") else {
+            panic!("seen should be a g-set");
+        };
+        let mut elements = seen.elements();
+        elements.sort();
+        assert_eq!(elements, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_rga_changes_replicate_through_oplog() {
+        let change = |op: &str, value: Option<&str>, position: Option<String>| ChangeRequest {
+            changes: vec![Change {
+                op: op.to_string(),
+                key: "list".to_string(),
+                value: value.map(str::to_string),
+                position,
+                ..Default::default()
+            }],
+        };
+        let ids = |state: &SyncState| -> Vec<String> {
+            let Some(CRDTValue::RGA(list)) = state.crdt_map.get("list") else {
+                panic!("list should be an rga");
+            };
+            list.elements()
+                .iter()
+                .map(|(id, _)| id.to_string())
+                .collect()
+        };
+
+        let mut state1 = SyncState::new("node1".to_string());
+        state1
+            .apply_changes(change("rga-insert", Some("a"), None))
+            .unwrap();
+        let a = ids(&state1)[0].clone();
+        state1
+            .apply_changes(change("rga-insert", Some("c"), Some(a.clone())))
+            .unwrap();
+
+        let mut state2 = SyncState::new("node2".to_string());
+        state2.merge(&state1);
+        state1
+            .apply_changes(change("rga-insert", Some("b"), Some(a.clone())))
+            .unwrap();
+        state2
+            .apply_changes(change("rga-delete", None, Some(a)))
+            .unwrap();
+
+        // 未知位置与非法格式均被拒绝
+        let error = state1
+            .apply_changes(change("rga-insert", Some("x"), Some("9@node9".to_string())))
+            .unwrap_err();
+        assert_eq!(error.code(), "change_rejected");
+        assert!(
+            change("rga-delete", None, Some("oops".to_string()))
+                .validate()
+                .is_err()
+        );
+
+        let mut merged12 = state1.clone();
+        merged12.merge(&state2);
+        let mut merged21 = state2.clone();
+        merged21.merge(&state1);
+        assert_eq!(merged12.state_hash(), merged21.state_hash());
+        assert_eq!(
+            merged12.crdt_map.get("list").unwrap().materialized(),
+            serde_json::json!(["b", "c"])
+        );
+
+        // 重放操作日志得到相同序列
+        let (replayed, _) = merged12.replay(|_| true);
+        assert_eq!(replayed.state_hash(), merged12.crdt_map.state_hash());
+    }
+
+    #[test]
+    fn test_sync_state_apply_changes_error_missing_value() {
+        let mut state = SyncState::new("node1".to_string());
+
+        let change = Change {
+            op: "add".to_string(),
+            key: "set1".to_string(),
+            value: None,
+            delta: None,
+            ..Default::default()
+        };
+
+        let request = ChangeRequest {
+            changes: vec![change],
+        };
+
+        let result = state.apply_changes(request);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_sync_state_apply_changes_error_unknown_op() {
+        let mut state = SyncState::new("node1".to_string());
+
+        let change = Change {
+            op: "unknown_op".to_string(),
+            key: "test".to_string(),
+            value: None,
+            delta: None,
+            ..Default::default()
+        };
+
+        let request = ChangeRequest {
+            changes: vec![change],
+        };
+
+        let result = state.apply_changes(request);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_convert_key_preserves_value_and_propagates() {
+        let mut state1 = SyncState::new("node1".to_string());
+        let mut state2 = SyncState::new("node2".to_string());
+
+        state1.apply_operation(Operation::GCounterIncrement {
+            key: "hits".to_string(),
+            node_id: "node1".to_string(),
+            delta: 5,
+        });
+        state2.merge(&state1);
+
+        state1.convert_key("hits", "pn-counter").unwrap();
+        match state1.crdt_map.get("hits") {
+            Some(CRDTValue::PNCounter(c)) => assert_eq!(c.value(), 5),
+            _ => panic!("Expected PNCounter"),
+        }
+        assert!(matches!(
+            state1.op_log.ops.last().map(|e| &e.op),
+            Some(Operation::ConvertType { .. })
+        ));
+
+        // 未转换的副本继续按 GCounter 递增
+        state2.apply_operation(Operation::GCounterIncrement {
+            key: "hits".to_string(),
+            node_id: "node2".to_string(),
+            delta: 2,
+        });
+        state1.apply_operation(Operation::PNCounterDecrement {
+            key: "hits".to_string(),
+            node_id: "node1".to_string(),
+            delta: 1,
+        });
+
+        state1.merge(&state2);
+        state2.merge(&state1);
+
+        assert_eq!(state1.state_hash(), state2.state_hash());
+        match state2.crdt_map.get("hits") {
+            Some(CRDTValue::PNCounter(c)) => assert_eq!(c.value(), 6),
+            _ => panic!("Expected PNCounter"),
+        }
+    }
+
+    #[test]
+    fn test_convert_key_rejects_unsupported() {
+        let mut state = SyncState::new("node1".to_string());
+        state.apply_operation(Operation::LwwRegisterSet {
+            key: "name".to_string(),
+            value: "x".to_string(),
+            timestamp: 1,
+            node_id: "node1".to_string(),
+        });
+
+        assert!(state.convert_key("name", "pn-counter").is_err());
+        assert!(state.convert_key("missing", "pn-counter").is_err());
+        assert_eq!(state.op_log.ops.len(), 1);
+    }
+
+    #[test]
+    fn test_sync_state_apply_changes_ensure() {
+        let mut state = SyncState::new("node1".to_string());
+
+        let ensure = |key: &str, crdt_type: &str, value: &str| ChangeRequest {
+            changes: vec![Change {
+                op: "ensure".to_string(),
+                key: key.to_string(),
+                value: Some(value.to_string()),
+                crdt_type: Some(crdt_type.to_string()),
+                ..Default::default()
+            }],
+        };
 
-        oplog.add_operation(op, &mut vc);
+        // 缺失的键会被创建
+        state
+            .apply_changes(ensure("name", "lww-register", "default"))
+            .unwrap();
+        if let Some(CRDTValue::LWWRegister(r)) = state.crdt_map.get("name") {
+            assert_eq!(r.get(), Some(&"default".to_string()));
+        } else {
+            panic!("Register not found or wrong type");
+        }
 
-        assert_eq!(oplog.ops.len(), 1);
-        assert_eq!(vc.get("node1"), 1);
+        // 已存在的键保持不变
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "set".to_string(),
+                    key: "name".to_string(),
+                    value: Some("custom".to_string()),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        let hash = state.state_hash();
+        let ops = state.op_log.ops.len();
+        state
+            .apply_changes(ensure("name", "lww-register", "default"))
+            .unwrap();
+        state.apply_changes(ensure("name", "or-set", "x")).unwrap();
+        assert_eq!(state.state_hash(), hash);
+        assert_eq!(state.op_log.ops.len(), ops);
+
+        // 不带初始值的 ensure 创建空 CRDT
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "ensure".to_string(),
+                    key: "hits".to_string(),
+                    crdt_type: Some("pn-counter".to_string()),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        assert!(matches!(
+            state.crdt_map.get("hits"),
+            Some(CRDTValue::PNCounter(c)) if c.value() == 0
+        ));
+
+        // 缺少或未知的类型会报错
+        assert!(state.apply_changes(ensure("other", "bogus", "x")).is_err());
+        assert!(
+            state
+                .apply_changes(ChangeRequest {
+                    changes: vec![Change {
+                        op: "ensure".to_string(),
+                        key: "other".to_string(),
+                        ..Default::default()
+                    }],
+                })
+                .is_err()
+        );
     }
 
     #[test]
-    fn test_oplog_merge() {
-        let mut oplog1 = OpLog::new("node1".to_string());
-        let mut oplog2 = OpLog::new("node2".to_string());
-        let mut vc = VectorClock::new();
+    fn test_convergence_property() {
+        // 测试 CRDT 的收敛性：两个节点以不同顺序合并应该得到相同结果
+        let mut state1 = SyncState::new("node1".to_string());
+        let mut state2 = SyncState::new("node2".to_string());
+        let mut state3 = SyncState::new("node3".to_string());
 
         let op1 = Operation::GCounterIncrement {
-            key: "counter1".to_string(),
+            key: "counter".to_string(),
             node_id: "node1".to_string(),
             delta: 5,
         };
-        oplog1.add_operation(op1, &mut vc);
+        state1.apply_operation(op1);
 
         let op2 = Operation::GCounterIncrement {
-            key: "counter2".to_string(),
+            key: "counter".to_string(),
             node_id: "node2".to_string(),
             delta: 3,
         };
-        oplog2.add_operation(op2, &mut vc);
+        state2.apply_operation(op2);
 
-        oplog1.merge(&oplog2);
+        // state3 先合并 state1，再合并 state2
+        state3.merge(&state1);
+        state3.merge(&state2);
 
-        assert_eq!(oplog1.ops.len(), 2);
+        // 创建另一个副本，以相反顺序合并
+        let mut state4 = SyncState::new("node4".to_string());
+        state4.merge(&state2);
+        state4.merge(&state1);
+
+        // 两者应该产生相同的状态哈希
+        assert_eq!(state3.state_hash(), state4.state_hash());
     }
 
     #[test]
-    fn test_sync_state_apply_gcounter_operation() {
-        let mut state = SyncState::new("node1".to_string());
+    fn test_conflict_stats_accumulate_on_merge() {
+        let set = |key: &str, value: &str, node: &str| Operation::LwwRegisterSet {
+            key: key.to_string(),
+            value: value.to_string(),
+            timestamp: 100,
+            node_id: node.to_string(),
+        };
 
-        let op = Operation::GCounterIncrement {
-            key: "counter1".to_string(),
+        let mut state1 = SyncState::new("node1".to_string());
+        let mut state2 = SyncState::new("node2".to_string());
+
+        // 两个节点并发写入同一个键
+        state1.apply_operation(set("title", "a", "node1"));
+        state2.apply_operation(set("title", "b", "node2"));
+        state1.merge(&state2);
+        assert_eq!(state1.conflict_count("title"), 1);
+
+        // node2 再次并发写入
+        state2.apply_operation(set("title", "c", "node2"));
+        state1.merge(&state2);
+        assert_eq!(state1.conflict_count("title"), 2);
+
+        // 重复合并同一状态不会重复计数
+        state1.merge(&state2);
+        assert_eq!(state1.conflict_count("title"), 2);
+
+        // 因果有序的写入不算冲突
+        state1.apply_operation(set("status", "x", "node1"));
+        state2.merge(&state1);
+        state2.apply_operation(set("status", "y", "node2"));
+        state1.merge(&state2);
+        assert_eq!(state1.conflict_count("status"), 0);
+
+        assert_eq!(
+            state1.conflict_stats_sorted(),
+            vec![("title".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn test_replace_with_adopts_peer_state() {
+        let mut local = SyncState::new("node1".to_string());
+        local.apply_operation(Operation::GCounterIncrement {
+            key: "local-only".to_string(),
             node_id: "node1".to_string(),
-            delta: 5,
+            delta: 7,
+        });
+        let local_hash = local.state_hash();
+
+        let mut peer = SyncState::new("node2".to_string());
+        peer.apply_operation(Operation::GCounterIncrement {
+            key: "counter".to_string(),
+            node_id: "node2".to_string(),
+            delta: 3,
+        });
+        peer.apply_operation(Operation::LwwRegisterSet {
+            key: "name".to_string(),
+            value: "peer".to_string(),
+            timestamp: 1,
+            node_id: "node2".to_string(),
+        });
+
+        let discarded = local.replace_with(peer.clone());
+        assert_eq!(discarded, local_hash);
+
+        assert_eq!(local.state_hash(), peer.state_hash());
+        assert_eq!(local.crdt_map.vector_clock, peer.crdt_map.vector_clock);
+        assert_eq!(local.op_log.ops.len(), peer.op_log.ops.len());
+        assert!(local.crdt_map.get("local-only").is_none());
+
+        // 之后的本地写入仍归属本节点
+        assert_eq!(local.node_id, "node1");
+        assert_eq!(local.op_log.node_id, "node1");
+    }
+
+    fn status_schema() -> SchemaRegistry {
+        let mut schemas = SchemaRegistry::new();
+        schemas.register(
+            "status".to_string(),
+            crate::schema::KeySchema {
+                allowed: Some(vec!["open".to_string(), "closed".to_string()]),
+                ..Default::default()
+            },
+        );
+        schemas
+    }
+
+    #[test]
+    fn test_apply_changes_enforces_schema() {
+        let mut state = SyncState::new("node1".to_string());
+        state.schemas = status_schema();
+
+        let set = |value: &str| ChangeRequest {
+            changes: vec![Change {
+                op: "set".to_string(),
+                key: "status".to_string(),
+                value: Some(value.to_string()),
+                delta: None,
+                ..Default::default()
+            }],
         };
 
-        state.apply_operation(op);
+        assert!(state.apply_changes(set("pending")).is_err());
+        assert!(state.crdt_map.get("status").is_none());
 
-        if let Some(CRDTValue::GCounter(c)) = state.crdt_map.entries.get("counter1") {
-            assert_eq!(c.value(), 5);
+        assert!(state.apply_changes(set("open")).is_ok());
+        if let Some(CRDTValue::LWWRegister(r)) = state.crdt_map.get("status") {
+            assert_eq!(r.get(), Some(&"open".to_string()));
         } else {
-            panic!("Counter not found or wrong type");
+            panic!("Register not found or wrong type");
         }
     }
 
     #[test]
-    fn test_sync_state_apply_pncounter_operations() {
+    fn test_merge_quarantines_schema_violations() {
+        let mut local = SyncState::new("node1".to_string());
+        local.schemas = status_schema();
+
+        // 对端未配置约束，写入了非法值
+        let mut peer = SyncState::new("node2".to_string());
+        peer.apply_operation(Operation::LwwRegisterSet {
+            key: "status".to_string(),
+            value: "bogus".to_string(),
+            timestamp: 1,
+            node_id: "node2".to_string(),
+        });
+        peer.apply_operation(Operation::LwwRegisterSet {
+            key: "name".to_string(),
+            value: "ok".to_string(),
+            timestamp: 1,
+            node_id: "node2".to_string(),
+        });
+
+        local.merge(&peer);
+
+        assert!(local.crdt_map.get("status").is_none());
+        assert!(local.crdt_map.get("name").is_some());
+        assert_eq!(local.op_log.ops.len(), 1);
+        assert_eq!(local.quarantine.len(), 1);
+    }
+
+    #[test]
+    fn test_bounded_counter_changes_converge_under_concurrent_decrements() {
+        let change = |op: &str, delta: u64| ChangeRequest {
+            changes: vec![Change {
+                op: op.to_string(),
+                key: "seats".to_string(),
+                delta: Some(delta),
+                ..Default::default()
+            }],
+        };
+        let mut state1 = SyncState::new("node1".to_string());
+        state1
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "ensure".to_string(),
+                    key: "seats".to_string(),
+                    crdt_type: Some("bounded-counter".to_string()),
+                    delta: Some(2),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        let mut state2 = SyncState::new("node2".to_string());
+        state2.merge(&state1);
+
+        // 本地视图会越界的递减被拒绝
+        assert!(state1.apply_changes(change("decrement", 3)).is_err());
+
+        // 各自剩余的 2 个座位都被本地接受，合并后截断为下界
+        state1.apply_changes(change("decrement", 2)).unwrap();
+        state2.apply_changes(change("decrement", 1)).unwrap();
+        state1.merge(&state2);
+        state2.merge(&state1);
+        assert_eq!(state1.state_hash(), state2.state_hash());
+        match state1.crdt_map.get("seats") {
+            Some(CRDTValue::BoundedCounter(c)) => {
+                assert_eq!(c.raw_value(), -1);
+                assert_eq!(c.value(), 0);
+            }
+            other => panic!("expected bounded-counter, got {:?}", other),
+        }
+        assert!(state2.apply_changes(change("decrement", 1)).is_err());
+
+        // 操作日志携带下界，重放得到相同的状态
+        assert_eq!(state1.replay(|_| true).0.state_hash(), state1.state_hash());
+
+        state2.apply_changes(change("increment", 5)).unwrap();
+        state1.merge(&state2);
+        assert_eq!(state1.crdt_map.get("seats").unwrap().materialized(), 4);
+    }
+
+    #[test]
+    fn test_signed_oplog_entries_verify_after_round_trip() {
+        let signer = Arc::new(SignatureManager::new("node1".to_string()));
         let mut state = SyncState::new("node1".to_string());
+        state.op_log.signer = Some(signer.clone());
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "set".to_string(),
+                    key: "name".to_string(),
+                    value: Some("alice".to_string()),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        let entry = &state.op_log.ops[0];
+        assert_eq!(
+            entry.public_key.as_deref(),
+            Some(signer.public_key_base64().as_str())
+        );
 
-        let op1 = Operation::PNCounterIncrement {
-            key: "counter1".to_string(),
+        // 经过序列化传输后签名仍然有效，但只有登记了 node1 公钥的节点才接受
+        let json = serde_json::to_string(&state).unwrap();
+        let mut received: SyncState = serde_json::from_str(&json).unwrap();
+        let receiver = SignatureManager::new("node2".to_string());
+        let invalid = received.op_log.verify_signatures(&receiver, false);
+        assert_eq!(invalid.len(), 1);
+        assert!(invalid[0].1.contains("No trusted public key"));
+        receiver.trust_peer_keys(signer.public_keys()).unwrap();
+        assert!(
+            received
+                .op_log
+                .verify_signatures(&receiver, true)
+                .is_empty()
+        );
+        assert!(
+            received.op_log.ops[0]
+                .verify_signature(&receiver, true)
+                .is_ok()
+        );
+
+        // 篡改操作内容后验证失败
+        if let Operation::LwwRegisterSet { value, .. } = &mut received.op_log.ops[0].op {
+            *value = "mallory".to_string();
+        }
+        let invalid = received.op_log.verify_signatures(&receiver, false);
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].0, state.op_log.ops[0].id);
+        assert!(
+            received.op_log.ops[0]
+                .verify_signature(&receiver, false)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_unsigned_entries_accepted_unless_required() {
+        let mut state = SyncState::new("node1".to_string());
+        state.apply_operation(Operation::GCounterIncrement {
+            key: "hits".to_string(),
             node_id: "node1".to_string(),
-            delta: 10,
-        };
-        state.apply_operation(op1);
+            delta: 1,
+        });
+        assert!(state.op_log.ops[0].signature.is_none());
 
-        let op2 = Operation::PNCounterDecrement {
+        let trust = SignatureManager::new("node2".to_string());
+        assert!(state.op_log.verify_signatures(&trust, false).is_empty());
+        let invalid = state.op_log.verify_signatures(&trust, true);
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].1, "Missing signature");
+    }
+
+    #[test]
+    fn test_oplog_entry_ts_iso_round_trip() {
+        let mut state = SyncState::new("node1".to_string());
+        state.apply_operation(Operation::GCounterIncrement {
             key: "counter1".to_string(),
             node_id: "node1".to_string(),
-            delta: 3,
+            delta: 1,
+        });
+
+        let entry = &state.op_log.ops[0];
+        let iso = entry.ts_iso();
+        assert!(iso.ends_with('Z'));
+
+        let parsed = chrono::DateTime::parse_from_rfc3339(&iso).unwrap();
+        assert_eq!(parsed.timestamp_millis(), entry.ts);
+    }
+
+    #[test]
+    fn test_operation_display() {
+        let ops = vec![
+            Operation::GCounterIncrement {
+                key: "counter1".to_string(),
+                node_id: "node1".to_string(),
+                delta: 5,
+            },
+            Operation::PNCounterDecrement {
+                key: "balance".to_string(),
+                node_id: "node2".to_string(),
+                delta: 3,
+            },
+            Operation::LwwRegisterSet {
+                key: "name".to_string(),
+                value: "Alice".to_string(),
+                timestamp: 42,
+                node_id: "node1".to_string(),
+            },
+            Operation::OrSetAdd {
+                key: "tags".to_string(),
+                value: "rust".to_string(),
+                dot: Dot::from("abc"),
+            },
+            Operation::ConvertType {
+                key: "counter1".to_string(),
+                from_type: "g-counter".to_string(),
+                to_type: "pn-counter".to_string(),
+            },
+        ];
+
+        let rendered: Vec<String> = ops.iter().map(|op| op.to_string()).collect();
+        assert_eq!(rendered[0], "GCounter.Increment(counter1, node1, +5)");
+        assert_eq!(rendered[1], "PNCounter.Decrement(balance, node2, -3)");
+        assert_eq!(
+            rendered[2],
+            "LWWRegister.Set(name, node1, \"Alice\", ts=42)"
+        );
+        assert_eq!(rendered[3], "ORSet.Add(tags, \"rust\", id=abc)");
+        assert_eq!(rendered[4], "Convert(counter1, g-counter -> pn-counter)");
+
+        for (op, text) in ops.iter().zip(&rendered) {
+            assert_eq!(Operation::parse_op_type(text), Some(op.op_type()));
+        }
+        assert_eq!(Operation::parse_op_type("Unknown(x)"), None);
+    }
+
+    #[test]
+    fn test_forget_node_preserves_value() {
+        let mut state1 = SyncState::new("node1".to_string());
+        let mut state2 = SyncState::new("node2".to_string());
+        state1
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "increment".to_string(),
+                    key: "counter".to_string(),
+                    delta: Some(5),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        state2
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "increment".to_string(),
+                    key: "counter".to_string(),
+                    delta: Some(3),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        state1.merge(&state2);
+        assert_eq!(state1.crdt_map.vector_clock.get("node2"), 1);
+
+        assert!(state1.forget_node("node1").is_err());
+        state1.forget_node("node2").unwrap();
+
+        assert!(!state1.crdt_map.vector_clock.clocks.contains_key("node2"));
+        match state1.crdt_map.get("counter") {
+            Some(CRDTValue::GCounter(c)) => {
+                assert_eq!(c.value(), 8);
+                assert!(!c.counts.contains_key("node2"));
+            }
+            _ => panic!("expected GCounter"),
+        }
+
+        // 被遗忘的节点重新出现，其操作不再被接受
+        state2
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "increment".to_string(),
+                    key: "counter".to_string(),
+                    delta: Some(10),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        state1.merge(&state2);
+        match state1.crdt_map.get("counter") {
+            Some(CRDTValue::GCounter(c)) => assert_eq!(c.value(), 8),
+            _ => panic!("expected GCounter"),
+        }
+        assert!(
+            state1
+                .op_log
+                .ops
+                .iter()
+                .all(|e| e.op.node_id() != Some("node2"))
+        );
+    }
+
+    #[test]
+    fn test_forgotten_nodes_propagate() {
+        let mut state1 = SyncState::new("node1".to_string());
+        let mut state2 = SyncState::new("node2".to_string());
+        let mut state3 = SyncState::new("node3".to_string());
+        state3
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "increment".to_string(),
+                    key: "counter".to_string(),
+                    delta: Some(4),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        state1.merge(&state3);
+        state2.merge(&state3);
+
+        state1.forget_node("node3").unwrap();
+        state2.merge(&state1);
+
+        assert!(state2.forgotten_nodes.contains("node3"));
+        assert_eq!(state1.state_hash(), state2.state_hash());
+    }
+
+    #[test]
+    fn test_counter_cap() {
+        let mut honest = SyncState::new("node1".to_string());
+        honest.counter_cap = Some(100);
+        let mut normal = SyncState::new("node2".to_string());
+        let mut attacker = SyncState::new("node3".to_string());
+
+        let increment = |delta| {
+            vec![Change {
+                op: "increment".to_string(),
+                key: "counter".to_string(),
+                delta: Some(delta),
+                ..Default::default()
+            }]
         };
-        state.apply_operation(op2);
+        normal.apply_changes(increment(10)).unwrap();
+        attacker.apply_changes(increment(1_000_000)).unwrap();
 
-        if let Some(CRDTValue::PNCounter(c)) = state.crdt_map.entries.get("counter1") {
-            assert_eq!(c.value(), 7);
-        } else {
-            panic!("Counter not found or wrong type");
+        // 本地写入不能超过上限
+        assert!(honest.apply_changes(increment(101)).is_err());
+        honest.apply_changes(increment(60)).unwrap();
+        assert!(honest.apply_changes(increment(41)).is_err());
+
+        honest.merge(&normal);
+        honest.merge(&attacker);
+        match honest.crdt_map.get("counter") {
+            Some(CRDTValue::PNCounter(c)) => {
+                assert_eq!(c.positive.counts.get("node2"), Some(&10));
+                assert_eq!(c.positive.counts.get("node3"), Some(&100));
+                assert_eq!(c.value(), 170);
+            }
+            _ => panic!("expected PNCounter"),
         }
+
+        // 上限配置在替换状态后保留
+        honest.reset();
+        assert_eq!(honest.counter_cap, Some(100));
     }
 
     #[test]
-    fn test_sync_state_apply_lww_register_operation() {
+    fn test_secret_values_are_encrypted() {
+        let encryptor = Arc::new(FieldEncryptor::new(&[7; 32], vec!["secret/*".to_string()]));
         let mut state = SyncState::new("node1".to_string());
+        state.encryptor = Some(encryptor.clone());
 
-        let op = Operation::LwwRegisterSet {
-            key: "register1".to_string(),
-            value: "test_value".to_string(),
-            timestamp: 12345,
-            node_id: "node1".to_string(),
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![
+                    Change {
+                        op: "set".to_string(),
+                        key: "secret/api".to_string(),
+                        value: Some("hunter2".to_string()),
+                        ..Default::default()
+                    },
+                    Change {
+                        op: "set".to_string(),
+                        key: "name".to_string(),
+                        value: Some("alice".to_string()),
+                        ..Default::default()
+                    },
+                ],
+            })
+            .unwrap();
+
+        let stored = match state.crdt_map.get("secret/api") {
+            Some(CRDTValue::LWWRegister(r)) => r.get().unwrap().clone(),
+            _ => panic!("expected LWWRegister"),
         };
+        assert!(FieldEncryptor::is_ciphertext(&stored));
+        assert!(!serde_json::to_string(&state).unwrap().contains("hunter2"));
 
-        state.apply_operation(op);
+        // 未授权读取看到密文，授权读取解密
+        let mut document = state.crdt_map.document();
+        assert_eq!(document["name"], serde_json::json!("alice"));
+        assert_eq!(document["secret/api"], serde_json::json!(stored));
+        encryptor.decrypt_document(&mut document);
+        assert_eq!(document["secret/api"], serde_json::json!("hunter2"));
 
-        if let Some(CRDTValue::LWWRegister(r)) = state.crdt_map.entries.get("register1") {
-            assert_eq!(r.get(), Some(&"test_value".to_string()));
-        } else {
-            panic!("Register not found or wrong type");
+        // 没有密钥的节点照常合并密文
+        let mut peer = SyncState::new("node2".to_string());
+        peer.merge(&state);
+        assert_eq!(peer.state_hash(), state.state_hash());
+    }
+
+    #[test]
+    fn test_injected_clock_decides_lww_winner() {
+        let clock1 = Arc::new(ManualClock::new(1_000));
+        let clock2 = Arc::new(ManualClock::new(2_000));
+        let mut state1 = SyncState::new("node1".to_string()).with_clock(clock1.clone());
+        let mut state2 = SyncState::new("node2".to_string()).with_clock(clock2.clone());
+
+        let set = |value: &str| {
+            vec![Change {
+                op: "set".to_string(),
+                key: "name".to_string(),
+                value: Some(value.to_string()),
+                ..Default::default()
+            }]
+        };
+
+        // node2 先写但时间戳更晚
+        state2.apply_changes(set("later")).unwrap();
+        state1.apply_changes(set("earlier")).unwrap();
+        assert_eq!(state1.op_log.ops[0].ts, 1_000);
+
+        state1.merge(&state2);
+        state2.merge(&state1);
+        for state in [&state1, &state2] {
+            match state.crdt_map.get("name") {
+                Some(CRDTValue::LWWRegister(r)) => {
+                    assert_eq!(r.get().map(String::as_str), Some("later"));
+                    assert_eq!(r.timestamp, 2_000);
+                }
+                _ => panic!("expected LWWRegister"),
+            }
+        }
+
+        // 推进 node1 的时钟后其写入胜出
+        clock1.set(3_000);
+        state1.apply_changes(set("latest")).unwrap();
+        state2.merge(&state1);
+        match state2.crdt_map.get("name") {
+            Some(CRDTValue::LWWRegister(r)) => {
+                assert_eq!(r.get().map(String::as_str), Some("latest"))
+            }
+            _ => panic!("expected LWWRegister"),
+        }
+        clock2.advance(5);
+        assert_eq!(clock2.now_millis(), 2_005);
+    }
+
+    #[test]
+    fn test_replication_status() {
+        let clock = Arc::new(ManualClock::new(5_000));
+        let mut local = SyncState::new("node1".to_string()).with_clock(clock);
+        let mut peer = SyncState::new("node2".to_string());
+        let increment = || {
+            vec![Change {
+                op: "increment".to_string(),
+                key: "counter".to_string(),
+                ..Default::default()
+            }]
+        };
+
+        peer.apply_changes(increment()).unwrap();
+        local.merge(&peer);
+        let status = local.replication_status();
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].peer, "node2");
+        assert_eq!(status[0].status, ReplicationStatus::InSync);
+        assert_eq!(status[0].last_sync, 5_000);
+
+        // 本地继续写入后，最近合并的对端状态落后
+        local.apply_changes(increment()).unwrap();
+        assert_eq!(
+            local.replication_status()[0].status,
+            ReplicationStatus::Behind
+        );
+
+        // 合并不会改变自身记录
+        let snapshot = local.clone();
+        local.merge(&snapshot);
+        assert_eq!(local.replication_status().len(), 1);
+    }
+
+    #[test]
+    fn test_move_element() {
+        let mut state = SyncState::new("node1".to_string());
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![
+                    Change {
+                        op: "add".to_string(),
+                        key: "todo".to_string(),
+                        value: Some("task1".to_string()),
+                        ..Default::default()
+                    },
+                    Change {
+                        op: "set".to_string(),
+                        key: "name".to_string(),
+                        value: Some("alice".to_string()),
+                        ..Default::default()
+                    },
+                ],
+            })
+            .unwrap();
+        let move_change = |from: &str, to: &str, value: &str| Change {
+            op: "move".to_string(),
+            from_key: Some(from.to_string()),
+            to_key: Some(to.to_string()),
+            value: Some(value.to_string()),
+            ..Default::default()
+        };
+
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![move_change("todo", "done", "task1")],
+            })
+            .unwrap();
+        let contains = |state: &SyncState, key: &str| match state.crdt_map.get(key) {
+            Some(CRDTValue::ORSet(set)) => set.contains(&"task1".to_string()),
+            _ => false,
+        };
+        assert!(!contains(&state, "todo"));
+        assert!(contains(&state, "done"));
+
+        // 校验失败时不修改任何集合
+        let ops_before = state.op_log.ops.len();
+        assert!(
+            state
+                .apply_changes(ChangeRequest {
+                    changes: vec![move_change("todo", "done", "task1")],
+                })
+                .is_err()
+        );
+        assert!(
+            state
+                .apply_changes(ChangeRequest {
+                    changes: vec![move_change("done", "name", "task1")],
+                })
+                .is_err()
+        );
+        assert_eq!(state.op_log.ops.len(), ops_before);
+        assert!(contains(&state, "done"));
+    }
+
+    #[test]
+    fn test_state_as_of_timestamp() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut state = SyncState::new("node1".to_string()).with_clock(clock.clone());
+        let set = |value: &str| ChangeRequest {
+            changes: vec![Change {
+                op: "set".to_string(),
+                key: "name".to_string(),
+                value: Some(value.to_string()),
+                ..Default::default()
+            }],
+        };
+
+        state.apply_changes(set("alice")).unwrap();
+        clock.set(2_000);
+        state.apply_changes(set("bob")).unwrap();
+
+        let (before, applied) = state.state_as_of(500);
+        assert_eq!(applied, 0);
+        assert!(before.entries.is_empty());
+
+        let (between, applied) = state.state_as_of(1_500);
+        assert_eq!(applied, 1);
+        assert_eq!(between.document()["name"], "alice");
+
+        let (latest, applied) = state.state_as_of(2_000);
+        assert_eq!(applied, 2);
+        assert_eq!(latest.state_hash(), state.state_hash());
+    }
+
+    #[test]
+    fn test_max_keys_rejects_new_keys() {
+        let mut state = SyncState::new("node1".to_string());
+        state.max_keys = Some(2);
+        let set = |key: &str, value: &str| ChangeRequest {
+            changes: vec![Change {
+                op: "set".to_string(),
+                key: key.to_string(),
+                value: Some(value.to_string()),
+                ..Default::default()
+            }],
+        };
+
+        state.apply_changes(set("a", "1")).unwrap();
+        state.apply_changes(set("b", "1")).unwrap();
+        let err = state.apply_changes(set("c", "1")).unwrap_err();
+        assert!(err.to_string().contains("Key limit reached"));
+        assert!(state.crdt_map.get("c").is_none());
+
+        // 已存在的键仍可更新
+        state.apply_changes(set("a", "2")).unwrap();
+        assert_eq!(state.crdt_map.document()["a"], "2");
+    }
+
+    #[test]
+    fn test_max_keys_on_merge_converges() {
+        let set = |key: &str| ChangeRequest {
+            changes: vec![Change {
+                op: "set".to_string(),
+                key: key.to_string(),
+                value: Some("v".to_string()),
+                ..Default::default()
+            }],
+        };
+        let mut state1 = SyncState::new("node1".to_string());
+        let mut state2 = SyncState::new("node2".to_string());
+        state1.max_keys = Some(3);
+        state2.max_keys = Some(3);
+        state1.apply_changes(set("a")).unwrap();
+        state1.apply_changes(set("d")).unwrap();
+        state2.apply_changes(set("b")).unwrap();
+        state2.apply_changes(set("c")).unwrap();
+
+        let snapshot1 = state1.clone();
+        state1.merge(&state2);
+        state2.merge(&snapshot1);
+
+        let keys: Vec<_> = state1.crdt_map.document().into_keys().collect();
+        assert_eq!(keys, vec!["a", "b", "c"]);
+        assert_eq!(state1.state_hash(), state2.state_hash());
+        assert!(state1.quarantine.iter().all(|q| q.entry.op.key() == "d"));
+        assert!(!state1.op_log.ops.iter().any(|e| e.op.key() == "d"));
+    }
+
+    fn add_to(key: &str, value: &str) -> ChangeRequest {
+        ChangeRequest {
+            changes: vec![Change {
+                op: "add".to_string(),
+                key: key.to_string(),
+                value: Some(value.to_string()),
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn test_max_set_elements_rejects_local_adds() {
+        let mut state = SyncState::new("node1".to_string());
+        state.max_set_elements = Some(10);
+        for i in 0..9 {
+            state
+                .apply_changes(add_to("tags", &format!("t{}", i)))
+                .unwrap();
         }
+        assert!(!state.stats(0).near_capacity_sets.is_empty());
+        state.apply_changes(add_to("tags", "t9")).unwrap();
+
+        let err = state.apply_changes(add_to("tags", "t10")).unwrap_err();
+        assert!(err.to_string().contains("Set element limit"));
+        // 已存在的元素仍可重复添加，其他集合不受影响
+        state.apply_changes(add_to("tags", "t0")).unwrap();
+        state.apply_changes(add_to("colors", "red")).unwrap();
+
+        let stats = state.stats(0);
+        assert_eq!(
+            stats.near_capacity_sets,
+            vec![SetCapacity {
+                key: "tags".to_string(),
+                elements: 10,
+                cap: 10,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_max_set_elements_on_merge_converges() {
+        let mut state1 = SyncState::new("node1".to_string());
+        let mut state2 = SyncState::new("node2".to_string());
+        state1.max_set_elements = Some(3);
+        state2.max_set_elements = Some(3);
+        state1.apply_changes(add_to("tags", "a")).unwrap();
+        state1.apply_changes(add_to("tags", "d")).unwrap();
+        state2.apply_changes(add_to("tags", "b")).unwrap();
+        state2.apply_changes(add_to("tags", "c")).unwrap();
+
+        let snapshot1 = state1.clone();
+        state1.merge(&state2);
+        state2.merge(&snapshot1);
+
+        let Some(CRDTValue::ORSet(tags)) = state1.crdt_map.get("tags") else {
+            panic!("tags should be an or-set");
+        };
+        let mut elements = tags.elements();
+        elements.sort();
+        assert_eq!(elements, vec!["a", "b", "c"]);
+        assert_eq!(state1.state_hash(), state2.state_hash());
+        assert_eq!(state1.quarantine.len(), 1);
+        assert!(matches!(
+            &state1.quarantine[0].entry.op,
+            Operation::OrSetAdd { value, .. } if value == "d"
+        ));
     }
 
     #[test]
-    fn test_sync_state_apply_orset_operations() {
+    fn test_repro_bundle_replay_reproduces_hash() {
         let mut state = SyncState::new("node1".to_string());
+        state.apply_changes(add_to("tags", "a")).unwrap();
+        let initial = state.clone();
 
-        let op1 = Operation::OrSetAdd {
-            key: "set1".to_string(),
-            value: "item1".to_string(),
-            unique_id: "id1".to_string(),
-        };
-        state.apply_operation(op1);
+        let mut peer = SyncState::new("node2".to_string());
+        peer.apply_changes(add_to("tags", "b")).unwrap();
+        state.merge(&peer);
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![
+                    Change {
+                        op: "increment".to_string(),
+                        key: "visits".to_string(),
+                        delta: Some(3),
+                        ..Default::default()
+                    },
+                    Change {
+                        op: "remove".to_string(),
+                        key: "tags".to_string(),
+                        value: Some("a".to_string()),
+                        ..Default::default()
+                    },
+                ],
+            })
+            .unwrap();
 
-        let op2 = Operation::OrSetAdd {
-            key: "set1".to_string(),
-            value: "item2".to_string(),
-            unique_id: "id2".to_string(),
-        };
-        state.apply_operation(op2);
+        let bundle = state.repro_bundle(Some(initial));
+        assert_eq!(bundle.ops.len(), 3);
 
-        if let Some(CRDTValue::ORSet(s)) = state.crdt_map.entries.get("set1") {
-            let elements = s.elements();
-            assert_eq!(elements.len(), 2);
-            assert!(elements.contains(&"item1".to_string()));
-            assert!(elements.contains(&"item2".to_string()));
-        } else {
-            panic!("Set not found or wrong type");
-        }
+        // 经 JSON 往返后在全新节点上重放
+        let json = serde_json::to_string(&bundle).unwrap();
+        let bundle: ReproBundle = serde_json::from_str(&json).unwrap();
+        let replayed = bundle.replay();
+        assert_eq!(replayed.state_hash(), bundle.expected_state_hash);
+        assert_eq!(replayed.state_hash(), state.state_hash());
+
+        // 无起点时从空状态重放全部操作
+        let replayed = state.repro_bundle(None).replay();
+        assert_eq!(replayed.state_hash(), state.state_hash());
     }
 
     #[test]
-    fn test_sync_state_merge() {
-        let mut state1 = SyncState::new("node1".to_string());
-        let mut state2 = SyncState::new("node2".to_string());
-
-        let op1 = Operation::GCounterIncrement {
-            key: "counter1".to_string(),
-            node_id: "node1".to_string(),
-            delta: 5,
+    fn test_stats_counts_composition() {
+        let mut state = SyncState::new("node1".to_string());
+        let change = |op: &str, key: &str, value: Option<&str>| Change {
+            op: op.to_string(),
+            key: key.to_string(),
+            value: value.map(str::to_string),
+            ..Default::default()
         };
-        state1.apply_operation(op1);
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![
+                    change("increment", "visits", None),
+                    change("increment", "likes", None),
+                    change("set", "name", Some("alice")),
+                    change("add", "tags", Some("a")),
+                    change("add", "tags", Some("b")),
+                    change("add", "tags", Some("c")),
+                    change("remove", "tags", Some("c")),
+                    change("add", "colors", Some("red")),
+                ],
+            })
+            .unwrap();
 
-        let op2 = Operation::GCounterIncrement {
-            key: "counter1".to_string(),
-            node_id: "node2".to_string(),
-            delta: 3,
+        let stats = state.stats(2);
+        assert_eq!(stats.total_entries, 5);
+        assert_eq!(stats.type_counts["pn-counter"], 2);
+        assert_eq!(stats.type_counts["lww-register"], 1);
+        assert_eq!(stats.type_counts["or-set"], 2);
+        assert!(!stats.type_counts.contains_key("g-counter"));
+        assert_eq!(stats.set_elements, 3);
+        assert_eq!(stats.oplog_len, 8);
+        assert_eq!(stats.largest_keys.len(), 2);
+        assert_eq!(stats.largest_keys[0].key, "tags");
+        assert!(stats.largest_keys[0].bytes >= stats.largest_keys[1].bytes);
+        assert!(stats.approx_bytes > stats.largest_keys[0].bytes);
+    }
+
+    #[test]
+    fn test_decrement_gcounter_promotion() {
+        let mut state = SyncState::new("node1".to_string());
+        state
+            .ensure_key("views".to_string(), "g-counter", None, Some(5))
+            .unwrap();
+        let decrement = || ChangeRequest {
+            changes: vec![Change {
+                op: "decrement".to_string(),
+                key: "views".to_string(),
+                delta: Some(2),
+                ..Default::default()
+            }],
         };
-        state2.apply_operation(op2);
 
-        state1.merge(&state2);
+        // 未开启自动转换时明确报错，且不记录任何操作
+        let ops_before = state.op_log.ops.len();
+        let err = state.apply_changes(decrement()).unwrap_err();
+        assert!(err.to_string().contains("Cannot decrement g-counter views"));
+        assert_eq!(state.op_log.ops.len(), ops_before);
+        assert!(matches!(
+            state.crdt_map.get("views"),
+            Some(CRDTValue::GCounter(_))
+        ));
 
-        if let Some(CRDTValue::GCounter(c)) = state1.crdt_map.entries.get("counter1") {
-            assert_eq!(c.value(), 8);
-        } else {
-            panic!("Counter not found or wrong type");
+        state.auto_promote_counters = true;
+        state.apply_changes(decrement()).unwrap();
+        match state.crdt_map.get("views") {
+            Some(CRDTValue::PNCounter(c)) => assert_eq!(c.value(), 3),
+            other => panic!("expected pn-counter, got {:?}", other),
         }
+        assert!(
+            state
+                .op_log
+                .ops
+                .iter()
+                .any(|e| matches!(e.op, Operation::ConvertType { .. }))
+        );
+
+        // 副本合并后收敛
+        let mut replica = SyncState::new("node2".to_string());
+        replica.merge(&state);
+        assert_eq!(replica.state_hash(), state.state_hash());
     }
 
     #[test]
-    fn test_sync_state_state_hash() {
-        let mut state = SyncState::new("node1".to_string());
-
-        let op = Operation::GCounterIncrement {
-            key: "counter1".to_string(),
-            node_id: "node1".to_string(),
-            delta: 5,
+    fn test_lww_map_changes_converge_across_replicas() {
+        let map_change = |op: &str, field: &str, value: Option<&str>| ChangeRequest {
+            changes: vec![Change {
+                op: op.to_string(),
+                key: "profile".to_string(),
+                field: Some(field.to_string()),
+                value: value.map(str::to_string),
+                ..Default::default()
+            }],
         };
-        state.apply_operation(op);
 
-        let hash1 = state.state_hash();
-        let hash2 = state.state_hash();
+        let mut node1 =
+            SyncState::new("node1".to_string()).with_clock(Arc::new(ManualClock::new(1_000)));
+        let mut node2 =
+            SyncState::new("node2".to_string()).with_clock(Arc::new(ManualClock::new(2_000)));
 
-        assert_eq!(hash1, hash2);
-        assert!(!hash1.is_empty());
+        node1
+            .apply_changes(map_change("map-set", "name", Some("alice")))
+            .unwrap();
+        node1
+            .apply_changes(map_change("map-set", "city", Some("paris")))
+            .unwrap();
+        // 同一毫秒内的后续写入仍覆盖先前的写入
+        node1
+            .apply_changes(map_change("map-remove", "city", None))
+            .unwrap();
+        node2
+            .apply_changes(map_change("map-set", "name", Some("bob")))
+            .unwrap();
+        node2
+            .apply_changes(map_change("map-set", "email", Some("bob@example.com")))
+            .unwrap();
+
+        let mut merged1 = node1.clone();
+        merged1.merge(&node2);
+        let mut merged2 = node2.clone();
+        merged2.merge(&node1);
+        assert_eq!(merged1.state_hash(), merged2.state_hash());
+
+        let document = merged1.crdt_map.document();
+        assert_eq!(
+            document["profile"],
+            serde_json::json!({"name": "bob", "email": "bob@example.com"})
+        );
+
+        // 通过操作日志重放得到相同结果
+        let mut replayed = SyncState::new("node3".to_string());
+        replayed.merge_ops(&merged1.op_log);
+        assert_eq!(
+            replayed.crdt_map.state_hash(),
+            merged1.crdt_map.state_hash()
+        );
+
+        // 缺少字段时拒绝
+        let mut missing = map_change("map-set", "name", Some("x"));
+        missing.changes[0].field = None;
+        assert!(missing.validate().is_err());
+        assert!(node1.apply_changes(missing).is_err());
+
+        // 键已是其他类型时拒绝
+        node1
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "increment".to_string(),
+                    key: "visits".to_string(),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        let mut wrong_type = map_change("map-set", "name", Some("x"));
+        wrong_type.changes[0].key = "visits".to_string();
+        assert!(node1.apply_changes(wrong_type).is_err());
     }
 
     #[test]
-    fn test_sync_state_export_oplog() {
-        let mut state = SyncState::new("node1".to_string());
-
-        let op = Operation::GCounterIncrement {
-            key: "counter1".to_string(),
-            node_id: "node1".to_string(),
-            delta: 5,
+    fn test_compact_preserves_state_and_converges_with_uncompacted_peer() {
+        let change = |op: &str, key: &str, value: Option<&str>| ChangeRequest {
+            changes: vec![Change {
+                op: op.to_string(),
+                key: key.to_string(),
+                value: value.map(str::to_string),
+                ..Default::default()
+            }],
         };
-        state.apply_operation(op);
+        let mut node1 = SyncState::new("node1".to_string());
+        let mut node2 = SyncState::new("node2".to_string());
+        node1
+            .apply_changes(change("increment", "hits", None))
+            .unwrap();
+        node1
+            .apply_changes(change("add", "tags", Some("a")))
+            .unwrap();
+        node2
+            .apply_changes(change("increment", "hits", None))
+            .unwrap();
+        node1.merge(&node2);
+        node2.merge(&node1);
 
-        let result = state.export_oplog();
-        assert!(result.is_ok());
+        let hash = node1.state_hash();
+        let frontier = node1.crdt_map.vector_clock.clone();
+        node1
+            .apply_changes(change("set", "name", Some("alice")))
+            .unwrap();
+        let hash_with_tail = node1.state_hash();
 
-        let json = result.unwrap();
-        assert!(json.contains("counter1"));
+        // 只截断被前沿覆盖的操作，状态不变
+        assert_eq!(node1.compact(&frontier), 3);
+        assert_eq!(node1.op_log.ops.len(), 1);
+        assert_eq!(node1.op_log.compacted, frontier);
+        assert_eq!(node1.state_hash(), hash_with_tail);
+
+        // 快照携带压缩前沿，反序列化后仍然有效
+        let snapshot: SyncState =
+            serde_json::from_str(&serde_json::to_string(&node1).unwrap()).unwrap();
+        assert_eq!(snapshot.op_log.compacted, frontier);
+        assert_eq!(snapshot.state_hash(), node1.state_hash());
+
+        // 与未压缩的对端双向合并后收敛，已折叠的操作不会重新加入日志，计数不会重复
+        node2
+            .apply_changes(change("increment", "hits", None))
+            .unwrap();
+        node1.merge(&node2);
+        node2.merge(&node1);
+        assert_eq!(node1.state_hash(), node2.state_hash());
+        assert_ne!(node1.state_hash(), hash);
+        assert_eq!(node1.crdt_map.document()["hits"], 3);
+        assert_eq!(node1.op_log.ops.len(), 2);
+
+        // 按操作合并时，被前沿覆盖的条目同样被跳过
+        let mut compacted = node1.clone();
+        assert_eq!(compacted.merge_ops(&node2.op_log), 0);
+        assert_eq!(compacted.state_hash(), node2.state_hash());
     }
 
     #[test]
-    fn test_sync_state_apply_changes_increment() {
-        let mut state = SyncState::new("node1".to_string());
-
-        let change = Change {
-            op: "increment".to_string(),
-            key: "counter1".to_string(),
-            value: None,
-            delta: Some(5),
+    fn test_compact_nets_only_counters_behind_stable_frontier() {
+        let change = |op: &str, delta: u64| ChangeRequest {
+            changes: vec![Change {
+                op: op.to_string(),
+                key: "score".to_string(),
+                delta: Some(delta),
+                ..Default::default()
+            }],
         };
+        let mut node1 = SyncState::new("node1".to_string());
+        let mut node2 = SyncState::new("node2".to_string());
+        let mut node3 = SyncState::new("node3".to_string());
+        node2.apply_changes(change("increment", 5)).unwrap();
+        node2.apply_changes(change("decrement", 2)).unwrap();
+        node3.merge(&node2);
+        node3.apply_changes(change("increment", 1)).unwrap();
+        node3.apply_changes(change("decrement", 3)).unwrap();
+        // node2 的计数已被所有对等节点观察到，node3 的计数 node2 尚未观察到
+        node1.merge(&node2);
+        node1.merge(&node3);
+        let value = node1.crdt_map.document()["score"].clone();
 
-        let request = ChangeRequest {
-            changes: vec![change],
+        node1.compact(&node1.stable_clock());
+        let Some(CRDTValue::PNCounter(counter)) = node1.crdt_map.get("score") else {
+            panic!("Counter not found or wrong type");
         };
+        assert_eq!(counter.positive.counts.get("node2"), Some(&3));
+        assert!(!counter.negative.counts.contains_key("node2"));
+        // 超出稳定前沿的贡献保持不变
+        assert_eq!(counter.positive.counts.get("node3"), Some(&1));
+        assert_eq!(counter.negative.counts.get("node3"), Some(&3));
+        assert_eq!(node1.crdt_map.document()["score"], value);
 
-        let result = state.apply_changes(request);
-        assert!(result.is_ok());
+        // 与未压缩的副本合并后恢复为未压缩的计数，仍然收敛
+        node3.merge(&node1);
+        node1.merge(&node3);
+        assert_eq!(node1.state_hash(), node3.state_hash());
+        assert_eq!(node1.crdt_map.document()["score"], value);
+    }
 
-        if let Some(CRDTValue::GCounter(c)) = state.crdt_map.entries.get("counter1") {
-            assert_eq!(c.value(), 5);
-        }
+    #[test]
+    fn test_compact_frontier_clamped_to_local_clock() {
+        let mut state = SyncState::new("node1".to_string());
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "increment".to_string(),
+                    key: "hits".to_string(),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+
+        let mut ahead = VectorClock::new();
+        ahead.clocks.insert("node1".to_string(), 5);
+        ahead.clocks.insert("node2".to_string(), 3);
+        assert_eq!(state.compact(&ahead), 1);
+        // 尚未观察到的操作不计入前沿，之后到达时仍会被接受
+        assert_eq!(state.op_log.compacted.get("node1"), 1);
+        assert_eq!(state.op_log.compacted.get("node2"), 0);
+
+        // 默认前沿不超过已知对等节点观察到的部分
+        let mut peer_clock = VectorClock::new();
+        peer_clock.clocks.insert("node1".to_string(), 0);
+        state.peer_clocks.insert(
+            "node2".to_string(),
+            PeerClock {
+                clock: peer_clock,
+                last_sync: 0,
+            },
+        );
+        assert!(state.stable_clock().is_empty());
     }
 
     #[test]
-    fn test_sync_state_apply_changes_decrement() {
+    fn test_change_ops_dispatch() {
+        for spec in CHANGE_OPS {
+            let mut state = SyncState::new("node1".to_string());
+            let result = state.apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: spec.op.to_string(),
+                    key: "k".to_string(),
+                    ..Default::default()
+                }],
+            });
+            // 缺少字段时可能报错，但不应被视为未知操作
+            if let Err(e) = result {
+                assert!(
+                    !matches!(e, ChangeError::UnknownOp(_)),
+                    "{}: {}",
+                    spec.op,
+                    e
+                );
+            }
+        }
+
         let mut state = SyncState::new("node1".to_string());
+        let err = state
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "rename".to_string(),
+                    key: "k".to_string(),
+                    ..Default::default()
+                }],
+            })
+            .unwrap_err();
+        assert_eq!(err, ChangeError::UnknownOp("rename".to_string()));
+        assert_eq!(err.to_string(), "Unknown operation: rename");
+    }
 
-        let changes = vec![
-            Change {
+    #[test]
+    fn test_compact_counter_requires_stability() {
+        let mut local = SyncState::new("node1".to_string());
+        let mut peer = SyncState::new("node2".to_string());
+        let increment = || ChangeRequest {
+            changes: vec![Change {
                 op: "increment".to_string(),
-                key: "counter1".to_string(),
-                value: None,
-                delta: Some(10),
-            },
-            Change {
-                op: "decrement".to_string(),
-                key: "counter1".to_string(),
-                value: None,
+                key: "counter".to_string(),
                 delta: Some(3),
-            },
-        ];
+                ..Default::default()
+            }],
+        };
+        local.apply_changes(increment()).unwrap();
+        peer.apply_changes(increment()).unwrap();
+        local.merge(&peer);
 
-        let request = ChangeRequest { changes };
+        // 对端尚未观察到本地增量
+        let err = local.compact_counter("counter").unwrap_err();
+        assert!(err.contains("not causally stable"));
 
-        let result = state.apply_changes(request);
-        assert!(result.is_ok());
+        peer.merge(&local);
+        local.merge(&peer);
+        assert_eq!(local.compact_counter("counter").unwrap(), 6);
+        assert!(local.compact_counter("missing").is_err());
 
-        if let Some(CRDTValue::PNCounter(c)) = state.crdt_map.entries.get("counter1") {
-            assert_eq!(c.value(), 7);
+        // 与持有明细的副本合并后收敛
+        peer.merge(&local);
+        local.merge(&peer);
+        assert_eq!(local.state_hash(), peer.state_hash());
+        match local.crdt_map.get("counter") {
+            Some(CRDTValue::PNCounter(c)) => assert_eq!(c.value(), 6),
+            other => panic!("expected pn-counter, got {:?}", other),
         }
     }
 
     #[test]
-    fn test_sync_state_apply_changes_add() {
-        let mut state = SyncState::new("node1".to_string());
-
-        let change = Change {
-            op: "add".to_string(),
-            key: "set1".to_string(),
-            value: Some("item1".to_string()),
-            delta: None,
+    fn test_change_request_validation() {
+        let change = |op: &str, key: &str| Change {
+            op: op.to_string(),
+            key: key.to_string(),
+            value: Some("v".to_string()),
+            ..Default::default()
         };
 
-        let request = ChangeRequest {
-            changes: vec![change],
+        let valid = ChangeRequest {
+            changes: vec![change("set", "name"), change("increment", "visits")],
         };
+        assert!(valid.validate().is_ok());
 
-        let result = state.apply_changes(request);
-        assert!(result.is_ok());
+        let errors = ChangeRequest {
+            changes: vec![change("set", ""), change("", "name")],
+        }
+        .validate()
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                FieldError::new("changes[0].key".to_string(), "must not be empty"),
+                FieldError::new("changes[1].op".to_string(), "must not be empty"),
+            ]
+        );
 
-        if let Some(CRDTValue::ORSet(s)) = state.crdt_map.entries.get("set1") {
-            assert!(s.contains(&"item1".to_string()));
+        let errors = ChangeRequest {
+            changes: vec![Change {
+                delta: Some(u64::MAX),
+                ..change("increment", "visits")
+            }],
         }
+        .validate()
+        .unwrap_err();
+        assert_eq!(errors[0].field, "changes[0].delta");
+
+        let mut request = SyncRequest {
+            from_node: String::new(),
+            state: SyncState::new("node2".to_string()),
+        };
+        let errors = request.validate().unwrap_err();
+        assert_eq!(errors[0].field, "from_node");
+        request.from_node = "node2".to_string();
+        assert!(request.validate().is_ok());
     }
 
     #[test]
-    fn test_sync_state_apply_changes_set() {
-        let mut state = SyncState::new("node1".to_string());
+    fn test_max_policy_keeps_larger_concurrent_write() {
+        let clock1 = Arc::new(ManualClock::new(1_000));
+        let clock2 = Arc::new(ManualClock::new(2_000));
+        let mut state1 = SyncState::new("node1".to_string()).with_clock(clock1);
+        let mut state2 = SyncState::new("node2".to_string()).with_clock(clock2);
 
-        let change = Change {
-            op: "set".to_string(),
-            key: "register1".to_string(),
-            value: Some("test_value".to_string()),
-            delta: None,
-        };
+        state1.set_policy("bid", ConflictPolicy::Max).unwrap();
+        state2.merge(&state1);
+        assert_eq!(state2.policies.get("bid"), ConflictPolicy::Max);
 
-        let request = ChangeRequest {
-            changes: vec![change],
+        // 较大的值时间戳更早，LWW 下会被较晚的写入覆盖
+        let set = |value: &str| ChangeRequest {
+            changes: vec![Change {
+                op: "set".to_string(),
+                key: "bid".to_string(),
+                value: Some(value.to_string()),
+                ..Default::default()
+            }],
         };
+        state1.apply_changes(set("10")).unwrap();
+        state2.apply_changes(set("5")).unwrap();
+        assert!(state2.apply_changes(set("high")).is_err());
 
-        let result = state.apply_changes(request);
-        assert!(result.is_ok());
+        let snapshot1 = state1.clone();
+        state1.merge(&state2);
+        state2.merge(&snapshot1);
 
-        if let Some(CRDTValue::LWWRegister(r)) = state.crdt_map.entries.get("register1") {
-            assert_eq!(r.get(), Some(&"test_value".to_string()));
+        for state in [&state1, &state2] {
+            match state.crdt_map.get("bid") {
+                Some(CRDTValue::MaxRegister(r)) => assert_eq!(r.get(), Some(&10)),
+                other => panic!("expected max-register, got {:?}", other),
+            }
         }
+        assert_eq!(state1.state_hash(), state2.state_hash());
+
+        // 已有 max-register 的键不能改回 LWW
+        assert!(state1.set_policy("bid", ConflictPolicy::Lww).is_err());
     }
 
     #[test]
-    fn test_sync_state_apply_changes_remove() {
-        let mut state = SyncState::new("node1".to_string());
-
-        let changes = vec![
-            Change {
-                op: "add".to_string(),
-                key: "set1".to_string(),
-                value: Some("item1".to_string()),
-                delta: None,
-            },
-            Change {
-                op: "remove".to_string(),
-                key: "set1".to_string(),
-                value: Some("item1".to_string()),
-                delta: None,
-            },
-        ];
+    fn test_keep_all_policy_preserves_concurrent_writes() {
+        let mut state1 = SyncState::new("node1".to_string());
+        let mut state2 = SyncState::new("node2".to_string());
+        state1.set_policy("title", ConflictPolicy::KeepAll).unwrap();
+        state2.merge(&state1);
 
-        let request = ChangeRequest { changes };
+        let set = |value: &str| ChangeRequest {
+            changes: vec![Change {
+                op: "set".to_string(),
+                key: "title".to_string(),
+                value: Some(value.to_string()),
+                ..Default::default()
+            }],
+        };
+        state1.apply_changes(set("draft")).unwrap();
+        state2.apply_changes(set("final")).unwrap();
 
-        let result = state.apply_changes(request);
-        assert!(result.is_ok());
+        state1.merge(&state2);
+        assert_eq!(
+            state1.crdt_map.document()["title"],
+            serde_json::json!(["draft", "final"])
+        );
+        let unresolved = state1.unresolved_writes();
+        assert_eq!(unresolved.len(), 1);
+        assert_eq!(unresolved[0].0, "title");
+        assert_eq!(unresolved[0].1.len(), 2);
 
-        if let Some(CRDTValue::ORSet(s)) = state.crdt_map.entries.get("set1") {
-            assert!(!s.contains(&"item1".to_string()));
-        }
+        // 观察到并发值之后的写入覆盖二者
+        state1.apply_changes(set("merged")).unwrap();
+        state2.merge(&state1);
+        assert_eq!(
+            state2.crdt_map.document()["title"],
+            serde_json::json!(["merged"])
+        );
+        assert!(state2.unresolved_writes().is_empty());
     }
 
     #[test]
-    fn test_sync_state_apply_changes_error_missing_value() {
-        let mut state = SyncState::new("node1".to_string());
-
-        let change = Change {
-            op: "add".to_string(),
-            key: "set1".to_string(),
-            value: None,
-            delta: None,
+    fn test_set_add_remove_race_detected() {
+        let mut state1 = SyncState::new("node1".to_string());
+        let mut state2 = SyncState::new("node2".to_string());
+        let change = |op: &str| ChangeRequest {
+            changes: vec![Change {
+                op: op.to_string(),
+                key: "tags".to_string(),
+                value: Some("x".to_string()),
+                ..Default::default()
+            }],
         };
 
-        let request = ChangeRequest {
-            changes: vec![change],
-        };
+        state1.apply_changes(change("add")).unwrap();
+        state2.merge(&state1);
+        // 观察到添加之后的移除不构成竞争
+        assert!(state2.set_add_remove_races().is_empty());
 
-        let result = state.apply_changes(request);
-        assert!(result.is_err());
+        // node2 移除的同时 node1 再次添加
+        state2.apply_changes(change("remove")).unwrap();
+        state1.apply_changes(change("add")).unwrap();
+        state1.merge(&state2);
+
+        let races = state1.set_add_remove_races();
+        assert_eq!(races.len(), 1);
+        assert_eq!((races[0].key, races[0].value), ("tags", "x"));
+        assert_eq!(races[0].adds.len(), 1);
+        assert_eq!(races[0].adds[0].origin_node(), Some("node1"));
+        assert_eq!(races[0].removes.len(), 1);
+        assert_eq!(races[0].removes[0].origin_node(), Some("node2"));
+        // add-wins：元素仍在集合中
+        assert_eq!(state1.crdt_map.document()["tags"], serde_json::json!(["x"]));
     }
 
     #[test]
-    fn test_sync_state_apply_changes_error_unknown_op() {
+    fn test_validate_changes_reports_type_mismatch_without_mutating() {
         let mut state = SyncState::new("node1".to_string());
-
-        let change = Change {
-            op: "unknown_op".to_string(),
-            key: "test".to_string(),
-            value: None,
-            delta: None,
-        };
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "set".to_string(),
+                    key: "name".to_string(),
+                    value: Some("alice".to_string()),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        let hash_before = state.state_hash();
+        let ops_before = state.op_log.ops.len();
 
         let request = ChangeRequest {
-            changes: vec![change],
+            changes: vec![
+                Change {
+                    op: "add".to_string(),
+                    key: "tags".to_string(),
+                    value: Some("x".to_string()),
+                    ..Default::default()
+                },
+                Change {
+                    op: "increment".to_string(),
+                    key: "name".to_string(),
+                    ..Default::default()
+                },
+                Change {
+                    op: "remove".to_string(),
+                    key: "tags".to_string(),
+                    ..Default::default()
+                },
+            ],
         };
+        let results = state.validate_changes(&request);
 
-        let result = state.apply_changes(request);
-        assert!(result.is_err());
+        assert!(results[0].valid);
+        assert!(!results[1].valid);
+        assert_eq!(results[1].errors[0].field, "changes[1]");
+        assert!(results[1].errors[0].message.contains("Type mismatch"));
+        assert_eq!(results[2].errors[0].field, "changes[2].value");
+
+        assert_eq!(state.state_hash(), hash_before);
+        assert_eq!(state.op_log.ops.len(), ops_before);
+        assert!(state.crdt_map.get("tags").is_none());
+
+        // 实际应用时同样拒绝类型不符的变更
+        assert!(
+            state
+                .apply_changes(ChangeRequest {
+                    changes: vec![request.changes[1].clone()],
+                })
+                .is_err()
+        );
+        assert_eq!(state.op_log.ops.len(), ops_before);
     }
 
     #[test]
-    fn test_convergence_property() {
-        // 测试 CRDT 的收敛性：两个节点以不同顺序合并应该得到相同结果
+    fn test_missing_ops_lists_only_peer_extra_ops() {
+        let mut local = SyncState::new("node1".to_string());
+        local.apply_changes(add_to("tags", "a")).unwrap();
+        let mut peer = SyncState::new("node2".to_string());
+        peer.merge(&local);
+        peer.apply_changes(add_to("tags", "b")).unwrap();
+        peer.apply_changes(add_to("colors", "red")).unwrap();
+
+        let missing: Vec<&str> = local
+            .op_log
+            .missing_ops(&peer.op_log)
+            .iter()
+            .map(|e| e.id.as_str())
+            .collect();
+        let expected: Vec<&str> = peer.op_log.ops[1..].iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(missing, expected);
+        assert!(peer.op_log.missing_ops(&local.op_log).is_empty());
+
+        local.merge(&peer);
+        assert!(local.op_log.missing_ops(&peer.op_log).is_empty());
+    }
+
+    #[test]
+    fn test_oplog_frontier_tracks_latest_op_per_node() {
+        let set = |key: &str, value: &str| ChangeRequest {
+            changes: vec![Change {
+                op: "set".to_string(),
+                key: key.to_string(),
+                value: Some(value.to_string()),
+                ..Default::default()
+            }],
+        };
         let mut state1 = SyncState::new("node1".to_string());
         let mut state2 = SyncState::new("node2".to_string());
-        let mut state3 = SyncState::new("node3".to_string());
+        state1.apply_changes(set("a", "1")).unwrap();
+        state1.apply_changes(set("b", "2")).unwrap();
+        state2
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "add".to_string(),
+                    key: "tags".to_string(),
+                    value: Some("x".to_string()),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        let node1_head = state1.op_log.ops.last().unwrap().id.clone();
+        state1.merge(&state2);
 
-        let op1 = Operation::GCounterIncrement {
-            key: "counter".to_string(),
-            node_id: "node1".to_string(),
-            delta: 5,
-        };
-        state1.apply_operation(op1);
+        let frontier = state1.op_log.frontier();
+        assert_eq!(frontier.len(), 2);
+        assert_eq!(frontier["node1"].seq, 2);
+        assert_eq!(frontier["node1"].id, node1_head);
+        // ORSet 操作不携带 node_id，通过 origin 识别发起节点
+        assert_eq!(frontier["node2"].seq, 1);
+        assert_eq!(frontier["node2"].id, state2.op_log.ops[0].id);
 
-        let op2 = Operation::GCounterIncrement {
-            key: "counter".to_string(),
-            node_id: "node2".to_string(),
-            delta: 3,
-        };
-        state2.apply_operation(op2);
+        state1.apply_changes(set("a", "3")).unwrap();
+        let frontier = state1.op_log.frontier();
+        assert_eq!(frontier["node1"].seq, 3);
+        assert_eq!(frontier["node1"].id, state1.op_log.ops.last().unwrap().id);
+    }
 
-        // state3 先合并 state1，再合并 state2
-        state3.merge(&state1);
-        state3.merge(&state2);
+    #[test]
+    fn test_multi_increment_logs_single_entry_and_replays() {
+        let mut state = SyncState::new("node1".to_string());
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "increment".to_string(),
+                    key: "hits:/a".to_string(),
+                    delta: Some(2),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        let ops_before = state.op_log.ops.len();
 
-        // 创建另一个副本，以相反顺序合并
-        let mut state4 = SyncState::new("node4".to_string());
-        state4.merge(&state2);
-        state4.merge(&state1);
+        let counters = HashMap::from([
+            ("hits:/a".to_string(), 3),
+            ("hits:/b".to_string(), 5),
+            ("hits:/c".to_string(), 1),
+        ]);
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "multi-inc".to_string(),
+                    counters,
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
 
-        // 两者应该产生相同的状态哈希
-        assert_eq!(state3.state_hash(), state4.state_hash());
+        assert_eq!(state.op_log.ops.len(), ops_before + 1);
+        let document = state.crdt_map.document();
+        assert_eq!(document["hits:/a"], serde_json::json!(5));
+        assert_eq!(document["hits:/b"], serde_json::json!(5));
+        assert_eq!(document["hits:/c"], serde_json::json!(1));
+
+        let (replayed, applied) = state.replay(|_| true);
+        assert_eq!(applied, state.op_log.ops.len());
+        assert_eq!(replayed.state_hash(), state.state_hash());
+
+        let by_key = state.op_log.filtered(&OpLogFilter {
+            key: Some("hits:/b".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(by_key.ops.len(), 1);
+
+        let mut replica = SyncState::new("node2".to_string());
+        replica.merge(&state);
+        assert_eq!(replica.state_hash(), state.state_hash());
+
+        // 任一计数器类型不符时整体拒绝
+        state
+            .apply_changes(ChangeRequest {
+                changes: vec![Change {
+                    op: "set".to_string(),
+                    key: "name".to_string(),
+                    value: Some("x".to_string()),
+                    ..Default::default()
+                }],
+            })
+            .unwrap();
+        let hash = state.state_hash();
+        assert!(
+            state
+                .multi_increment(HashMap::from([
+                    ("hits:/a".to_string(), 1),
+                    ("name".to_string(), 1),
+                ]))
+                .is_err()
+        );
+        assert_eq!(state.state_hash(), hash);
     }
 }
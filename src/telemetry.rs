@@ -0,0 +1,272 @@
+use anyhow::{Context, Result};
+use opentelemetry::metrics::{Counter, Histogram};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{KeyValue, global};
+use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{Resource, runtime};
+use silent::prelude::*;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetryLayer;
+
+/// 上报的服务名
+const SERVICE_NAME: &str = "silent-crdt";
+
+/// OTLP 导出器（追踪 + 指标），退出前需调用 `shutdown` 以刷出缓冲数据
+pub struct OtelExporter {
+    tracer_provider: TracerProvider,
+    meter_provider: SdkMeterProvider,
+}
+
+impl OtelExporter {
+    /// 初始化指向 OTLP gRPC 收集器（如 `http://localhost:4317`）的导出器，并注册为全局指标提供者
+    pub fn init(endpoint: &str, node_id: &str) -> Result<Self> {
+        let resource = Resource::new(vec![
+            KeyValue::new("service.name", SERVICE_NAME),
+            KeyValue::new("service.instance.id", node_id.to_string()),
+        ]);
+
+        let span_exporter = SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()
+            .context("Failed to build OTLP span exporter")?;
+        let tracer_provider = TracerProvider::builder()
+            .with_batch_exporter(span_exporter, runtime::Tokio)
+            .with_resource(resource.clone())
+            .build();
+
+        let metric_exporter = MetricExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()
+            .context("Failed to build OTLP metric exporter")?;
+        let reader = PeriodicReader::builder(metric_exporter, runtime::Tokio).build();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(reader)
+            .with_resource(resource)
+            .build();
+        global::set_meter_provider(meter_provider.clone());
+
+        Ok(Self {
+            tracer_provider,
+            meter_provider,
+        })
+    }
+
+    /// 将 tracing span 转发到 OTLP 的订阅层
+    pub fn layer<S>(&self) -> OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>
+    where
+        S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+    {
+        tracing_opentelemetry::layer().with_tracer(self.tracer_provider.tracer(SERVICE_NAME))
+    }
+
+    /// 刷出并关闭导出器
+    pub fn shutdown(self) {
+        if let Err(e) = self.tracer_provider.shutdown() {
+            tracing::warn!("Failed to shut down OTLP tracer provider: {}", e);
+        }
+        if let Err(e) = self.meter_provider.shutdown() {
+            tracing::warn!("Failed to shut down OTLP meter provider: {}", e);
+        }
+    }
+}
+
+/// HTTP 请求指标（未配置导出器时为 no-op）
+struct RequestMetrics {
+    requests: Counter<u64>,
+    duration: Histogram<f64>,
+}
+
+fn request_metrics() -> &'static RequestMetrics {
+    static METRICS: OnceLock<RequestMetrics> = OnceLock::new();
+    METRICS.get_or_init(|| {
+        let meter = global::meter(SERVICE_NAME);
+        RequestMetrics {
+            requests: meter
+                .u64_counter("http.server.requests")
+                .with_description("HTTP 请求数")
+                .build(),
+            duration: meter
+                .f64_histogram("http.server.duration")
+                .with_description("HTTP 请求处理耗时")
+                .with_unit("ms")
+                .build(),
+        }
+    })
+}
+
+/// 单个 HTTP 请求的 span，OTel 中以 `<METHOD> <path>` 命名
+pub fn request_span(method: &str, path: &str) -> tracing::Span {
+    tracing::info_span!(
+        "http.request",
+        otel.name = %format!("{} {}", method, path),
+        http.method = %method,
+        http.route = %path,
+        outcome = tracing::field::Empty,
+    )
+}
+
+/// 请求追踪中间件：为每个请求创建 span 并记录请求数与耗时
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestTelemetry;
+
+#[async_trait::async_trait]
+impl MiddleWareHandler for RequestTelemetry {
+    async fn handle(&self, req: Request, next: &Next) -> silent::Result<Response> {
+        let method = req.method().to_string();
+        let path = req.uri().path().to_string();
+        let span = request_span(&method, &path);
+        let start = Instant::now();
+
+        let result = next.call(req).instrument(span.clone()).await;
+
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        span.record("outcome", outcome);
+        let attributes = [
+            KeyValue::new("http.method", method),
+            KeyValue::new("outcome", outcome),
+        ];
+        let metrics = request_metrics();
+        metrics.requests.add(1, &attributes);
+        metrics
+            .duration
+            .record(start.elapsed().as_secs_f64() * 1000.0, &attributes);
+
+        result
+    }
+}
+
+/// 慢操作日志中最多列出的键数量
+const SLOW_OP_MAX_KEYS: usize = 10;
+
+/// 慢操作记录器：临界区耗时超过阈值时记录警告日志（未配置阈值时不计时）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlowOpLogger {
+    threshold: Option<Duration>,
+}
+
+impl SlowOpLogger {
+    pub fn new(threshold_ms: Option<u64>) -> Self {
+        Self {
+            threshold: threshold_ms.map(Duration::from_millis),
+        }
+    }
+
+    /// 执行 `f` 并在耗时超过阈值时记录操作类型、耗时、操作数与涉及的键
+    pub fn time<T>(
+        &self,
+        kind: &str,
+        op_count: usize,
+        keys: impl FnOnce() -> Vec<String>,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        let Some(threshold) = self.threshold else {
+            return f();
+        };
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+        if elapsed > threshold {
+            let mut keys = keys();
+            let total_keys = keys.len();
+            keys.truncate(SLOW_OP_MAX_KEYS);
+            tracing::warn!(
+                kind,
+                duration_ms = elapsed.as_millis() as u64,
+                threshold_ms = threshold.as_millis() as u64,
+                op_count,
+                total_keys,
+                keys = ?keys,
+                "Slow operation"
+            );
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// 收集日志输出的写入端
+    #[derive(Clone, Default)]
+    struct Captured(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_slow_op_logged_only_above_threshold() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let logger = SlowOpLogger::new(Some(20));
+        let keys = || vec!["counter1".to_string()];
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert_eq!(logger.time("merge", 3, keys, || 1), 1);
+            logger.time("apply_changes", 2, keys, || {
+                std::thread::sleep(Duration::from_millis(40))
+            });
+            // 未配置阈值时不记录
+            SlowOpLogger::default().time("state_hash", 1, keys, || {
+                std::thread::sleep(Duration::from_millis(40))
+            });
+        });
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output
+            .lines()
+            .filter(|l| l.contains("Slow operation"))
+            .collect();
+        assert_eq!(lines.len(), 1, "{}", output);
+        assert!(lines[0].contains("kind=\"apply_changes\""));
+        assert!(lines[0].contains("op_count=2"));
+        assert!(lines[0].contains("counter1"));
+    }
+
+    #[test]
+    fn test_request_span_is_exported() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME)));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = request_span("GET", "/state");
+            let _entered = span.enter();
+            tracing::info!("handling request");
+        });
+        provider.force_flush();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "GET /state");
+        assert!(
+            spans[0]
+                .attributes
+                .iter()
+                .any(|kv| kv.key.as_str() == "http.route" && kv.value.as_str() == "/state")
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::{Context, Result, anyhow};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tonic::transport::{Identity, ServerTlsConfig};
+
+/// HTTP 与 gRPC 服务共用的 PEM 证书与私钥
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl TlsFiles {
+    pub fn new(cert: PathBuf, key: PathBuf) -> Self {
+        Self { cert, key }
+    }
+
+    fn read(&self) -> Result<(Vec<u8>, Vec<u8>)> {
+        Ok((read_pem(&self.cert)?, read_pem(&self.key)?))
+    }
+
+    /// HTTP 服务使用的 rustls 配置（ALPN 同时支持 HTTP/2 与 HTTP/1.1）
+    pub fn rustls_config(&self) -> Result<Arc<rustls::ServerConfig>> {
+        let (cert_pem, key_pem) = self.read()?;
+        let certs = rustls_pemfile::certs(&mut cert_pem.as_slice())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .with_context(|| format!("Invalid certificate: {}", self.cert.display()))?;
+        if certs.is_empty() {
+            return Err(anyhow!("No certificate found in {}", self.cert.display()));
+        }
+        let key = rustls_pemfile::private_key(&mut key_pem.as_slice())
+            .with_context(|| format!("Invalid private key: {}", self.key.display()))?
+            .ok_or_else(|| anyhow!("No private key found in {}", self.key.display()))?;
+
+        let mut config = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(Arc::new(config))
+    }
+
+    /// gRPC 服务使用的 TLS 配置
+    pub fn grpc_config(&self) -> Result<ServerTlsConfig> {
+        let (cert_pem, key_pem) = self.read()?;
+        Ok(ServerTlsConfig::new().identity(Identity::from_pem(cert_pem, key_pem)))
+    }
+}
+
+/// 读取 PEM 文件（证书、私钥或对等节点 CA）
+pub fn read_pem(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::AppState;
+    use crate::grpc_service::CrdtServiceImpl;
+    use crate::grpc_service::crdt::HealthCheckRequest;
+    use crate::grpc_service::crdt::crdt_service_client::CrdtServiceClient;
+    use crate::storage::MemoryStorage;
+    use tonic::transport::{Certificate, Channel, ClientTlsConfig, Server};
+
+    /// 生成 localhost 的自签名证书并写入临时目录
+    fn self_signed(dir: &Path) -> (TlsFiles, String) {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_pem = certified.cert.pem();
+        let files = TlsFiles::new(dir.join("cert.pem"), dir.join("key.pem"));
+        std::fs::write(&files.cert, &cert_pem).unwrap();
+        std::fs::write(&files.key, certified.key_pair.serialize_pem()).unwrap();
+        (files, cert_pem)
+    }
+
+    #[test]
+    fn test_rustls_config_loads_pem_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (files, _) = self_signed(dir.path());
+        let config = files.rustls_config().unwrap();
+        assert_eq!(config.alpn_protocols[0], b"h2");
+
+        // 私钥文件中没有私钥时报错
+        let swapped = TlsFiles::new(files.cert.clone(), files.cert.clone());
+        assert!(swapped.rustls_config().is_err());
+        let missing = TlsFiles::new(dir.path().join("missing.pem"), files.key.clone());
+        assert!(missing.rustls_config().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tls_server_rejects_plaintext() {
+        let dir = tempfile::tempdir().unwrap();
+        let (files, cert_pem) = self_signed(dir.path());
+        let app_state = AppState::new(
+            "node1".to_string(),
+            Arc::new(MemoryStorage::new()),
+            "secret".to_string(),
+            false,
+        )
+        .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = Server::builder()
+            .tls_config(files.grpc_config().unwrap())
+            .unwrap()
+            .add_service(CrdtServiceImpl::new(app_state).into_server())
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener));
+        tokio::spawn(server);
+
+        // 明文连接无法完成请求
+        let plaintext = Channel::from_shared(format!("http://localhost:{}", port))
+            .unwrap()
+            .connect()
+            .await;
+        if let Ok(channel) = plaintext {
+            let result = CrdtServiceClient::new(channel)
+                .health_check(HealthCheckRequest {})
+                .await;
+            assert!(result.is_err());
+        }
+
+        // 信任自签名证书的 TLS 客户端正常访问
+        let tls = ClientTlsConfig::new()
+            .ca_certificate(Certificate::from_pem(cert_pem))
+            .domain_name("localhost");
+        let channel = Channel::from_shared(format!("https://localhost:{}", port))
+            .unwrap()
+            .tls_config(tls)
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let response = CrdtServiceClient::new(channel)
+            .health_check(HealthCheckRequest {})
+            .await
+            .unwrap();
+        assert_eq!(response.into_inner().status, "ok");
+    }
+}